@@ -0,0 +1,325 @@
+//! Aging Bloom filter, forgetting keys after a time to live.
+//!
+//! Answers "was this key seen in the last `ttl` units of time?", e.g. to
+//! drop replayed request IDs. Time is split into generations, each with its
+//! own Bloom filter: keys are inserted into the current generation, and
+//! queries check all of them. Advancing time past the end of the current
+//! generation starts a new one, recycling the filter of the oldest.
+//!
+//! With `G` generations, each spans `ttl / (G - 1)` (rounded up), so that a
+//! key is remembered for at least `ttl`, and at most one generation longer.
+//! More generations make for more accurate expiry, at the cost of memory
+//! and query time. Each generation gets a `1 / G` share of the false
+//! positive rate, which thus bounds the overall rate.
+//!
+//! Time is whatever unit the caller counts in (seconds, milliseconds, or
+//! ticks of a timer), as long as it never decreases.
+//!
+//! With the `mmap` feature, older generations, which only take queries, can
+//! be spilled to memory-mapped files, see
+//! `AgingBloomFilter::spill_cold_generations`: long TTLs then take many
+//! generations without all of them being resident.
+
+#[cfg(all(feature = "mmap", unix))]
+use {
+    crate::storage::{BitStorage, MmapBitSet},
+    std::{io, path::Path},
+};
+use {
+    crate::{
+        analysis::check_fp_rate,
+        hash::ProbeHasher,
+        BloomFilter,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, collections::VecDeque, hash::Hash},
+};
+
+/// Default number of generations.
+pub const DEFAULT_GENERATIONS: usize = 4;
+
+/// Bloom filter whose keys expire after a time to live.
+pub struct AgingBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    /// Filters of the live generations kept in memory, from the current one.
+    generations: VecDeque<BloomFilter<K, H>>,
+    /// Filters of the older generations spilled to files, from the newest.
+    #[cfg(all(feature = "mmap", unix))]
+    cold: VecDeque<BloomFilter<K, H, MmapBitSet>>,
+    /// Time span of a generation.
+    span: u64,
+    /// Start time of the current generation.
+    start: u64,
+    ttl: u64,
+}
+
+impl<K> AgingBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter forgetting keys after `ttl` units of time, sized
+    /// for a desired number of keys inserted within any `ttl` long interval
+    /// and false positive rate, with [`DEFAULT_GENERATIONS`] generations.
+    ///
+    /// Time starts at 0.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ttl` is zero, or the rate is not within `0..1`
+    /// (exclusive).
+    pub fn new(ttl: u64, capacity: usize, fp_rate: f64) -> Self {
+        Self::with_params(ttl, capacity, fp_rate, DEFAULT_GENERATIONS)
+            .expect("default parameters are valid")
+    }
+
+    /// Creates a new filter forgetting keys after `ttl` units of time, sized
+    /// for a desired number of keys inserted within any `ttl` long interval,
+    /// false positive rate, and number of generations.
+    ///
+    /// Fails unless `ttl` is positive, there are at least 2 generations, and
+    /// the rate is within `0..1` (exclusive).
+    pub fn with_params(
+        ttl: u64,
+        capacity: usize,
+        fp_rate: f64,
+        generations: usize,
+    ) -> QueryFilterResult<Self> {
+        Self::with_params_and_hasher(ttl, capacity, fp_rate, generations, ProbeHasher::default())
+    }
+}
+
+impl<K, H> AgingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Creates a new filter forgetting keys after `ttl` units of time, sized
+    /// for a desired number of keys inserted within any `ttl` long interval,
+    /// false positive rate, number of generations, and hasher.
+    ///
+    /// See [`with_params`](AgingBloomFilter::with_params).
+    pub fn with_params_and_hasher(
+        ttl: u64,
+        capacity: usize,
+        fp_rate: f64,
+        generations: usize,
+        hasher: H,
+    ) -> QueryFilterResult<Self> {
+        if ttl == 0 {
+            return Err(QueryFilterError::Other(
+                "invalid TTL: must be positive".to_owned(),
+            ));
+        }
+        if generations < 2 {
+            return Err(QueryFilterError::Other(format!(
+                "invalid generation count: {generations} is less than 2"
+            )));
+        }
+        check_fp_rate(fp_rate)?;
+        let span = ttl.div_ceil(generations as u64 - 1);
+        let capacity = capacity.div_ceil(generations - 1).max(1);
+        let fp_rate = fp_rate / generations as f64;
+        Ok(Self {
+            generations: (0..generations)
+                .map(|_| BloomFilter::with_capacity_and_hasher(capacity, fp_rate, hasher.clone()))
+                .collect(),
+            #[cfg(all(feature = "mmap", unix))]
+            cold: VecDeque::new(),
+            span,
+            start: 0,
+            ttl,
+        })
+    }
+}
+
+impl<K, H> AgingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Returns the time to live of keys.
+    pub fn ttl(&self) -> u64 {
+        self.ttl
+    }
+
+    /// Returns the time span of a generation.
+    pub fn generation_span(&self) -> u64 {
+        self.span
+    }
+
+    /// Returns the number of generations.
+    pub fn generation_count(&self) -> usize {
+        let count = self.generations.len();
+        #[cfg(all(feature = "mmap", unix))]
+        let count = count + self.cold.len();
+        count
+    }
+
+    /// Returns the start time of the current generation.
+    pub fn generation_start(&self) -> u64 {
+        self.start
+    }
+
+    /// Returns the memory used by the bits of the generations kept in memory,
+    /// in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.generations
+            .iter()
+            .map(BloomFilter::size_in_bytes)
+            .sum()
+    }
+
+    /// Advances time to `now`, expiring the generations that ended more than
+    /// a TTL ago.
+    ///
+    /// Time never moves back: an earlier time is ignored.
+    pub fn advance(&mut self, now: u64) {
+        if now < self.start.saturating_add(self.span) {
+            return;
+        }
+        let elapsed = (now - self.start) / self.span;
+        // Past as many generations as there are, all of them expired.
+        for _ in 0..elapsed.min(self.generation_count() as u64) {
+            self.rotate();
+        }
+        self.start += elapsed * self.span;
+        event!(start = self.start, elapsed; "advanced aging Bloom filter");
+    }
+
+    /// Advances time to the start of the next generation, for callers
+    /// driving the filter from a timer firing every
+    /// [`generation_span`](AgingBloomFilter::generation_span).
+    pub fn tick(&mut self) {
+        self.advance(self.start.saturating_add(self.span));
+    }
+
+    /// Starts a new generation, recycling the filter of the oldest one.
+    ///
+    /// Once generations were spilled, the oldest one in memory turns cold,
+    /// taking over the file of the oldest cold one.
+    fn rotate(&mut self) {
+        let mut oldest = self.generations.pop_back().expect("there are generations");
+        #[cfg(all(feature = "mmap", unix))]
+        if let Some(mut coldest) = self.cold.pop_back() {
+            coldest.clear();
+            for index in oldest.ones() {
+                coldest.bits_mut().insert(index);
+            }
+            self.cold.push_front(coldest);
+        }
+        oldest.clear();
+        self.generations.push_front(oldest);
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl<K, H> AgingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Moves all but the `hot` most recent generations out of memory, into
+    /// memory-mapped files in `dir` (named `generation-<i>.mqbm`, truncated
+    /// if they exist), see [`BloomFilter::to_mmap`].
+    ///
+    /// Queries only page in the bits of cold generations they probe. As time
+    /// advances, the oldest generation in memory is written over the file of
+    /// the expired one, so that files are reused rather than created. They
+    /// are left in place when the filter is dropped.
+    ///
+    /// Fails (leaving all generations in memory) unless `hot` is positive,
+    /// if generations were already spilled, or if a file cannot be written.
+    pub fn spill_cold_generations(&mut self, hot: usize, dir: impl AsRef<Path>) -> io::Result<()> {
+        if hot == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the current generation must stay in memory",
+            ));
+        }
+        if !self.cold.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "generations were already spilled",
+            ));
+        }
+        let cold = self
+            .generations
+            .iter()
+            .skip(hot)
+            .enumerate()
+            .map(|(index, generation)| {
+                generation.to_mmap(dir.as_ref().join(format!("generation-{index}.mqbm")))
+            })
+            .collect::<io::Result<VecDeque<_>>>()?;
+        self.generations.truncate(hot);
+        self.cold = cold;
+        event!(hot = self.generations.len(), cold = self.cold.len(); "spilled aging Bloom filter generations");
+        Ok(())
+    }
+
+    /// Returns the number of generations spilled to files.
+    pub fn cold_generation_count(&self) -> usize {
+        self.cold.len()
+    }
+}
+
+impl<K, H> QueryFilter<K> for AgingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let found = self
+            .generations
+            .iter()
+            .any(|generation| generation.contains(key));
+        // Cold generations are only paged in if the ones in memory miss.
+        #[cfg(all(feature = "mmap", unix))]
+        let found = found || self.cold.iter().any(|generation| generation.contains(key));
+        found
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for AgingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Inserts a key into the current generation.
+    ///
+    /// Inserting a key again renews its time to live.
+    fn insert(&mut self, key: K) {
+        self.generations
+            .front_mut()
+            .expect("there are generations")
+            .insert(key);
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for AgingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Removes all keys, keeping the current time.
+    fn clear(&mut self) {
+        for generation in &mut self.generations {
+            generation.clear();
+        }
+        #[cfg(all(feature = "mmap", unix))]
+        for generation in &mut self.cold {
+            generation.clear();
+        }
+    }
+}
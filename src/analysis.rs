@@ -0,0 +1,391 @@
+//! Theoretical analysis of filter parameters.
+//!
+//! Closed-form approximations that allow exploring space/accuracy trade-offs
+//! of different filters without actually building them. Throughout the module
+//! `m` denotes the number of bits, `n` the number of inserted items, and `k`
+//! the number of hash functions (probes).
+
+#[cfg(any(
+    feature = "bf",
+    feature = "sbf",
+    feature = "xor",
+    feature = "cuckoo",
+    feature = "qf"
+))]
+use crate::{QueryFilterError, QueryFilterResult};
+
+/// Fails with [`InvalidFpRate`](QueryFilterError::InvalidFpRate) unless the
+/// false positive rate is within `(0, 1)`.
+#[cfg(any(
+    feature = "bf",
+    feature = "sbf",
+    feature = "xor",
+    feature = "cuckoo",
+    feature = "qf"
+))]
+pub(crate) fn check_fp_rate(fp_rate: f64) -> QueryFilterResult<()> {
+    if !(fp_rate > 0. && fp_rate < 1.) {
+        return Err(QueryFilterError::InvalidFpRate(fp_rate));
+    }
+    Ok(())
+}
+
+/// Given a capacity and a desired false positive rate, returns the optimal
+/// number of bits to use (size of the filter, `m`), along with an for an
+/// optimal `k`.
+pub fn optimal_bit_count(capacity: usize, fp_rate: f64) -> usize {
+    let ln2 = std::f64::consts::LN_2;
+    let n = capacity as f64;
+    let p = fp_rate;
+
+    (-n * p.ln() / ln2.powi(2)).ceil() as usize
+}
+
+/// Given a desired false positive rate and the number of bits, returns the
+/// optimal capacity (number of items hashed into filter, `n`).
+pub fn optimal_capacity(bit_count: usize, fp_rate: f64) -> usize {
+    let ln2 = std::f64::consts::LN_2;
+    let m = bit_count as f64;
+    let p = fp_rate;
+
+    (m * ln2.powi(2) / -p.ln()).round() as usize
+}
+
+/// Returns the optimal number of hash functions to use (`k`).
+///
+/// Current implementation relies on double hashing, so for a given key, it
+/// creates this many hash values (while internally using up to two
+/// different hash functions -- mostly one).
+pub fn optimal_hash_count(capacity: usize, bit_count: usize) -> usize {
+    let ln2 = std::f64::consts::LN_2;
+    let n = capacity as f64;
+    let m = bit_count as f64;
+
+    (m / n * ln2).ceil() as usize
+}
+
+/// Returns the expected false positive rate of a Bloom filter with `m` bits
+/// and `k` hash functions, once `n` items have been inserted.
+///
+/// Uses the standard approximation `(1 - e^(-kn/m))^k`.
+pub fn fp_rate(bit_count: usize, capacity: usize, hash_count: usize) -> f64 {
+    expected_fill(bit_count, capacity, hash_count).powi(hash_count as i32)
+}
+
+/// Returns the expected fraction of bits set in a Bloom filter with `m` bits
+/// and `k` hash functions, once `n` items have been inserted.
+pub fn expected_fill(bit_count: usize, capacity: usize, hash_count: usize) -> f64 {
+    if bit_count == 0 {
+        return 1.;
+    }
+    let m = bit_count as f64;
+    let n = capacity as f64;
+    let k = hash_count as f64;
+
+    1. - (-k * n / m).exp()
+}
+
+/// Returns the expected false positive rate of a blocked Bloom filter with
+/// `m` bits split into blocks of `block_bits` bits, where all `k` probes of a
+/// key land in a single block.
+///
+/// Block loads are uneven (Poisson distributed), and overloaded blocks
+/// dominate the rate, so it is always higher than the one of a classic
+/// filter of the same size, see [Cache-, Hash- and Space-Efficient Bloom
+/// Filters, 2007][1].
+///
+/// [1]: https://algo2.iti.kit.edu/documents/cacheefficientbloomfilters-jea.pdf
+pub fn blocked_fp_rate(
+    bit_count: usize,
+    capacity: usize,
+    hash_count: usize,
+    block_bits: usize,
+) -> f64 {
+    if bit_count == 0 {
+        return 1.;
+    }
+    let lambda = block_bits as f64 * capacity as f64 / bit_count as f64;
+    let terms = (lambda + 10. * lambda.sqrt() + 10.).ceil() as usize;
+    let mut load = (-lambda).exp();
+    let mut rate = 0.;
+    for i in 0..terms {
+        rate += load * fp_rate(block_bits, i, hash_count);
+        load *= lambda / (i + 1) as f64;
+    }
+    rate
+}
+
+/// Returns the upper bound on the false positive rate of a cuckoo filter with
+/// buckets of `bucket_size` entries holding `fingerprint_bits`-bit
+/// fingerprints.
+///
+/// A lookup compares the fingerprint against at most `2b` entries (two
+/// candidate buckets), so the rate is `1 - (1 - 2^-f)^(2b)`, as derived in
+/// [Cuckoo Filter: Practically Better Than Bloom, 2014][1].
+///
+/// [1]: https://www.cs.cmu.edu/~dga/papers/cuckoo-conext2014.pdf
+pub fn cuckoo_fp_rate(bucket_size: usize, fingerprint_bits: u32) -> f64 {
+    let f = fingerprint_bits as i32;
+    let b = bucket_size as i32;
+
+    1. - (1. - 2f64.powi(-f)).powi(2 * b)
+}
+
+/// Returns the maximum load factor a cuckoo filter with buckets of
+/// `bucket_size` entries reliably reaches before insertions start to fail.
+///
+/// Figures are the empirical ones reported in the original paper (for two
+/// candidate buckets per item).
+pub fn cuckoo_max_load_factor(bucket_size: usize) -> f64 {
+    match bucket_size {
+        0 => 0.,
+        1 => 0.5,
+        2 => 0.84,
+        3 => 0.91,
+        4 => 0.95,
+        5..=7 => 0.96,
+        _ => 0.98,
+    }
+}
+
+/// Returns the number of bits a cuckoo filter spends per stored item, given
+/// the fingerprint size and the load factor the table is operated at.
+pub fn cuckoo_bits_per_item(fingerprint_bits: u32, load_factor: f64) -> f64 {
+    fingerprint_bits as f64 / load_factor
+}
+
+/// Returns the smallest fingerprint size (in bits) for which a cuckoo filter
+/// with buckets of `bucket_size` entries stays within the desired false
+/// positive rate.
+pub fn cuckoo_fingerprint_bits(fp_rate: f64, bucket_size: usize) -> u32 {
+    (2. * bucket_size as f64 / fp_rate).log2().ceil().max(1.) as u32
+}
+
+/// Returns the memory (in bytes) a cuckoo filter needs to hold `capacity`
+/// items, given the fingerprint size and the load factor the table is
+/// operated at.
+pub fn cuckoo_size_in_bytes(capacity: usize, fingerprint_bits: u32, load_factor: f64) -> usize {
+    (capacity as f64 * cuckoo_bits_per_item(fingerprint_bits, load_factor) / 8.).ceil() as usize
+}
+
+/// Number of metadata bits a quotient filter keeps per slot (occupied,
+/// continuation, and shifted flags).
+pub const QUOTIENT_METADATA_BITS: u32 = 3;
+
+/// Returns the false positive rate of a quotient filter with
+/// `remainder_bits`-bit remainders, operated at a given load factor.
+///
+/// A false positive requires another item with the same quotient and
+/// remainder, so the rate is `1 - e^(-α / 2^r)`, i.e. roughly `α * 2^-r`, see
+/// [Don't Thrash: How to Cache Your Hash on Flash, 2012][1].
+///
+/// [1]: https://vldb.org/pvldb/vol5/p1627_michaelabender_vldb2012.pdf
+pub fn quotient_fp_rate(remainder_bits: u32, load_factor: f64) -> f64 {
+    1. - (-load_factor * 2f64.powi(-(remainder_bits as i32))).exp()
+}
+
+/// Returns the smallest remainder size (in bits) for which a quotient filter
+/// operated at a given load factor stays within the desired false positive
+/// rate.
+pub fn quotient_remainder_bits(fp_rate: f64, load_factor: f64) -> u32 {
+    (load_factor / fp_rate).log2().ceil().max(1.) as u32
+}
+
+/// Returns the memory (in bytes) a quotient filter needs to hold `capacity`
+/// items without exceeding a given load factor.
+///
+/// The number of slots is a power of two, and each slot holds the remainder
+/// and [`QUOTIENT_METADATA_BITS`] bits of metadata.
+pub fn quotient_size_in_bytes(capacity: usize, remainder_bits: u32, load_factor: f64) -> usize {
+    let slots = ((capacity as f64 / load_factor).ceil() as usize).next_power_of_two();
+    (slots * (remainder_bits + QUOTIENT_METADATA_BITS) as usize).div_ceil(8)
+}
+
+/// Space overhead factor of a (3-wise) xor filter: the fingerprint array
+/// holds `1.23 * n` slots.
+pub const XOR_SPACE_OVERHEAD: f64 = 1.23;
+
+/// Returns the false positive rate of a xor filter with `fingerprint_bits`-bit
+/// fingerprints, i.e. `2^-f`.
+pub fn xor_fp_rate(fingerprint_bits: u32) -> f64 {
+    2f64.powi(-(fingerprint_bits as i32))
+}
+
+/// Returns the number of bits a xor filter spends per key, given the
+/// fingerprint size.
+pub fn xor_bits_per_key(fingerprint_bits: u32) -> f64 {
+    XOR_SPACE_OVERHEAD * fingerprint_bits as f64
+}
+
+/// Returns the smallest fingerprint size (in bits) for which a xor filter
+/// stays within the desired false positive rate.
+pub fn xor_fingerprint_bits(fp_rate: f64) -> u32 {
+    (1. / fp_rate).log2().ceil().max(1.) as u32
+}
+
+/// Returns the memory (in bytes) a xor filter over `capacity` keys needs,
+/// given the fingerprint size.
+pub fn xor_size_in_bytes(capacity: usize, fingerprint_bits: u32) -> usize {
+    let slots = 32 + (capacity as f64 * XOR_SPACE_OVERHEAD).ceil() as usize;
+    (slots * fingerprint_bits as usize).div_ceil(8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn optimal_bit_count_works() {
+        // Given `n` (capacity) and `p` (false positive rate), find `m` (size) and
+        // optimal `k`.
+        let test_cases = [
+            (10, 0.05, 63, 5),
+            (100, 0.05, 624, 5),
+            (100, 0.1, 480, 4),
+            (100, 0.01, 959, 7),
+            (1000, 0.01, 9586, 7),
+            (10000, 0.01, 95851, 7),
+            (100000, 0.01, 958506, 7),
+        ];
+        for (n, p, m, k) in test_cases {
+            assert_eq!(optimal_bit_count(n, p), m);
+            assert_eq!(optimal_hash_count(n, m), k);
+            assert_eq!(optimal_bit_count(optimal_capacity(m, p), p), m);
+        }
+    }
+
+    #[test]
+    fn optimal_capacity_works() {
+        let test_cases = [
+            (1usize << 13, 0.01, 855, 7), // 1 KiB
+            (1usize << 13, 0.05, 1314, 5),
+            (1usize << 23, 0.01, 875175, 7), // 1 MiB
+            (1usize << 23, 0.05, 1345358, 5),
+            (1usize << 33, 0.01, 896179684, 7), // 1 GiB
+            (1usize << 33, 0.05, 1377646461, 5),
+        ];
+        for (m, p, n, k) in test_cases {
+            assert_eq!(optimal_capacity(m, p), n);
+            assert_eq!(optimal_hash_count(n, m), k);
+            assert_eq!(optimal_capacity(optimal_bit_count(n, p), p), n);
+        }
+    }
+
+    #[test]
+    fn optimal_hash_count_works() {
+        let test_cases = [
+            (10, 63, 5),
+            (100, 624, 5),
+            (100, 480, 4),
+            (100, 959, 7),
+            (1000, 9586, 7),
+            (10000, 95851, 7),
+            (100000, 958506, 7),
+        ];
+        for (n, m, k) in test_cases {
+            assert_eq!(optimal_hash_count(n, m), k);
+        }
+    }
+
+    #[test]
+    fn fp_rate_works() {
+        // Optimally sized filters should land on (or just below) the target rate.
+        let test_cases = [(100, 0.05), (1000, 0.01), (100000, 0.01), (100000, 0.001)];
+        for (n, p) in test_cases {
+            let m = optimal_bit_count(n, p);
+            let k = optimal_hash_count(n, m);
+            let rate = fp_rate(m, n, k);
+            assert!(rate <= p * 1.05, "n={n}, p={p}, rate={rate}");
+            assert!(rate >= p * 0.8, "n={n}, p={p}, rate={rate}");
+        }
+
+        assert_eq!(fp_rate(1000, 0, 7), 0.);
+        assert_eq!(fp_rate(0, 10, 7), 1.);
+    }
+
+    #[test]
+    fn blocked_fp_rate_works() {
+        let (n, m, k) = (100000, 1 << 20, 7);
+        let classic = fp_rate(m, n, k);
+        let blocked = blocked_fp_rate(m, n, k, 64);
+        assert!(blocked > classic, "classic={classic}, blocked={blocked}");
+        // Larger blocks even out the load, and approach the classic filter.
+        let large = blocked_fp_rate(m, n, k, 1 << 14);
+        assert!(large < blocked && large < classic * 1.05);
+
+        assert_eq!(blocked_fp_rate(m, 0, k, 64), 0.);
+        assert_eq!(blocked_fp_rate(0, n, k, 64), 1.);
+    }
+
+    #[test]
+    fn expected_fill_works() {
+        // At the optimum roughly half of the bits are set.
+        let m = optimal_bit_count(10000, 0.01);
+        let k = optimal_hash_count(10000, m);
+        assert!((expected_fill(m, 10000, k) - 0.5).abs() < 0.05);
+
+        assert_eq!(expected_fill(1000, 0, 7), 0.);
+        assert!(expected_fill(1000, 1000000, 7) > 0.999);
+    }
+
+    #[test]
+    fn cuckoo_math_works() {
+        // (b, f, rate)
+        let test_cases = [
+            (4, 8, 0.0309),
+            (4, 12, 0.00195),
+            (4, 16, 0.000122),
+            (2, 8, 0.0155),
+        ];
+        for (b, f, rate) in test_cases {
+            assert!((cuckoo_fp_rate(b, f) - rate).abs() < rate * 0.01);
+        }
+
+        assert_eq!(cuckoo_max_load_factor(4), 0.95);
+        assert!((cuckoo_bits_per_item(12, 0.95) - 12.63).abs() < 0.01);
+    }
+
+    #[test]
+    fn cuckoo_planning_works() {
+        for fp_rate in [0.1, 0.01, 0.001, 0.0001] {
+            for b in [2, 4, 8] {
+                let f = cuckoo_fingerprint_bits(fp_rate, b);
+                assert!(cuckoo_fp_rate(b, f) <= fp_rate);
+                assert!(cuckoo_fp_rate(b, f - 1) > fp_rate);
+            }
+        }
+        assert_eq!(cuckoo_fingerprint_bits(0.01, 4), 10);
+
+        // 1M items, 12-bit fingerprints at 95% load.
+        assert_eq!(cuckoo_size_in_bytes(1_000_000, 12, 0.95), 1578948);
+    }
+
+    #[test]
+    fn quotient_planning_works() {
+        for fp_rate in [0.1, 0.01, 0.001] {
+            for load_factor in [0.5, 0.75, 0.95] {
+                let r = quotient_remainder_bits(fp_rate, load_factor);
+                assert!(quotient_fp_rate(r, load_factor) <= fp_rate);
+                assert!(quotient_fp_rate(r - 1, load_factor) > fp_rate * 0.95);
+            }
+        }
+        assert!((quotient_fp_rate(8, 0.75) - 0.75 / 256.).abs() < 1e-5);
+
+        // 1M items at 75% load need 2^21 slots of 8 + 3 bits.
+        assert_eq!(
+            quotient_size_in_bytes(1_000_000, 8, 0.75),
+            (1 << 21) * 11 / 8
+        );
+    }
+
+    #[test]
+    fn xor_math_works() {
+        assert_eq!(xor_fp_rate(8), 1. / 256.);
+        assert_eq!(xor_fp_rate(16), 1. / 65536.);
+        assert!((xor_bits_per_key(8) - 9.84).abs() < 1e-9);
+
+        assert_eq!(xor_fingerprint_bits(0.01), 7);
+        assert_eq!(xor_fingerprint_bits(1. / 256.), 8);
+        assert_eq!(xor_size_in_bytes(1_000_000, 8), 1230032);
+    }
+}
@@ -0,0 +1,212 @@
+//! Bloom filter over atomic words.
+//!
+//! An [`AtomicBloomFilter`] supports inserts through a shared reference (see
+//! [`ConcurrentInsertableQueryFilter`]), so that many threads can insert and
+//! query concurrently without a lock. It either owns its words, see
+//! [`with_capacity`](AtomicBloomFilter::with_capacity), or operates on a
+//! caller-provided `&[AtomicU64]` region, see [`new`](AtomicBloomFilter::new).
+//! A region may be shared between processes when it lives in shared memory
+//! (e.g. a POSIX shared-memory segment mapped by each process): mapping the
+//! segment, and viewing it as a slice of atomics, is left to the caller.
+//!
+//! # Publication semantics
+//!
+//! Bits are set with release, and read with acquire ordering. An insert that
+//! happens-before a query (i.e. completed, and made known to the querying
+//! thread or process through any synchronization) is always seen by it, so
+//! there are no false negatives. A key whose insert is still in progress may
+//! or may not be reported as present. Inserts never lose each other's bits.
+
+use {
+    crate::{
+        analysis::{optimal_bit_count, optimal_hash_count},
+        hash::ProbeHasher,
+        ConcurrentInsertableQueryFilter,
+        QueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{
+        borrow::Borrow,
+        hash::Hash,
+        marker::PhantomData,
+        ops::Deref,
+        sync::atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// Bloom filter over atomic words, owned or borrowed.
+pub struct AtomicBloomFilter<'a, K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    words: Words<'a>,
+    hasher: H,
+    k: usize,
+    // Keys are never stored, so the filter is `Send` and `Sync` whatever
+    // their type.
+    phantom: PhantomData<fn(K)>,
+}
+
+/// Words of a filter: a borrowed region, or an allocation of its own.
+enum Words<'a> {
+    Borrowed(&'a [AtomicU64]),
+    Owned(Box<[AtomicU64]>),
+}
+
+impl Deref for Words<'_> {
+    type Target = [AtomicU64];
+
+    fn deref(&self) -> &[AtomicU64] {
+        match self {
+            Self::Borrowed(words) => words,
+            Self::Owned(words) => words,
+        }
+    }
+}
+
+impl<K> AtomicBloomFilter<'static, K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter, owning its words, with a desired capacity and
+    /// false positive rate.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> AtomicBloomFilter<'static, K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter, owning its words, with a desired capacity, false
+    /// positive rate, and hasher.
+    pub fn with_capacity_and_hasher(capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        let words = (0..AtomicBloomFilter::<K>::word_count(capacity, fp_rate))
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        Self::with_words(Words::Owned(words), capacity, hasher)
+    }
+}
+
+impl<'a, K> AtomicBloomFilter<'a, K>
+where
+    K: Eq + Hash,
+{
+    /// Returns the number of words a region needs, for a desired capacity and
+    /// false positive rate.
+    pub fn word_count(capacity: usize, fp_rate: f64) -> usize {
+        optimal_bit_count(capacity, fp_rate).div_ceil(64).max(1)
+    }
+
+    /// Creates a filter over a region of words, expected to hold `capacity`
+    /// keys.
+    ///
+    /// All filters sharing a region must be created with the same capacity
+    /// (and hasher), as it determines the number of hash functions.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is empty.
+    pub fn new(words: &'a [AtomicU64], capacity: usize) -> Self {
+        Self::with_hasher(words, capacity, ProbeHasher::default())
+    }
+}
+
+impl<'a, K, H> AtomicBloomFilter<'a, K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a filter over a region of words, expected to hold `capacity`
+    /// keys, with a given hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the region is empty.
+    pub fn with_hasher(words: &'a [AtomicU64], capacity: usize, hasher: H) -> Self {
+        assert!(!words.is_empty(), "region must not be empty");
+        Self::with_words(Words::Borrowed(words), capacity, hasher)
+    }
+
+    fn with_words(words: Words<'a>, capacity: usize, hasher: H) -> Self {
+        Self {
+            k: optimal_hash_count(capacity.max(1), words.len() * 64),
+            words,
+            hasher,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Inserts an element into the filter.
+    ///
+    /// The element may be any borrowed form of the filter's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for the key
+    /// type.
+    pub fn insert<Q>(&self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for (word, mask) in self.probes(key) {
+            // Skip the write (and the cache line invalidation it causes in
+            // other readers) if the bit is already set.
+            if self.words[word].load(Ordering::Relaxed) & mask == 0 {
+                self.words[word].fetch_or(mask, Ordering::Release);
+            }
+        }
+    }
+
+    /// Removes all elements from the filter.
+    ///
+    /// Not atomic as a whole: concurrent queries may observe a partially
+    /// cleared filter.
+    pub fn clear(&self) {
+        for word in self.words.iter() {
+            word.store(0, Ordering::Release);
+        }
+    }
+
+    /// Returns the word index and bit mask of each probe of a key.
+    fn probes<'b, Q: Hash + ?Sized>(
+        &'b self,
+        key: &'b Q,
+    ) -> impl Iterator<Item = (usize, u64)> + 'b {
+        let bit_count = self.words.len() as u64 * 64;
+        self.hasher.hash_iter(key, self.k).map(move |hash| {
+            let bit = hash % bit_count;
+            ((bit / 64) as usize, 1 << (bit % 64))
+        })
+    }
+}
+
+impl<K, H> QueryFilter<K> for AtomicBloomFilter<'_, K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.probes(key)
+            .all(|(word, mask)| self.words[word].load(Ordering::Acquire) & mask != 0)
+    }
+}
+
+impl<K, H> ConcurrentInsertableQueryFilter<K> for AtomicBloomFilter<'_, K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Sync,
+{
+    fn insert(&self, key: K) {
+        AtomicBloomFilter::insert(self, &key);
+    }
+}
@@ -0,0 +1,267 @@
+//! Workload generation for benchmarking filters.
+//!
+//! Reproducible key sets and mixed read/write workloads, so that filter
+//! configurations can be compared on the target hardware with consistent
+//! methodology. All generators are driven by [`KeyGen`], a seeded SplitMix64
+//! generator: the same seed yields the same keys on every platform and
+//! version of this crate. Realized false positive rates are best measured
+//! with [`FpProfiler`](crate::profiler::FpProfiler), over key sets from
+//! [`KeyGen::disjoint_u64`].
+
+use {
+    crate::InsertableQueryFilter,
+    std::{
+        collections::HashSet,
+        hint::black_box,
+        ops::RangeInclusive,
+        time::{Duration, Instant},
+    },
+};
+
+/// Seeded, deterministic key generator.
+#[derive(Debug, Clone)]
+pub struct KeyGen {
+    state: u64,
+}
+
+impl KeyGen {
+    /// Creates a new generator from a seed.
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Returns the next uniformly distributed 64-bit value.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        mix(self.state)
+    }
+
+    /// Returns the next uniformly distributed value within `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Returns `count` uniformly distributed keys (possibly repeating).
+    pub fn uniform_u64(&mut self, count: usize) -> Vec<u64> {
+        (0..count).map(|_| self.next_u64()).collect()
+    }
+
+    /// Returns two disjoint sets of distinct, uniformly distributed keys: one
+    /// to insert, and a holdout set to measure false positives with.
+    pub fn disjoint_u64(&mut self, inserted: usize, holdout: usize) -> (Vec<u64>, Vec<u64>) {
+        // Mixing distinct counters yields distinct keys (the mix is a
+        // bijection), so no deduplication is needed.
+        let offset = self.next_u64();
+        let key = |i: usize| mix(offset.wrapping_add(i as u64));
+        (
+            (0..inserted).map(key).collect(),
+            (inserted..inserted + holdout).map(key).collect(),
+        )
+    }
+
+    /// Returns `count` keys drawn from `universe` distinct keys with Zipfian
+    /// popularity: the `r`-th most popular key is drawn with probability
+    /// proportional to `1 / r^exponent`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `universe` is zero.
+    pub fn zipfian_u64(&mut self, universe: usize, exponent: f64, count: usize) -> Vec<u64> {
+        let zipf = Zipf::new(universe, exponent);
+        let offset = self.next_u64();
+        (0..count)
+            .map(|_| mix(offset.wrapping_add(zipf.sample(self) as u64)))
+            .collect()
+    }
+
+    /// Returns `count` distinct alphanumeric strings, with lengths uniformly
+    /// distributed within `len`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are fewer than `count` strings of the given lengths.
+    pub fn strings(&mut self, count: usize, len: RangeInclusive<usize>) -> Vec<String> {
+        const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+        let available = len
+            .clone()
+            .map(|len| (ALPHABET.len() as f64).powi(len as i32))
+            .sum::<f64>();
+        assert!(available >= count as f64, "not enough distinct strings");
+
+        let (min, span) = (*len.start(), len.end() - len.start() + 1);
+        let mut seen = HashSet::with_capacity(count);
+        let mut strings = Vec::with_capacity(count);
+        while strings.len() < count {
+            let len = min + (self.next_u64() % span as u64) as usize;
+            let string = (0..len)
+                .map(|_| ALPHABET[(self.next_u64() % ALPHABET.len() as u64) as usize] as char)
+                .collect::<String>();
+            if seen.insert(string.clone()) {
+                strings.push(string);
+            }
+        }
+        strings
+    }
+}
+
+/// Zipfian distribution over ranks `0..n`, sampled by inverting its CDF.
+#[derive(Debug, Clone)]
+pub struct Zipf {
+    cdf: Vec<f64>,
+}
+
+impl Zipf {
+    /// Creates the distribution over `n` ranks, with a given exponent.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    pub fn new(n: usize, exponent: f64) -> Self {
+        assert!(n > 0, "distribution must have at least one rank");
+        let mut sum = 0.;
+        let mut cdf = (1..=n)
+            .map(|rank| {
+                sum += (rank as f64).powf(-exponent);
+                sum
+            })
+            .collect::<Vec<_>>();
+        cdf.iter_mut().for_each(|p| *p /= sum);
+        Self { cdf }
+    }
+
+    /// Returns a random rank, `0` being the most popular one.
+    pub fn sample(&self, gen: &mut KeyGen) -> usize {
+        let p = gen.next_f64();
+        self.cdf
+            .partition_point(|&cdf| cdf <= p)
+            .min(self.cdf.len() - 1)
+    }
+}
+
+/// How reads pick among the keys inserted so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Access {
+    /// All inserted keys are equally likely.
+    Uniform,
+    /// Earlier inserted keys are more popular, with a given Zipf exponent.
+    Zipfian(f64),
+}
+
+/// Mixed insert/query workload over `u64` keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Workload {
+    /// Total number of operations.
+    pub operations: usize,
+    /// Fraction of operations that are queries, the rest being inserts.
+    pub read_fraction: f64,
+    /// Fraction of queries for keys that were never inserted.
+    pub negative_fraction: f64,
+    /// Distribution of (positive) queries over inserted keys.
+    pub access: Access,
+    /// Seed of the generated operations.
+    pub seed: u64,
+}
+
+/// Outcome of running a [`Workload`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorkloadReport {
+    /// Number of inserts performed.
+    pub inserts: usize,
+    /// Number of queries for inserted keys.
+    pub positive_reads: usize,
+    /// Number of queries for keys never inserted.
+    pub negative_reads: usize,
+    /// Number of queries for keys never inserted, answered positively.
+    pub false_positives: usize,
+    /// Time spent executing operations (excluding their generation).
+    pub elapsed: Duration,
+}
+
+impl WorkloadReport {
+    /// Returns the executed operations per second.
+    pub fn ops_per_sec(&self) -> f64 {
+        let ops = self.inserts + self.positive_reads + self.negative_reads;
+        ops as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Returns the realized false positive rate of negative queries, or
+    /// `None` if there were none.
+    pub fn fp_rate(&self) -> Option<f64> {
+        (self.negative_reads > 0).then(|| self.false_positives as f64 / self.negative_reads as f64)
+    }
+}
+
+/// Single generated operation.
+#[derive(Clone, Copy)]
+enum Op {
+    Insert(u64),
+    Read(u64),
+    NegativeRead(u64),
+}
+
+impl Workload {
+    /// Runs the workload against a filter.
+    ///
+    /// Operations are generated up front, so that only their execution is
+    /// timed. Queries issued before the first insert are turned into
+    /// inserts.
+    pub fn run<F: InsertableQueryFilter<u64>>(&self, filter: &mut F) -> WorkloadReport {
+        let ops = self.generate();
+        let start = Instant::now();
+        let mut false_positives = 0;
+        for &op in &ops {
+            match op {
+                Op::Insert(key) => filter.insert(key),
+                Op::Read(key) => {
+                    black_box(filter.contains(&key));
+                }
+                Op::NegativeRead(key) => false_positives += filter.contains(&key) as usize,
+            }
+        }
+        let elapsed = start.elapsed();
+
+        let count = |f: fn(&Op) -> bool| ops.iter().filter(|op| f(op)).count();
+        WorkloadReport {
+            inserts: count(|op| matches!(op, Op::Insert(_))),
+            positive_reads: count(|op| matches!(op, Op::Read(_))),
+            negative_reads: count(|op| matches!(op, Op::NegativeRead(_))),
+            false_positives,
+            elapsed,
+        }
+    }
+
+    fn generate(&self) -> Vec<Op> {
+        let mut gen = KeyGen::new(self.seed);
+        let (inserted, absent) = gen.disjoint_u64(self.operations, self.operations);
+        let zipf = match self.access {
+            Access::Uniform => None,
+            Access::Zipfian(exponent) => Some(Zipf::new(self.operations.max(1), exponent)),
+        };
+
+        let (mut inserts, mut negatives) = (0, 0);
+        (0..self.operations)
+            .map(|_| {
+                if inserts == 0 || gen.next_f64() >= self.read_fraction {
+                    inserts += 1;
+                    Op::Insert(inserted[inserts - 1])
+                } else if gen.next_f64() < self.negative_fraction {
+                    negatives += 1;
+                    Op::NegativeRead(absent[negatives - 1])
+                } else {
+                    let index = match &zipf {
+                        Some(zipf) => zipf.sample(&mut gen) % inserts,
+                        None => (gen.next_u64() % inserts as u64) as usize,
+                    };
+                    Op::Read(inserted[index])
+                }
+            })
+            .collect()
+    }
+}
+
+/// SplitMix64 finalizer, a bijection on 64-bit values.
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
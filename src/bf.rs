@@ -1,16 +1,64 @@
+#[cfg(all(feature = "mmap", unix))]
 use {
-    crate::{ClearableQueryFilter, InsertableQueryFilter, QueryFilter},
+    crate::storage::{Advice, MmapBitSet},
+    std::{fs::File, path::Path},
+};
+use {
+    crate::{
+        analysis::check_fp_rate,
+        hash::{KeyHasher, ProbeHasher, ProbeStrategy},
+        storage::{BitStorage, SharedBitSet},
+        ClearableQueryFilter,
+        FilterStats,
+        FreezableQueryFilter,
+        InsertableQueryFilter,
+        MergeableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
     fixedbitset::FixedBitSet as BitSet,
-    hash_iter::{DoubleHashHasher, HashIterHasher},
-    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+    hash_iter::HashIterHasher,
+    std::{
+        borrow::Borrow,
+        f64::consts::LN_2,
+        hash::Hash,
+        io::{self, Read, Write},
+        marker::PhantomData,
+        ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign},
+    },
+    xxhash_rust::xxh3::{xxh3_64, Xxh3},
 };
 
-pub struct BloomFilter<K>
+pub use crate::analysis::{optimal_bit_count, optimal_capacity, optimal_hash_count};
+
+/// Number of keys hashed ahead of touching the bits, in batch operations.
+const BATCH: usize = 64;
+
+/// False positive rate of filters collected from an iterator.
+pub const DEFAULT_FP_RATE: f64 = 0.01;
+
+/// Largest number of bits a [`BloomFilterBuilder`] accepts (128 GiB worth).
+pub const MAX_BIT_COUNT: u64 = 1 << 40;
+
+/// Classic Bloom filter.
+///
+/// Probe sequences are generated by the hasher `H`, by default a
+/// [`ProbeHasher`] using enhanced double hashing. Other probe strategies
+/// can be selected at construction, see [`with_capacity_and_hasher`].
+///
+/// Bits are kept in a [`BitStorage`], by default a contiguous bit set. Use
+/// [`into_shared`] to switch to copy-on-write storage that supports cheap
+/// snapshots.
+///
+/// [`with_capacity_and_hasher`]: BloomFilter::with_capacity_and_hasher
+/// [`into_shared`]: BloomFilter::into_shared
+pub struct BloomFilter<K, H = ProbeHasher, S = BitSet>
 where
     K: Eq + Hash,
 {
-    bits: BitSet,
-    hasher: DoubleHashHasher,
+    bits: S,
+    hasher: H,
     k: usize,
     phantom: PhantomData<K>,
 }
@@ -25,34 +73,88 @@ where
         Self::with_capacity(capacity, fp_rate)
     }
 
+    /// Returns a builder for a filter with a desired capacity and false
+    /// positive rate, validating them, see [`BloomFilterBuilder`].
+    pub fn builder(capacity: usize, fp_rate: f64) -> BloomFilterBuilder<K> {
+        BloomFilterBuilder::new(capacity, fp_rate)
+    }
+
     /// Creates a new Bloom filter with a desired size (in bytes) and false
     /// positive rate.
     pub fn with_size(size: usize, fp_rate: f64) -> Self {
-        Self::with_size_and_hasher(size, fp_rate, DoubleHashHasher::new())
+        Self::with_size_and_hasher(size, fp_rate, ProbeHasher::default())
     }
 
     /// Creates a new Bloom filter with a desired capacity and false positive
     /// rate.
     pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
-        Self::with_capacity_and_hasher(capacity, fp_rate, DoubleHashHasher::new())
+        Self::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
     }
 
+    /// Creates a new Bloom filter with exactly `bit_count` bits and
+    /// `hash_count` hash functions.
+    pub fn with_bit_count(bit_count: usize, hash_count: usize) -> Self {
+        Self::with_bit_count_and_hasher(bit_count, hash_count, ProbeHasher::default())
+    }
+
+    /// Creates a new Bloom filter holding the given keys, with a desired
+    /// false positive rate.
+    ///
+    /// The capacity is the upper bound of the iterator's size hint, if any
+    /// (and at least 1). Otherwise, keys are buffered first to count them.
+    ///
+    /// ```
+    /// use mqfilters::{BloomFilter, QueryFilter};
+    ///
+    /// let filter = BloomFilter::collect_with_fp_rate(0..1000u64, 0.001);
+    /// assert!(filter.contains(&42));
+    /// ```
+    pub fn collect_with_fp_rate(keys: impl IntoIterator<Item = K>, fp_rate: f64) -> Self {
+        let keys = keys.into_iter();
+        match keys.size_hint() {
+            (_, Some(upper)) => {
+                let mut filter = Self::with_capacity(upper.max(1), fp_rate);
+                filter.insert_many(keys);
+                filter
+            }
+            (_, None) => {
+                let keys = keys.collect::<Vec<_>>();
+                let mut filter = Self::with_capacity(keys.len().max(1), fp_rate);
+                filter.insert_many(keys);
+                filter
+            }
+        }
+    }
+}
+
+impl<K, H> BloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
     /// Creates a new Bloom filter with a desired size (in bytes), false
     /// positive, and hasher.
-    pub fn with_size_and_hasher(size: usize, fp_rate: f64, hasher: DoubleHashHasher) -> Self {
+    pub fn with_size_and_hasher(size: usize, fp_rate: f64, hasher: H) -> Self {
         let capacity = optimal_capacity(size * 8, fp_rate);
         Self::with_capacity_and_hasher(capacity, fp_rate, hasher)
     }
 
     /// Creates a new Bloom filter with a desired capacity, false positive rate,
     /// and hasher.
-    pub fn with_capacity_and_hasher(
-        capacity: usize,
-        fp_rate: f64,
-        hasher: DoubleHashHasher,
-    ) -> Self {
+    ///
+    /// ```
+    /// use mqfilters::{
+    ///     hash::{ProbeHasher, ProbeStrategy},
+    ///     BloomFilter,
+    /// };
+    ///
+    /// let hasher = ProbeHasher::new(ProbeStrategy::Triple);
+    /// let filter = BloomFilter::<u64>::with_capacity_and_hasher(1000, 0.001, hasher);
+    /// ```
+    pub fn with_capacity_and_hasher(capacity: usize, fp_rate: f64, hasher: H) -> Self {
         let bit_count = optimal_bit_count(capacity, fp_rate);
         let k = optimal_hash_count(capacity, bit_count);
+        event!(capacity, fp_rate, bit_count, k; "created Bloom filter");
         Self {
             bits: BitSet::with_capacity(bit_count),
             hasher,
@@ -61,54 +163,788 @@ where
         }
     }
 
+    /// Creates a new Bloom filter with exactly `bit_count` bits, `hash_count`
+    /// hash functions, and a given hasher.
+    ///
+    /// Useful when filters need to be sized in lockstep, e.g. shards that are
+    /// to be merged with [`union_with`](MergeableQueryFilter::union_with).
+    pub fn with_bit_count_and_hasher(bit_count: usize, hash_count: usize, hasher: H) -> Self {
+        Self {
+            bits: BitSet::with_capacity(bit_count),
+            hasher,
+            k: hash_count,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Rebuilds a filter from the indices of its set bits, see
+    /// [`ones`](BloomFilter::ones).
+    ///
+    /// The filter answers queries exactly like the original one, provided it
+    /// is given the same bit count, hash count, and hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if some index is not less than `bit_count`.
+    pub fn from_set_bits(
+        bit_count: usize,
+        hash_count: usize,
+        hasher: H,
+        indices: impl IntoIterator<Item = usize>,
+    ) -> Self {
+        let mut filter = Self::with_bit_count_and_hasher(bit_count, hash_count, hasher);
+        for index in indices {
+            assert!(index < bit_count, "bit index {index} out of bounds");
+            filter.bits.insert(index);
+        }
+        filter
+    }
+
+    /// Converts the filter to use copy-on-write storage, see
+    /// [`snapshot`](BloomFilter::snapshot).
+    pub fn into_shared(self) -> BloomFilter<K, H, SharedBitSet> {
+        event!(bit_count = self.bits.len(); "converted Bloom filter to shared storage");
+        BloomFilter {
+            bits: SharedBitSet::from(&self.bits),
+            hasher: self.hasher,
+            k: self.k,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Builder of [`BloomFilter`]s, rejecting invalid parameters.
+///
+/// Constructors such as [`BloomFilter::with_capacity`] trust their inputs: a
+/// false positive rate outside of `(0, 1)` or a zero capacity yields
+/// a meaningless (or huge) filter. Parameters from configuration or user
+/// input should go through [`build`](BloomFilterBuilder::build) instead:
+///
+/// ```
+/// use mqfilters::{BloomFilter, QueryFilterError};
+///
+/// let filter = BloomFilter::<u64>::builder(1000, 0.01).build().unwrap();
+/// assert_eq!(filter.hash_count(), 7);
+///
+/// let err = BloomFilter::<u64>::builder(1000, 1.5).build().err();
+/// assert_eq!(err, Some(QueryFilterError::InvalidFpRate(1.5)));
+/// ```
+#[derive(Debug, Clone)]
+pub struct BloomFilterBuilder<K, H = ProbeHasher> {
+    capacity: usize,
+    fp_rate: f64,
+    hasher: H,
+    /// Hints given to memory-mapped bits, in order.
+    #[cfg(all(feature = "mmap", unix))]
+    advice: Vec<Advice>,
+    /// Whether to lock memory-mapped bits in memory.
+    #[cfg(all(feature = "mmap", unix))]
+    lock: bool,
+    phantom: PhantomData<K>,
+}
+
+impl<K> BloomFilterBuilder<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new builder for a filter with a desired capacity and false
+    /// positive rate, and the default hasher.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self {
+            capacity,
+            fp_rate,
+            hasher: ProbeHasher::default(),
+            #[cfg(all(feature = "mmap", unix))]
+            advice: Vec::new(),
+            #[cfg(all(feature = "mmap", unix))]
+            lock: false,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, H> BloomFilterBuilder<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Sets the hasher used to generate probe sequences.
+    pub fn hasher<T>(self, hasher: T) -> BloomFilterBuilder<K, T>
+    where
+        T: HashIterHasher<u64>,
+    {
+        BloomFilterBuilder {
+            capacity: self.capacity,
+            fp_rate: self.fp_rate,
+            hasher,
+            #[cfg(all(feature = "mmap", unix))]
+            advice: self.advice,
+            #[cfg(all(feature = "mmap", unix))]
+            lock: self.lock,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Advises the kernel of how the bits of a filter built by
+    /// [`build_mmap`](BloomFilterBuilder::build_mmap) are to be accessed,
+    /// see [`MmapBitSet::advise`]. Hints are given in the order they are
+    /// added.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn advise(mut self, advice: Advice) -> Self {
+        self.advice.push(advice);
+        self
+    }
+
+    /// Locks the bits of a filter built by
+    /// [`build_mmap`](BloomFilterBuilder::build_mmap) in memory, so that
+    /// queries never fault, see [`MmapBitSet::lock`].
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn lock(mut self) -> Self {
+        self.lock = true;
+        self
+    }
+
+    /// Builds the filter.
+    ///
+    /// Fails with [`ZeroCapacity`](QueryFilterError::ZeroCapacity),
+    /// [`InvalidFpRate`](QueryFilterError::InvalidFpRate) unless the rate is
+    /// within `(0, 1)`, or [`TooLarge`](QueryFilterError::TooLarge) if the
+    /// filter would need more than [`MAX_BIT_COUNT`] bits.
+    pub fn build(self) -> QueryFilterResult<BloomFilter<K, H>> {
+        self.validate()?;
+        Ok(BloomFilter::with_capacity_and_hasher(
+            self.capacity,
+            self.fp_rate,
+            self.hasher,
+        ))
+    }
+
+    /// Builds the filter, backed by a memory-mapped file, see
+    /// [`BloomFilter::create_mmap`], then gives it the
+    /// [hints](BloomFilterBuilder::advise) and
+    /// [locks](BloomFilterBuilder::lock) it as configured.
+    ///
+    /// Fails with [`InvalidInput`](io::ErrorKind::InvalidInput) where
+    /// [`build`](BloomFilterBuilder::build) fails, or with the error of a
+    /// failed system call.
+    #[cfg(all(feature = "mmap", unix))]
+    pub fn build_mmap(self, path: impl AsRef<Path>) -> io::Result<BloomFilter<K, H, MmapBitSet>> {
+        self.validate()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let filter =
+            BloomFilter::create_mmap_with_hasher(path, self.capacity, self.fp_rate, self.hasher)?;
+        for advice in self.advice {
+            filter.advise(advice)?;
+        }
+        if self.lock {
+            filter.lock()?;
+        }
+        Ok(filter)
+    }
+
+    /// Fails unless the parameters are valid, see
+    /// [`build`](BloomFilterBuilder::build).
+    fn validate(&self) -> QueryFilterResult<()> {
+        if self.capacity == 0 {
+            return Err(QueryFilterError::ZeroCapacity);
+        }
+        check_fp_rate(self.fp_rate)?;
+        let bits = (-(self.capacity as f64) * self.fp_rate.ln() / LN_2.powi(2)).ceil() as u64;
+        let max = MAX_BIT_COUNT.min(usize::MAX as u64);
+        if bits > max {
+            return Err(QueryFilterError::TooLarge { bits, max });
+        }
+        Ok(())
+    }
+}
+
+impl<K, H> BloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + PartialEq,
+{
+    /// Merges another filter into this one, so that it holds the union of both
+    /// key sets. Returns the estimated false positive rate of the result.
+    ///
+    /// Filters must share the hasher and the number of hash functions, but may
+    /// differ in size, as long as the bit count of one is a multiple (e.g. a
+    /// power-of-two multiple) of the other's. Probes are reduced modulo the
+    /// bit count, so the larger filter can be folded onto the smaller one:
+    /// the result has the smaller size, and a higher false positive rate than
+    /// either of the inputs.
+    pub fn fold_union_with(&mut self, other: &Self) -> QueryFilterResult<f64> {
+        if self.k != other.k || self.hasher != other.hasher {
+            return Err(QueryFilterError::IncompatibleFilters(
+                "hasher or number of hash functions differ",
+            ));
+        }
+        let (len, other_len) = (self.bits.len(), other.bits.len());
+        let smaller = len.min(other_len);
+        if smaller == 0 || len.max(other_len) % smaller != 0 {
+            return Err(QueryFilterError::IncompatibleFilters(
+                "bit counts are not multiples of each other",
+            ));
+        }
+
+        if len > other_len {
+            let mut folded = BitSet::with_capacity(other_len);
+            for index in self.bits.ones() {
+                folded.insert(index % other_len);
+            }
+            self.bits = folded;
+        }
+        let len = self.bits.len();
+        for index in other.bits.ones() {
+            self.bits.insert(index % len);
+        }
+        let fp_rate = self.approx_fp_rate();
+        event!(bit_count = len, other_bit_count = other_len, fp_rate; "folded Bloom filter union");
+        Ok(fp_rate)
+    }
+
+    /// Returns `true` if every set bit of this filter is set in another one,
+    /// i.e. if all keys of this filter are believed to be in the other.
+    ///
+    /// Keys absent from the other filter may still pass, if their bits are
+    /// all set there (a false positive), so this is only a probable subset
+    /// relation. A `false` is definite, though. Filters must share the bit
+    /// count, hasher, and number of hash functions.
+    pub fn is_probable_subset(&self, other: &Self) -> QueryFilterResult<bool> {
+        self.check_same_shape(other)?;
+        Ok((0..self.bits.word_count()).all(|i| self.bits.word(i) & !other.bits.word(i) == 0))
+    }
+
+    /// Returns the estimated fraction of this filter's keys that are also in
+    /// another one, within `[0, 1]`.
+    ///
+    /// Key counts of both filters and of their union are estimated from their
+    /// set bits, and the intersection derived from them. The estimate is
+    /// rough for small filters, or ones close to saturation. An empty filter
+    /// is fully contained in any other. Filters must share the bit count,
+    /// hasher, and number of hash functions.
+    pub fn containment_in(&self, other: &Self) -> QueryFilterResult<f64> {
+        self.check_same_shape(other)?;
+        let union_ones = (0..self.bits.word_count())
+            .map(|i| (self.bits.word(i) | other.bits.word(i)).count_ones() as usize)
+            .sum();
+        let bit_count = self.bits.len();
+        let count = estimate_count(bit_count, self.bits.count_ones(..), self.k);
+        if count < 0.5 {
+            return Ok(1.);
+        }
+        let other_count = estimate_count(bit_count, other.bits.count_ones(..), self.k);
+        let union_count = estimate_count(bit_count, union_ones, self.k);
+        let common = count + other_count - union_count;
+        Ok((common / count).clamp(0., 1.))
+    }
+}
+
+/// Combines filters of the same bit count, hasher, and number of hash
+/// functions, bitwise.
+///
+/// See [`fold_union_with`](BloomFilter::fold_union_with) to also combine
+/// filters of different sizes.
+impl<K, H> MergeableQueryFilter<K> for BloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + PartialEq,
+{
+    fn try_union_with(&mut self, other: &Self) -> QueryFilterResult<()> {
+        self.check_same_shape(other)?;
+        self.bits.union_with(&other.bits);
+        event!(bit_count = self.bits.len(), k = self.k; "merged Bloom filter union");
+        Ok(())
+    }
+
+    fn try_intersect_with(&mut self, other: &Self) -> QueryFilterResult<()> {
+        self.check_same_shape(other)?;
+        self.bits.intersect_with(&other.bits);
+        event!(bit_count = self.bits.len(), k = self.k; "merged Bloom filter intersection");
+        Ok(())
+    }
+}
+
+/// Implements a bitwise operator (and its assigning form) between filters of
+/// the same shape, on top of a [`MergeableQueryFilter`] method.
+///
+/// Operands are taken by value or by reference: `a | &b` reuses the bits of
+/// `a`, while `&a | &b` allocates a new filter.
+macro_rules! impl_bit_op {
+    ($op:ident, $fn:ident, $op_assign:ident, $fn_assign:ident, $merge:ident, $try:literal) => {
+        /// # Panics
+        ///
+        /// Panics if the filters differ in shape, see
+        #[doc = $try]
+        impl<K, H> $op_assign<&Self> for BloomFilter<K, H>
+        where
+            K: Eq + Hash,
+            H: HashIterHasher<u64> + PartialEq,
+        {
+            fn $fn_assign(&mut self, rhs: &Self) {
+                MergeableQueryFilter::$merge(self, rhs);
+            }
+        }
+
+        impl<K, H> $op<&Self> for BloomFilter<K, H>
+        where
+            K: Eq + Hash,
+            H: HashIterHasher<u64> + PartialEq,
+        {
+            type Output = Self;
+
+            fn $fn(mut self, rhs: &Self) -> Self {
+                self.$fn_assign(rhs);
+                self
+            }
+        }
+
+        impl<K, H> $op for BloomFilter<K, H>
+        where
+            K: Eq + Hash,
+            H: HashIterHasher<u64> + PartialEq,
+        {
+            type Output = Self;
+
+            fn $fn(self, rhs: Self) -> Self {
+                self.$fn(&rhs)
+            }
+        }
+
+        impl<K, H> $op for &BloomFilter<K, H>
+        where
+            K: Eq + Hash,
+            H: HashIterHasher<u64> + PartialEq + Clone,
+        {
+            type Output = BloomFilter<K, H>;
+
+            fn $fn(self, rhs: Self) -> BloomFilter<K, H> {
+                let filter = BloomFilter {
+                    bits: self.bits.clone(),
+                    hasher: self.hasher.clone(),
+                    k: self.k,
+                    phantom: PhantomData,
+                };
+                filter.$fn(rhs)
+            }
+        }
+    };
+}
+
+impl_bit_op!(
+    BitOr,
+    bitor,
+    BitOrAssign,
+    bitor_assign,
+    union_with,
+    "[`try_union_with`](MergeableQueryFilter::try_union_with)."
+);
+impl_bit_op!(
+    BitAnd,
+    bitand,
+    BitAndAssign,
+    bitand_assign,
+    intersect_with,
+    "[`try_intersect_with`](MergeableQueryFilter::try_intersect_with)."
+);
+
+impl<K, H> BloomFilter<K, H, SharedBitSet>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Returns a point-in-time snapshot of the filter.
+    ///
+    /// Snapshot shares the underlying memory with the filter, so taking it is
+    /// `O(chunks)`. Subsequent inserts into either of them copy only the
+    /// chunks they touch, and are not visible to the other one.
+    pub fn snapshot(&self) -> Self {
+        Self {
+            bits: self.bits.clone(),
+            hasher: self.hasher.clone(),
+            k: self.k,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<K, H, S> BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    /// Creates a new Bloom filter over given (empty) storage, with
+    /// `hash_count` hash functions and a given hasher.
+    ///
+    /// Allows picking a storage backend other than the default contiguous bit
+    /// set, e.g. a compressed bitmap (with the `roaring` feature) for filters
+    /// sized for the worst case that mostly stay very sparse:
+    ///
+    /// ```
+    /// # #[cfg(feature = "roaring")] {
+    /// use mqfilters::{
+    ///     analysis::{optimal_bit_count, optimal_hash_count},
+    ///     hash::ProbeHasher,
+    ///     storage::SparseBitSet,
+    ///     BloomFilter,
+    ///     InsertableQueryFilter,
+    /// };
+    ///
+    /// let bit_count = optimal_bit_count(1_000_000_000, 0.01);
+    /// let hash_count = optimal_hash_count(1_000_000_000, bit_count);
+    /// let bits = SparseBitSet::with_len(bit_count);
+    /// let mut filter = BloomFilter::with_storage(bits, hash_count, ProbeHasher::default());
+    /// filter.insert(42);
+    /// # }
+    /// ```
+    pub fn with_storage(bits: S, hash_count: usize, hasher: H) -> Self {
+        Self {
+            bits,
+            hasher,
+            k: hash_count,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns `true` if the key is believed to be in the filter, along with
+    /// each probed bit index and whether that bit was set.
+    ///
+    /// Meant for diagnostics, e.g. analyzing a reported false positive
+    /// against a filter rebuilt offline: unlike [`contains`], all `k` probes
+    /// are evaluated, even past the first unset bit.
+    ///
+    /// [`contains`]: QueryFilter::contains
+    pub fn contains_with_trace<Q>(&self, key: &Q) -> (bool, Vec<(usize, bool)>)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let trace = self
+            .hasher
+            .hash_iter(key, self.k)
+            .map(|hash| {
+                let index = (hash % self.bits.len() as u64) as usize;
+                (index, self.bits.contains(index))
+            })
+            .collect::<Vec<_>>();
+        (trace.iter().all(|&(_, set)| set), trace)
+    }
+
+    /// Returns the confidence that the key is in the filter: zero if it is
+    /// definitely absent, otherwise one minus the estimated false positive
+    /// rate (see [`approx_fp_rate`]).
+    ///
+    /// Meant for scoring systems weighting filter evidence: a positive
+    /// answer from a nearly empty filter is near certain, one from an
+    /// overfilled filter is barely better than a guess.
+    ///
+    /// [`approx_fp_rate`]: BloomFilter::approx_fp_rate
+    pub fn contains_with_confidence<Q>(&self, key: &Q) -> f64
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if self.contains(key) {
+            1. - self.approx_fp_rate()
+        } else {
+            0.
+        }
+    }
+
+    /// Inserts a batch of keys.
+    ///
+    /// Equivalent to inserting the keys one by one, but faster for filters
+    /// much larger than the CPU caches: the probes of a chunk of keys are all
+    /// computed, and their memory prefetched, ahead of setting any bits, so
+    /// that cache misses overlap instead of stalling one after another.
+    pub fn insert_many(&mut self, keys: impl IntoIterator<Item = K>) {
+        let mut keys = keys.into_iter().peekable();
+        let mut indices = Vec::with_capacity(BATCH * self.k);
+        while keys.peek().is_some() {
+            indices.clear();
+            for key in keys.by_ref().take(BATCH) {
+                indices.extend(self.indices(&key));
+            }
+            for &index in &indices {
+                self.bits.prefetch(index);
+            }
+            for &index in &indices {
+                self.bits.insert(index);
+            }
+        }
+    }
+
+    /// Returns, for each of a batch of keys, `true` if the key is believed
+    /// to be in the filter.
+    ///
+    /// Equivalent to querying the keys one by one, but faster, see
+    /// [`insert_many`](BloomFilter::insert_many). All probes are evaluated,
+    /// even past the first unset bit, which pays off as long as most keys
+    /// are in the filter, or it does not fit in the CPU caches.
+    pub fn contains_many<Q>(&self, keys: &[&Q]) -> Vec<bool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let mut indices = Vec::with_capacity(BATCH * self.k);
+        let mut found = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(BATCH) {
+            indices.clear();
+            indices.extend(chunk.iter().flat_map(|key| self.indices(*key)));
+            for &index in &indices {
+                self.bits.prefetch(index);
+            }
+            found.extend(
+                indices
+                    .chunks(self.k.max(1))
+                    .take(chunk.len())
+                    .map(|probes| probes.iter().all(|&index| self.bits.contains(index))),
+            );
+        }
+        found
+    }
+
+    /// Returns the bit indices probed by a key.
+    fn indices<'a, Q>(&'a self, key: &'a Q) -> impl Iterator<Item = usize> + 'a
+    where
+        Q: Hash + ?Sized,
+    {
+        let len = self.bits.len() as u64;
+        self.hasher
+            .hash_iter(key, self.k)
+            .map(move |hash| (hash % len) as usize)
+    }
+
+    /// Inserts a key given in any borrowed form, for combinators that only
+    /// hold a reference to it.
+    pub(crate) fn insert_borrowed<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for hash in self.hasher.hash_iter(key, self.k) {
+            let index = (hash % self.bits.len() as u64) as usize;
+            self.bits.insert(index);
+        }
+    }
+
+    pub(crate) fn bits(&self) -> &S {
+        &self.bits
+    }
+
+    pub(crate) fn bits_mut(&mut self) -> &mut S {
+        &mut self.bits
+    }
+
     /// Returns the approximate number of elements currently in the filter.
     pub fn approx_current_capacity(&self) -> usize {
-        let bits_count = self.bits.len() as f64;
-        let ones_count = self.bits.count_ones(..) as f64;
-        let hash_count = self.k as f64;
-        let count = -(bits_count / hash_count) * (1. - (ones_count / bits_count)).ln();
+        estimate_count(self.bits.len(), self.bits.count_ones(), self.k).round() as usize
+    }
 
-        count.round() as usize
+    /// Returns the number of bits.
+    pub fn bit_count(&self) -> usize {
+        self.bits.len()
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the indices of set bits, in ascending order.
+    ///
+    /// Along with the bit count, hash count, and hasher, this is all it takes
+    /// to rebuild the filter with [`from_set_bits`], which makes for compact
+    /// transport of sparse filters.
+    ///
+    /// [`from_set_bits`]: BloomFilter::from_set_bits
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.bits.word_count()).flat_map(move |i| {
+            let mut word = self.bits.word(i);
+            std::iter::from_fn(move || {
+                (word != 0).then(|| {
+                    let bit = word.trailing_zeros() as usize;
+                    word &= word - 1;
+                    i * 64 + bit
+                })
+            })
+        })
+    }
+
+    /// Returns the fraction of set bits.
+    ///
+    /// Bits are set at random, so that a filter with a fill ratio of 1/2 is
+    /// at its optimal capacity, and the false positive rate climbs quickly
+    /// past that.
+    pub fn fill_ratio(&self) -> f64 {
+        self.bits.count_ones() as f64 / self.bits.len() as f64
+    }
+
+    /// Returns the memory used by the filter's bits, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.bits.word_count() * 8
+    }
+
+    /// Returns the estimated false positive rate, given the current fraction
+    /// of set bits.
+    pub fn approx_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.k as i32)
     }
 }
 
-/// Given a capacity and a desired false positive rate, returns the optimal
-/// number of bits to use (size of the filter, `m`), along with an for an
-/// optimal `k`.
-pub fn optimal_bit_count(capacity: usize, fp_rate: f64) -> usize {
-    let ln2 = std::f64::consts::LN_2;
-    let n = capacity as f64;
-    let p = fp_rate;
+impl<K, S> BloomFilter<K, ProbeHasher, S>
+where
+    K: Eq + Hash,
+    S: BitStorage,
+{
+    /// Inserts a key fed in chunks, see [`KeyHasher`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key hasher was not created from the filter's hasher.
+    pub fn insert_streamed(&mut self, key: &KeyHasher) {
+        assert_eq!(key.hasher(), &self.hasher, "key hashed with another hasher");
+        for hash in key.probes(self.k) {
+            let index = (hash % self.bits.len() as u64) as usize;
+            self.bits.insert(index);
+        }
+    }
 
-    (-n * p.ln() / ln2.powi(2)).ceil() as usize
+    /// Returns `true` if a key fed in chunks is believed to be in the filter,
+    /// see [`KeyHasher`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key hasher was not created from the filter's hasher.
+    pub fn contains_streamed(&self, key: &KeyHasher) -> bool {
+        assert_eq!(key.hasher(), &self.hasher, "key hashed with another hasher");
+        key.probes(self.k).all(|hash| {
+            let index = (hash % self.bits.len() as u64) as usize;
+            self.bits.contains(index)
+        })
+    }
 }
 
-/// Given a desired false positive rate and the number of bits, returns the
-/// optimal capacity (number of items hashed into filter, `n`).
-pub fn optimal_capacity(bit_count: usize, fp_rate: f64) -> usize {
-    let ln2 = std::f64::consts::LN_2;
-    let m = bit_count as f64;
-    let p = fp_rate;
+impl<S> BloomFilter<u64, ProbeHasher, S>
+where
+    S: BitStorage,
+{
+    /// Inserts a batch of `u64` keys.
+    ///
+    /// Equivalent to inserting the keys one by one, but faster: probes are
+    /// computed without going through the [`Hash`] trait, and for a whole
+    /// batch of keys ahead of setting any bits, so that the hashing loop runs
+    /// uninterrupted and memory accesses are independent of each other.
+    pub fn insert_u64_batch(&mut self, keys: &[u64]) {
+        let mut indices = Vec::with_capacity(BATCH * self.k);
+        for chunk in keys.chunks(BATCH) {
+            indices.clear();
+            indices.extend(chunk.iter().flat_map(|&key| self.indices_u64(key)));
+            for &index in &indices {
+                self.bits.prefetch(index);
+            }
+            for &index in &indices {
+                self.bits.insert(index);
+            }
+        }
+    }
 
-    (m * ln2.powi(2) / -p.ln()).round() as usize
+    /// Returns, for each of a batch of `u64` keys, `true` if the key is
+    /// believed to be in the filter.
+    ///
+    /// Equivalent to querying the keys one by one, but faster, see
+    /// [`insert_u64_batch`](BloomFilter::insert_u64_batch).
+    pub fn contains_u64_batch(&self, keys: &[u64]) -> Vec<bool> {
+        let mut indices = Vec::with_capacity(BATCH * self.k);
+        let mut found = Vec::with_capacity(keys.len());
+        for chunk in keys.chunks(BATCH) {
+            indices.clear();
+            indices.extend(chunk.iter().flat_map(|&key| self.indices_u64(key)));
+            for &index in &indices {
+                self.bits.prefetch(index);
+            }
+            found.extend(
+                indices
+                    .chunks(self.k.max(1))
+                    .take(chunk.len())
+                    .map(|probes| probes.iter().all(|&index| self.bits.contains(index))),
+            );
+        }
+        found
+    }
+
+    fn indices_u64(&self, key: u64) -> impl Iterator<Item = usize> {
+        let len = self.bits.len() as u64;
+        self.hasher
+            .probes_u64(key, self.k)
+            .map(move |hash| (hash % len) as usize)
+    }
 }
 
-/// Returns the optimal number of hash functions to use (`k`).
-///
-/// Current implementation relies on double hashing, so for a given key, it
-/// creates this many hash values (while internally using up to two
-/// different hash functions -- mostly one).
-pub fn optimal_hash_count(capacity: usize, bit_count: usize) -> usize {
-    let ln2 = std::f64::consts::LN_2;
-    let n = capacity as f64;
-    let m = bit_count as f64;
+impl<K, H, S> BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + PartialEq,
+    S: BitStorage,
+{
+    /// Returns the approximate sizes of set differences `|A \ B|` and
+    /// `|B \ A|`, where `A` is this filter's key set and `B` the other's.
+    ///
+    /// Sizes of `A`, `B`, and `A ∪ B` are estimated from the number of set
+    /// bits (the union being the bitwise or of both filters), as described in
+    /// [Theory and Practice of Bloom Filters for Distributed Systems, 2012][1].
+    /// Filters must share the size, hasher, and number of hash functions.
+    ///
+    /// [1]: https://doi.org/10.1109/SURV.2011.031611.00024
+    pub fn estimate_difference<T>(
+        &self,
+        other: &BloomFilter<K, H, T>,
+    ) -> QueryFilterResult<(usize, usize)>
+    where
+        T: BitStorage,
+    {
+        self.check_same_shape(other)?;
+        let union_ones = (0..self.bits.word_count())
+            .map(|i| (self.bits.word(i) | other.bits.word(i)).count_ones() as usize)
+            .sum();
+        let estimate = |ones| estimate_count(self.bits.len(), ones, self.k);
+        let union = estimate(union_ones);
+        let (a, b) = (
+            estimate(self.bits.count_ones()),
+            estimate(other.bits.count_ones()),
+        );
+        let difference = |count: f64| (union - count).max(0.).round() as usize;
+        Ok((difference(b), difference(a)))
+    }
 
-    (m / n * ln2).ceil() as usize
+    /// Fails unless both filters have the same bit count, hasher, and number
+    /// of hash functions.
+    fn check_same_shape<T: BitStorage>(
+        &self,
+        other: &BloomFilter<K, H, T>,
+    ) -> QueryFilterResult<()> {
+        if self.k != other.k || self.hasher != other.hasher {
+            return Err(QueryFilterError::IncompatibleFilters(
+                "hasher or number of hash functions differ",
+            ));
+        }
+        if self.bits.len() != other.bits.len() {
+            return Err(QueryFilterError::IncompatibleFilters("bit counts differ"));
+        }
+        Ok(())
+    }
 }
 
-impl<K> QueryFilter<K> for BloomFilter<K>
+impl<K, H, S> QueryFilter<K> for BloomFilter<K, H, S>
 where
     K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
 {
     fn contains<Q>(&self, key: &Q) -> bool
     where
@@ -125,81 +961,745 @@ where
     }
 }
 
-impl<K> InsertableQueryFilter<K> for BloomFilter<K>
+impl<K, H, S> InsertableQueryFilter<K> for BloomFilter<K, H, S>
 where
     K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
 {
     fn insert(&mut self, key: K) {
-        for hash in self.hasher.hash_iter(&key, self.k) {
-            let index = (hash % self.bits.len() as u64) as usize;
-            self.bits.insert(index);
-        }
+        self.insert_borrowed(&key);
+    }
+}
+
+impl<K, H, S> FilterStats for BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    fn slot_count(&self) -> usize {
+        self.bit_count()
+    }
+
+    fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    fn fill_ratio(&self) -> f64 {
+        self.fill_ratio()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.size_in_bytes()
+    }
+}
+
+impl<K, H, S> Extend<K> for BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    fn extend<I: IntoIterator<Item = K>>(&mut self, keys: I) {
+        self.insert_many(keys);
+    }
+}
+
+/// Collects keys into a filter with a [`DEFAULT_FP_RATE`] false positive
+/// rate, sized as [`collect_with_fp_rate`](BloomFilter::collect_with_fp_rate)
+/// does.
+impl<K> FromIterator<K> for BloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn from_iter<I: IntoIterator<Item = K>>(keys: I) -> Self {
+        Self::collect_with_fp_rate(keys, DEFAULT_FP_RATE)
     }
 }
 
-impl<K> ClearableQueryFilter<K> for BloomFilter<K>
+impl<K, H, S> ClearableQueryFilter<K> for BloomFilter<K, H, S>
 where
     K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
 {
     fn clear(&mut self) {
+        event!(bit_count = self.bits.len(), k = self.k; "cleared Bloom filter");
         self.bits.clear();
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn optimal_bit_count_works() {
-        // Given `n` (capacity) and `p` (false positive rate), find `m` (size) and
-        // optimal `k`.
-        let test_cases = [
-            (10, 0.05, 63, 5),
-            (100, 0.05, 624, 5),
-            (100, 0.1, 480, 4),
-            (100, 0.01, 959, 7),
-            (1000, 0.01, 9586, 7),
-            (10000, 0.01, 95851, 7),
-            (100000, 0.01, 958506, 7),
-        ];
-        for (n, p, m, k) in test_cases {
-            assert_eq!(optimal_bit_count(n, p), m);
-            assert_eq!(optimal_hash_count(n, m), k);
-            assert_eq!(optimal_bit_count(optimal_capacity(m, p), p), m);
-        }
-    }
-
-    #[test]
-    fn optimal_capacity_works() {
-        let test_cases = [
-            (1 << 13 as usize, 0.01, 855, 7), // 1 KiB
-            (1 << 13 as usize, 0.05, 1314, 5),
-            (1 << 23 as usize, 0.01, 875175, 7), // 1 MiB
-            (1 << 23 as usize, 0.05, 1345358, 5),
-            (1 << 33 as usize, 0.01, 896179684, 7), // 1 GiB
-            (1 << 33 as usize, 0.05, 1377646461, 5),
-        ];
-        for (m, p, n, k) in test_cases {
-            assert_eq!(optimal_capacity(m, p), n);
-            assert_eq!(optimal_hash_count(n, m), k);
-            assert_eq!(optimal_capacity(optimal_bit_count(n, p), p), n);
-        }
-    }
-
-    #[test]
-    fn optimal_hash_count_works() {
-        let test_cases = [
-            (10, 63, 5),
-            (100, 624, 5),
-            (100, 480, 4),
-            (100, 959, 7),
-            (1000, 9586, 7),
-            (10000, 95851, 7),
-            (100000, 958506, 7),
-        ];
-        for (n, m, k) in test_cases {
-            assert_eq!(optimal_hash_count(n, m), k);
+impl<K, H, S> FreezableQueryFilter<K> for BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    type Frozen = FrozenBloomFilter<K, H>;
+
+    fn freeze(self) -> Self::Frozen {
+        event!(bit_count = self.bits.len(), k = self.k; "froze Bloom filter");
+        FrozenBloomFilter {
+            words: (0..self.bits.word_count())
+                .map(|i| self.bits.word(i))
+                .collect(),
+            bit_count: self.bits.len() as u64,
+            hasher: self.hasher,
+            k: self.k,
+            phantom: PhantomData,
         }
     }
 }
+
+/// Read-only Bloom filter.
+///
+/// Produced by [`BloomFilter::freeze`], answers queries identically to the
+/// filter it was frozen from. Bits are re-packed into a single exactly sized
+/// allocation of 64-bit words, regardless of the storage used while
+/// building.
+pub struct FrozenBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    words: Box<[u64]>,
+    bit_count: u64,
+    hasher: H,
+    k: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K, H> FrozenBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the approximate number of elements in the filter.
+    pub fn approx_current_capacity(&self) -> usize {
+        let bits_count = self.bit_count as f64;
+        let ones_count = self
+            .words
+            .iter()
+            .map(|word| word.count_ones() as f64)
+            .sum::<f64>();
+        let hash_count = self.k as f64;
+        let count = -(bits_count / hash_count) * (1. - (ones_count / bits_count)).ln();
+
+        count.round() as usize
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<K, H> FrozenBloomFilter<K, H>
+where
+    K: Eq + Hash + Sync,
+    H: HashIterHasher<u64> + Sync,
+{
+    /// Returns, for each key, `true` if the key is believed to be in the
+    /// filter, querying keys in parallel.
+    ///
+    /// Keys are split into batches across the threads of the current rayon
+    /// pool. Within a batch, all probe indices are computed before any bit
+    /// is touched, so that memory accesses of different keys overlap.
+    pub fn par_contains_many<Q>(&self, keys: &[Q]) -> Vec<bool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + Sync,
+    {
+        use rayon::prelude::*;
+
+        let mut found = vec![false; keys.len()];
+        keys.par_chunks(BATCH)
+            .zip(found.par_chunks_mut(BATCH))
+            .for_each_init(
+                || Vec::with_capacity(BATCH * self.k),
+                |indices, (keys, found)| {
+                    indices.clear();
+                    indices.extend(keys.iter().flat_map(|key| {
+                        self.hasher
+                            .hash_iter(key, self.k)
+                            .map(|hash| hash % self.bit_count)
+                    }));
+                    for (found, probes) in found.iter_mut().zip(indices.chunks(self.k.max(1))) {
+                        *found = probes.iter().all(|&index| {
+                            self.words[(index / 64) as usize] & (1 << (index % 64)) != 0
+                        });
+                    }
+                },
+            );
+        found
+    }
+}
+
+impl<K, H> QueryFilter<K> for FrozenBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for hash in self.hasher.hash_iter(key, self.k) {
+            let index = hash % self.bit_count;
+            if self.words[(index / 64) as usize] & (1 << (index % 64)) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Magic bytes opening a streamed Bloom filter.
+const STREAM_MAGIC: [u8; 4] = *b"MQBF";
+
+/// Version of the streamed Bloom filter format.
+const STREAM_VERSION: u8 = 1;
+
+/// Number of 64-bit words streamed at once (64 KiB).
+const STREAM_CHUNK_WORDS: usize = 8192;
+
+/// Key whose probe identifies the hasher of a streamed Bloom filter.
+const FINGERPRINT_KEY: &str = "mqfilters";
+
+impl<K, H, S> BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    /// Writes the filter to a stream, a chunk of bits at a time.
+    ///
+    /// Unlike serializing with serde, no copy of the bit array is made, so
+    /// filters larger than the free memory can be written to files or
+    /// sockets. The format (little-endian) is:
+    ///
+    /// - magic `MQBF` and version byte (`1`),
+    /// - hash count (`u32`), bit count (`u64`), and a fingerprint of the hasher
+    ///   (`u64`), so that a mismatched hasher is detected on decoding,
+    /// - the bits, as `u64` words with the lowest bit first,
+    /// - an XXH3 checksum (`u64`) of everything before it.
+    ///
+    /// Writes are not buffered beyond a chunk (64 KiB).
+    pub fn encode_into(&self, mut writer: impl Write) -> io::Result<()> {
+        let mut checksum = Xxh3::new();
+        let mut chunk = Vec::with_capacity(STREAM_CHUNK_WORDS * 8);
+        chunk.extend_from_slice(&STREAM_MAGIC);
+        chunk.push(STREAM_VERSION);
+        chunk.extend_from_slice(&(self.k as u32).to_le_bytes());
+        chunk.extend_from_slice(&(self.bits.len() as u64).to_le_bytes());
+        chunk.extend_from_slice(&fingerprint(&self.hasher).to_le_bytes());
+
+        let word_count = self.bits.word_count();
+        let mut index = 0;
+        loop {
+            let end = (index + STREAM_CHUNK_WORDS).min(word_count);
+            for i in index..end {
+                chunk.extend_from_slice(&self.bits.word(i).to_le_bytes());
+            }
+            checksum.update(&chunk);
+            writer.write_all(&chunk)?;
+            chunk.clear();
+            if end == word_count {
+                break;
+            }
+            index = end;
+        }
+        writer.write_all(&checksum.digest().to_le_bytes())
+    }
+}
+
+impl<K> BloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Reads a filter written by [`encode_into`](BloomFilter::encode_into),
+    /// a chunk of bits at a time.
+    ///
+    /// Fails with [`InvalidData`](io::ErrorKind::InvalidData) if the stream
+    /// is not a valid filter, was corrupted, or was written by a filter using
+    /// a hasher other than the default one (see [`decode_from_with_hasher`]).
+    ///
+    /// [`decode_from_with_hasher`]: BloomFilter::decode_from_with_hasher
+    pub fn decode_from(reader: impl Read) -> io::Result<Self> {
+        Self::decode_from_with_hasher(reader, ProbeHasher::default())
+    }
+}
+
+impl<K, H> BloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Reads a filter written by [`encode_into`](BloomFilter::encode_into),
+    /// with the hasher it was written with.
+    ///
+    /// Bits are read straight into the new filter, without buffering the
+    /// stream beyond a chunk (64 KiB).
+    pub fn decode_from_with_hasher(mut reader: impl Read, hasher: H) -> io::Result<Self> {
+        let invalid = |reason| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid Bloom filter: {reason}"),
+            )
+        };
+
+        let mut checksum = Xxh3::new();
+        let mut header = [0; 25];
+        reader.read_exact(&mut header)?;
+        checksum.update(&header);
+        if header[..4] != STREAM_MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        if header[4] != STREAM_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let k = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+        let bit_count = u64::from_le_bytes(header[9..17].try_into().unwrap());
+        let bit_count = usize::try_from(bit_count).map_err(|_| invalid("too many bits"))?;
+        if bit_count == 0 || k == 0 {
+            return Err(invalid("no bits or hash functions"));
+        }
+        if u64::from_le_bytes(header[17..25].try_into().unwrap()) != fingerprint(&hasher) {
+            return Err(invalid("hasher differs"));
+        }
+
+        // The bit count is not trusted until the bits are read: words are
+        // collected as chunks arrive, rather than allocated upfront.
+        let word_count = bit_count.div_ceil(64);
+        let mut words = Vec::new();
+        let mut chunk = vec![0; STREAM_CHUNK_WORDS.min(word_count) * 8];
+        while words.len() < word_count {
+            let len = STREAM_CHUNK_WORDS.min(word_count - words.len());
+            let chunk = &mut chunk[..len * 8];
+            reader.read_exact(chunk)?;
+            checksum.update(chunk);
+            words.extend(
+                chunk
+                    .chunks_exact(8)
+                    .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap())),
+            );
+        }
+        let mut expected = [0; 8];
+        reader.read_exact(&mut expected)?;
+        if u64::from_le_bytes(expected) != checksum.digest() {
+            return Err(invalid("checksum mismatch"));
+        }
+        if bit_count % 64 != 0 && words[word_count - 1] >> (bit_count % 64) != 0 {
+            return Err(invalid("bits set past the bit count"));
+        }
+        let mut bits = BitSet::with_capacity(bit_count);
+        let blocks = bits.as_mut_slice();
+        for (index, &word) in words.iter().enumerate() {
+            set_word(blocks, index, word);
+        }
+        Ok(Self::with_storage(bits, k, hasher))
+    }
+}
+
+/// Magic bytes opening a memory-mapped Bloom filter file.
+#[cfg(all(feature = "mmap", unix))]
+const MMAP_MAGIC: [u8; 4] = *b"MQBM";
+
+/// Version of the memory-mapped Bloom filter file format.
+#[cfg(all(feature = "mmap", unix))]
+const MMAP_VERSION: u8 = 1;
+
+/// Length of a memory-mapped Bloom filter file's header, padded so that the
+/// bits start on a cache line.
+#[cfg(all(feature = "mmap", unix))]
+const MMAP_HEADER_LEN: usize = 64;
+
+#[cfg(all(feature = "mmap", unix))]
+impl<K> BloomFilter<K, ProbeHasher, MmapBitSet>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new Bloom filter with a desired capacity and false positive
+    /// rate, backed by a memory-mapped file (truncated if it exists).
+    ///
+    /// The bits live in the file rather than on the heap: they are paged in
+    /// on demand, shared with every other process mapping the file, and kept
+    /// across restarts, see [`open_mmap`](BloomFilter::open_mmap). The file
+    /// holds a header (magic `MQBM`, version byte, hash count, bit count, and
+    /// a fingerprint of the hasher, little-endian) padded to 64 bytes,
+    /// followed by the bits, as little-endian `u64` words.
+    pub fn create_mmap(path: impl AsRef<Path>, capacity: usize, fp_rate: f64) -> io::Result<Self> {
+        Self::create_mmap_with_hasher(path, capacity, fp_rate, ProbeHasher::default())
+    }
+
+    /// Opens a Bloom filter file created by
+    /// [`create_mmap`](BloomFilter::create_mmap), mapping it into memory.
+    ///
+    /// Fails with [`InvalidData`](io::ErrorKind::InvalidData) if the file is
+    /// not a valid filter, or was created with a hasher other than the
+    /// default one (see [`open_mmap_with_hasher`]).
+    ///
+    /// [`open_mmap_with_hasher`]: BloomFilter::open_mmap_with_hasher
+    pub fn open_mmap(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::open_mmap_with_hasher(path, ProbeHasher::default())
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl<K, H> BloomFilter<K, H, MmapBitSet>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new Bloom filter with a desired capacity, false positive
+    /// rate, and hasher, backed by a memory-mapped file, see
+    /// [`create_mmap`](BloomFilter::create_mmap).
+    pub fn create_mmap_with_hasher(
+        path: impl AsRef<Path>,
+        capacity: usize,
+        fp_rate: f64,
+        hasher: H,
+    ) -> io::Result<Self> {
+        let bit_count = optimal_bit_count(capacity, fp_rate);
+        let k = optimal_hash_count(capacity, bit_count);
+        event!(capacity, fp_rate, bit_count, k; "created memory-mapped Bloom filter");
+        Self::create_mmap_with_shape(path, bit_count, k, hasher)
+    }
+
+    /// Creates a new, empty Bloom filter file of `bit_count` bits and `k`
+    /// hash functions, mapping it into memory.
+    fn create_mmap_with_shape(
+        path: impl AsRef<Path>,
+        bit_count: usize,
+        k: usize,
+        hasher: H,
+    ) -> io::Result<Self> {
+        let mut header = [0; MMAP_HEADER_LEN];
+        header[..4].copy_from_slice(&MMAP_MAGIC);
+        header[4] = MMAP_VERSION;
+        header[5..9].copy_from_slice(&(k as u32).to_le_bytes());
+        header[9..17].copy_from_slice(&(bit_count as u64).to_le_bytes());
+        header[17..25].copy_from_slice(&fingerprint(&hasher).to_le_bytes());
+
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.write_all(&header)?;
+        // Extending the file zeroes (and on most file systems, does not
+        // allocate) the bits.
+        file.set_len((MMAP_HEADER_LEN + bit_count.div_ceil(64) * 8) as u64)?;
+        let bits = MmapBitSet::map(&file, MMAP_HEADER_LEN, bit_count)?;
+        Ok(Self::with_storage(bits, k, hasher))
+    }
+
+    /// Opens a Bloom filter file created by
+    /// [`create_mmap_with_hasher`](BloomFilter::create_mmap_with_hasher),
+    /// with the hasher it was created with.
+    pub fn open_mmap_with_hasher(path: impl AsRef<Path>, hasher: H) -> io::Result<Self> {
+        let invalid = |reason| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid Bloom filter file: {reason}"),
+            )
+        };
+
+        let mut file = File::options().read(true).write(true).open(path)?;
+        let mut header = [0; MMAP_HEADER_LEN];
+        file.read_exact(&mut header)?;
+        if header[..4] != MMAP_MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        if header[4] != MMAP_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let k = u32::from_le_bytes(header[5..9].try_into().unwrap()) as usize;
+        let bit_count = u64::from_le_bytes(header[9..17].try_into().unwrap());
+        if u64::from_le_bytes(header[17..25].try_into().unwrap()) != fingerprint(&hasher) {
+            return Err(invalid("hasher differs"));
+        }
+        let file_len = bit_count
+            .div_ceil(64)
+            .checked_mul(8)
+            .and_then(|len| len.checked_add(MMAP_HEADER_LEN as u64));
+        if bit_count == 0 || file_len != Some(file.metadata()?.len()) {
+            return Err(invalid("file length does not match the bit count"));
+        }
+        let bit_count = usize::try_from(bit_count).map_err(|_| invalid("too many bits"))?;
+        let bits = MmapBitSet::map(&file, MMAP_HEADER_LEN, bit_count)?;
+        Ok(Self::with_storage(bits, k, hasher))
+    }
+
+    /// Writes modified bits back to the file, see [`MmapBitSet::flush`].
+    pub fn flush(&self) -> io::Result<()> {
+        self.bits.flush()
+    }
+
+    /// Advises the kernel of how the bits are about to be accessed, see
+    /// [`MmapBitSet::advise`].
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        self.bits.advise(advice)
+    }
+
+    /// Locks the bits in memory, so that queries never fault, see
+    /// [`MmapBitSet::lock`].
+    pub fn lock(&self) -> io::Result<()> {
+        self.bits.lock()
+    }
+
+    /// Unlocks bits locked by [`lock`](BloomFilter::lock).
+    pub fn unlock(&self) -> io::Result<()> {
+        self.bits.unlock()
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl<K, H, S> BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+    S: BitStorage,
+{
+    /// Writes the filter to a new file (truncated if it exists), mapping it
+    /// into memory, see [`create_mmap`](BloomFilter::create_mmap).
+    ///
+    /// Useful to move a filter that is no longer written to out of memory:
+    /// its bits are then only paged in as queries touch them.
+    pub fn to_mmap(&self, path: impl AsRef<Path>) -> io::Result<BloomFilter<K, H, MmapBitSet>> {
+        let mut filter = BloomFilter::create_mmap_with_shape(
+            path,
+            self.bits.len(),
+            self.k,
+            self.hasher.clone(),
+        )?;
+        for index in self.ones() {
+            filter.bits.insert(index);
+        }
+        Ok(filter)
+    }
+}
+
+/// Magic bytes opening a Bloom filter encoded by [`BloomFilter::to_bytes`].
+const BYTES_MAGIC: [u8; 4] = *b"MQBB";
+
+/// Version of the byte encoding format.
+const BYTES_VERSION: u8 = 1;
+
+/// Length of the byte encoding header, up to the bits.
+const BYTES_HEADER_LEN: usize = 42;
+
+impl<K, S> BloomFilter<K, ProbeHasher, S>
+where
+    K: Eq + Hash,
+    S: BitStorage,
+{
+    /// Encodes the filter into a self-contained byte buffer, see
+    /// [`from_bytes`](BloomFilter::from_bytes).
+    ///
+    /// Unlike [`encode_into`](BloomFilter::encode_into), the hasher's seeds
+    /// and strategy are included, so that the filter can be decoded without
+    /// knowing them. The format is stable: any crate version supporting its
+    /// version byte decodes it to a filter answering queries identically.
+    /// It is (little-endian):
+    ///
+    /// - magic `MQBB` and version byte (`1`),
+    /// - probe strategy (`u8`: `0` for double, `1` for enhanced double, `2` for
+    ///   triple hashing), hash count (`u32`), bit count (`u64`), and the three
+    ///   seeds (`u64` each),
+    /// - the bits, as `u64` words with the lowest bit first,
+    /// - an XXH3 checksum (`u64`) of everything before it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let word_count = self.bits.word_count();
+        let mut bytes = Vec::with_capacity(BYTES_HEADER_LEN + word_count * 8 + 8);
+        bytes.extend_from_slice(&BYTES_MAGIC);
+        bytes.push(BYTES_VERSION);
+        bytes.push(match self.hasher.strategy() {
+            ProbeStrategy::Double => 0,
+            ProbeStrategy::EnhancedDouble => 1,
+            ProbeStrategy::Triple => 2,
+        });
+        bytes.extend_from_slice(&(self.k as u32).to_le_bytes());
+        bytes.extend_from_slice(&(self.bits.len() as u64).to_le_bytes());
+        for seed in self.hasher.seeds() {
+            bytes.extend_from_slice(&seed.to_le_bytes());
+        }
+        for i in 0..word_count {
+            bytes.extend_from_slice(&self.bits.word(i).to_le_bytes());
+        }
+        let checksum = xxh3_64(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+}
+
+impl<K> BloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Decodes a filter encoded by [`to_bytes`](BloomFilter::to_bytes), with
+    /// the hasher it was encoded with.
+    ///
+    /// Fails if the bytes are not a valid filter, were corrupted, or were
+    /// encoded in a format version this crate version does not support.
+    pub fn from_bytes(bytes: &[u8]) -> QueryFilterResult<Self> {
+        let invalid = |reason| QueryFilterError::Other(format!("invalid Bloom filter: {reason}"));
+
+        if bytes.len() < BYTES_HEADER_LEN + 8 {
+            return Err(invalid("truncated"));
+        }
+        if bytes[..4] != BYTES_MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        if bytes[4] != BYTES_VERSION {
+            return Err(invalid("unsupported version"));
+        }
+        let (body, checksum) = bytes.split_at(bytes.len() - 8);
+        if u64::from_le_bytes(checksum.try_into().unwrap()) != xxh3_64(body) {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let strategy = match bytes[5] {
+            0 => ProbeStrategy::Double,
+            1 => ProbeStrategy::EnhancedDouble,
+            2 => ProbeStrategy::Triple,
+            _ => return Err(invalid("unknown probe strategy")),
+        };
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap());
+        let k = u32::from_le_bytes(bytes[6..10].try_into().unwrap()) as usize;
+        let bit_count = usize::try_from(u64_at(10)).map_err(|_| invalid("too many bits"))?;
+        let hasher = ProbeHasher::new(strategy)
+            .with_seed1(u64_at(18))
+            .with_seed2(u64_at(26))
+            .with_seed3(u64_at(34));
+
+        let words = &body[BYTES_HEADER_LEN..];
+        let word_count = bit_count.div_ceil(64);
+        if bit_count == 0 || words.len() != word_count * 8 {
+            return Err(invalid("bit count does not match the bits"));
+        }
+        let mut bits = BitSet::with_capacity(bit_count);
+        let blocks = bits.as_mut_slice();
+        for (i, bytes) in words.chunks_exact(8).enumerate() {
+            set_word(blocks, i, u64::from_le_bytes(bytes.try_into().unwrap()));
+        }
+        if bit_count % 64 != 0 && bits.word(word_count - 1) >> (bit_count % 64) != 0 {
+            return Err(invalid("bits set past the bit count"));
+        }
+        Ok(Self::with_storage(bits, k, hasher))
+    }
+}
+
+/// Sets the `index`-th 64-bit word of a bit set's blocks.
+///
+/// Blocks are `usize`, so on 32-bit targets a word spans two of them.
+fn set_word(blocks: &mut [usize], index: usize, word: u64) {
+    let per_word = (u64::BITS / usize::BITS) as usize;
+    for j in 0..per_word {
+        if let Some(block) = blocks.get_mut(index * per_word + j) {
+            *block = (word >> (j as u32 * usize::BITS)) as usize;
+        }
+    }
+}
+
+/// Returns a fingerprint of a hasher, telling hashers apart.
+///
+/// Several probes are combined, as probe strategies may only differ past the
+/// first one or two.
+fn fingerprint<H: HashIterHasher<u64>>(hasher: &H) -> u64 {
+    hasher
+        .hash_iter(&FINGERPRINT_KEY, 4)
+        .fold(0, |fingerprint, hash| fingerprint.rotate_left(17) ^ hash)
+}
+
+/// Serialized form of a Bloom filter.
+///
+/// Parameters are kept in clear. Bits are packed into bytes, lowest bit
+/// first, and written as a base64 string in human-readable formats (JSON,
+/// YAML), or as raw bytes in binary ones. Both forms carry the same bytes,
+/// so a filter converts between them losslessly.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "BloomFilter")]
+struct SerdeBloomFilter<H> {
+    bit_count: usize,
+    hash_count: usize,
+    hasher: H,
+    bits: crate::storage::SerdeBits,
+}
+
+#[cfg(feature = "serde")]
+impl<K, H, S> serde::Serialize for BloomFilter<K, H, S>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Serialize,
+    S: BitStorage,
+{
+    fn serialize<T: serde::Serializer>(&self, serializer: T) -> Result<T::Ok, T::Error> {
+        let mut bytes = (0..self.bits.word_count())
+            .flat_map(|i| self.bits.word(i).to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes.truncate(self.bits.len().div_ceil(8));
+        SerdeBloomFilter {
+            bit_count: self.bits.len(),
+            hash_count: self.k,
+            hasher: &self.hasher,
+            bits: crate::storage::SerdeBits(bytes),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, H> serde::Deserialize<'de> for BloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeBloomFilter::<H>::deserialize(deserializer)?;
+        let bytes = filter.bits.0;
+        if filter.bit_count == 0 || bytes.len() != filter.bit_count.div_ceil(8) {
+            return Err(D::Error::custom(format!(
+                "expected {} bytes of bits, got {}",
+                filter.bit_count.div_ceil(8),
+                bytes.len()
+            )));
+        }
+        let indices = bytes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, &byte)| {
+                (0..8)
+                    .filter(move |bit| byte & (1 << bit) != 0)
+                    .map(move |bit| i * 8 + bit)
+            })
+            .collect::<Vec<_>>();
+        if indices
+            .last()
+            .is_some_and(|&index| index >= filter.bit_count)
+        {
+            return Err(D::Error::custom("bits set past the bit count"));
+        }
+        Ok(Self::from_set_bits(
+            filter.bit_count,
+            filter.hash_count,
+            filter.hasher,
+            indices,
+        ))
+    }
+}
+
+/// Estimates the number of keys in a Bloom filter of `m` bits and `k` hash
+/// functions with a given number of set bits.
+fn estimate_count(bit_count: usize, ones_count: usize, hash_count: usize) -> f64 {
+    let m = bit_count as f64;
+    let k = hash_count as f64;
+    -(m / k) * (1. - ones_count as f64 / m).ln()
+}
@@ -0,0 +1,233 @@
+//! Builds and queries filters stored in the crate's binary formats.
+//!
+//! ```text
+//! mqfilters build [--type bloom|cuckoo] [--capacity N] [--fp-rate R] <keys> <filter>
+//! mqfilters query <filter> [<keys>]
+//! mqfilters merge <output> <filter>...
+//! mqfilters stats <filter>
+//! ```
+//!
+//! Keys are read one per line, from the standard input if the path is `-`
+//! (or, for queries, omitted), and hashed as strings: a filter built here
+//! answers `contains` on `&str` keys in the library. Bloom filters are
+//! stored by [`BloomFilter::to_bytes`] and cuckoo filters by
+//! [`CuckooFilter::to_bytes`], and told apart by their magic bytes.
+
+use {
+    mqfilters::{
+        bf::DEFAULT_FP_RATE,
+        hash::ProbeHasher,
+        BloomFilter,
+        CuckooFilter,
+        QueryFilter,
+        QueryFilterError,
+    },
+    std::{
+        env,
+        fs,
+        io::{self, BufRead, BufWriter, Write},
+        process::ExitCode,
+    },
+};
+
+const USAGE: &str = "\
+usage: mqfilters build [--type bloom|cuckoo] [--capacity N] [--fp-rate R] <keys> <filter>
+       mqfilters query <filter> [<keys>]
+       mqfilters merge <output> <filter>...
+       mqfilters stats <filter>
+
+Keys are read one per line, from the standard input if the path is `-`.";
+
+/// Outcome of a command, with a message for the standard error if it failed.
+type CliResult<T> = Result<T, String>;
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.first().map(String::as_str) {
+        Some("build") => build(&args[1..]),
+        Some("query") => query(&args[1..]),
+        Some("merge") => merge(&args[1..]),
+        Some("stats") => stats(&args[1..]),
+        Some("-h" | "--help") => {
+            println!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        _ => Err(USAGE.to_owned()),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("mqfilters: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Filter of any type the tool handles.
+enum Filter {
+    Bloom(BloomFilter<String>),
+    Cuckoo(CuckooFilter<String>),
+}
+
+impl Filter {
+    /// Reads a filter from a file, of the type given by its magic bytes.
+    fn read(path: &str) -> CliResult<Self> {
+        let bytes = fs::read(path).map_err(|err| format!("{path}: {err}"))?;
+        let filter = match bytes.get(..4) {
+            Some(b"MQBB") => BloomFilter::from_bytes(&bytes).map(Filter::Bloom),
+            Some(b"MQCF") => CuckooFilter::from_bytes(&bytes).map(Filter::Cuckoo),
+            _ => return Err(format!("{path}: not a Bloom or cuckoo filter")),
+        };
+        filter.map_err(|err| format!("{path}: {}", describe(err)))
+    }
+
+    /// Writes the filter to a file.
+    fn write(&self, path: &str) -> CliResult<()> {
+        let bytes = match self {
+            Filter::Bloom(filter) => filter.to_bytes(),
+            Filter::Cuckoo(filter) => filter.to_bytes(),
+        };
+        fs::write(path, bytes).map_err(|err| format!("{path}: {err}"))
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        match self {
+            Filter::Bloom(filter) => filter.contains(key),
+            Filter::Cuckoo(filter) => filter.contains(key),
+        }
+    }
+}
+
+/// Builds a filter holding the keys of a file.
+fn build(args: &[String]) -> CliResult<()> {
+    let (mut kind, mut capacity, mut fp_rate) = ("bloom", None, DEFAULT_FP_RATE);
+    let mut paths = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("missing value for {arg}"))
+        };
+        match arg.as_str() {
+            "--type" => kind = value()?,
+            "--capacity" => capacity = Some(parse(value()?, "capacity")?),
+            "--fp-rate" => fp_rate = parse(value()?, "false positive rate")?,
+            option if option.starts_with("--") => return Err(format!("unknown option {option}")),
+            path => paths.push(path),
+        }
+    }
+    let [keys, output] = paths[..] else {
+        return Err(USAGE.to_owned());
+    };
+
+    let keys = read_keys(keys)?;
+    let capacity = capacity.unwrap_or(keys.len().max(1));
+    let filter = match kind {
+        "bloom" => {
+            let mut filter = BloomFilter::builder(capacity, fp_rate)
+                .build()
+                .map_err(describe)?;
+            filter.insert_many(keys);
+            Filter::Bloom(filter)
+        }
+        "cuckoo" => {
+            let mut filter =
+                CuckooFilter::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
+                    .map_err(describe)?;
+            for key in keys {
+                filter.try_insert(key).map_err(describe)?;
+            }
+            Filter::Cuckoo(filter)
+        }
+        kind => return Err(format!("unknown filter type {kind}, not bloom or cuckoo")),
+    };
+    filter.write(output)
+}
+
+/// Prints, for each key, whether the filter may contain it.
+fn query(args: &[String]) -> CliResult<()> {
+    let (filter, keys) = match args {
+        [filter] => (filter, "-"),
+        [filter, keys] => (filter, keys.as_str()),
+        _ => return Err(USAGE.to_owned()),
+    };
+    let filter = Filter::read(filter)?;
+    let mut stdout = BufWriter::new(io::stdout().lock());
+    for key in read_keys(keys)? {
+        writeln!(stdout, "{key}\t{}", filter.contains(&key)).map_err(|err| err.to_string())?;
+    }
+    stdout.flush().map_err(|err| err.to_string())
+}
+
+/// Merges Bloom filters into one holding the union of their keys.
+///
+/// Filters of different sizes are folded onto the smallest, see
+/// [`BloomFilter::fold_union_with`].
+fn merge(args: &[String]) -> CliResult<()> {
+    let [output, first, rest @ ..] = args else {
+        return Err(USAGE.to_owned());
+    };
+    let bloom = |path: &String| match Filter::read(path)? {
+        Filter::Bloom(filter) => Ok(filter),
+        Filter::Cuckoo(_) => Err(format!("{path}: only Bloom filters can be merged")),
+    };
+    let mut merged = bloom(first)?;
+    for path in rest {
+        merged
+            .fold_union_with(&bloom(path)?)
+            .map_err(|err| format!("{path}: {}", describe(err)))?;
+    }
+    Filter::Bloom(merged).write(output)
+}
+
+/// Prints the parameters and load of a filter.
+fn stats(args: &[String]) -> CliResult<()> {
+    let [path] = args else {
+        return Err(USAGE.to_owned());
+    };
+    match Filter::read(path)? {
+        Filter::Bloom(filter) => {
+            println!("type\tbloom");
+            println!("bits\t{}", filter.bit_count());
+            println!("hash functions\t{}", filter.hash_count());
+            println!("approx keys\t{}", filter.approx_current_capacity());
+            println!("fill ratio\t{:.4}", filter.fill_ratio());
+            println!("approx fp rate\t{:.6}", filter.approx_fp_rate());
+            println!("size in bytes\t{}", filter.size_in_bytes());
+        }
+        Filter::Cuckoo(filter) => {
+            println!("type\tcuckoo");
+            println!("keys\t{}", filter.len());
+            println!("capacity\t{}", filter.capacity());
+            println!("fingerprint bits\t{}", filter.fingerprint_bits());
+            println!("bucket size\t{}", filter.bucket_size());
+            println!("load factor\t{:.4}", filter.load_factor());
+            println!("size in bytes\t{}", filter.size_in_bytes());
+        }
+    }
+    Ok(())
+}
+
+/// Reads keys, one per line, from a file or the standard input (`-`).
+fn read_keys(path: &str) -> CliResult<Vec<String>> {
+    let keys = if path == "-" {
+        io::stdin().lock().lines().collect()
+    } else {
+        fs::read_to_string(path).map(|keys| keys.lines().map(str::to_owned).collect())
+    };
+    keys.map_err(|err| format!("{path}: {err}"))
+}
+
+/// Parses a command line value.
+fn parse<T: std::str::FromStr>(value: &str, what: &str) -> CliResult<T> {
+    value.parse().map_err(|_| format!("invalid {what} {value}"))
+}
+
+/// Describes an error, with the reason of otherwise opaque ones.
+fn describe(err: QueryFilterError) -> String {
+    match err {
+        QueryFilterError::Other(reason) => reason,
+        err => err.to_string(),
+    }
+}
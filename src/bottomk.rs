@@ -0,0 +1,168 @@
+//! Bottom-k distinct sampling sketch.
+//!
+//! A [`BottomK`] sketch keeps the `k` keys with the smallest hashes, i.e. a
+//! uniform sample of `k` distinct keys of the stream, no matter how often
+//! each key repeats. The `k`-th smallest hash estimates the number of
+//! distinct keys, and sketches of different streams can be combined into the
+//! sketch of their union, or compared to estimate their similarity. See
+//! [On Synopses for Distinct-Value Estimation Under Multiset Operations,
+//! 2007][1].
+//!
+//! [1]: https://dl.acm.org/doi/10.1145/1247480.1247504
+
+use {
+    std::{
+        collections::{BTreeMap, BTreeSet},
+        hash::{BuildHasher, Hash},
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Sample of the `k` distinct keys with the smallest hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BottomK<K> {
+    samples: BTreeMap<u64, K>,
+    k: usize,
+    seed: u64,
+}
+
+impl<K> BottomK<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new empty sketch, keeping up to `k` samples.
+    ///
+    /// The relative standard error of distinct count estimates is about
+    /// `1 / sqrt(k - 2)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero.
+    pub fn new(k: usize) -> Self {
+        Self::with_seed(k, 0)
+    }
+
+    /// Creates a new empty sketch, keeping up to `k` samples, with a given
+    /// hash seed.
+    ///
+    /// Only sketches built with the same `k` and seed can be combined.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is zero.
+    pub fn with_seed(k: usize, seed: u64) -> Self {
+        assert!(k > 0, "sketch must keep at least one sample");
+        Self {
+            samples: BTreeMap::new(),
+            k,
+            seed,
+        }
+    }
+
+    /// Returns the maximum number of samples kept.
+    pub fn k(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the number of samples currently kept.
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Returns `true` if no key has been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// Returns the sampled keys, in hash order.
+    pub fn samples(&self) -> impl Iterator<Item = &K> {
+        self.samples.values()
+    }
+
+    /// Adds a key to the sketched stream.
+    pub fn insert(&mut self, key: K) {
+        let hash = Xxh3Builder::new().with_seed(self.seed).hash_one(&key);
+        self.insert_hashed(hash, key);
+    }
+
+    /// Returns the estimated number of distinct keys in the stream.
+    ///
+    /// Exact while fewer than `k` distinct keys have been seen.
+    pub fn distinct_estimate(&self) -> f64 {
+        match self.samples.last_key_value() {
+            Some((&max, _)) if self.samples.len() == self.k && self.k > 1 => {
+                // The k-th smallest of n uniform hashes is around k / n.
+                (self.k - 1) as f64 / (max as f64 / 2f64.powi(64))
+            }
+            _ => self.samples.len() as f64,
+        }
+    }
+
+    /// Returns the estimated Jaccard similarity of the sketched streams'
+    /// distinct keys.
+    ///
+    /// Two empty streams are considered identical.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sketches differ in `k` or seed.
+    pub fn jaccard_estimate(&self, other: &Self) -> f64 {
+        self.assert_compatible(other);
+        // Bottom-k of the union is a uniform sample of the union, check how
+        // much of it is in both streams.
+        let union = self
+            .samples
+            .keys()
+            .chain(other.samples.keys())
+            .collect::<BTreeSet<_>>();
+        let union = union.into_iter().take(self.k).collect::<Vec<_>>();
+        if union.is_empty() {
+            return 1.;
+        }
+        let both = union
+            .iter()
+            .filter(|hash| self.samples.contains_key(hash) && other.samples.contains_key(hash))
+            .count();
+        both as f64 / union.len() as f64
+    }
+
+    fn insert_hashed(&mut self, hash: u64, key: K) {
+        if self.samples.len() == self.k {
+            match self.samples.last_key_value() {
+                Some((&max, _)) if hash < max => {
+                    if self.samples.contains_key(&hash) {
+                        return;
+                    }
+                    self.samples.pop_last();
+                }
+                _ => return,
+            }
+        }
+        self.samples.entry(hash).or_insert(key);
+    }
+
+    fn assert_compatible(&self, other: &Self) {
+        assert!(
+            self.k == other.k && self.seed == other.seed,
+            "sketches differ in k or seed"
+        );
+    }
+}
+
+impl<K> BottomK<K>
+where
+    K: Eq + Hash + Clone,
+{
+    /// Merges another sketch into this one, so that it sketches the union of
+    /// both streams.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sketches differ in `k` or seed.
+    pub fn merge(&mut self, other: &Self) {
+        self.assert_compatible(other);
+        for (&hash, key) in &other.samples {
+            self.insert_hashed(hash, key.clone());
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Memory accounting across filters.
+//!
+//! Services running many filters need to cap their total memory, not just
+//! size each filter on its own. A [`MemoryBudget`] is a registry where
+//! filters report their allocated bytes under a name, checked against a
+//! shared limit: a report that would push the total over the limit is
+//! passed to a policy hook, which either denies it (the default, so that
+//! the caller skips the allocation) or allows it, e.g. after freeing memory
+//! elsewhere by folding or rotating filters.
+//!
+//! Accounting is cooperative: filters do not report on their own, callers
+//! report before allocating (or after resizing) and release on drop.
+
+use {
+    crate::{QueryFilterError, QueryFilterResult},
+    std::{
+        collections::BTreeMap,
+        sync::{Mutex, OnceLock},
+    },
+};
+
+/// Report that would push the total over the limit, passed to the policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Overage<'a> {
+    /// Name the bytes are reported under.
+    pub name: &'a str,
+    /// Bytes reported under the name.
+    pub requested: usize,
+    /// Total bytes, were the report accepted.
+    pub total: usize,
+    /// Limit of the budget.
+    pub limit: usize,
+}
+
+/// What to do with a report exceeding the budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OverBudget {
+    /// Reject the report, leaving the previously reported bytes in place.
+    Deny,
+    /// Accept the report, going over the limit.
+    Allow,
+}
+
+type Policy = Box<dyn Fn(&Overage) -> OverBudget + Send + Sync>;
+
+/// Registry of memory used by named filters, with a total limit.
+pub struct MemoryBudget {
+    usages: Mutex<BTreeMap<String, usize>>,
+    limit: Mutex<usize>,
+    policy: Option<Policy>,
+}
+
+impl MemoryBudget {
+    /// Creates a new budget of `limit` bytes, denying reports exceeding it.
+    pub fn new(limit: usize) -> Self {
+        Self {
+            usages: Mutex::new(BTreeMap::new()),
+            limit: Mutex::new(limit),
+            policy: None,
+        }
+    }
+
+    /// Sets the hook deciding on reports exceeding the budget.
+    ///
+    /// The hook runs without any lock held, so it may free memory and
+    /// report the freed bytes to this very budget.
+    pub fn with_policy(
+        self,
+        policy: impl Fn(&Overage) -> OverBudget + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            policy: Some(Box::new(policy)),
+            ..self
+        }
+    }
+
+    /// Returns the process-wide budget, unlimited until
+    /// [`set_limit`](MemoryBudget::set_limit) is called on it.
+    pub fn global() -> &'static MemoryBudget {
+        static GLOBAL: OnceLock<MemoryBudget> = OnceLock::new();
+        GLOBAL.get_or_init(|| MemoryBudget::new(usize::MAX))
+    }
+
+    /// Returns the limit, in bytes.
+    pub fn limit(&self) -> usize {
+        *self.limit.lock().unwrap()
+    }
+
+    /// Sets the limit, in bytes.
+    ///
+    /// Lowering the limit below the current total does not evict anything,
+    /// it only makes subsequent growing reports go through the policy.
+    pub fn set_limit(&self, limit: usize) {
+        *self.limit.lock().unwrap() = limit;
+    }
+
+    /// Reports the bytes allocated under a name, replacing its previous
+    /// report.
+    ///
+    /// Reports that do not grow the usage of a name are always accepted.
+    /// Others exceeding the limit go through the policy, and fail if it
+    /// denies them, in which case the previous report stays in place.
+    pub fn report(&self, name: &str, bytes: usize) -> QueryFilterResult<()> {
+        let overage = {
+            let mut usages = self.usages.lock().unwrap();
+            let previous = usages.get(name).copied().unwrap_or(0);
+            let total = usages.values().sum::<usize>() - previous + bytes;
+            let limit = self.limit();
+            if bytes <= previous || total <= limit {
+                usages.insert(name.to_owned(), bytes);
+                return Ok(());
+            }
+            Overage {
+                name,
+                requested: bytes,
+                total,
+                limit,
+            }
+        };
+
+        let decision = self
+            .policy
+            .as_ref()
+            .map_or(OverBudget::Deny, |policy| policy(&overage));
+        match decision {
+            OverBudget::Allow => {
+                self.usages.lock().unwrap().insert(name.to_owned(), bytes);
+                Ok(())
+            }
+            OverBudget::Deny => Err(QueryFilterError::Other(format!(
+                "memory budget exceeded: {} bytes for {name:?} would make {} of {} bytes",
+                overage.requested, overage.total, overage.limit,
+            ))),
+        }
+    }
+
+    /// Removes the report under a name, returning its bytes.
+    pub fn release(&self, name: &str) -> Option<usize> {
+        self.usages.lock().unwrap().remove(name)
+    }
+
+    /// Returns the bytes reported under a name.
+    pub fn usage(&self, name: &str) -> Option<usize> {
+        self.usages.lock().unwrap().get(name).copied()
+    }
+
+    /// Returns the total bytes reported.
+    pub fn total(&self) -> usize {
+        self.usages.lock().unwrap().values().sum()
+    }
+
+    /// Returns the bytes reported under each name, in name order.
+    pub fn usages(&self) -> Vec<(String, usize)> {
+        let usages = self.usages.lock().unwrap();
+        usages
+            .iter()
+            .map(|(name, &bytes)| (name.clone(), bytes))
+            .collect()
+    }
+}
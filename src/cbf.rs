@@ -0,0 +1,409 @@
+//! Counting Bloom filter.
+//!
+//! A Bloom filter whose slots are small counters instead of single bits, so
+//! that keys can be removed: inserting increments the counters a key probes,
+//! removing decrements them. Counters are 4 bits wide, packed 16 to a word,
+//! which takes four times the memory of a plain Bloom filter of the same
+//! false positive rate.
+//!
+//! A counter that reaches its maximum (15) saturates: it is never
+//! incremented nor decremented again, since its true count is lost. This
+//! never causes false negatives, at the cost of never freeing the slot.
+//! Removing a key that was never inserted may decrement counters of other
+//! keys down to zero, which does cause false negatives: only remove keys
+//! known to be in the filter.
+//!
+//! Once keys stop being removed, e.g. when a filter built during ingestion is
+//! shipped to readers, it can be projected down to a plain Bloom filter
+//! answering the same queries with `CountingBloomFilter::to_bloom` (with the
+//! `bf` feature).
+
+use {
+    crate::{
+        analysis::{optimal_bit_count, optimal_hash_count},
+        hash::ProbeHasher,
+        ClearableQueryFilter,
+        FilterStats,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+        RemovableQueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+#[cfg(feature = "bf")]
+use crate::BloomFilter;
+
+/// Value at which counters saturate.
+pub const MAX_COUNT: u8 = 15;
+
+/// Number of counters packed in a word.
+const COUNTERS_PER_WORD: usize = 16;
+
+/// Bloom filter with 4-bit counters, supporting removal.
+pub struct CountingBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    words: Vec<u64>,
+    counter_count: usize,
+    saturated: usize,
+    hasher: H,
+    k: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K> CountingBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate)
+    }
+
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter with a desired capacity, false positive rate, and
+    /// hasher.
+    ///
+    /// Sized like a plain Bloom filter, with one counter per bit.
+    pub fn with_capacity_and_hasher(capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        let counter_count = optimal_bit_count(capacity, fp_rate);
+        let k = optimal_hash_count(capacity, counter_count);
+        Self::with_counter_count_and_hasher(counter_count, k, hasher)
+    }
+
+    /// Creates a new filter with exactly `counter_count` counters,
+    /// `hash_count` hash functions, and a given hasher.
+    pub fn with_counter_count_and_hasher(
+        counter_count: usize,
+        hash_count: usize,
+        hasher: H,
+    ) -> Self {
+        let counter_count = counter_count.max(1);
+        Self {
+            words: vec![0; counter_count.div_ceil(COUNTERS_PER_WORD)],
+            counter_count,
+            saturated: 0,
+            hasher,
+            k: hash_count,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the number of counters.
+    pub fn counter_count(&self) -> usize {
+        self.counter_count
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the number of saturated counters, i.e. counters stuck at
+    /// [`MAX_COUNT`] that removals can no longer free.
+    pub fn saturated_count(&self) -> usize {
+        self.saturated
+    }
+
+    /// Returns the memory used by the filter's counters, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.words.len() * 8
+    }
+
+    /// Returns the value of the counter at `index`.
+    pub fn counter(&self, index: usize) -> u8 {
+        let (word, shift) = (index / COUNTERS_PER_WORD, index % COUNTERS_PER_WORD * 4);
+        (self.words[word] >> shift & 0xf) as u8
+    }
+
+    /// Returns the fraction of non-zero counters.
+    pub fn fill_ratio(&self) -> f64 {
+        let nonzero = (0..self.counter_count)
+            .filter(|&index| self.counter(index) > 0)
+            .count();
+        nonzero as f64 / self.counter_count as f64
+    }
+
+    /// Returns the approximate number of distinct keys in the filter, from
+    /// the fraction of non-zero counters.
+    pub fn approx_current_capacity(&self) -> usize {
+        let m = self.counter_count as f64;
+        (-(m / self.k as f64) * (1. - self.fill_ratio()).ln()).round() as usize
+    }
+
+    /// Returns the number of inserts (repeated keys included) not undone by
+    /// removals, i.e. the sum of all counters divided by the number of hash
+    /// functions.
+    ///
+    /// Exact as long as no counter saturated; approximate after that.
+    pub fn approx_total_insertions(&self) -> u64 {
+        let sum = (0..self.counter_count)
+            .map(|index| self.counter(index) as u64)
+            .sum::<u64>();
+        sum / self.k.max(1) as u64
+    }
+
+    /// Returns the estimated false positive rate, given the current fraction
+    /// of non-zero counters.
+    pub fn approx_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.k as i32)
+    }
+
+    /// Inserts a key, failing (without inserting it) if that would saturate
+    /// one of its counters, after which it could no longer be removed.
+    pub fn try_insert(&mut self, key: K) -> QueryFilterResult<()> {
+        self.try_increment(&self.indices(&key))
+    }
+
+    /// Inserts all keys, or none of them: if inserting a key fails (see
+    /// [`try_insert`](CountingBloomFilter::try_insert)), the keys of the batch
+    /// inserted before it are removed again, leaving the filter as it was.
+    pub fn insert_batch_atomic(
+        &mut self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> QueryFilterResult<()> {
+        let mut inserted = Vec::<Vec<usize>>::new();
+        for key in keys {
+            let indices = self.indices(&key);
+            if let Err(err) = self.try_increment(&indices) {
+                for indices in inserted.iter().rev() {
+                    self.decrement(indices);
+                }
+                event!(rolled_back = inserted.len(); "rolled back counting Bloom filter batch");
+                return Err(err);
+            }
+            inserted.push(indices);
+        }
+        Ok(())
+    }
+
+    /// Increments the counters at the given indices, unless one of them
+    /// would saturate.
+    fn try_increment(&mut self, indices: &[usize]) -> QueryFilterResult<()> {
+        let saturates = indices.iter().any(|&index| {
+            let probes = indices.iter().filter(|&&other| other == index).count();
+            self.counter(index) as usize + probes >= MAX_COUNT as usize
+        });
+        if saturates {
+            return Err(QueryFilterError::Full);
+        }
+        for &index in indices {
+            self.set_counter(index, self.counter(index) + 1);
+        }
+        Ok(())
+    }
+
+    /// Decrements the counters at the given indices, but saturated ones.
+    fn decrement(&mut self, indices: &[usize]) {
+        for &index in indices {
+            let count = self.counter(index);
+            if count > 0 && count < MAX_COUNT {
+                self.set_counter(index, count - 1);
+            }
+        }
+    }
+
+    fn set_counter(&mut self, index: usize, value: u8) {
+        let (word, shift) = (index / COUNTERS_PER_WORD, index % COUNTERS_PER_WORD * 4);
+        self.words[word] = self.words[word] & !(0xf << shift) | (value as u64) << shift;
+    }
+
+    /// Returns the indices of the counters probed by a key.
+    fn indices<Q: Hash + ?Sized>(&self, key: &Q) -> Vec<usize> {
+        let len = self.counter_count as u64;
+        self.hasher
+            .hash_iter(key, self.k)
+            .map(|hash| (hash % len) as usize)
+            .collect()
+    }
+}
+
+#[cfg(feature = "bf")]
+impl<K, H> CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Returns a plain Bloom filter with a bit set for each non-zero counter,
+    /// and the same hash functions: it answers queries exactly like this
+    /// filter, in a quarter of the memory, but no longer supports removal.
+    pub fn to_bloom(&self) -> BloomFilter<K, H> {
+        BloomFilter::from_set_bits(
+            self.counter_count,
+            self.k,
+            self.hasher.clone(),
+            (0..self.counter_count).filter(|&index| self.counter(index) > 0),
+        )
+    }
+}
+
+impl<K, H> QueryFilter<K> for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.indices(key)
+            .into_iter()
+            .all(|index| self.counter(index) > 0)
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn insert(&mut self, key: K) {
+        for index in self.indices(&key) {
+            let count = self.counter(index);
+            if count < MAX_COUNT {
+                self.set_counter(index, count + 1);
+                self.saturated += (count + 1 == MAX_COUNT) as usize;
+            }
+        }
+    }
+}
+
+impl<K, H> RemovableQueryFilter<K> for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Removes a key, which must have been inserted (see the [module
+    /// documentation](self)). Saturated counters are left as they are.
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.decrement(&self.indices(key));
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn clear(&mut self) {
+        self.words.fill(0);
+        self.saturated = 0;
+    }
+}
+
+impl<K, H> FilterStats for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn slot_count(&self) -> usize {
+        self.counter_count
+    }
+
+    fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    fn fill_ratio(&self) -> f64 {
+        self.fill_ratio()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.size_in_bytes()
+    }
+}
+
+/// Serialized form of a counting Bloom filter.
+///
+/// Counters are packed as in memory, 16 to a little-endian word.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "CountingBloomFilter")]
+struct SerdeCountingBloomFilter<H> {
+    counter_count: usize,
+    hash_count: usize,
+    hasher: H,
+    counters: crate::storage::SerdeBits,
+}
+
+#[cfg(feature = "serde")]
+impl<K, H> serde::Serialize for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeCountingBloomFilter {
+            counter_count: self.counter_count,
+            hash_count: self.k,
+            hasher: &self.hasher,
+            counters: crate::storage::SerdeBits::from_words(&self.words),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, H> serde::Deserialize<'de> for CountingBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeCountingBloomFilter::<H>::deserialize(deserializer)?;
+        if filter.counter_count == 0 {
+            return Err(D::Error::custom("no counters"));
+        }
+        let words = filter
+            .counters
+            .into_words(filter.counter_count.div_ceil(COUNTERS_PER_WORD))?;
+        let mut filter = Self {
+            words,
+            counter_count: filter.counter_count,
+            saturated: 0,
+            hasher: filter.hasher,
+            k: filter.hash_count,
+            phantom: PhantomData,
+        };
+        let tail = filter.counter_count % COUNTERS_PER_WORD;
+        if tail != 0
+            && filter
+                .words
+                .last()
+                .is_some_and(|word| word >> (tail * 4) != 0)
+        {
+            return Err(D::Error::custom("counters set past the counter count"));
+        }
+        filter.saturated = (0..filter.counter_count)
+            .filter(|&index| filter.counter(index) == MAX_COUNT)
+            .count();
+        Ok(filter)
+    }
+}
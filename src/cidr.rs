@@ -0,0 +1,146 @@
+//! IP prefix (CIDR) membership filter.
+//!
+//! A [`CidrFilter`] holds IP prefixes (e.g. `10.0.0.0/8`) and answers whether
+//! an address is covered by any of them, without expanding prefixes into
+//! their (possibly 2^96) addresses. Each prefix is stored in a Bloom filter
+//! as its masked address and length, and an address is looked up by masking
+//! it to every prefix length in use, and probing for each. A bitmap of used
+//! lengths keeps the number of probes to the handful of lengths a typical
+//! blocklist actually contains.
+//!
+//! Each probed length is a separate chance of a false positive, so lookups
+//! have a false positive rate of up to `lengths * fp_rate`.
+
+use {
+    crate::{
+        analysis::{optimal_bit_count, optimal_hash_count},
+        hash::ProbeHasher,
+    },
+    fixedbitset::FixedBitSet,
+    hash_iter::HashIterHasher,
+    std::net::IpAddr,
+};
+
+/// Bloom filter of IP prefixes, answering address coverage queries.
+pub struct CidrFilter<H = ProbeHasher> {
+    bits: FixedBitSet,
+    /// Prefix lengths in use, for IPv4 (`0..=32`) and IPv6 (`0..=128`).
+    lengths: [FixedBitSet; 2],
+    hasher: H,
+    k: usize,
+}
+
+impl CidrFilter {
+    /// Creates a new filter for a desired number of prefixes and false
+    /// positive rate (per probed prefix length).
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<H> CidrFilter<H>
+where
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter for a desired number of prefixes and false
+    /// positive rate (per probed prefix length), and a given hasher.
+    pub fn with_hasher(capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        let bit_count = optimal_bit_count(capacity, fp_rate);
+        Self {
+            bits: FixedBitSet::with_capacity(bit_count),
+            lengths: [
+                FixedBitSet::with_capacity(33),
+                FixedBitSet::with_capacity(129),
+            ],
+            hasher,
+            k: optimal_hash_count(capacity, bit_count),
+        }
+    }
+
+    /// Inserts the prefix of `len` leading bits of an address. Bits past the
+    /// prefix are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` exceeds the address width (32 for IPv4, 128 for IPv6).
+    pub fn insert(&mut self, addr: IpAddr, len: u8) {
+        let key = key(addr, len);
+        self.lengths[key.0 as usize].insert(len as usize);
+        let bit_count = self.bits.len() as u64;
+        for hash in self.hasher.hash_iter(&key, self.k) {
+            self.bits.insert((hash % bit_count) as usize);
+        }
+    }
+
+    /// Returns `true` if the prefix is believed to have been inserted (as
+    /// is, not merely covered by a shorter one).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `len` exceeds the address width (32 for IPv4, 128 for IPv6).
+    pub fn contains_prefix(&self, addr: IpAddr, len: u8) -> bool {
+        let key = key(addr, len);
+        self.lengths[key.0 as usize][len as usize] && self.contains_key(key)
+    }
+
+    /// Returns `true` if the address is believed to be covered by any of the
+    /// inserted prefixes.
+    pub fn covers(&self, addr: IpAddr) -> bool {
+        self.matching_len(addr).is_some()
+    }
+
+    /// Returns the length of the longest inserted prefix believed to cover
+    /// the address, if any.
+    pub fn matching_len(&self, addr: IpAddr) -> Option<u8> {
+        let family = family(addr);
+        self.lengths[family]
+            .ones()
+            .rev()
+            .map(|len| len as u8)
+            .find(|&len| self.contains_key(key(addr, len)))
+    }
+
+    /// Returns the number of distinct prefix lengths in use, for IPv4 and
+    /// IPv6 respectively, i.e. the number of probes per lookup.
+    pub fn length_count(&self) -> (usize, usize) {
+        (
+            self.lengths[0].count_ones(..),
+            self.lengths[1].count_ones(..),
+        )
+    }
+
+    /// Removes all prefixes.
+    pub fn clear(&mut self) {
+        self.bits.clear();
+        self.lengths.iter_mut().for_each(FixedBitSet::clear);
+    }
+
+    fn contains_key(&self, key: (u8, u128, u8)) -> bool {
+        let bit_count = self.bits.len() as u64;
+        self.hasher
+            .hash_iter(&key, self.k)
+            .all(|hash| self.bits[(hash % bit_count) as usize])
+    }
+}
+
+/// Returns the index of an address' family: `0` for IPv4, `1` for IPv6.
+fn family(addr: IpAddr) -> usize {
+    match addr {
+        IpAddr::V4(_) => 0,
+        IpAddr::V6(_) => 1,
+    }
+}
+
+/// Returns the key of a prefix: family, masked address, and length.
+fn key(addr: IpAddr, len: u8) -> (u8, u128, u8) {
+    let (bits, width) = match addr {
+        IpAddr::V4(addr) => (u32::from(addr) as u128, 32),
+        IpAddr::V6(addr) => (u128::from(addr), 128),
+    };
+    assert!(len <= width, "prefix length {len} exceeds {width} bits");
+    let masked = match len {
+        0 => 0,
+        len => bits >> (width - len),
+    };
+    (family(addr) as u8, masked, len)
+}
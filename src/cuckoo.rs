@@ -0,0 +1,650 @@
+//! Cuckoo filter.
+//!
+//! Following [Cuckoo Filter: Practically Better Than Bloom, 2014][1]: each
+//! key is reduced to a short fingerprint, stored in one of two candidate
+//! buckets of a few slots each. The second bucket is derived from the first
+//! one and the fingerprint alone (partial-key cuckoo hashing), so stored
+//! fingerprints can be moved between their buckets to make room, without
+//! knowing their keys. Unlike Bloom filters, cuckoo filters support removal:
+//! a key's fingerprint is simply deleted from its bucket.
+//!
+//! Removing a key that was never inserted may delete the fingerprint of
+//! another key that shares it, which turns that key into a false negative:
+//! only remove keys known to be in the filter.
+//!
+//! Filters can be persisted alongside the data they summarize in a stable,
+//! versioned binary format, see [`to_bytes`](CuckooFilter::to_bytes).
+//!
+//! [1]: https://www.cs.cmu.edu/~dga/papers/cuckoo-conext2014.pdf
+
+use {
+    crate::{
+        analysis::{check_fp_rate, cuckoo_fingerprint_bits, cuckoo_max_load_factor},
+        hash::ProbeHasher,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+        RemovableQueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+    xxhash_rust::xxh3::xxh3_64,
+};
+
+/// Default number of slots per bucket.
+pub const DEFAULT_BUCKET_SIZE: usize = 4;
+
+/// Largest supported fingerprint size, in bits.
+pub const MAX_FINGERPRINT_BITS: u32 = 16;
+
+/// Largest supported number of slots per bucket.
+pub const MAX_BUCKET_SIZE: usize = 8;
+
+/// Number of fingerprints moved around before an insert gives up.
+const MAX_KICKS: usize = 500;
+
+/// Magic bytes opening an encoded cuckoo filter.
+const MAGIC: [u8; 4] = *b"MQCF";
+
+/// Version of the encoded cuckoo filter format.
+pub const VERSION: u8 = 1;
+
+/// Size of the header preceding the slots of an encoded filter.
+const HEADER_SIZE: usize = 34;
+
+/// Key whose hash identifies the hasher of an encoded filter.
+const FINGERPRINT_KEY: &str = "mqfilters";
+
+/// Cuckoo filter, supporting removal.
+pub struct CuckooFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    /// Fingerprints, `bucket_size` slots per bucket, zero marking an empty
+    /// slot.
+    slots: Vec<u16>,
+    bucket_size: usize,
+    fingerprint_bits: u32,
+    len: usize,
+    /// Fingerprint evicted by a failed insert, and one of its buckets.
+    victim: Option<(usize, u16)>,
+    /// State of the generator picking fingerprints to evict.
+    state: u64,
+    hasher: H,
+    phantom: PhantomData<K>,
+}
+
+impl<K> CuckooFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate,
+    /// with buckets of [`DEFAULT_BUCKET_SIZE`] slots.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rate is not within `0..1` (exclusive), or calls for
+    /// more than [`MAX_FINGERPRINT_BITS`] bits per fingerprint, see
+    /// [`with_capacity_and_hasher`](CuckooFilter::with_capacity_and_hasher).
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate)
+    }
+
+    /// Creates a new filter with a desired capacity and false positive rate,
+    /// with buckets of [`DEFAULT_BUCKET_SIZE`] slots.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the rate is not within `0..1` (exclusive), or calls for
+    /// more than [`MAX_FINGERPRINT_BITS`] bits per fingerprint, see
+    /// [`with_capacity_and_hasher`](CuckooFilter::with_capacity_and_hasher).
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
+            .expect("false positive rate is valid")
+    }
+
+    /// Creates a new filter with a desired capacity, fingerprint size (in
+    /// bits), and number of slots per bucket.
+    ///
+    /// The false positive rate is about `2 * bucket_size / 2^fingerprint_bits`.
+    /// Fails unless the fingerprint size is within
+    /// `1..=MAX_FINGERPRINT_BITS`, and the bucket size within
+    /// `1..=MAX_BUCKET_SIZE`.
+    pub fn with_params(
+        capacity: usize,
+        fingerprint_bits: u32,
+        bucket_size: usize,
+    ) -> QueryFilterResult<Self> {
+        Self::with_params_and_hasher(
+            capacity,
+            fingerprint_bits,
+            bucket_size,
+            ProbeHasher::default(),
+        )
+    }
+
+    /// Decodes a filter encoded by [`to_bytes`](CuckooFilter::to_bytes).
+    ///
+    /// Fails if the data is truncated or corrupted, is of another format
+    /// version, or was encoded by a filter using a hasher other than the
+    /// default one (see [`from_bytes_with_hasher`]).
+    ///
+    /// [`from_bytes_with_hasher`]: CuckooFilter::from_bytes_with_hasher
+    pub fn from_bytes(bytes: &[u8]) -> QueryFilterResult<Self> {
+        Self::from_bytes_with_hasher(bytes, ProbeHasher::default())
+    }
+}
+
+impl<K, H> CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter with a desired capacity, false positive rate,
+    /// and hasher, with buckets of [`DEFAULT_BUCKET_SIZE`] slots.
+    ///
+    /// The fingerprint size is the smallest meeting the rate, see
+    /// [`cuckoo_fingerprint_bits`]. Fails with
+    /// [`InvalidFpRate`](QueryFilterError::InvalidFpRate) unless the rate is
+    /// within `0..1` (exclusive), and met by [`MAX_FINGERPRINT_BITS`]-bit
+    /// fingerprints (about `1.2e-4` and above).
+    pub fn with_capacity_and_hasher(
+        capacity: usize,
+        fp_rate: f64,
+        hasher: H,
+    ) -> QueryFilterResult<Self> {
+        check_fp_rate(fp_rate)?;
+        let fingerprint_bits = cuckoo_fingerprint_bits(fp_rate, DEFAULT_BUCKET_SIZE);
+        if fingerprint_bits > MAX_FINGERPRINT_BITS {
+            return Err(QueryFilterError::InvalidFpRate(fp_rate));
+        }
+        Self::with_params_and_hasher(capacity, fingerprint_bits, DEFAULT_BUCKET_SIZE, hasher)
+    }
+
+    /// Creates a new filter with a desired capacity, fingerprint size (in
+    /// bits), number of slots per bucket, and hasher.
+    ///
+    /// See [`with_params`](CuckooFilter::with_params).
+    pub fn with_params_and_hasher(
+        capacity: usize,
+        fingerprint_bits: u32,
+        bucket_size: usize,
+        hasher: H,
+    ) -> QueryFilterResult<Self> {
+        if !(1..=MAX_FINGERPRINT_BITS).contains(&fingerprint_bits) {
+            return Err(QueryFilterError::InvalidFingerprintBits {
+                bits: fingerprint_bits,
+                max: MAX_FINGERPRINT_BITS,
+            });
+        }
+        if !(1..=MAX_BUCKET_SIZE).contains(&bucket_size) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid bucket size: {bucket_size} slots is not within 1..={MAX_BUCKET_SIZE}"
+            )));
+        }
+        // Bucket count is a power of two, so that alternate buckets are
+        // derived by XOR within range.
+        let max_load = cuckoo_max_load_factor(bucket_size);
+        let buckets = (capacity as f64 / (bucket_size as f64 * max_load)).ceil() as usize;
+        let buckets = buckets.max(1).next_power_of_two();
+        Ok(Self {
+            slots: vec![0; buckets * bucket_size],
+            bucket_size,
+            fingerprint_bits,
+            len: 0,
+            victim: None,
+            state: 0,
+            hasher,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the hasher used to hash keys.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the number of stored fingerprints.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no fingerprints are stored.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the total number of slots.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the fraction of occupied slots.
+    pub fn load_factor(&self) -> f64 {
+        self.len as f64 / self.slots.len() as f64
+    }
+
+    /// Returns the load factor the filter is guaranteed to reach, past which
+    /// inserts fail: the one reliably reached with buckets of this size, see
+    /// [`cuckoo_max_load_factor`] (50% for single-slot buckets, 84% for two
+    /// slots, 95% for four, and 98% for eight).
+    pub fn max_load_factor(&self) -> f64 {
+        cuckoo_max_load_factor(self.bucket_size)
+    }
+
+    /// Returns the number of keys the filter is guaranteed to hold, at its
+    /// [maximum load factor](CuckooFilter::max_load_factor).
+    pub fn capacity(&self) -> usize {
+        (self.max_load_factor() * self.slots.len() as f64) as usize
+    }
+
+    /// Returns the fingerprint size, in bits.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fingerprint_bits
+    }
+
+    /// Returns the number of slots per bucket.
+    pub fn bucket_size(&self) -> usize {
+        self.bucket_size
+    }
+
+    /// Returns the memory used by the filter's slots, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.slots.len() * 2
+    }
+
+    /// Encodes the filter in a stable binary format, for persisting it.
+    ///
+    /// The format (little-endian) is:
+    ///
+    /// - magic `MQCF` and version byte ([`VERSION`]),
+    /// - fingerprint size in bits (`u8`), bucket size in slots (`u8`), bucket
+    ///   count (`u64`), and the hash of a fixed key (`u64`), so that a
+    ///   mismatched hasher is detected on decoding,
+    /// - whether a fingerprint is held aside (`u8`, see
+    ///   [`try_insert`](CuckooFilter::try_insert)), then its bucket (`u64`) and
+    ///   fingerprint (`u16`), zero if none is,
+    /// - the slots (`u16` each, zero if empty), bucket by bucket,
+    /// - an XXH3 checksum (`u64`) of everything before it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.slots.len() * 2 + 8);
+        bytes.extend_from_slice(&MAGIC);
+        bytes.push(VERSION);
+        bytes.push(self.fingerprint_bits as u8);
+        bytes.push(self.bucket_size as u8);
+        bytes.extend_from_slice(&(self.bucket_count() as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.hash(FINGERPRINT_KEY).to_le_bytes());
+        let (bucket, fingerprint) = self.victim.unwrap_or_default();
+        bytes.push(self.victim.is_some() as u8);
+        bytes.extend_from_slice(&(bucket as u64).to_le_bytes());
+        bytes.extend_from_slice(&fingerprint.to_le_bytes());
+        for slot in &self.slots {
+            bytes.extend_from_slice(&slot.to_le_bytes());
+        }
+        bytes.extend_from_slice(&xxh3_64(&bytes).to_le_bytes());
+        bytes
+    }
+
+    /// Decodes a filter encoded by [`to_bytes`](CuckooFilter::to_bytes), with
+    /// the hasher it was encoded with.
+    ///
+    /// See [`from_bytes`](CuckooFilter::from_bytes).
+    pub fn from_bytes_with_hasher(bytes: &[u8], hasher: H) -> QueryFilterResult<Self> {
+        let invalid =
+            |reason: &str| QueryFilterError::Other(format!("invalid cuckoo filter: {reason}"));
+        let (body, checksum) = bytes
+            .split_last_chunk::<8>()
+            .filter(|(body, _)| body.len() >= HEADER_SIZE)
+            .ok_or_else(|| invalid("truncated header"))?;
+        if body[..4] != MAGIC {
+            return Err(invalid("bad magic"));
+        }
+        if body[4] != VERSION {
+            return Err(invalid(&format!("unsupported version {}", body[4])));
+        }
+        if xxh3_64(body) != u64::from_le_bytes(*checksum) {
+            return Err(invalid("checksum mismatch"));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(body[at..at + 8].try_into().unwrap());
+        let victim = (body[23] != 0).then(|| {
+            (
+                u64_at(24) as usize,
+                u16::from_le_bytes([body[32], body[33]]),
+            )
+        });
+        let slots = body[HEADER_SIZE..]
+            .chunks(2)
+            .map(|slot| slot.try_into().map(u16::from_le_bytes))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| invalid("truncated slots"))?;
+        if slots.len() as u64 != u64_at(7).saturating_mul(body[6] as u64) {
+            return Err(invalid("slot count does not match bucket count"));
+        }
+        let filter = Self::from_parts(body[5] as u32, body[6] as usize, victim, hasher, slots)?;
+        if filter.hash(FINGERPRINT_KEY) != u64_at(15) {
+            return Err(invalid("hasher mismatch"));
+        }
+        Ok(filter)
+    }
+
+    /// Creates a filter from its stored state, checking it is consistent.
+    fn from_parts(
+        fingerprint_bits: u32,
+        bucket_size: usize,
+        victim: Option<(usize, u16)>,
+        hasher: H,
+        slots: Vec<u16>,
+    ) -> QueryFilterResult<Self> {
+        let invalid = |reason: &str| QueryFilterError::Other(reason.to_owned());
+        let empty = Self::with_params_and_hasher(0, fingerprint_bits, bucket_size, hasher)?;
+        let buckets = slots.len() / bucket_size;
+        if !slots.len().is_multiple_of(bucket_size) || !buckets.is_power_of_two() {
+            return Err(invalid("slot count is not a power of two buckets"));
+        }
+        let max = (1u32 << fingerprint_bits) - 1;
+        let fingerprints = slots.iter().chain(victim.as_ref().map(|(_, f)| f));
+        if fingerprints
+            .clone()
+            .any(|&fingerprint| fingerprint as u32 > max)
+        {
+            return Err(invalid("fingerprints wider than the fingerprint size"));
+        }
+        if victim.is_some_and(|(bucket, fingerprint)| bucket >= buckets || fingerprint == 0) {
+            return Err(invalid("invalid victim"));
+        }
+        Ok(Self {
+            len: fingerprints
+                .filter(|&&fingerprint| fingerprint != 0)
+                .count(),
+            slots,
+            victim,
+            ..empty
+        })
+    }
+
+    /// Inserts a key, failing (without inserting it) with
+    /// [`Full`](QueryFilterError::Full) if the filter is full.
+    ///
+    /// The filter is full once it holds [`capacity`](CuckooFilter::capacity)
+    /// keys. It may also fill up earlier (though unlikely) if an insert finds
+    /// no room even after moving fingerprints around: that insert succeeds,
+    /// with one fingerprint (possibly of another key) held aside, and further
+    /// inserts fail until a key is removed.
+    pub fn try_insert(&mut self, key: K) -> QueryFilterResult<()> {
+        let (bucket, fingerprint) = self.locate(&key);
+        self.insert_located(bucket, fingerprint)
+    }
+
+    /// Inserts all keys, or none of them: if inserting a key fails (see
+    /// [`try_insert`](CuckooFilter::try_insert)), the keys of the batch
+    /// inserted before it are removed again.
+    ///
+    /// The filter is then left holding the same fingerprints as before,
+    /// though possibly moved to their other buckets.
+    pub fn insert_batch_atomic(
+        &mut self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> QueryFilterResult<()> {
+        let mut inserted = Vec::new();
+        for key in keys {
+            let (bucket, fingerprint) = self.locate(&key);
+            if let Err(err) = self.insert_located(bucket, fingerprint) {
+                for &(bucket, fingerprint) in inserted.iter().rev() {
+                    self.remove_located(bucket, fingerprint);
+                }
+                event!(rolled_back = inserted.len(); "rolled back cuckoo filter batch");
+                return Err(err);
+            }
+            inserted.push((bucket, fingerprint));
+        }
+        Ok(())
+    }
+
+    /// Inserts a fingerprint into one of its buckets, see
+    /// [`try_insert`](CuckooFilter::try_insert).
+    fn insert_located(&mut self, bucket: usize, fingerprint: u16) -> QueryFilterResult<()> {
+        if self.len >= self.capacity() {
+            return Err(QueryFilterError::Full);
+        }
+        // Another walk may find room for a fingerprint left out earlier.
+        if let Some((victim_bucket, victim)) = self.victim {
+            self.victim = self.place(victim_bucket, victim);
+            if self.victim.is_some() {
+                return Err(QueryFilterError::Full);
+            }
+        }
+        self.victim = self.place(bucket, fingerprint);
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Removes a fingerprint from one of its buckets, if found there.
+    fn remove_located(&mut self, bucket: usize, fingerprint: u16) {
+        let alternate = self.alternate(bucket, fingerprint);
+        if let Some((victim_bucket, victim)) = self.victim {
+            if victim == fingerprint && (victim_bucket == bucket || victim_bucket == alternate) {
+                self.victim = None;
+                self.len -= 1;
+                return;
+            }
+        }
+        let size = self.bucket_size;
+        for candidate in [bucket, alternate] {
+            let slots = &mut self.slots[candidate * size..(candidate + 1) * size];
+            if let Some(slot) = slots.iter_mut().find(|slot| **slot == fingerprint) {
+                *slot = 0;
+                self.len -= 1;
+                // Room was made, possibly for the evicted fingerprint.
+                if let Some((victim_bucket, victim)) = self.victim {
+                    self.victim = self.place(victim_bucket, victim);
+                }
+                return;
+            }
+        }
+    }
+
+    /// Stores a fingerprint in one of its buckets, moving other fingerprints
+    /// around if needed. Returns the fingerprint left out (and one of its
+    /// buckets) if no room was found.
+    fn place(&mut self, bucket: usize, fingerprint: u16) -> Option<(usize, u16)> {
+        let alternate = self.alternate(bucket, fingerprint);
+        if self.put(bucket, fingerprint) || self.put(alternate, fingerprint) {
+            return None;
+        }
+
+        let mut bucket = if self.next_random() & 1 == 0 {
+            bucket
+        } else {
+            alternate
+        };
+        let mut fingerprint = fingerprint;
+        for _ in 0..MAX_KICKS {
+            let slot = bucket * self.bucket_size + self.next_random() as usize % self.bucket_size;
+            std::mem::swap(&mut fingerprint, &mut self.slots[slot]);
+            bucket = self.alternate(bucket, fingerprint);
+            if self.put(bucket, fingerprint) {
+                return None;
+            }
+        }
+        Some((bucket, fingerprint))
+    }
+
+    fn hash<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.hasher.hash_iter(key, 1).next().unwrap_or_default()
+    }
+
+    /// Returns the bucket and fingerprint of a key.
+    fn locate<Q: Hash + ?Sized>(&self, key: &Q) -> (usize, u16) {
+        let hash = self.hash(key);
+        // Low bits select the bucket, high ones make the fingerprint, which
+        // is never zero (the empty slot marker).
+        let bucket = hash as usize & (self.bucket_count() - 1);
+        let fingerprint = (hash >> 32) as u32 & ((1 << self.fingerprint_bits) - 1);
+        (bucket, fingerprint.max(1) as u16)
+    }
+
+    /// Returns the other candidate bucket of a fingerprint stored in a given
+    /// one.
+    fn alternate(&self, bucket: usize, fingerprint: u16) -> usize {
+        // Murmur3 finalizer, so that similar fingerprints land far apart.
+        let mut z = fingerprint as u64;
+        z = (z ^ (z >> 33)).wrapping_mul(0xff51afd7ed558ccd);
+        z = (z ^ (z >> 33)).wrapping_mul(0xc4ceb9fe1a85ec53);
+        (bucket ^ z as usize) & (self.bucket_count() - 1)
+    }
+
+    fn bucket_count(&self) -> usize {
+        self.slots.len() / self.bucket_size
+    }
+
+    fn bucket(&self, bucket: usize) -> &[u16] {
+        &self.slots[bucket * self.bucket_size..(bucket + 1) * self.bucket_size]
+    }
+
+    /// Stores a fingerprint in a free slot of a bucket, if any.
+    fn put(&mut self, bucket: usize, fingerprint: u16) -> bool {
+        let size = self.bucket_size;
+        let slots = &mut self.slots[bucket * size..(bucket + 1) * size];
+        match slots.iter_mut().find(|slot| **slot == 0) {
+            Some(slot) => {
+                *slot = fingerprint;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the next value of the SplitMix64 generator picking evictions.
+    fn next_random(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl<K, H> QueryFilter<K> for CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (bucket, fingerprint) = self.locate(key);
+        let alternate = self.alternate(bucket, fingerprint);
+        self.bucket(bucket).contains(&fingerprint)
+            || self.bucket(alternate).contains(&fingerprint)
+            || self.victim.is_some_and(|(victim_bucket, victim)| {
+                victim == fingerprint && (victim_bucket == bucket || victim_bucket == alternate)
+            })
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Inserts a key.
+    ///
+    /// Tables are sized for the capacity they are created with at their
+    /// [maximum load factor](CuckooFilter::max_load_factor), so that inserts
+    /// within it find room (but for single-slot buckets, where moving
+    /// fingerprints around may run into a cycle at any load).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the filter is full, i.e. holds
+    /// [`capacity`](CuckooFilter::capacity) keys (at least the capacity it
+    /// was created with) or found no room for a key, see
+    /// [`try_insert`](CuckooFilter::try_insert) for a fallible version.
+    fn insert(&mut self, key: K) {
+        self.try_insert(key).expect("cuckoo filter is full");
+    }
+}
+
+impl<K, H> RemovableQueryFilter<K> for CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Removes a key, which must have been inserted (see the [module
+    /// documentation](self)). Does nothing if the key is not found.
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (bucket, fingerprint) = self.locate(key);
+        self.remove_located(bucket, fingerprint);
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn clear(&mut self) {
+        self.slots.fill(0);
+        self.len = 0;
+        self.victim = None;
+    }
+}
+
+/// Serialized form of a cuckoo filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "CuckooFilter")]
+struct SerdeCuckooFilter<H, V> {
+    fingerprint_bits: u32,
+    bucket_size: usize,
+    victim: Option<(usize, u16)>,
+    hasher: H,
+    slots: V,
+}
+
+#[cfg(feature = "serde")]
+impl<K, H> serde::Serialize for CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeCuckooFilter {
+            fingerprint_bits: self.fingerprint_bits,
+            bucket_size: self.bucket_size,
+            victim: self.victim,
+            hasher: &self.hasher,
+            slots: &self.slots,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, H> serde::Deserialize<'de> for CuckooFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeCuckooFilter::<H, Vec<u16>>::deserialize(deserializer)?;
+        Self::from_parts(
+            filter.fingerprint_bits,
+            filter.bucket_size,
+            filter.victim,
+            filter.hasher,
+            filter.slots,
+        )
+        .map_err(D::Error::custom)
+    }
+}
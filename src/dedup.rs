@@ -0,0 +1,188 @@
+//! Windowed deduplication of event streams.
+//!
+//! Stream consumers with at-least-once delivery see the same event again
+//! after retries and rebalances, typically shortly after the original one.
+//! [`WindowedDedup`] drops such redeliveries: events are assigned to
+//! tumbling event-time windows by their timestamp, and each window keeps a
+//! Bloom filter of the keys it has seen. A watermark (the event time below
+//! which no more events are expected) finalizes windows: once it passes the
+//! end of a window, the window's filter is dropped and its statistics
+//! reported, and events still arriving for it are flagged as late.
+//!
+//! Deduplication is exactly-once-ish: a false positive of a window's filter
+//! makes a new event look like a duplicate, at the configured rate.
+
+use {
+    crate::{hash::ProbeHasher, BloomFilter, InsertableQueryFilter, QueryFilter},
+    hash_iter::HashIterHasher,
+    std::{collections::BTreeMap, hash::Hash},
+};
+
+/// Outcome of observing an event with a [`WindowedDedup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Observation {
+    /// The event was not seen before in its window.
+    New,
+    /// The event was (probably) seen before in its window.
+    Duplicate,
+    /// The event's window was already finalized by the watermark.
+    Late,
+}
+
+/// Statistics of a single window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowStats {
+    /// Event time the window starts at (inclusive).
+    pub start: u64,
+    /// Event time the window ends at (exclusive).
+    pub end: u64,
+    /// Number of events observed in the window, duplicates included.
+    pub events: usize,
+    /// Number of events dropped as duplicates.
+    pub duplicates: usize,
+    /// Estimated false positive rate of the window's filter, i.e. the
+    /// fraction of new events mistaken for duplicates.
+    pub fp_rate: f64,
+}
+
+/// Open window: its filter and counts.
+struct Window<K, H>
+where
+    K: Eq + Hash,
+{
+    filter: BloomFilter<K, H>,
+    events: usize,
+    duplicates: usize,
+}
+
+/// Deduplicator of events over tumbling event-time windows, finalized by a
+/// watermark.
+pub struct WindowedDedup<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    windows: BTreeMap<u64, Window<K, H>>,
+    window_len: u64,
+    watermark: u64,
+    late: usize,
+    capacity: usize,
+    fp_rate: f64,
+    hasher: H,
+}
+
+impl<K> WindowedDedup<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new deduplicator with windows spanning `window_len` units
+    /// of event time, each sized for a desired number of distinct events and
+    /// false positive rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_len` is zero.
+    pub fn new(window_len: u64, capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(window_len, capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> WindowedDedup<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Creates a new deduplicator with windows spanning `window_len` units
+    /// of event time, each sized for a desired number of distinct events and
+    /// false positive rate, and a given hasher.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `window_len` is zero.
+    pub fn with_hasher(window_len: u64, capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        assert!(window_len > 0, "window length must be positive");
+        Self {
+            windows: BTreeMap::new(),
+            window_len,
+            watermark: 0,
+            late: 0,
+            capacity,
+            fp_rate,
+            hasher,
+        }
+    }
+
+    /// Observes an event with a given key and event time, telling whether it
+    /// is new within its window.
+    pub fn observe(&mut self, key: K, event_time: u64) -> Observation {
+        let start = event_time - event_time % self.window_len;
+        if start.saturating_add(self.window_len) <= self.watermark {
+            self.late += 1;
+            return Observation::Late;
+        }
+
+        let (capacity, fp_rate, hasher) = (self.capacity, self.fp_rate, &self.hasher);
+        let window = self.windows.entry(start).or_insert_with(|| Window {
+            filter: BloomFilter::with_capacity_and_hasher(capacity, fp_rate, hasher.clone()),
+            events: 0,
+            duplicates: 0,
+        });
+        window.events += 1;
+        if window.filter.contains(&key) {
+            window.duplicates += 1;
+            Observation::Duplicate
+        } else {
+            window.filter.insert(key);
+            Observation::New
+        }
+    }
+
+    /// Advances the watermark, finalizing and dropping the windows ending at
+    /// or before it. Returns the final statistics of those windows, oldest
+    /// first.
+    ///
+    /// The watermark never moves back: an older watermark is ignored.
+    pub fn advance_watermark(&mut self, watermark: u64) -> Vec<WindowStats> {
+        self.watermark = self.watermark.max(watermark);
+        let mut finalized = Vec::new();
+        while let Some(entry) = self.windows.first_entry() {
+            if entry.key().saturating_add(self.window_len) > self.watermark {
+                break;
+            }
+            let (start, window) = entry.remove_entry();
+            finalized.push(self.stats(start, &window));
+        }
+        event!(
+            watermark = self.watermark,
+            finalized = finalized.len();
+            "advanced deduplication watermark"
+        );
+        finalized
+    }
+
+    /// Returns the current watermark.
+    pub fn watermark(&self) -> u64 {
+        self.watermark
+    }
+
+    /// Returns the number of events flagged as late so far.
+    pub fn late_events(&self) -> usize {
+        self.late
+    }
+
+    /// Returns the statistics of the open windows, oldest first.
+    pub fn open_windows(&self) -> impl Iterator<Item = WindowStats> + '_ {
+        self.windows
+            .iter()
+            .map(|(&start, window)| self.stats(start, window))
+    }
+
+    fn stats(&self, start: u64, window: &Window<K, H>) -> WindowStats {
+        WindowStats {
+            start,
+            end: start.saturating_add(self.window_len),
+            events: window.events,
+            duplicates: window.duplicates,
+            fp_rate: window.filter.approx_fp_rate(),
+        }
+    }
+}
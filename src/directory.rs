@@ -0,0 +1,228 @@
+//! Directory of on-disk Bloom filters, opened lazily.
+//!
+//! Stores keeping their data in immutable segments (SSTables, daily
+//! partitions, ...) usually keep a Bloom filter per segment. A
+//! [`FilterDirectory`] manages such filters as the files of a directory,
+//! named after the id of their segment:
+//!
+//! - `<id>.mqbb` files, encoded by [`BloomFilter::to_bytes`], are read whole,
+//! - with the `mmap` feature, `<id>.mqbm` files, created by
+//!   `BloomFilter::create_mmap`, are mapped into memory.
+//!
+//! Filters are only opened once queried, and at most a given number of them
+//! are kept open, closing the least recently used first: a store with years
+//! of daily segments only pays for the ones its queries touch.
+
+use {
+    crate::{BloomFilter, QueryFilter, QueryFilterError},
+    std::{
+        borrow::Borrow,
+        collections::BTreeMap,
+        fs,
+        hash::Hash,
+        io,
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex, PoisonError},
+    },
+};
+
+#[cfg(all(feature = "mmap", unix))]
+use crate::{hash::ProbeHasher, storage::MmapBitSet};
+
+/// Extension of filter files read whole.
+const BYTES_EXTENSION: &str = "mqbb";
+
+/// Extension of memory-mapped filter files.
+#[cfg(all(feature = "mmap", unix))]
+const MMAP_EXTENSION: &str = "mqbm";
+
+/// Open filter of a segment.
+enum Segment<K>
+where
+    K: Eq + Hash,
+{
+    Bytes(BloomFilter<K>),
+    #[cfg(all(feature = "mmap", unix))]
+    Mapped(BloomFilter<K, ProbeHasher, MmapBitSet>),
+}
+
+impl<K: Eq + Hash> Segment<K> {
+    /// Opens the filter file at `path`, by its extension.
+    fn open(path: &Path) -> io::Result<Self> {
+        #[cfg(all(feature = "mmap", unix))]
+        if path.extension().is_some_and(|ext| ext == MMAP_EXTENSION) {
+            return BloomFilter::open_mmap(path).map(Segment::Mapped);
+        }
+        BloomFilter::from_bytes(&fs::read(path)?)
+            .map(Segment::Bytes)
+            .map_err(|err| {
+                let reason = match err {
+                    QueryFilterError::Other(reason) => reason,
+                    err => err.to_string(),
+                };
+                io::Error::new(io::ErrorKind::InvalidData, reason)
+            })
+    }
+
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        match self {
+            Segment::Bytes(filter) => filter.contains(key),
+            #[cfg(all(feature = "mmap", unix))]
+            Segment::Mapped(filter) => filter.contains(key),
+        }
+    }
+}
+
+/// Bloom filters of segments, stored as files of a directory and opened on
+/// demand.
+pub struct FilterDirectory<K>
+where
+    K: Eq + Hash,
+{
+    path: PathBuf,
+    /// Filter files, by segment id.
+    files: BTreeMap<String, PathBuf>,
+    max_open: usize,
+    /// Open filters, the least recently used first.
+    open: Mutex<Vec<(String, Arc<Segment<K>>)>>,
+}
+
+impl<K> FilterDirectory<K>
+where
+    K: Eq + Hash,
+{
+    /// Lists the filter files of a directory, keeping at most `max_open` of
+    /// them open at a time.
+    ///
+    /// No filter is opened yet. Fails if the directory cannot be read, or
+    /// with [`InvalidInput`](io::ErrorKind::InvalidInput) if `max_open` is
+    /// zero.
+    pub fn open(path: impl AsRef<Path>, max_open: usize) -> io::Result<Self> {
+        if max_open == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "at least one filter must be kept open",
+            ));
+        }
+        let mut directory = Self {
+            path: path.as_ref().to_owned(),
+            files: BTreeMap::new(),
+            max_open,
+            open: Mutex::new(Vec::with_capacity(max_open)),
+        };
+        directory.refresh()?;
+        Ok(directory)
+    }
+
+    /// Lists the filter files of the directory again, picking up added
+    /// segments and dropping removed ones.
+    ///
+    /// Filters already open stay open (and are still queried) until closed
+    /// to make room for others, even if their file was replaced.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        let mut files = BTreeMap::new();
+        for entry in fs::read_dir(&self.path)? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|ext| ext.to_str());
+            #[cfg(all(feature = "mmap", unix))]
+            let known = matches!(extension, Some(BYTES_EXTENSION | MMAP_EXTENSION));
+            #[cfg(not(all(feature = "mmap", unix)))]
+            let known = extension == Some(BYTES_EXTENSION);
+            if let (true, Some(id)) = (known, path.file_stem().and_then(|id| id.to_str())) {
+                files.insert(id.to_owned(), path);
+            }
+        }
+        let open = self.open.get_mut().unwrap_or_else(PoisonError::into_inner);
+        open.retain(|(id, _)| files.contains_key(id));
+        self.files = files;
+        Ok(())
+    }
+
+    /// Returns the ids of the segments, in ascending order.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.files.keys().map(String::as_str)
+    }
+
+    /// Returns the number of segments.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns `true` if there are no segments.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Returns the number of filters currently open.
+    pub fn open_count(&self) -> usize {
+        self.open
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    /// Returns `true` if the filter of a segment may contain a key, opening
+    /// it if it is not open yet.
+    ///
+    /// Fails with [`NotFound`](io::ErrorKind::NotFound) if there is no such
+    /// segment, and with [`InvalidData`](io::ErrorKind::InvalidData) if its
+    /// file is not a valid filter.
+    pub fn contains<Q>(&self, id: &str, key: &Q) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        Ok(self.segment(id)?.contains(key))
+    }
+
+    /// Returns `true` if the filter of any segment may contain a key,
+    /// opening filters in segment order until one does.
+    pub fn contains_any_segment<Q>(&self, key: &Q) -> io::Result<bool>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for id in self.files.keys() {
+            if self.contains(id, key)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the filter of a segment, opening it if needed and marking it
+    /// as the most recently used.
+    fn segment(&self, id: &str) -> io::Result<Arc<Segment<K>>> {
+        let lock = || self.open.lock().unwrap_or_else(PoisonError::into_inner);
+        {
+            let mut open = lock();
+            if let Some(index) = open.iter().position(|(open_id, _)| open_id == id) {
+                let entry = open.remove(index);
+                let segment = Arc::clone(&entry.1);
+                open.push(entry);
+                return Ok(segment);
+            }
+        }
+
+        let path = self
+            .files
+            .get(id)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no segment {id}")))?;
+        // Opened without holding the lock, so that queries of open filters
+        // do not wait on the disk. Concurrent misses may both open the
+        // filter; only one copy is kept.
+        let segment = Arc::new(Segment::open(path)?);
+        event!(id, path:?; "opened segment filter");
+        let mut open = lock();
+        open.retain(|(open_id, _)| open_id != id);
+        if open.len() == self.max_open {
+            open.remove(0);
+        }
+        open.push((id.to_owned(), Arc::clone(&segment)));
+        Ok(segment)
+    }
+}
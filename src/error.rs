@@ -1,6 +1,49 @@
 /// Query filter error.
 #[derive(Debug, PartialEq, thiserror::Error)]
 pub enum QueryFilterError {
+    /// Static structure could not be built, even after retrying with
+    /// different seeds.
+    #[error("Construction failed after {0} attempts.")]
+    ConstructionFailed(usize),
+
+    /// Minimal perfect hash function could not place all keys within its
+    /// maximum number of levels.
+    #[error("Construction failed: keys left after {0} levels.")]
+    LevelsExhausted(usize),
+
+    /// The same key was supplied more than once, with conflicting values.
+    #[error("Duplicate key with conflicting values.")]
+    DuplicateKey,
+
+    /// Value does not fit into the configured number of bits.
+    #[error("Value {value} does not fit into {bits} bits.")]
+    ValueOutOfRange { value: u64, bits: u32 },
+
+    /// False positive rate is outside of the supported range, usually `(0,
+    /// 1)`.
+    #[error("Invalid false positive rate {0}.")]
+    InvalidFpRate(f64),
+
+    /// Fingerprint size is outside of the supported range, `1..=max` bits.
+    #[error("Invalid fingerprint size of {bits} bits, not within 1..={max}.")]
+    InvalidFingerprintBits { bits: u32, max: u32 },
+
+    /// Capacity is zero.
+    #[error("Capacity must be positive.")]
+    ZeroCapacity,
+
+    /// Parameters call for a structure larger than supported.
+    #[error("Size of {bits} bits exceeds the maximum of {max} bits.")]
+    TooLarge { bits: u64, max: u64 },
+
+    /// Filter has no room left for another key.
+    #[error("Filter is full.")]
+    Full,
+
+    /// Filters cannot be combined, as they differ in parameters.
+    #[error("Incompatible filters: {0}.")]
+    IncompatibleFilters(&'static str),
+
     /// Some error occurred.
     #[error("Some error occurred.")]
     Other(String),
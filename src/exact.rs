@@ -0,0 +1,130 @@
+//! Exact filter for `u32` keys.
+//!
+//! When keys are drawn from a dense-ish `u32` id space (row ids, user ids,
+//! document numbers), a compressed bitmap of the keys themselves often costs
+//! no more than a Bloom filter at a usable false positive rate, while giving
+//! exact answers. [`ExactU32Filter`] keeps keys in a [roaring bitmap][1]:
+//! dense ranges are stored as bitmaps or runs, sparse ones as sorted arrays,
+//! so memory adapts to the key distribution.
+//!
+//! [1]: https://roaringbitmap.org
+
+use {
+    crate::{ClearableQueryFilter, InsertableQueryFilter, QueryFilter, RemovableQueryFilter},
+    roaring::RoaringBitmap,
+    std::{
+        borrow::Borrow,
+        hash::{Hash, Hasher},
+    },
+};
+
+/// Exact (no false positives) filter over `u32` keys, backed by a roaring
+/// bitmap.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ExactU32Filter {
+    keys: RoaringBitmap,
+}
+
+impl ExactU32Filter {
+    /// Creates a new, empty filter.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Builds the filter from keys. Repeated keys are fine.
+    pub fn from_keys(keys: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            keys: keys.into_iter().collect(),
+        }
+    }
+
+    /// Returns the number of keys in the filter.
+    pub fn len(&self) -> usize {
+        self.keys.len() as usize
+    }
+
+    /// Returns `true` if the filter holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns an iterator over the keys, in ascending order.
+    pub fn keys(&self) -> impl Iterator<Item = u32> + '_ {
+        self.keys.iter()
+    }
+
+    /// Returns the approximate memory used by the filter, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.keys.serialized_size()
+    }
+}
+
+impl QueryFilter<u32> for ExactU32Filter {
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        u32: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.keys.contains(key_of(key))
+    }
+}
+
+impl InsertableQueryFilter<u32> for ExactU32Filter {
+    fn insert(&mut self, key: u32) {
+        self.keys.insert(key);
+    }
+}
+
+impl RemovableQueryFilter<u32> for ExactU32Filter {
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        u32: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.keys.remove(key_of(key));
+    }
+}
+
+impl ClearableQueryFilter<u32> for ExactU32Filter {
+    fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+impl FromIterator<u32> for ExactU32Filter {
+    fn from_iter<I: IntoIterator<Item = u32>>(keys: I) -> Self {
+        Self::from_keys(keys)
+    }
+}
+
+/// Recovers the `u32` key from its borrowed form.
+///
+/// The only borrowed form of `u32` is `u32` itself, but that can't be spelled
+/// out in the trait bounds. [`Borrow`] requires [`Hash`] on the borrowed form
+/// to match the owned one, so the key is recovered from what it feeds into a
+/// hasher.
+fn key_of<Q: Hash + ?Sized>(key: &Q) -> u32 {
+    let mut hasher = KeyRecorder(0);
+    key.hash(&mut hasher);
+    hasher.0
+}
+
+/// Hasher that records the last `u32` written into it.
+struct KeyRecorder(u32);
+
+impl Hasher for KeyRecorder {
+    fn finish(&self) -> u64 {
+        self.0 as u64
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // `u32` keys are always written with `write_u32`, see `key_of`.
+        if let Ok(bytes) = bytes.try_into() {
+            self.0 = u32::from_ne_bytes(bytes);
+        }
+    }
+
+    fn write_u32(&mut self, key: u32) {
+        self.0 = key;
+    }
+}
@@ -0,0 +1,348 @@
+//! Static binary fuse filter.
+//!
+//! Following [Binary Fuse Filters: Fast and Smaller Than Xor Filters,
+//! 2022][1]: like an [xor filter](crate::xor), a fingerprint is stored for
+//! each key of a fixed set, split across three slots whose xor yields it.
+//! Slots are picked within three consecutive segments out of many small
+//! ones, rather than out of three large ones, which lets construction
+//! succeed with far less slack: about `1.13 * r` bits per key (for larger
+//! sets) instead of `1.23 * r`, and a faster build.
+//!
+//! Keys may also be split across four slots instead (see [`Arity`]), for
+//! about `1.08 * r` bits per key, at the cost of a slower build and query.
+//!
+//! [1]: https://arxiv.org/abs/2201.01174
+
+use {
+    crate::{
+        peeling::{self, MAX_ATTEMPTS},
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+        ops::BitXor,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Largest segment length, so that segments stay cache-friendly.
+const MAX_SEGMENT_LENGTH: usize = 1 << 18;
+
+/// Fingerprint stored by a [`BinaryFuseFilter`].
+///
+/// Implemented for `u8`, `u16`, and `u32`, with false positive rates of
+/// about `2^-8`, `2^-16`, and `2^-32` respectively.
+pub trait Fingerprint: Copy + Default + Eq + BitXor<Output = Self> {
+    /// Derives the fingerprint from a key hash.
+    fn from_hash(hash: u64) -> Self;
+}
+
+macro_rules! impl_fingerprint {
+    ($($ty:ty),+) => {$(
+        impl Fingerprint for $ty {
+            fn from_hash(hash: u64) -> Self {
+                (hash ^ hash >> 32) as $ty
+            }
+        }
+    )+};
+}
+
+impl_fingerprint!(u8, u16, u32);
+
+/// Number of slots each key's fingerprint is split across.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    /// Three slots, for a faster build.
+    #[default]
+    Three,
+    /// Four slots, for about 5% less space on larger sets.
+    Four,
+}
+
+impl Arity {
+    fn slot_count(self) -> usize {
+        match self {
+            Self::Three => 3,
+            Self::Four => 4,
+        }
+    }
+}
+
+/// Binary fuse filter over a fixed set of keys, with fingerprints of type
+/// `F`.
+pub struct BinaryFuseFilter<K, F = u8>
+where
+    K: Eq + Hash,
+{
+    fingerprints: Vec<F>,
+    layout: Layout,
+    seed: u64,
+    phantom: PhantomData<K>,
+}
+
+impl<K, F> BinaryFuseFilter<K, F>
+where
+    K: Eq + Hash,
+    F: Fingerprint,
+{
+    /// Builds the filter from a set of distinct keys.
+    ///
+    /// # Panics
+    ///
+    /// Panics if construction fails, see
+    /// [`try_from_keys`](BinaryFuseFilter::try_from_keys).
+    pub fn from_keys(keys: impl IntoIterator<Item = K>) -> Self {
+        match Self::try_from_keys(keys) {
+            Ok(filter) => filter,
+            Err(error) => panic!("failed to build binary fuse filter: {error}"),
+        }
+    }
+
+    /// Builds the filter from a set of distinct keys, splitting each key's
+    /// fingerprint across three slots.
+    ///
+    /// Fails if some key is repeated, or (vanishingly unlikely) if
+    /// construction does not succeed within a bounded number of attempts.
+    pub fn try_from_keys(keys: impl IntoIterator<Item = K>) -> QueryFilterResult<Self> {
+        Self::with_arity(keys, Arity::Three)
+    }
+
+    /// Builds the filter from a set of distinct keys, splitting each key's
+    /// fingerprint across `arity` slots.
+    ///
+    /// See [`try_from_keys`](BinaryFuseFilter::try_from_keys).
+    pub fn with_arity(keys: impl IntoIterator<Item = K>, arity: Arity) -> QueryFilterResult<Self> {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let layout = Layout::new(keys.len(), arity);
+        for attempt in 0..MAX_ATTEMPTS {
+            let seed = peeling::seed(attempt);
+            let mut hashes = keys.iter().map(|key| hash(seed, key)).collect::<Vec<_>>();
+            hashes.sort_unstable();
+            if hashes.windows(2).any(|w| w[0] == w[1]) {
+                // Given a 64-bit hash, this is the same key rather than an
+                // actual collision.
+                return Err(QueryFilterError::DuplicateKey);
+            }
+
+            let fingerprints = match arity {
+                Arity::Three => assign::<3, F>(&hashes, layout),
+                Arity::Four => assign::<4, F>(&hashes, layout),
+            };
+            let Some(fingerprints) = fingerprints else {
+                continue;
+            };
+
+            event!(
+                keys = hashes.len(),
+                slots = layout.len(),
+                arity = arity.slot_count(),
+                attempts = attempt + 1;
+                "built binary fuse filter"
+            );
+            return Ok(Self {
+                fingerprints,
+                layout,
+                seed,
+                phantom: PhantomData,
+            });
+        }
+        Err(QueryFilterError::ConstructionFailed(MAX_ATTEMPTS))
+    }
+
+    /// Returns the number of slots each key's fingerprint is split across.
+    pub fn arity(&self) -> Arity {
+        self.layout.arity
+    }
+
+    /// Returns the memory used by the stored fingerprints, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        std::mem::size_of_val(self.fingerprints.as_slice())
+    }
+}
+
+impl<K, F> QueryFilter<K> for BinaryFuseFilter<K, F>
+where
+    K: Eq + Hash,
+    F: Fingerprint,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let hash = hash(self.seed, key);
+        let xor = |slots: &[usize]| {
+            slots
+                .iter()
+                .fold(F::default(), |value, &slot| value ^ self.fingerprints[slot])
+        };
+        let value = match self.layout.arity {
+            Arity::Three => xor(&self.layout.slots::<3>(hash)),
+            Arity::Four => xor(&self.layout.slots::<4>(hash)),
+        };
+        value == F::from_hash(hash)
+    }
+}
+
+/// Peels the hypergraph of key hashes over `N` slots each, and assigns
+/// fingerprints to slots in reverse peeling order. Returns `None` if peeling
+/// fails.
+fn assign<const N: usize, F: Fingerprint>(hashes: &[u64], layout: Layout) -> Option<Vec<F>> {
+    let order = peeling::peel_with(hashes, layout.len(), |hash| layout.slots::<N>(hash))?;
+    let mut fingerprints = vec![F::default(); layout.len()];
+    for &(i, slot) in order.iter().rev() {
+        let hash = hashes[i];
+        fingerprints[slot] = layout
+            .slots::<N>(hash)
+            .into_iter()
+            .filter(|&other| other != slot)
+            .fold(F::from_hash(hash), |value, other| {
+                value ^ fingerprints[other]
+            });
+    }
+    Some(fingerprints)
+}
+
+/// Segmentation of the fingerprint table.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    segment_length: usize,
+    /// Number of segments a key's first slot may fall in.
+    segment_count: usize,
+    arity: Arity,
+}
+
+impl Layout {
+    /// Returns the layout for `n` keys, sized as in the reference
+    /// implementation.
+    fn new(n: usize, arity: Arity) -> Self {
+        let ln_n = (n as f64).ln();
+        let segment_length = if n == 0 {
+            4
+        } else {
+            let exponent = match arity {
+                Arity::Three => ln_n / 3.33f64.ln() + 2.25,
+                Arity::Four => ln_n / 2.91f64.ln() - 0.5,
+            };
+            (1usize << exponent.floor().max(0.) as u32).min(MAX_SEGMENT_LENGTH)
+        };
+        let size_factor = match arity {
+            _ if n <= 1 => 0.,
+            Arity::Three => 1.125f64.max(0.875 + 0.25 * 1e6f64.ln() / ln_n),
+            Arity::Four => 1.075f64.max(0.77 + 0.305 * 6e5f64.ln() / ln_n),
+        };
+        let capacity = (n as f64 * size_factor).round() as usize;
+        let segment_count = capacity
+            .div_ceil(segment_length)
+            .saturating_sub(arity.slot_count() - 1)
+            .max(1);
+        Self {
+            segment_length,
+            segment_count,
+            arity,
+        }
+    }
+
+    /// Returns the total number of slots.
+    fn len(&self) -> usize {
+        (self.segment_count + self.arity.slot_count() - 1) * self.segment_length
+    }
+
+    /// Returns the `N` slots (in consecutive segments) of a given key hash.
+    fn slots<const N: usize>(&self, hash: u64) -> [usize; N] {
+        let mask = self.segment_length as u64 - 1;
+        let span = (self.segment_count * self.segment_length) as u128;
+        let h0 = ((hash as u128 * span) >> 64) as u64;
+        std::array::from_fn(|i| {
+            let offset = match i {
+                0 => 0,
+                1 => hash >> 18 & mask,
+                2 => hash & mask,
+                _ => hash >> 36 & mask,
+            };
+            ((h0 + (i * self.segment_length) as u64) ^ offset) as usize
+        })
+    }
+}
+
+fn hash<Q: Hash + ?Sized>(seed: u64, key: &Q) -> u64 {
+    Xxh3Builder::new().with_seed(seed).hash_one(key)
+}
+
+/// Serialized form of a binary fuse filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "BinaryFuseFilter")]
+struct SerdeBinaryFuseFilter<V> {
+    arity: usize,
+    segment_length: usize,
+    segment_count: usize,
+    seed: u64,
+    fingerprints: V,
+}
+
+#[cfg(feature = "serde")]
+impl<K, F> serde::Serialize for BinaryFuseFilter<K, F>
+where
+    K: Eq + Hash,
+    F: Fingerprint + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeBinaryFuseFilter {
+            arity: self.layout.arity.slot_count(),
+            segment_length: self.layout.segment_length,
+            segment_count: self.layout.segment_count,
+            seed: self.seed,
+            fingerprints: &self.fingerprints,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, F> serde::Deserialize<'de> for BinaryFuseFilter<K, F>
+where
+    K: Eq + Hash,
+    F: Fingerprint + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeBinaryFuseFilter::<Vec<F>>::deserialize(deserializer)?;
+        let arity = match filter.arity {
+            3 => Arity::Three,
+            4 => Arity::Four,
+            arity => return Err(D::Error::custom(format!("invalid arity {arity}"))),
+        };
+        let layout = Layout {
+            segment_length: filter.segment_length,
+            segment_count: filter.segment_count,
+            arity,
+        };
+        if !layout.segment_length.is_power_of_two()
+            || layout.segment_length > MAX_SEGMENT_LENGTH
+            || layout.segment_count == 0
+        {
+            return Err(D::Error::custom("invalid segment layout"));
+        }
+        let slot_count = layout
+            .segment_count
+            .checked_add(arity.slot_count() - 1)
+            .and_then(|count| count.checked_mul(layout.segment_length));
+        if slot_count != Some(filter.fingerprints.len()) {
+            return Err(D::Error::custom(
+                "fingerprint count does not match segment layout",
+            ));
+        }
+        Ok(Self {
+            fingerprints: filter.fingerprints,
+            layout,
+            seed: filter.seed,
+            phantom: PhantomData,
+        })
+    }
+}
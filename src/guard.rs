@@ -0,0 +1,181 @@
+//! Filters persisted on drop.
+//!
+//! A [`FilterGuard`] owns a filter along with a [`PersistTarget`], and saves
+//! the filter whenever it is dropped with unsaved changes, so that a service
+//! does not lose its filter to a forgotten shutdown hook. Saves can also be
+//! made periodic: after each insert through the guard (or an explicit
+//! [`tick`](FilterGuard::tick)), the filter is saved if it has changes older
+//! than the configured interval. There is no background thread, a guard
+//! that sees no activity does not save.
+//!
+//! Errors of saves made on drop cannot be returned, so they are only logged
+//! (with the `log` feature). Use [`into_inner`](FilterGuard::into_inner) or
+//! [`persist`](FilterGuard::persist) where the outcome matters.
+
+use {
+    crate::{InsertableQueryFilter, QueryFilter},
+    std::{
+        borrow::Borrow,
+        hash::Hash,
+        io,
+        ops::{Deref, DerefMut},
+        time::{Duration, Instant},
+    },
+};
+
+/// Destination a filter is saved to.
+///
+/// Implemented for closures, e.g. serializing the filter into a file.
+pub trait PersistTarget<F> {
+    /// Saves the filter.
+    fn persist(&mut self, filter: &F) -> io::Result<()>;
+}
+
+impl<F, T> PersistTarget<F> for T
+where
+    T: FnMut(&F) -> io::Result<()>,
+{
+    fn persist(&mut self, filter: &F) -> io::Result<()> {
+        self(filter)
+    }
+}
+
+/// Filter that is saved to a target on drop, and optionally periodically.
+///
+/// Dereferences to the wrapped filter. Mutable access marks the filter as
+/// changed.
+pub struct FilterGuard<F, T>
+where
+    T: PersistTarget<F>,
+{
+    /// Filter and target, taken out only by `into_inner`.
+    inner: Option<(F, T)>,
+    interval: Option<Duration>,
+    /// When the oldest unsaved change was made, if any.
+    dirty_since: Option<Instant>,
+}
+
+impl<F, T> FilterGuard<F, T>
+where
+    T: PersistTarget<F>,
+{
+    /// Wraps a filter, to be saved to a target on drop.
+    pub fn new(filter: F, target: T) -> Self {
+        Self {
+            inner: Some((filter, target)),
+            interval: None,
+            dirty_since: None,
+        }
+    }
+
+    /// Also saves the filter once its unsaved changes get older than
+    /// `interval`, checked on each insert or tick.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// Returns `true` if the filter has changes not saved yet.
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_since.is_some()
+    }
+
+    /// Saves the filter if its unsaved changes are older than the interval.
+    pub fn tick(&mut self) -> io::Result<()> {
+        match (self.dirty_since, self.interval) {
+            (Some(since), Some(interval)) if since.elapsed() >= interval => self.persist(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Saves the filter now, whether it has changes or not.
+    pub fn persist(&mut self) -> io::Result<()> {
+        let (filter, target) = self.inner.as_mut().unwrap();
+        target.persist(filter)?;
+        self.dirty_since = None;
+        Ok(())
+    }
+
+    /// Saves the filter if it has changes, and returns it along with the
+    /// target, without saving again on drop.
+    pub fn into_inner(mut self) -> io::Result<(F, T)> {
+        if self.is_dirty() {
+            self.persist()?;
+        }
+        Ok(self.inner.take().unwrap())
+    }
+
+    fn filter(&self) -> &F {
+        &self.inner.as_ref().unwrap().0
+    }
+
+    fn filter_mut(&mut self) -> &mut F {
+        &mut self.inner.as_mut().unwrap().0
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty_since.get_or_insert_with(Instant::now);
+    }
+}
+
+impl<F, T> FilterGuard<F, T>
+where
+    T: PersistTarget<F>,
+{
+    /// Inserts a key into the filter, then saves it if the interval elapsed.
+    ///
+    /// The key is inserted even if saving fails.
+    pub fn insert<K>(&mut self, key: K) -> io::Result<()>
+    where
+        F: InsertableQueryFilter<K>,
+        K: Eq + Hash,
+    {
+        self.filter_mut().insert(key);
+        self.mark_dirty();
+        self.tick()
+    }
+
+    /// Returns `true` if the key is believed to be in the filter.
+    pub fn contains<K, Q>(&self, key: &Q) -> bool
+    where
+        F: QueryFilter<K>,
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.filter().contains(key)
+    }
+}
+
+impl<F, T> Deref for FilterGuard<F, T>
+where
+    T: PersistTarget<F>,
+{
+    type Target = F;
+
+    fn deref(&self) -> &F {
+        self.filter()
+    }
+}
+
+impl<F, T> DerefMut for FilterGuard<F, T>
+where
+    T: PersistTarget<F>,
+{
+    fn deref_mut(&mut self) -> &mut F {
+        self.mark_dirty();
+        self.filter_mut()
+    }
+}
+
+impl<F, T> Drop for FilterGuard<F, T>
+where
+    T: PersistTarget<F>,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() && self.is_dirty() {
+            if let Err(error) = self.persist() {
+                event!(error:% = error; "failed to persist filter on drop");
+            }
+        }
+    }
+}
@@ -0,0 +1,512 @@
+//! Probe sequence generation.
+//!
+//! Filters that need `k` probe positions per key do not run `k` independent
+//! hash functions, instead they derive the whole probe sequence from a couple
+//! of base hashes, as described in [Less Hashing, Same Performance: Building a
+//! Better Bloom Filter, 2006][1]. How the sequence is derived matters for the
+//! false positive rate, see [`ProbeStrategy`].
+//!
+//! [1]: https://www.eecs.harvard.edu/~michaelm/postscripts/rsa2008.pdf
+
+use {
+    hash_iter::HashIterHasher,
+    std::{
+        hash::{BuildHasher, Hash},
+        io,
+    },
+    xxhash_rust::xxh3::{xxh3_64_with_seed, Xxh3, Xxh3Builder},
+};
+
+/// Strategy used to derive the probe sequence from base hashes.
+///
+/// With `h1`, `h2`, and `h3` being independent base hashes of a key, the
+/// `i`-th probe is computed as:
+///
+/// | Strategy           | Probe `h(i)`                          | Base hashes |
+/// |--------------------|---------------------------------------|-------------|
+/// | [`Double`]         | `h1 + i * h2`                         | 2           |
+/// | [`EnhancedDouble`] | `h1 + i * h2 + (i^3 - i) / 6`         | 2           |
+/// | [`Triple`]         | `h1 + i * h2 + i * (i - 1) / 2 * h3`  | 3           |
+///
+/// Plain double hashing is the cheapest, but it degrades at high `k`: two
+/// keys that collide on `(h1, h2)` modulo the table size collide on every
+/// probe, and keys with `h2` close to a multiple of the table size keep
+/// probing the same few positions. The cubic term of enhanced double hashing
+/// breaks such patterns at no extra hashing cost, which is why it is the
+/// default. Triple hashing gets closest to `k` truly independent hash
+/// functions (see [Bloom Filters in Probabilistic Verification, 2004][1]) at
+/// the cost of computing one more base hash per key, which pays off for large
+/// `k` (low target false positive rates).
+///
+/// [`Double`]: ProbeStrategy::Double
+/// [`EnhancedDouble`]: ProbeStrategy::EnhancedDouble
+/// [`Triple`]: ProbeStrategy::Triple
+/// [1]: https://www.khoury.northeastern.edu/~pete/pub/bloom-filters-verification.pdf
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum ProbeStrategy {
+    /// Plain double hashing.
+    Double,
+    /// Enhanced double hashing.
+    #[default]
+    EnhancedDouble,
+    /// Triple hashing.
+    Triple,
+}
+
+/// Hasher producing probe sequences according to a [`ProbeStrategy`].
+///
+/// Base hashes are computed with seeded XXH3. With default seeds and strategy
+/// the produced sequence is identical to the one of
+/// [`hash_iter::DoubleHashHasher::new()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProbeHasher {
+    seed1: u64,
+    seed2: u64,
+    seed3: u64,
+    strategy: ProbeStrategy,
+}
+
+impl ProbeHasher {
+    /// Creates a new hasher with default seeds and a given strategy.
+    pub fn new(strategy: ProbeStrategy) -> Self {
+        Self {
+            seed1: 12345,
+            seed2: 67890,
+            seed3: 24680,
+            strategy,
+        }
+    }
+
+    /// Creates a new hasher with a given strategy, and seeds drawn from a
+    /// random number generator.
+    ///
+    /// Lets applications with their own seeding policy (deterministic
+    /// simulation, replay) control filter randomness explicitly.
+    #[cfg(feature = "rand")]
+    pub fn from_rng(strategy: ProbeStrategy, rng: &mut impl rand::Rng) -> Self {
+        Self {
+            seed1: rng.random(),
+            seed2: rng.random(),
+            seed3: rng.random(),
+            strategy,
+        }
+    }
+
+    /// Sets the seed of the first base hash.
+    pub fn with_seed1(self, seed1: u64) -> Self {
+        Self { seed1, ..self }
+    }
+
+    /// Sets the seed of the second base hash.
+    pub fn with_seed2(self, seed2: u64) -> Self {
+        Self { seed2, ..self }
+    }
+
+    /// Sets the seed of the third base hash (only used by triple hashing).
+    pub fn with_seed3(self, seed3: u64) -> Self {
+        Self { seed3, ..self }
+    }
+
+    /// Returns the seeds of the base hashes.
+    pub fn seeds(&self) -> [u64; 3] {
+        [self.seed1, self.seed2, self.seed3]
+    }
+
+    /// Returns the probe strategy.
+    pub fn strategy(&self) -> ProbeStrategy {
+        self.strategy
+    }
+
+    /// Returns the first `count` probes of a `u64` key.
+    ///
+    /// Produces exactly the same sequence as [`hash_iter`], without going
+    /// through the [`Hash`] trait and a streaming hasher: a single-shot XXH3
+    /// of eight bytes boils down to a handful of multiplications.
+    ///
+    /// [`hash_iter`]: HashIterHasher::hash_iter
+    pub fn probes_u64(&self, key: u64, count: usize) -> Probes {
+        let bytes = key.to_ne_bytes();
+        let hash = |seed| xxh3_64_with_seed(&bytes, seed);
+        let hash3 = match self.strategy {
+            ProbeStrategy::Triple => hash(self.seed3),
+            _ => 0,
+        };
+        Probes {
+            hash1: hash(self.seed1),
+            hash2: hash(self.seed2),
+            hash3,
+            strategy: self.strategy,
+            k: count as u64,
+            cnt: 0,
+        }
+    }
+
+    /// Returns an incremental hasher, for keys fed in chunks.
+    pub fn key_hasher(&self) -> KeyHasher {
+        KeyHasher {
+            hasher: *self,
+            states: self.seeds().map(Xxh3::with_seed),
+        }
+    }
+}
+
+impl Default for ProbeHasher {
+    fn default() -> Self {
+        Self::new(ProbeStrategy::default())
+    }
+}
+
+impl HashIterHasher<u64> for ProbeHasher {
+    fn hash_iter<K: Hash + ?Sized>(&self, key: &K, count: usize) -> impl Iterator<Item = u64> {
+        let hash = |seed| Xxh3Builder::new().with_seed(seed).hash_one(key);
+        let hash3 = match self.strategy {
+            ProbeStrategy::Triple => hash(self.seed3),
+            _ => 0,
+        };
+        Probes {
+            hash1: hash(self.seed1),
+            hash2: hash(self.seed2),
+            hash3,
+            strategy: self.strategy,
+            k: count as u64,
+            cnt: 0,
+        }
+    }
+}
+
+/// Hasher producing probe sequences according to a [`ProbeStrategy`], with
+/// base hashes computed by arbitrary [`BuildHasher`]s.
+///
+/// Lets filters use a hash function other than XXH3, e.g. keyed SipHash
+/// (std's `RandomState`) for keys chosen by untrusted parties, a faster one
+/// for small keys, or whatever another system uses, for bit-compatible
+/// filters.
+///
+/// Base hashes must be independent: builders must be seeded or keyed
+/// differently, e.g. distinct `RandomState` instances. Identical builders
+/// make every probe of a key land on the same position.
+///
+/// ```
+/// use {
+///     mqfilters::{
+///         hash::{BuildProbeHasher, ProbeStrategy},
+///         BloomFilter,
+///         InsertableQueryFilter,
+///         QueryFilter,
+///     },
+///     std::hash::RandomState,
+/// };
+///
+/// let hasher = BuildProbeHasher::new(ProbeStrategy::EnhancedDouble, [
+///     RandomState::new(),
+///     RandomState::new(),
+///     RandomState::new(),
+/// ]);
+/// let mut filter = BloomFilter::with_capacity_and_hasher(1000, 0.01, hasher);
+/// filter.insert("hello");
+/// assert!(filter.contains("hello"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildProbeHasher<B> {
+    builders: [B; 3],
+    strategy: ProbeStrategy,
+}
+
+impl<B> BuildProbeHasher<B>
+where
+    B: BuildHasher,
+{
+    /// Creates a new hasher with a given strategy, computing each base hash
+    /// with the corresponding builder. The third builder is only used by
+    /// triple hashing.
+    pub fn new(strategy: ProbeStrategy, builders: [B; 3]) -> Self {
+        Self { builders, strategy }
+    }
+
+    /// Returns the builders of the base hashes.
+    pub fn builders(&self) -> &[B; 3] {
+        &self.builders
+    }
+
+    /// Returns the probe strategy.
+    pub fn strategy(&self) -> ProbeStrategy {
+        self.strategy
+    }
+}
+
+impl<B> HashIterHasher<u64> for BuildProbeHasher<B>
+where
+    B: BuildHasher,
+{
+    fn hash_iter<K: Hash + ?Sized>(&self, key: &K, count: usize) -> impl Iterator<Item = u64> {
+        let hash3 = match self.strategy {
+            ProbeStrategy::Triple => self.builders[2].hash_one(key),
+            _ => 0,
+        };
+        Probes {
+            hash1: self.builders[0].hash_one(key),
+            hash2: self.builders[1].hash_one(key),
+            hash3,
+            strategy: self.strategy,
+            k: count as u64,
+            cnt: 0,
+        }
+    }
+}
+
+/// Incremental hasher, for keys too large to be held contiguously in memory
+/// (file contents, blobs, etc.).
+///
+/// Accepts the key in chunks (directly, or as an [`io::Write`] sink), and
+/// finishes into the probe sequence. The key is hashed as the concatenation of
+/// all chunks, regardless of how it is split, but *not* the way its [`Hash`]
+/// implementation would hash it: keys inserted in chunks can only be queried
+/// in chunks.
+///
+/// ```
+/// use {
+///     mqfilters::BloomFilter,
+///     std::io::{self, Read},
+/// };
+///
+/// let mut filter = BloomFilter::<Vec<u8>>::new(1000, 0.01);
+///
+/// let mut key = filter.hasher().key_hasher();
+/// io::copy(&mut io::repeat(42).take(1 << 20), &mut key).unwrap();
+/// filter.insert_streamed(&key);
+///
+/// let mut key = filter.hasher().key_hasher();
+/// for _ in 0..16 {
+///     key.update(&[42; 1 << 16]);
+/// }
+/// assert!(filter.contains_streamed(&key));
+/// ```
+#[derive(Clone)]
+pub struct KeyHasher {
+    hasher: ProbeHasher,
+    states: [Xxh3; 3],
+}
+
+impl KeyHasher {
+    /// Feeds the next chunk of the key.
+    pub fn update(&mut self, chunk: &[u8]) {
+        // The third base hash is only needed by triple hashing.
+        let used = match self.hasher.strategy {
+            ProbeStrategy::Triple => 3,
+            _ => 2,
+        };
+        for state in &mut self.states[..used] {
+            state.update(chunk);
+        }
+    }
+
+    /// Returns the hasher this key hasher was created from.
+    pub fn hasher(&self) -> &ProbeHasher {
+        &self.hasher
+    }
+
+    /// Returns the first `count` probes of the key fed so far.
+    pub fn probes(&self, count: usize) -> Probes {
+        let hash3 = match self.hasher.strategy {
+            ProbeStrategy::Triple => self.states[2].digest(),
+            _ => 0,
+        };
+        Probes {
+            hash1: self.states[0].digest(),
+            hash2: self.states[1].digest(),
+            hash3,
+            strategy: self.hasher.strategy,
+            k: count as u64,
+            cnt: 0,
+        }
+    }
+}
+
+impl io::Write for KeyHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Iterator over the probe sequence of a single key.
+#[derive(Debug)]
+pub struct Probes {
+    hash1: u64,
+    hash2: u64,
+    hash3: u64,
+    strategy: ProbeStrategy,
+    k: u64,
+    cnt: u64,
+}
+
+impl Iterator for Probes {
+    type Item = u64;
+
+    /// Returns the next probe, using forward differencing to avoid
+    /// multiplications.
+    fn next(&mut self) -> Option<Self::Item> {
+        // All arithmetic is modulo `u64::MAX` (rather than `2^64`), keeping the
+        // sequence compatible with `hash_iter`.
+        const N: u64 = u64::MAX;
+
+        if self.cnt == self.k {
+            return None;
+        }
+
+        if self.cnt == 0 {
+            self.cnt += 1;
+            return Some(self.hash1 % N);
+        }
+
+        self.hash1 = self.hash1.wrapping_add(self.hash2) % N;
+        match self.strategy {
+            ProbeStrategy::Double => {}
+            ProbeStrategy::EnhancedDouble => {
+                self.hash2 = self.hash2.wrapping_add(self.cnt) % N;
+            }
+            ProbeStrategy::Triple => {
+                self.hash2 = self.hash2.wrapping_add(self.hash3) % N;
+            }
+        }
+        self.cnt += 1;
+
+        Some(self.hash1)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.k - self.cnt) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Probes {}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, hash_iter::DoubleHashHasher};
+
+    #[test]
+    fn default_matches_hash_iter() {
+        let reference = DoubleHashHasher::new();
+        let hasher = ProbeHasher::default();
+        for key in 0..1000 {
+            assert!(hasher.hash_iter(&key, 20).eq(reference.hash_iter(&key, 20)));
+        }
+    }
+
+    #[test]
+    fn strategies_differ() {
+        let key = "mykey";
+        let k = 10;
+        let double = ProbeHasher::new(ProbeStrategy::Double);
+        let enhanced = ProbeHasher::new(ProbeStrategy::EnhancedDouble);
+        let triple = ProbeHasher::new(ProbeStrategy::Triple);
+
+        // Same starting point, since all strategies share the first base hash.
+        let first = double.hash_iter(&key, 1).next();
+        assert_eq!(first, enhanced.hash_iter(&key, 1).next());
+        assert_eq!(first, triple.hash_iter(&key, 1).next());
+
+        let double = double.hash_iter(&key, k).collect::<Vec<_>>();
+        let enhanced = enhanced.hash_iter(&key, k).collect::<Vec<_>>();
+        let triple = triple.hash_iter(&key, k).collect::<Vec<_>>();
+        assert_eq!(double.len(), k);
+        assert_ne!(double, enhanced);
+        assert_ne!(double, triple);
+        assert_ne!(enhanced, triple);
+    }
+
+    #[test]
+    fn probes_u64_match_hash_iter() {
+        for strategy in [
+            ProbeStrategy::Double,
+            ProbeStrategy::EnhancedDouble,
+            ProbeStrategy::Triple,
+        ] {
+            let hasher = ProbeHasher::new(strategy).with_seed2(7);
+            for key in (0..1000).chain([u64::MAX]) {
+                assert!(hasher.probes_u64(key, 10).eq(hasher.hash_iter(&key, 10)));
+            }
+        }
+    }
+
+    #[test]
+    fn build_probe_hasher_matches_probe_hasher() {
+        for strategy in [ProbeStrategy::Double, ProbeStrategy::Triple] {
+            let hasher = ProbeHasher::new(strategy);
+            let builders = hasher
+                .seeds()
+                .map(|seed| Xxh3Builder::new().with_seed(seed));
+            let build = BuildProbeHasher::new(strategy, builders);
+            for key in 0..1000 {
+                assert!(build.hash_iter(&key, 10).eq(hasher.hash_iter(&key, 10)));
+            }
+        }
+    }
+
+    #[test]
+    fn key_hasher_ignores_chunking() {
+        let data = (0..10000).map(|i| i as u8).collect::<Vec<_>>();
+        for strategy in [ProbeStrategy::Double, ProbeStrategy::Triple] {
+            let hasher = ProbeHasher::new(strategy);
+            let mut whole = hasher.key_hasher();
+            whole.update(&data);
+            let mut chunked = hasher.key_hasher();
+            for chunk in data.chunks(333) {
+                chunked.update(chunk);
+            }
+            assert!(whole.probes(10).eq(chunked.probes(10)));
+
+            let mut other = hasher.with_seed1(1).key_hasher();
+            other.update(&data);
+            assert!(!whole.probes(10).eq(other.probes(10)));
+        }
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn seeds_from_rng() {
+        struct Counter(u64);
+
+        impl rand::RngCore for Counter {
+            fn next_u32(&mut self) -> u32 {
+                self.next_u64() as u32
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                self.0 += 1;
+                self.0
+            }
+
+            fn fill_bytes(&mut self, dst: &mut [u8]) {
+                rand::rand_core::impls::fill_bytes_via_next(self, dst)
+            }
+        }
+
+        let hasher = ProbeHasher::from_rng(ProbeStrategy::Triple, &mut Counter(0));
+        assert_eq!(hasher.seeds(), [1, 2, 3]);
+        assert_eq!(hasher.strategy(), ProbeStrategy::Triple);
+    }
+
+    #[test]
+    fn seeds_change_probes() {
+        let key = "mykey";
+        let hasher = ProbeHasher::new(ProbeStrategy::Triple);
+        let probes = hasher.hash_iter(&key, 5).collect::<Vec<_>>();
+        for other in [
+            hasher.with_seed1(1),
+            hasher.with_seed2(2),
+            hasher.with_seed3(3),
+        ] {
+            assert_ne!(probes, other.hash_iter(&key, 5).collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! HBase Bloom chunk interoperability.
+//!
+//! HBase splits the Bloom filter of a store file (HFile) into chunks, each a
+//! plain bit array stored in its own block, and located through the Bloom
+//! block index by the first key it covers. A [`BloomChunk`] is bit-compatible
+//! with a single such chunk: keys are hashed with HBase's MurmurHash (v2)
+//! variant, combined by double hashing, and bits are numbered from the lowest
+//! bit of the first byte. Key composition follows the store's Bloom type, see
+//! [`BloomType`].
+//!
+//! Locating the chunks (reading HFile trailers, block headers and the Bloom
+//! block index) is left to the caller: the chunk payload and the hash count
+//! from the Bloom metadata are all that is needed to query a chunk.
+
+use crate::analysis::{optimal_bit_count, optimal_hash_count};
+
+/// What part of a cell is added to the Bloom filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BloomType {
+    /// Row only.
+    Row,
+    /// Row and column qualifier.
+    RowCol,
+}
+
+impl BloomType {
+    /// Returns the Bloom key of a cell.
+    ///
+    /// For [`RowCol`](BloomType::RowCol) this is the serialized key of the
+    /// first cell on the row and column: row length (`u16`), row, empty
+    /// family (a zero length byte), qualifier, latest timestamp (`i64::MAX`),
+    /// and the maximum key type (`0xff`), all in network byte order. The
+    /// qualifier is ignored for [`Row`](BloomType::Row).
+    pub fn key(self, row: &[u8], qualifier: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Row => row.to_vec(),
+            Self::RowCol => {
+                let mut key = Vec::with_capacity(row.len() + qualifier.len() + 12);
+                key.extend_from_slice(&(row.len() as u16).to_be_bytes());
+                key.extend_from_slice(row);
+                key.push(0);
+                key.extend_from_slice(qualifier);
+                key.extend_from_slice(&i64::MAX.to_be_bytes());
+                key.push(0xff);
+                key
+            }
+        }
+    }
+}
+
+/// Single chunk of an HBase Bloom filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomChunk {
+    bytes: Vec<u8>,
+    hash_count: u32,
+}
+
+impl BloomChunk {
+    /// Creates a new empty chunk for a desired number of keys and false
+    /// positive rate.
+    pub fn new(max_keys: usize, fp_rate: f64) -> Self {
+        let bit_count = optimal_bit_count(max_keys, fp_rate).max(8);
+        let hash_count = optimal_hash_count(max_keys, bit_count).max(1);
+        Self {
+            bytes: vec![0; bit_count.div_ceil(8)],
+            hash_count: hash_count as u32,
+        }
+    }
+
+    /// Wraps a chunk payload read from a store file, along with the hash
+    /// count recorded in the Bloom metadata.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the payload is empty.
+    pub fn from_bytes(bytes: Vec<u8>, hash_count: u32) -> Self {
+        assert!(!bytes.is_empty(), "chunk must not be empty");
+        Self { bytes, hash_count }
+    }
+
+    /// Returns the chunk payload, as stored in a store file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> u32 {
+        self.hash_count
+    }
+
+    /// Adds a Bloom key (see [`BloomType::key`]) to the chunk.
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.bits(key) {
+            self.bytes[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Returns `true` if the Bloom key is believed to be in the chunk.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.bits(key)
+            .all(|bit| self.bytes[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Returns the bits selected by a key.
+    ///
+    /// Mirrors HBase's Java arithmetic: wrapping 32-bit signed sums, and the
+    /// absolute value of the (signed) remainder.
+    fn bits(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let bit_count = (self.bytes.len() * 8) as i32;
+        let hash1 = murmur2(key, 0);
+        let hash2 = murmur2(key, hash1);
+        (0..self.hash_count as i32).map(move |i| {
+            let hash = hash1.wrapping_add(i.wrapping_mul(hash2));
+            (hash % bit_count).unsigned_abs() as usize
+        })
+    }
+}
+
+/// HBase's variant of 32-bit MurmurHash (v2).
+///
+/// Unlike the reference implementation, trailing bytes are sign-extended
+/// (Java bytes are signed), which matters for keys ending in bytes `>= 0x80`.
+fn murmur2(key: &[u8], seed: i32) -> i32 {
+    const M: i32 = 0x5bd1e995;
+    const R: u32 = 24;
+
+    let mut h = seed ^ key.len() as i32;
+    let mut chunks = key.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = i32::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= ((k as u32) >> R) as i32;
+        k = k.wrapping_mul(M);
+        h = h.wrapping_mul(M) ^ k;
+    }
+
+    let tail = chunks.remainder();
+    let byte = |i: usize| tail[i] as i8 as i32;
+    if tail.len() >= 3 {
+        h ^= byte(2) << 16;
+    }
+    if tail.len() >= 2 {
+        h ^= byte(1) << 8;
+    }
+    if !tail.is_empty() {
+        h ^= byte(0);
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= ((h as u32) >> 13) as i32;
+    h = h.wrapping_mul(M);
+    h ^ ((h as u32) >> 15) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn murmur2_sign_extends_tail() {
+        // Same as the reference implementation for ASCII tails...
+        assert_eq!(murmur2(b"", 0), 0);
+        assert_ne!(murmur2(b"abc", 0), murmur2(b"abd", 0));
+        // ...but a high tail byte flips all the upper bits.
+        let reference = |key: &[u8]| {
+            let mut h = key.len() as u32;
+            h ^= key[0] as u32;
+            h = h.wrapping_mul(0x5bd1e995);
+            h ^= h >> 13;
+            h = h.wrapping_mul(0x5bd1e995);
+            h ^ (h >> 15)
+        };
+        assert_eq!(murmur2(b"a", 0) as u32, reference(b"a"));
+        assert_ne!(murmur2(&[0x80], 0) as u32, reference(&[0x80]));
+    }
+}
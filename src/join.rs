@@ -0,0 +1,135 @@
+//! Bloom join (semi-join reduction).
+//!
+//! When joining a small relation with a large (or remote, or streamed) one,
+//! most rows of the large side typically have no match. A [`BloomJoin`]
+//! builds a Bloom filter over the join keys of the small (build) side, and
+//! uses it to drop probe side rows that certainly have no match, before
+//! they are shipped or joined. Rows passing the filter still go through the
+//! actual join, which discards the few false positives.
+//!
+//! Counts of passed and suppressed rows are kept, so that the realized
+//! selectivity can be compared with the planner's estimate, e.g. to stop
+//! applying the filter when it suppresses too little to pay for itself.
+
+use {
+    crate::{hash::ProbeHasher, BloomFilter, InsertableQueryFilter, QueryFilter},
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash},
+};
+
+/// Bloom filter over the build side keys of a join, filtering probe side
+/// rows.
+pub struct BloomJoin<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    filter: BloomFilter<K, H>,
+    passed: usize,
+    suppressed: usize,
+}
+
+impl<K> BloomJoin<K>
+where
+    K: Eq + Hash,
+{
+    /// Builds the filter from the build side keys.
+    ///
+    /// The filter is sized from the expected number of distinct keys (e.g.
+    /// the column's distinct count statistic) and a desired false positive
+    /// rate. Underestimating the distinct count raises the false positive
+    /// rate, but never drops a matching row.
+    pub fn build(keys: impl IntoIterator<Item = K>, distinct_keys: usize, fp_rate: f64) -> Self {
+        Self::build_with_hasher(keys, distinct_keys, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> BloomJoin<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Builds the filter from the build side keys, with a given hasher.
+    ///
+    /// See [`build`](BloomJoin::build) for sizing.
+    pub fn build_with_hasher(
+        keys: impl IntoIterator<Item = K>,
+        distinct_keys: usize,
+        fp_rate: f64,
+        hasher: H,
+    ) -> Self {
+        let mut filter =
+            BloomFilter::with_capacity_and_hasher(distinct_keys.max(1), fp_rate, hasher);
+        for key in keys {
+            filter.insert(key);
+        }
+        Self {
+            filter,
+            passed: 0,
+            suppressed: 0,
+        }
+    }
+
+    /// Returns the filter over the build side keys, e.g. to ship it to the
+    /// nodes scanning the probe side.
+    pub fn filter(&self) -> &BloomFilter<K, H> {
+        &self.filter
+    }
+
+    /// Returns `true` if a probe side row with a given key may have a match,
+    /// and counts the row as passed or suppressed.
+    pub fn probe<Q>(&mut self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let pass = self.filter.contains(key);
+        if pass {
+            self.passed += 1;
+        } else {
+            self.suppressed += 1;
+        }
+        pass
+    }
+
+    /// Filters a stream of probe side rows, keeping those whose key (as
+    /// extracted by `key`) may have a match.
+    pub fn filter_rows<'a, R, Q, F>(
+        &'a mut self,
+        rows: impl IntoIterator<Item = R> + 'a,
+        key: F,
+    ) -> impl Iterator<Item = R> + 'a
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+        F: for<'r> Fn(&'r R) -> &'r Q + 'a,
+    {
+        rows.into_iter().filter(move |row| self.probe(key(row)))
+    }
+
+    /// Returns the number of probe side rows passed so far.
+    pub fn passed(&self) -> usize {
+        self.passed
+    }
+
+    /// Returns the number of probe side rows suppressed so far.
+    pub fn suppressed(&self) -> usize {
+        self.suppressed
+    }
+
+    /// Returns the fraction of probed rows that passed, or `None` if no
+    /// rows were probed yet.
+    ///
+    /// This is an upper bound on the join's true selectivity, inflated by
+    /// false positives: at most [`BloomFilter::approx_fp_rate`] of the
+    /// non-matching rows.
+    pub fn selectivity(&self) -> Option<f64> {
+        let probed = self.passed + self.suppressed;
+        (probed > 0).then(|| self.passed as f64 / probed as f64)
+    }
+
+    /// Resets the passed and suppressed counts, keeping the filter.
+    pub fn reset_counts(&mut self) {
+        self.passed = 0;
+        self.suppressed = 0;
+    }
+}
@@ -1,13 +1,191 @@
+/// Emits a debug-level event with structured fields, if the `log` feature is
+/// enabled (otherwise only borrows the fields' values, so that variables
+/// used just for logging do not go unused).
+macro_rules! event {
+    (@value $key:ident = $value:expr) => {
+        &$value
+    };
+    (@value $key:ident) => {
+        &$key
+    };
+    ($($key:ident $(:$capture:tt)? $(= $value:expr)?),+; $($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        log::debug!($($key $(:$capture)? $(= $value)?),+; $($arg)+);
+        #[cfg(not(feature = "log"))]
+        let _ = ($(event!(@value $key $(= $value)?),)+);
+    };
+    ($($arg:tt)+) => {
+        #[cfg(feature = "log")]
+        log::debug!($($arg)+);
+    };
+}
+
+pub mod analysis;
+pub mod budget;
 pub mod error;
-pub use error::{QueryFilterError, QueryFilterResult};
+pub mod guard;
+pub mod hash;
+pub mod multi;
+pub mod profiler;
+pub mod rebuild;
+pub mod sharded;
+pub mod storage;
+pub use {
+    error::{QueryFilterError, QueryFilterResult},
+    guard::FilterGuard,
+    multi::MultiFilter,
+    sharded::ShardedFilter,
+};
 
+#[cfg(feature = "bf")]
+pub mod aging;
+#[cfg(feature = "atomic")]
+pub mod atomic;
+#[cfg(feature = "bench_utils")]
+pub mod bench_utils;
 #[cfg(feature = "bf")]
 pub mod bf;
+#[cfg(feature = "bottomk")]
+pub mod bottomk;
+#[cfg(feature = "cbf")]
+pub mod cbf;
+#[cfg(feature = "cidr")]
+pub mod cidr;
+#[cfg(feature = "cuckoo")]
+pub mod cuckoo;
+#[cfg(feature = "bf")]
+pub mod dedup;
+#[cfg(feature = "bf")]
+pub mod directory;
+#[cfg(feature = "roaring")]
+pub mod exact;
+#[cfg(feature = "fuse")]
+pub mod fuse;
+#[cfg(feature = "hbase")]
+pub mod hbase;
+#[cfg(feature = "bf")]
+pub mod join;
+#[cfg(feature = "minhash")]
+pub mod minhash;
+#[cfg(feature = "mphf")]
+pub mod mphf;
+#[cfg(feature = "namespaced")]
+pub mod namespaced;
+#[cfg(feature = "bf")]
+pub mod negative;
+#[cfg(feature = "orc")]
+pub mod orc;
+#[cfg(feature = "bf")]
+pub mod partitioned;
+#[cfg(feature = "pbf")]
+pub mod pbf;
+#[cfg(feature = "prefix")]
+pub mod prefix;
+#[cfg(feature = "qf")]
+pub mod qf;
+#[cfg(feature = "bf")]
+pub mod raw;
+#[cfg(feature = "redis")]
+pub mod redis;
+#[cfg(feature = "bf")]
+pub mod replication;
+#[cfg(feature = "retrieval")]
+pub mod retrieval;
+#[cfg(feature = "ribbon")]
+pub mod ribbon;
+#[cfg(feature = "sbbf")]
+pub mod sbbf;
+#[cfg(feature = "sbf")]
+pub mod sbf;
+#[cfg(feature = "bf")]
+pub mod scalable;
+#[cfg(feature = "spectral")]
+pub mod spectral;
+#[cfg(feature = "squid")]
+pub mod squid;
+#[cfg(feature = "tbf")]
+pub mod tbf;
+#[cfg(feature = "testing")]
+pub mod testing;
+#[cfg(feature = "theta")]
+pub mod theta;
+#[cfg(feature = "bf")]
+pub mod tombstone;
+#[cfg(feature = "xor")]
+pub mod xor;
 
-use std::{borrow::Borrow, hash::Hash};
+#[cfg(any(
+    feature = "retrieval",
+    feature = "xor",
+    feature = "fuse",
+    feature = "ribbon"
+))]
+mod peeling;
 
+use std::{borrow::Borrow, hash::Hash, rc::Rc, sync::Arc};
+
+#[cfg(feature = "bf")]
+pub use aging::AgingBloomFilter;
+#[cfg(feature = "atomic")]
+pub use atomic::AtomicBloomFilter;
 #[cfg(feature = "bf")]
 pub use bf::BloomFilter;
+#[cfg(feature = "bottomk")]
+pub use bottomk::BottomK;
+#[cfg(feature = "cbf")]
+pub use cbf::CountingBloomFilter;
+#[cfg(feature = "cidr")]
+pub use cidr::CidrFilter;
+#[cfg(feature = "cuckoo")]
+pub use cuckoo::CuckooFilter;
+#[cfg(feature = "bf")]
+pub use dedup::WindowedDedup;
+#[cfg(feature = "bf")]
+pub use directory::FilterDirectory;
+#[cfg(feature = "roaring")]
+pub use exact::ExactU32Filter;
+#[cfg(feature = "fuse")]
+pub use fuse::BinaryFuseFilter;
+#[cfg(feature = "bf")]
+pub use join::BloomJoin;
+#[cfg(feature = "minhash")]
+pub use minhash::MinHash;
+#[cfg(feature = "mphf")]
+pub use mphf::MphfFilter;
+#[cfg(feature = "namespaced")]
+pub use namespaced::NamespacedFilter;
+#[cfg(feature = "bf")]
+pub use negative::WithNegativeCache;
+#[cfg(feature = "bf")]
+pub use partitioned::PartitionedFilter;
+#[cfg(feature = "pbf")]
+pub use pbf::PatternBloomFilter;
+#[cfg(feature = "prefix")]
+pub use prefix::HashPrefixSet;
+#[cfg(feature = "qf")]
+pub use qf::QuotientFilter;
+#[cfg(feature = "bf")]
+pub use raw::RawFilter;
+#[cfg(feature = "retrieval")]
+pub use retrieval::{BloomierFilter, XorRetrieval};
+#[cfg(feature = "ribbon")]
+pub use ribbon::RibbonFilter;
+#[cfg(feature = "sbbf")]
+pub use sbbf::BlockedBloomFilter;
+#[cfg(feature = "sbf")]
+pub use sbf::StableBloomFilter;
+#[cfg(feature = "bf")]
+pub use scalable::ScalableBloomFilter;
+#[cfg(feature = "spectral")]
+pub use spectral::SpectralBloomFilter;
+#[cfg(feature = "tbf")]
+pub use tbf::TwoBlockBloomFilter;
+#[cfg(feature = "theta")]
+pub use theta::ThetaSketch;
+#[cfg(feature = "bf")]
+pub use tombstone::WithTombstones;
+#[cfg(feature = "xor")]
+pub use xor::XorFilter;
 
 /// Defines membership query filter.
 ///
@@ -35,6 +213,16 @@ pub trait InsertableQueryFilter<K>: QueryFilter<K> {
         K: Eq + Hash;
 }
 
+/// Defines a filter that supports adding elements through a shared
+/// reference, so that several threads can insert (and query) concurrently,
+/// without a lock.
+pub trait ConcurrentInsertableQueryFilter<K>: QueryFilter<K> + Sync {
+    /// Inserts an element into the filter.
+    fn insert(&self, key: K)
+    where
+        K: Eq + Hash;
+}
+
 /// Defines a filter that supports removal of elements.
 pub trait RemovableQueryFilter<K>: QueryFilter<K> {
     /// Removes an element from the filter.
@@ -44,8 +232,182 @@ pub trait RemovableQueryFilter<K>: QueryFilter<K> {
         Q: Eq + Hash + ?Sized;
 }
 
+/// Defines a filter that can be converted into a compact, read-only form.
+///
+/// Covers the common build-once/serve-forever lifecycle: once no more
+/// updates are expected, everything needed only for updates is dropped, and
+/// the remaining state is re-packed for query throughput and minimal memory.
+pub trait FreezableQueryFilter<K>: QueryFilter<K> {
+    /// Read-only form of the filter.
+    type Frozen: QueryFilter<K>;
+
+    /// Converts the filter into its read-only form.
+    fn freeze(self) -> Self::Frozen;
+}
+
+/// Defines a static function: a structure that stores a value for each key of
+/// a fixed set, and retrieves it later.
+///
+/// Keys themselves are not stored, so querying a key outside of the set
+/// returns an arbitrary (garbage) value, instead of signaling absence. Many
+/// static filters are special cases of retrieval: storing an `r`-bit
+/// fingerprint per key and comparing it with the retrieved value yields a
+/// filter with false positive rate of `2^-r`.
+pub trait StaticRetrieval<K, V> {
+    /// Returns the value stored for the key.
+    ///
+    /// The value may be any borrowed form of the key type, but [`Hash`] and
+    /// [`Eq`] on the borrowed form *must* match those for the key type.
+    fn get<Q>(&self, key: &Q) -> V
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized;
+}
+
 /// Defines a filter that supports clearing all elements.
 pub trait ClearableQueryFilter<K>: QueryFilter<K> {
     /// Removes all elements from the filter.
     fn clear(&mut self);
 }
+
+/// Defines a filter that can be combined with another one of the same shape,
+/// e.g. one built over another shard of the same key space.
+pub trait MergeableQueryFilter<K>: QueryFilter<K> {
+    /// Merges another filter into this one, so that it holds the union of
+    /// both key sets, or fails with
+    /// [`IncompatibleFilters`](QueryFilterError::IncompatibleFilters) if the
+    /// filters differ in shape (size, hashing, or number of hash functions).
+    fn try_union_with(&mut self, other: &Self) -> QueryFilterResult<()>;
+
+    /// Restricts this filter to the keys also in another one, or fails with
+    /// [`IncompatibleFilters`](QueryFilterError::IncompatibleFilters) if the
+    /// filters differ in shape (size, hashing, or number of hash functions).
+    ///
+    /// The result may answer `true` for more keys than a filter built from
+    /// the actual intersection would.
+    fn try_intersect_with(&mut self, other: &Self) -> QueryFilterResult<()>;
+
+    /// Merges another filter into this one, see
+    /// [`try_union_with`](MergeableQueryFilter::try_union_with).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the filters differ in shape.
+    fn union_with(&mut self, other: &Self) {
+        self.try_union_with(other).expect("filters differ in shape");
+    }
+
+    /// Restricts this filter to the keys also in another one, see
+    /// [`try_intersect_with`](MergeableQueryFilter::try_intersect_with).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the filters differ in shape.
+    fn intersect_with(&mut self, other: &Self) {
+        self.try_intersect_with(other)
+            .expect("filters differ in shape");
+    }
+}
+
+/// Defines a filter of hashed slots (bits or counters) that reports how full
+/// it is, e.g. to export metrics, or to rotate a filter once saturated.
+pub trait FilterStats {
+    /// Returns the number of slots, i.e. bits or counters.
+    fn slot_count(&self) -> usize;
+
+    /// Returns the number of hash functions, i.e. slots probed per key.
+    fn hash_count(&self) -> usize;
+
+    /// Returns the fraction of occupied (non-zero) slots, within `0..=1`.
+    fn fill_ratio(&self) -> f64;
+
+    /// Returns the memory used by the slots, in bytes.
+    fn size_in_bytes(&self) -> usize;
+
+    /// Returns the estimated false positive rate, given the current fill
+    /// ratio.
+    fn approx_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.hash_count() as i32)
+    }
+}
+
+/// Implements the query trait for a pointer type, delegating to the pointee.
+macro_rules! impl_query_filter_for_pointer {
+    ($($pointer:ty),+) => {$(
+        impl<K, F> QueryFilter<K> for $pointer
+        where
+            F: QueryFilter<K> + ?Sized,
+        {
+            fn contains<Q>(&self, key: &Q) -> bool
+            where
+                K: Borrow<Q>,
+                Q: Eq + Hash + ?Sized,
+            {
+                (**self).contains(key)
+            }
+        }
+    )+};
+}
+
+/// Implements the mutation traits for a pointer type, delegating to the
+/// pointee.
+macro_rules! impl_mutable_filter_for_pointer {
+    ($($pointer:ty),+) => {$(
+        impl<K, F> InsertableQueryFilter<K> for $pointer
+        where
+            F: InsertableQueryFilter<K> + ?Sized,
+        {
+            fn insert(&mut self, key: K)
+            where
+                K: Eq + Hash,
+            {
+                (**self).insert(key)
+            }
+        }
+
+        impl<K, F> RemovableQueryFilter<K> for $pointer
+        where
+            F: RemovableQueryFilter<K> + ?Sized,
+        {
+            fn remove<Q>(&mut self, key: &Q)
+            where
+                K: Borrow<Q>,
+                Q: Eq + Hash + ?Sized,
+            {
+                (**self).remove(key)
+            }
+        }
+
+        impl<K, F> ClearableQueryFilter<K> for $pointer
+        where
+            F: ClearableQueryFilter<K> + ?Sized,
+        {
+            fn clear(&mut self) {
+                (**self).clear()
+            }
+        }
+    )+};
+}
+
+/// Implements the concurrent insertion trait for a pointer type, delegating
+/// to the pointee.
+macro_rules! impl_concurrent_filter_for_pointer {
+    ($($pointer:ty),+) => {$(
+        impl<K, F> ConcurrentInsertableQueryFilter<K> for $pointer
+        where
+            F: ConcurrentInsertableQueryFilter<K> + Send + ?Sized,
+        {
+            fn insert(&self, key: K)
+            where
+                K: Eq + Hash,
+            {
+                (**self).insert(key)
+            }
+        }
+    )+};
+}
+
+// Shared and owned filters can be passed to generic code interchangeably.
+impl_query_filter_for_pointer!(&F, &mut F, Box<F>, Rc<F>, Arc<F>);
+impl_mutable_filter_for_pointer!(&mut F, Box<F>);
+impl_concurrent_filter_for_pointer!(&F, Box<F>, Arc<F>);
@@ -0,0 +1,163 @@
+//! MinHash signatures for set similarity.
+//!
+//! A [`MinHash`] sketch summarizes a set with a fixed number of minimum hash
+//! values, and estimates the Jaccard similarity `|A ∩ B| / |A ∪ B|` of two
+//! sets by comparing their sketches. Sketches are built with one-permutation
+//! hashing (each key is hashed once, into one of `k` bins), and empty bins are
+//! filled by rotation densification, as described in [Densifying One
+//! Permutation Hashing via Rotation for Fast Near Neighbor Search, 2014][1].
+//!
+//! [1]: https://proceedings.mlr.press/v32/shrivastava14.html
+
+use {
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+const EMPTY: u64 = u64::MAX;
+
+/// Offset added to values borrowed from a bin `j` positions away, so that
+/// borrowed values differ from original ones.
+const ROTATION_OFFSET: u64 = 0x9e3779b97f4a7c15;
+
+/// MinHash sketch of a set of keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MinHash<K> {
+    mins: Vec<u64>,
+    seed: u64,
+    phantom: PhantomData<K>,
+}
+
+impl<K> MinHash<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new empty sketch with `bins` bins.
+    ///
+    /// The standard error of Jaccard estimates is about `1 / sqrt(bins)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bins` is zero.
+    pub fn new(bins: usize) -> Self {
+        Self::with_seed(bins, 0)
+    }
+
+    /// Creates a new empty sketch with `bins` bins, and a given hash seed.
+    ///
+    /// Only sketches built with the same seed can be compared or merged.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bins` is zero.
+    pub fn with_seed(bins: usize, seed: u64) -> Self {
+        assert!(bins > 0, "sketch must have at least one bin");
+        Self {
+            mins: vec![EMPTY; bins],
+            seed,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of bins.
+    pub fn bins(&self) -> usize {
+        self.mins.len()
+    }
+
+    /// Returns `true` if no key has been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.mins.iter().all(|&min| min == EMPTY)
+    }
+
+    /// Adds a key to the sketched set.
+    ///
+    /// The key may be any borrowed form of the sketch's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for the
+    /// key type.
+    pub fn insert<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let hash = Xxh3Builder::new().with_seed(self.seed).hash_one(key);
+        let bin = ((hash as u128 * self.mins.len() as u128) >> 64) as usize;
+        // High bits select the bin, so rank within the bin by the low ones.
+        let value = hash.rotate_left(32);
+        self.mins[bin] = self.mins[bin].min(value);
+    }
+
+    /// Merges another sketch into this one, so that it sketches the union of
+    /// both sets.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sketches differ in the number of bins or seed.
+    pub fn merge(&mut self, other: &Self) {
+        self.assert_compatible(other);
+        for (min, &other) in self.mins.iter_mut().zip(&other.mins) {
+            *min = (*min).min(other);
+        }
+    }
+
+    /// Returns the estimated Jaccard similarity of the sketched sets.
+    ///
+    /// Two empty sets are considered identical.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sketches differ in the number of bins or seed.
+    pub fn jaccard_estimate(&self, other: &Self) -> f64 {
+        self.assert_compatible(other);
+        match (self.is_empty(), other.is_empty()) {
+            (true, true) => return 1.,
+            (true, false) | (false, true) => return 0.,
+            (false, false) => {}
+        }
+        let matches = self
+            .signature()
+            .into_iter()
+            .zip(other.signature())
+            .filter(|(a, b)| a == b)
+            .count();
+        matches as f64 / self.bins() as f64
+    }
+
+    /// Returns the densified signature: each empty bin takes the value of
+    /// the closest non-empty bin to its right (cyclically), offset by the
+    /// distance.
+    ///
+    /// All bins are empty only for an empty set, in which case so is the
+    /// signature.
+    pub fn signature(&self) -> Vec<u64> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let bins = self.mins.len();
+        let mut signature = self.mins.clone();
+        // Walk right to left twice, so that bins near the end see the
+        // non-empty bins at the start.
+        let mut next = None;
+        for i in (0..2 * bins).rev() {
+            let bin = i % bins;
+            if self.mins[bin] != EMPTY {
+                next = Some(i);
+            } else if let Some(next) = next.filter(|_| i < bins) {
+                let distance = (next - i) as u64;
+                signature[bin] =
+                    self.mins[next % bins].wrapping_add(distance.wrapping_mul(ROTATION_OFFSET));
+            }
+        }
+        signature
+    }
+
+    fn assert_compatible(&self, other: &Self) {
+        assert!(
+            self.bins() == other.bins() && self.seed == other.seed,
+            "sketches differ in bins or seed"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+//! Minimal-perfect-hash-based filter.
+//!
+//! A minimal perfect hash function (MPHF) maps each of `n` keys of a static
+//! set onto a distinct index within `[0, n)`. Storing an `r`-bit fingerprint
+//! of each key at its index yields a filter with false positive rate of about
+//! `2^-r`, using `r` bits per key plus the MPHF itself (~3.7 bits per key).
+//!
+//! The MPHF follows [Fast and scalable minimal perfect hashing for massive
+//! key sets, 2017][1] (BBHash): keys are hashed into a bit array per level,
+//! keys that landed alone keep their position, while colliding ones are
+//! pushed to the next (smaller) level. A key's index is the rank of its bit
+//! across all levels.
+//!
+//! [1]: https://arxiv.org/abs/1702.03154
+
+use {
+    crate::{storage::PackedArray, QueryFilter, QueryFilterError, QueryFilterResult},
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Ratio of level size to the number of keys placed at that level.
+///
+/// Larger values speed up construction and queries (fewer levels) at the
+/// cost of space, `2.0` gives roughly 3.7 bits per key.
+const GAMMA: f64 = 2.0;
+
+/// Maximum number of levels, before construction is given up on.
+const MAX_LEVELS: usize = 64;
+
+/// Number of bits covered by a single rank sample.
+const RANK_BLOCK_BITS: usize = 512;
+
+/// Static filter made of a minimal perfect hash function and an array of
+/// fingerprints.
+pub struct MphfFilter<K>
+where
+    K: Eq + Hash,
+{
+    mphf: Mphf,
+    fingerprints: PackedArray,
+    phantom: PhantomData<K>,
+}
+
+impl<K> MphfFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Builds a filter over the given set of keys, storing `fingerprint_bits`
+    /// bits per key. Repeated keys are fine.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fingerprint_bits` is not within `1..=64`.
+    pub fn from_keys(
+        keys: impl IntoIterator<Item = K>,
+        fingerprint_bits: u32,
+    ) -> QueryFilterResult<Self> {
+        let mut hashes = keys.into_iter().map(|key| hash(&key)).collect::<Vec<_>>();
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        let (mphf, placed) = Mphf::build(&hashes)?;
+        let mut fingerprints = PackedArray::new(hashes.len(), fingerprint_bits);
+        for (hash, bit) in placed {
+            fingerprints.set(mphf.rank(bit), fingerprint(hash));
+        }
+
+        let filter = Self {
+            mphf,
+            fingerprints,
+            phantom: PhantomData,
+        };
+        event!(
+            keys = filter.len(),
+            fingerprint_bits,
+            size_in_bytes = filter.size_in_bytes();
+            "built MPHF filter"
+        );
+        Ok(filter)
+    }
+
+    /// Returns the number of keys in the filter.
+    pub fn len(&self) -> usize {
+        self.fingerprints.len()
+    }
+
+    /// Returns `true` if the filter holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.fingerprints.is_empty()
+    }
+
+    /// Returns the number of bits stored per key for fingerprints.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.fingerprints.bits()
+    }
+
+    /// Returns the memory used by the filter, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.mphf.size_in_bytes() + self.fingerprints.size_in_bytes()
+    }
+}
+
+impl<K> QueryFilter<K> for MphfFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let hash = hash(key);
+        self.mphf.index(hash).is_some_and(|index| {
+            self.fingerprints.get(index)
+                == fingerprint(hash) & PackedArray::max_value(self.fingerprints.bits())
+        })
+    }
+}
+
+/// BBHash-style minimal perfect hash function over 64-bit key hashes.
+struct Mphf {
+    /// Bits of all levels, concatenated.
+    bits: Vec<u64>,
+    /// Offset (in bits) and length of each level.
+    levels: Vec<(usize, usize)>,
+    /// Number of set bits preceding each block of `RANK_BLOCK_BITS` bits.
+    ranks: Vec<usize>,
+}
+
+impl Mphf {
+    /// Builds the function over distinct hashes.
+    ///
+    /// Returns the function, along with the bit assigned to each hash.
+    fn build(hashes: &[u64]) -> QueryFilterResult<(Self, Vec<(u64, usize)>)> {
+        let mut bits = Vec::new();
+        let mut levels = Vec::new();
+        let mut placed = Vec::with_capacity(hashes.len());
+        let mut remaining = hashes.to_vec();
+
+        while !remaining.is_empty() {
+            if levels.len() == MAX_LEVELS {
+                return Err(QueryFilterError::LevelsExhausted(MAX_LEVELS));
+            }
+            let level = levels.len();
+            let len = ((remaining.len() as f64 * GAMMA).ceil() as usize).next_multiple_of(64);
+            let offset = bits.len() * 64;
+
+            let mut seen = vec![0u64; len / 64];
+            let mut collided = vec![0u64; len / 64];
+            for &hash in &remaining {
+                let pos = position(hash, level, len);
+                if seen[pos / 64] & (1 << (pos % 64)) != 0 {
+                    collided[pos / 64] |= 1 << (pos % 64);
+                }
+                seen[pos / 64] |= 1 << (pos % 64);
+            }
+
+            remaining.retain(|&hash| {
+                let pos = position(hash, level, len);
+                let collides = collided[pos / 64] & (1 << (pos % 64)) != 0;
+                if !collides {
+                    placed.push((hash, offset + pos));
+                }
+                collides
+            });
+            bits.extend(
+                seen.iter()
+                    .zip(&collided)
+                    .map(|(seen, collided)| seen & !collided),
+            );
+            levels.push((offset, len));
+        }
+
+        let words_per_block = RANK_BLOCK_BITS / 64;
+        let mut ranks = Vec::with_capacity(bits.len().div_ceil(words_per_block));
+        let mut rank = 0;
+        for block in bits.chunks(words_per_block) {
+            ranks.push(rank);
+            rank += block
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum::<usize>();
+        }
+
+        Ok((
+            Self {
+                bits,
+                levels,
+                ranks,
+            },
+            placed,
+        ))
+    }
+
+    /// Returns the index of a given hash, or `None` if it is definitely not
+    /// one of the hashes the function was built over.
+    fn index(&self, hash: u64) -> Option<usize> {
+        self.levels
+            .iter()
+            .enumerate()
+            .map(|(level, &(offset, len))| offset + position(hash, level, len))
+            .find(|&bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+            .map(|bit| self.rank(bit))
+    }
+
+    /// Returns the number of set bits preceding the given bit.
+    fn rank(&self, bit: usize) -> usize {
+        let word = bit / 64;
+        let block_start = word - word % (RANK_BLOCK_BITS / 64);
+        let preceding = self.bits[block_start..word]
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum::<usize>();
+        let partial = (self.bits[word] & ((1 << (bit % 64)) - 1)).count_ones() as usize;
+
+        self.ranks[bit / RANK_BLOCK_BITS] + preceding + partial
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.bits.len() * 8 + self.ranks.len() * size_of::<usize>()
+    }
+}
+
+fn hash<Q: Hash + ?Sized>(key: &Q) -> u64 {
+    Xxh3Builder::new().hash_one(key)
+}
+
+/// Returns the fingerprint of a key hash (truncated to the needed width on
+/// store).
+fn fingerprint(hash: u64) -> u64 {
+    hash.rotate_left(32)
+}
+
+/// Returns the position of a key hash within a level of a given length.
+fn position(hash: u64, level: usize, len: usize) -> usize {
+    // Remix the hash with a per-level constant (Murmur3 finalizer), so that
+    // keys colliding at one level are independent at the next one.
+    let mut z = hash ^ (level as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 33)).wrapping_mul(0xff51afd7ed558ccd);
+    z = (z ^ (z >> 33)).wrapping_mul(0xc4ceb9fe1a85ec53);
+    z ^= z >> 33;
+
+    ((z as u128 * len as u128) >> 64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mphf_is_minimal_and_perfect() {
+        let hashes = (0..10000u64).map(|i| hash(&i)).collect::<Vec<_>>();
+        let (mphf, placed) = Mphf::build(&hashes).unwrap();
+        assert_eq!(placed.len(), hashes.len());
+
+        let mut seen = vec![false; hashes.len()];
+        for hash in hashes {
+            let index = mphf.index(hash).unwrap();
+            assert!(!seen[index]);
+            seen[index] = true;
+        }
+        assert!(seen.into_iter().all(|seen| seen));
+
+        // Around 3.7 bits per key.
+        assert!(mphf.size_in_bytes() * 8 < 10000 * 4);
+    }
+}
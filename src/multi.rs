@@ -0,0 +1,70 @@
+//! Union view over several filters.
+//!
+//! [`MultiFilter`] queries a number of borrowed filters as one logical set,
+//! e.g. per-day or per-segment filters, without materializing a merged copy.
+
+use {
+    crate::QueryFilter,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Lazy union of borrowed filters.
+///
+/// A key is believed to be in the union if any member filter believes so.
+/// Members are queried in order, stopping at the first match, so the false
+/// positive rate of the union is at most the sum of the members' ones.
+pub struct MultiFilter<'a, K, F> {
+    filters: Vec<&'a F>,
+    phantom: PhantomData<K>,
+}
+
+impl<'a, K, F> MultiFilter<'a, K, F>
+where
+    F: QueryFilter<K>,
+{
+    /// Creates a new union over given filters.
+    pub fn new(filters: impl IntoIterator<Item = &'a F>) -> Self {
+        Self {
+            filters: filters.into_iter().collect(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the member filters, in query order.
+    pub fn filters(&self) -> &[&'a F] {
+        &self.filters
+    }
+
+    /// Returns the number of member filters.
+    pub fn len(&self) -> usize {
+        self.filters.len()
+    }
+
+    /// Returns `true` if there are no member filters.
+    pub fn is_empty(&self) -> bool {
+        self.filters.is_empty()
+    }
+
+    /// Returns the index of the first member filter believed to contain the
+    /// key, or `None` if no member does.
+    pub fn position<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.filters.iter().position(|filter| filter.contains(key))
+    }
+}
+
+impl<K, F> QueryFilter<K> for MultiFilter<'_, K, F>
+where
+    F: QueryFilter<K>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.position(key).is_some()
+    }
+}
@@ -0,0 +1,139 @@
+//! Namespaced Bloom filter.
+//!
+//! A [`NamespacedFilter`] serves many independent key sets (tenants) out of a
+//! single allocation: the bit array is split into equally sized regions, one
+//! per namespace, each acting as a classic Bloom filter of its own. Tenants
+//! are isolated (keys of one never cause false positives in another), and can
+//! be cleared and inspected individually, without the overhead of thousands
+//! of separately allocated tiny filters.
+
+use {
+    crate::{
+        analysis::{optimal_bit_count, optimal_hash_count},
+        hash::ProbeHasher,
+    },
+    fixedbitset::FixedBitSet,
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Bloom filter partitioned into per-namespace regions.
+pub struct NamespacedFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    bits: FixedBitSet,
+    region_bits: usize,
+    hasher: H,
+    k: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K> NamespacedFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with `namespaces` regions, each sized for a
+    /// desired capacity and false positive rate.
+    pub fn new(namespaces: usize, capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(namespaces, capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> NamespacedFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter with `namespaces` regions, each sized for a
+    /// desired capacity and false positive rate, and a given hasher.
+    ///
+    /// Regions are rounded up to a whole number of 64-bit words, so that
+    /// clearing one never touches its neighbours' words.
+    pub fn with_hasher(namespaces: usize, capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        let bit_count = optimal_bit_count(capacity, fp_rate);
+        let k = optimal_hash_count(capacity, bit_count);
+        let region_bits = bit_count.div_ceil(64).max(1) * 64;
+        Self {
+            bits: FixedBitSet::with_capacity(namespaces * region_bits),
+            region_bits,
+            hasher,
+            k,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of namespaces.
+    pub fn namespace_count(&self) -> usize {
+        self.bits.len() / self.region_bits
+    }
+
+    /// Returns the number of bits in each namespace's region.
+    pub fn region_bits(&self) -> usize {
+        self.region_bits
+    }
+
+    /// Inserts a key into a namespace.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the namespace is out of range.
+    pub fn insert(&mut self, namespace: usize, key: K) {
+        let offset = self.offset(namespace);
+        for hash in self.hasher.hash_iter(&key, self.k) {
+            self.bits
+                .insert(offset + (hash % self.region_bits as u64) as usize);
+        }
+    }
+
+    /// Returns `true` if the key is believed to be in a namespace.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the namespace is out of range.
+    pub fn contains<Q>(&self, namespace: usize, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let offset = self.offset(namespace);
+        self.hasher
+            .hash_iter(key, self.k)
+            .all(|hash| self.bits[offset + (hash % self.region_bits as u64) as usize])
+    }
+
+    /// Removes all keys of a namespace, leaving others intact.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the namespace is out of range.
+    pub fn clear_namespace(&mut self, namespace: usize) {
+        let offset = self.offset(namespace);
+        self.bits
+            .set_range(offset..offset + self.region_bits, false);
+    }
+
+    /// Returns the approximate number of elements currently in a namespace.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the namespace is out of range.
+    pub fn approx_namespace_capacity(&self, namespace: usize) -> usize {
+        let offset = self.offset(namespace);
+        let bits_count = self.region_bits as f64;
+        let ones_count = self.bits.count_ones(offset..offset + self.region_bits) as f64;
+        let hash_count = self.k as f64;
+        let count = -(bits_count / hash_count) * (1. - (ones_count / bits_count)).ln();
+
+        count.round() as usize
+    }
+
+    /// Returns the offset of a namespace's region.
+    fn offset(&self, namespace: usize) -> usize {
+        assert!(
+            namespace < self.namespace_count(),
+            "namespace {namespace} out of range"
+        );
+        namespace * self.region_bits
+    }
+}
@@ -0,0 +1,155 @@
+//! Negative cache combinator.
+//!
+//! A membership filter in front of a backend saves lookups of keys that are
+//! certainly absent, but a key it reports as present (rightly or not) still
+//! costs a backend lookup, every time. [`WithNegativeCache`] adds the mirror
+//! image: keys the backend confirmed missing are recorded in a second Bloom
+//! filter, so that repeated lookups of the same missing keys (hot misses,
+//! false positives of the main filter) are answered without the backend.
+//!
+//! Unlike the main filter, the negative cache errs the other way: its false
+//! positives, and entries for keys added to the backend since, make present
+//! keys look missing. Entries are therefore aged out: the cache is made of
+//! two generations, and once the current one has recorded its capacity of
+//! keys, the previous one is dropped, so an entry lives for at least one and
+//! at most two generations.
+
+use {
+    crate::{hash::ProbeHasher, BloomFilter, InsertableQueryFilter, QueryFilter},
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash},
+};
+
+/// Outcome of a lookup through a [`WithNegativeCache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Lookup {
+    /// The main filter rules the key out: it is certainly absent.
+    Absent,
+    /// The backend recently confirmed the key missing.
+    KnownMissing,
+    /// The key may be present, and the backend must be consulted.
+    MaybePresent,
+}
+
+/// Filter combined with a rotating cache of keys confirmed missing.
+pub struct WithNegativeCache<F, K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    filter: F,
+    current: BloomFilter<K, H>,
+    previous: Option<BloomFilter<K, H>>,
+    recorded: usize,
+    capacity: usize,
+    fp_rate: f64,
+    hasher: H,
+}
+
+impl<F, K> WithNegativeCache<F, K>
+where
+    F: QueryFilter<K>,
+    K: Eq + Hash,
+{
+    /// Wraps a filter, with a negative cache generation sized for a desired
+    /// number of keys and false positive rate.
+    pub fn new(filter: F, capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(filter, capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<F, K, H> WithNegativeCache<F, K, H>
+where
+    F: QueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Wraps a filter, with a negative cache generation sized for a desired
+    /// number of keys and false positive rate, and a given hasher.
+    pub fn with_hasher(filter: F, capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        Self {
+            filter,
+            current: BloomFilter::with_capacity_and_hasher(capacity, fp_rate, hasher.clone()),
+            previous: None,
+            recorded: 0,
+            capacity,
+            fp_rate,
+            hasher,
+        }
+    }
+
+    /// Returns the wrapped filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Returns the wrapped filter, mutably.
+    ///
+    /// Keys added to the backend may still be cached as missing, see
+    /// [`insert`](WithNegativeCache::insert).
+    pub fn filter_mut(&mut self) -> &mut F {
+        &mut self.filter
+    }
+
+    /// Looks a key up, telling whether the backend needs to be consulted.
+    pub fn lookup<Q>(&self, key: &Q) -> Lookup
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if !self.filter.contains(key) {
+            Lookup::Absent
+        } else if self.current.contains(key)
+            || self
+                .previous
+                .as_ref()
+                .is_some_and(|previous| previous.contains(key))
+        {
+            Lookup::KnownMissing
+        } else {
+            Lookup::MaybePresent
+        }
+    }
+
+    /// Records a key the backend confirmed missing.
+    pub fn record_missing(&mut self, key: K) {
+        if self.recorded == self.capacity {
+            self.rotate();
+        }
+        self.current.insert(key);
+        self.recorded += 1;
+    }
+
+    /// Starts a new cache generation, dropping the previous one.
+    pub fn rotate(&mut self) {
+        let next =
+            BloomFilter::with_capacity_and_hasher(self.capacity, self.fp_rate, self.hasher.clone());
+        self.previous = Some(std::mem::replace(&mut self.current, next));
+        self.recorded = 0;
+        event!(capacity = self.capacity; "rotated negative cache");
+    }
+
+    /// Drops all cached misses, e.g. after a bulk load into the backend.
+    pub fn clear_negative_cache(&mut self) {
+        self.rotate();
+        self.previous = None;
+    }
+}
+
+impl<F, K, H> WithNegativeCache<F, K, H>
+where
+    F: InsertableQueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Inserts a key into the wrapped filter.
+    ///
+    /// Bloom filters cannot forget keys, so if the key was cached as missing,
+    /// it keeps being reported as [`KnownMissing`](Lookup::KnownMissing)
+    /// until its cache generation is dropped. Callers for whom that is not
+    /// acceptable should [`clear_negative_cache`] after inserting.
+    ///
+    /// [`clear_negative_cache`]: WithNegativeCache::clear_negative_cache
+    pub fn insert(&mut self, key: K) {
+        self.filter.insert(key);
+    }
+}
@@ -0,0 +1,397 @@
+//! Apache ORC Bloom filter interoperability.
+//!
+//! ORC writers may keep a Bloom filter per column and row group, stored in
+//! the column's `BLOOM_FILTER_UTF8` (or legacy `BLOOM_FILTER`) stream as a
+//! protobuf `BloomFilterIndex` message: one `BloomFilter` per row group. An
+//! [`OrcBloomFilter`] is bit-compatible with a single such filter, and
+//! [`encode_index`]/[`decode_index`] convert between filters and (already
+//! decompressed) stream contents.
+//!
+//! ORC hashes values by type: integers (and dates, timestamps as
+//! milliseconds) with Thomas Wang's 64-bit integer hash, floating point
+//! values by their bits, and everything else (strings, decimals, binary) by
+//! a 64-bit Murmur3 variant of its UTF-8 bytes. The two halves of the 64-bit
+//! hash are combined by double hashing, using Java's 32-bit arithmetic.
+//!
+//! Filters in legacy `BLOOM_FILTER` streams, written by old writers, hashed
+//! strings in the writer's default charset, so non-ASCII strings should only
+//! be queried against `BLOOM_FILTER_UTF8` streams.
+
+use crate::{QueryFilterError, QueryFilterResult};
+
+/// Murmur3 seed used by ORC.
+const SEED: u64 = 104729;
+
+/// Hash of a null value.
+const NULL_HASH: u64 = 2862933555777941757;
+
+/// Single (row group) ORC Bloom filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrcBloomFilter {
+    words: Vec<u64>,
+    hash_count: u32,
+}
+
+impl OrcBloomFilter {
+    /// Creates a new empty filter for a desired number of values and false
+    /// positive rate, sized the way ORC writers size theirs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `fp_rate` is not within `(0, 1)`.
+    pub fn new(expected_entries: usize, fp_rate: f64) -> Self {
+        assert!(
+            fp_rate > 0.0 && fp_rate < 1.0,
+            "false positive rate must be within (0, 1)"
+        );
+        let n = expected_entries.max(1) as f64;
+        let bits = (-n * fp_rate.ln() / (2f64.ln() * 2f64.ln())) as i32 as usize;
+        // Always rounded up to the *next* multiple of 64.
+        let bit_count = bits + (64 - bits % 64);
+        let hash_count = ((bit_count as f64 / n * 2f64.ln()).round() as u32).max(1);
+        Self {
+            words: vec![0; bit_count / 64],
+            hash_count,
+        }
+    }
+
+    /// Wraps the words of a filter read from a stream, along with its hash
+    /// count.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no words.
+    pub fn from_words(words: Vec<u64>, hash_count: u32) -> Self {
+        assert!(!words.is_empty(), "filter must not be empty");
+        Self { words, hash_count }
+    }
+
+    /// Returns the bits of the filter, as 64-bit words.
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Returns the number of bits.
+    pub fn bit_count(&self) -> usize {
+        self.words.len() * 64
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> u32 {
+        self.hash_count
+    }
+
+    /// Adds an integer value (also used for booleans, dates as days, and
+    /// timestamps as milliseconds).
+    pub fn insert_long(&mut self, value: i64) {
+        self.insert_hash(long_hash(value));
+    }
+
+    /// Adds a floating point value.
+    pub fn insert_double(&mut self, value: f64) {
+        self.insert_long(double_bits(value));
+    }
+
+    /// Adds a raw byte value, e.g. the UTF-8 bytes of a string.
+    pub fn insert_bytes(&mut self, value: &[u8]) {
+        self.insert_hash(murmur3(value, SEED));
+    }
+
+    /// Adds a string value (also used for decimals, in their string form).
+    pub fn insert_str(&mut self, value: &str) {
+        self.insert_bytes(value.as_bytes());
+    }
+
+    /// Adds a null value.
+    pub fn insert_null(&mut self) {
+        self.insert_hash(NULL_HASH);
+    }
+
+    /// Returns `true` if the integer value is believed to be in the filter.
+    pub fn contains_long(&self, value: i64) -> bool {
+        self.contains_hash(long_hash(value))
+    }
+
+    /// Returns `true` if the floating point value is believed to be in the
+    /// filter.
+    pub fn contains_double(&self, value: f64) -> bool {
+        self.contains_long(double_bits(value))
+    }
+
+    /// Returns `true` if the byte value is believed to be in the filter.
+    pub fn contains_bytes(&self, value: &[u8]) -> bool {
+        self.contains_hash(murmur3(value, SEED))
+    }
+
+    /// Returns `true` if the string value is believed to be in the filter.
+    pub fn contains_str(&self, value: &str) -> bool {
+        self.contains_bytes(value.as_bytes())
+    }
+
+    /// Returns `true` if a null value is believed to be in the filter.
+    pub fn contains_null(&self) -> bool {
+        self.contains_hash(NULL_HASH)
+    }
+
+    fn insert_hash(&mut self, hash: u64) {
+        for bit in self.bits(hash) {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn contains_hash(&self, hash: u64) -> bool {
+        self.bits(hash)
+            .all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    /// Returns the bits selected by a hash.
+    ///
+    /// Mirrors ORC's Java arithmetic: wrapping 32-bit signed sums, with
+    /// negative sums bit-flipped.
+    fn bits(&self, hash: u64) -> impl Iterator<Item = usize> {
+        let bit_count = self.bit_count();
+        let hash1 = hash as i32;
+        let hash2 = (hash >> 32) as i32;
+        (1..=self.hash_count as i32).map(move |i| {
+            let mut combined = hash1.wrapping_add(i.wrapping_mul(hash2));
+            if combined < 0 {
+                combined = !combined;
+            }
+            combined as usize % bit_count
+        })
+    }
+}
+
+/// Encodes filters (one per row group) as a `BloomFilterIndex` message, the
+/// contents of a `BLOOM_FILTER_UTF8` stream (before compression).
+pub fn encode_index(filters: &[OrcBloomFilter]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for filter in filters {
+        let mut message = Vec::with_capacity(filter.words.len() * 8 + 16);
+        // numHashFunctions = 1 (varint)
+        message.push(1 << 3);
+        write_varint(&mut message, filter.hash_count as u64);
+        // utf8bitset = 3 (bytes)
+        message.push(3 << 3 | 2);
+        write_varint(&mut message, filter.words.len() as u64 * 8);
+        message.extend(filter.words.iter().flat_map(|word| word.to_le_bytes()));
+
+        // bloomFilter = 1 (message)
+        bytes.push(1 << 3 | 2);
+        write_varint(&mut bytes, message.len() as u64);
+        bytes.extend_from_slice(&message);
+    }
+    bytes
+}
+
+/// Decodes a `BloomFilterIndex` message, the contents of a
+/// `BLOOM_FILTER_UTF8` or `BLOOM_FILTER` stream (after decompression).
+///
+/// Fails if the message is malformed, or a filter has no bits.
+pub fn decode_index(bytes: &[u8]) -> QueryFilterResult<Vec<OrcBloomFilter>> {
+    let mut filters = Vec::new();
+    for field in Fields(bytes) {
+        if let (1, Value::Bytes(message)) = field? {
+            filters.push(decode_filter(message)?);
+        }
+    }
+    Ok(filters)
+}
+
+fn decode_filter(bytes: &[u8]) -> QueryFilterResult<OrcBloomFilter> {
+    let mut hash_count = 0;
+    let mut words = Vec::new();
+    for field in Fields(bytes) {
+        match field? {
+            (1, Value::Varint(value)) => hash_count = value as u32,
+            // `bitset`, either unpacked or packed.
+            (2, Value::Fixed64(word)) => words.push(word),
+            (2, Value::Bytes(packed)) | (3, Value::Bytes(packed)) => {
+                if packed.len() % 8 != 0 {
+                    return Err(invalid("bitset length is not a multiple of 8"));
+                }
+                words.extend(
+                    packed
+                        .chunks_exact(8)
+                        .map(|word| u64::from_le_bytes(word.try_into().unwrap())),
+                );
+            }
+            _ => {}
+        }
+    }
+    if words.is_empty() {
+        return Err(invalid("empty bitset"));
+    }
+    Ok(OrcBloomFilter::from_words(words, hash_count))
+}
+
+fn invalid(reason: &str) -> QueryFilterError {
+    QueryFilterError::Other(format!("invalid ORC bloom filter index: {reason}"))
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Value of a protobuf field.
+enum Value<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Fixed32,
+    Bytes(&'a [u8]),
+}
+
+/// Iterator over the fields of a protobuf message, as `(number, value)`.
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn varint(&mut self) -> QueryFilterResult<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().ok_or_else(|| invalid("truncated"))?;
+            self.0 = rest;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(invalid("varint too long"))
+    }
+
+    fn take(&mut self, len: usize) -> QueryFilterResult<&'a [u8]> {
+        if self.0.len() < len {
+            return Err(invalid("truncated"));
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn field(&mut self) -> QueryFilterResult<(u64, Value<'a>)> {
+        let key = self.varint()?;
+        let value = match key & 7 {
+            0 => Value::Varint(self.varint()?),
+            1 => Value::Fixed64(u64::from_le_bytes(self.take(8)?.try_into().unwrap())),
+            2 => {
+                let len = self.varint()? as usize;
+                Value::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Value::Fixed32
+            }
+            wire_type => return Err(invalid(&format!("unsupported wire type {wire_type}"))),
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for Fields<'a> {
+    type Item = QueryFilterResult<(u64, Value<'a>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let field = self.field();
+        if field.is_err() {
+            // Stop after the first error.
+            self.0 = &[];
+        }
+        Some(field)
+    }
+}
+
+/// Thomas Wang's 64-bit integer hash, with Java's (arithmetic) right shifts.
+fn long_hash(mut key: i64) -> u64 {
+    key = (!key).wrapping_add(key << 21);
+    key ^= key >> 24;
+    key = key.wrapping_add(key << 3).wrapping_add(key << 8);
+    key ^= key >> 14;
+    key = key.wrapping_add(key << 2).wrapping_add(key << 4);
+    key ^= key >> 28;
+    key = key.wrapping_add(key << 31);
+    key as u64
+}
+
+/// Returns the bits of a double, like Java's `Double.doubleToLongBits` (all
+/// NaNs collapse into the canonical one).
+fn double_bits(value: f64) -> i64 {
+    if value.is_nan() {
+        0x7ff8000000000000
+    } else {
+        value.to_bits() as i64
+    }
+}
+
+/// ORC's 64-bit Murmur3 variant (not the first half of Murmur3 x64 128).
+fn murmur3(data: &[u8], seed: u64) -> u64 {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+    let mix = |k: u64| k.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+
+    let mut hash = seed;
+    let mut chunks = data.chunks_exact(8);
+    for chunk in &mut chunks {
+        hash ^= mix(u64::from_le_bytes(chunk.try_into().unwrap()));
+        hash = hash
+            .rotate_left(27)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dce729);
+    }
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let k = tail.iter().rev().fold(0, |k, &byte| k << 8 | byte as u64);
+        hash ^= mix(k);
+    }
+
+    hash ^= data.len() as u64;
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_match_java() {
+        // Reference values computed with ORC's Java implementation.
+        for (value, hash) in [
+            ("", 0x74a18dc8f20adb48),
+            ("a", 0xddd9b0af19f61187),
+            ("hello", 0x3928100018224141),
+            ("orc-bloom", 0xfd270c10d44979df),
+            ("0123456789abcdef-x", 0xd5186eca6cadd413),
+            ("été", 0x9646d11354dfb59e),
+        ] {
+            assert_eq!(murmur3(value.as_bytes(), SEED), hash, "{value:?}");
+        }
+        for (value, hash) in [
+            (0, 0),
+            (1, 0x5bca7c69b794f8ce),
+            (-1, 0x5bca868437950d03),
+            (42, 0x0f3db82f1e7b6f7a),
+            (i64::MIN, 0x3be7d0f7780de548),
+            (1234567890123, 0x2331ba7f2269f7c6),
+        ] {
+            assert_eq!(long_hash(value), hash, "{value}");
+        }
+    }
+
+    #[test]
+    fn bits_match_java() {
+        let filter = OrcBloomFilter::new(1000, 0.05);
+        assert_eq!(filter.bit_count(), 6272);
+        assert_eq!(filter.hash_count(), 4);
+        let bits = filter.bits(murmur3(b"hello", SEED)).collect::<Vec<_>>();
+        assert_eq!(bits, [3649, 3902, 3134, 2366]);
+    }
+}
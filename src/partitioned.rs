@@ -0,0 +1,174 @@
+//! Partitioned Bloom filter, for horizontally scaled membership.
+//!
+//! A [`PartitionedFilter`] splits its keys across independent Bloom filters
+//! (partitions), each of which may live on a different node. Keys are routed
+//! with rendezvous (highest random weight) hashing: every partition scores
+//! the key, and the highest score wins. Adding a partition only moves the
+//! keys it now wins (about `1 / (n + 1)` of them), and removing one only
+//! moves the keys it owned, to their runner-up partitions.
+//!
+//! Bloom filters cannot enumerate their keys, so moved keys are not carried
+//! over: until they are inserted again (from the source of truth, selecting
+//! keys with [`partition_of`]), queries for them are false negatives.
+//!
+//! [`partition_of`]: PartitionedFilter::partition_of
+
+use {
+    crate::{hash::ProbeHasher, BloomFilter, InsertableQueryFilter, QueryFilter},
+    hash_iter::HashIterHasher,
+    std::{
+        borrow::Borrow,
+        collections::BTreeMap,
+        hash::{BuildHasher, Hash},
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Bloom filter split into partitions by rendezvous hashing.
+pub struct PartitionedFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    partitions: BTreeMap<u64, BloomFilter<K, H>>,
+    capacity: usize,
+    fp_rate: f64,
+    hasher: H,
+}
+
+impl<K> PartitionedFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with given partition ids, each partition sized
+    /// for a desired capacity and false positive rate.
+    pub fn new(partitions: impl IntoIterator<Item = u64>, capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(partitions, capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> PartitionedFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Creates a new filter with given partition ids, each partition sized
+    /// for a desired capacity and false positive rate, and a given hasher.
+    pub fn with_hasher(
+        partitions: impl IntoIterator<Item = u64>,
+        capacity: usize,
+        fp_rate: f64,
+        hasher: H,
+    ) -> Self {
+        let mut filter = Self {
+            partitions: BTreeMap::new(),
+            capacity,
+            fp_rate,
+            hasher,
+        };
+        for id in partitions {
+            filter.add_partition(id);
+        }
+        filter
+    }
+
+    /// Returns the ids of all partitions, in ascending order.
+    pub fn partition_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.partitions.keys().copied()
+    }
+
+    /// Returns the number of partitions.
+    pub fn partition_count(&self) -> usize {
+        self.partitions.len()
+    }
+
+    /// Returns the id of the partition owning a key, or `None` if there are
+    /// no partitions.
+    pub fn partition_of<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let hash = Xxh3Builder::new().hash_one(key);
+        self.partitions
+            .keys()
+            .copied()
+            .max_by_key(|&id| score(hash, id))
+    }
+
+    /// Returns a partition, e.g. to export it to another node.
+    pub fn partition(&self, id: u64) -> Option<&BloomFilter<K, H>> {
+        self.partitions.get(&id)
+    }
+
+    /// Adds a new, empty partition. Does nothing if the partition exists.
+    ///
+    /// Keys now owned by the partition must be inserted again.
+    pub fn add_partition(&mut self, id: u64) {
+        let (capacity, fp_rate) = (self.capacity, self.fp_rate);
+        let hasher = &self.hasher;
+        self.partitions.entry(id).or_insert_with(|| {
+            BloomFilter::with_capacity_and_hasher(capacity, fp_rate, hasher.clone())
+        });
+    }
+
+    /// Adds a partition imported from elsewhere (e.g. another node),
+    /// replacing and returning the existing one, if any.
+    ///
+    /// The imported filter must use the same hasher as this one, otherwise
+    /// keys inserted into it are not found.
+    pub fn import_partition(
+        &mut self,
+        id: u64,
+        partition: BloomFilter<K, H>,
+    ) -> Option<BloomFilter<K, H>> {
+        self.partitions.insert(id, partition)
+    }
+
+    /// Removes and returns a partition.
+    ///
+    /// Keys it owned move to other partitions, and must be inserted again.
+    pub fn remove_partition(&mut self, id: u64) -> Option<BloomFilter<K, H>> {
+        self.partitions.remove(&id)
+    }
+}
+
+impl<K, H> QueryFilter<K> for PartitionedFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.partition_of(key)
+            .is_some_and(|id| self.partitions[&id].contains(key))
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for PartitionedFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Inserts a key into the partition owning it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no partitions.
+    fn insert(&mut self, key: K) {
+        let id = self.partition_of(&key).expect("no partitions");
+        self.partitions.get_mut(&id).unwrap().insert(key);
+    }
+}
+
+/// Returns the rendezvous score of a partition for a key hash.
+fn score(hash: u64, id: u64) -> u64 {
+    // Murmur3 finalizer over the combined hash, so that scores of different
+    // partitions are independent.
+    let mut z = hash ^ id.wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 33)).wrapping_mul(0xff51afd7ed558ccd);
+    z = (z ^ (z >> 33)).wrapping_mul(0xc4ceb9fe1a85ec53);
+    z ^ (z >> 33)
+}
@@ -0,0 +1,223 @@
+//! Pattern Bloom filter.
+//!
+//! The most query-efficient point in the blocked Bloom filter design space,
+//! following [Cache-, Hash- and Space-Efficient Bloom Filters, 2007][1]: all
+//! probes of a key land in a single 64-bit word, and instead of computing `k`
+//! bit positions per key, the key selects one of `2^t` precomputed patterns
+//! of `k` bits. A query is therefore a single load plus a mask compare. The
+//! price is space: words fill unevenly, so for the same false positive rate
+//! the filter needs noticeably more bits than a classic Bloom filter (sizing
+//! accounts for that, see [`blocked_fp_rate`]). With a limited number of
+//! patterns, keys sharing a word also share patterns now and then, which
+//! makes very low false positive rates (well below 0.1%) expensive: this
+//! filter is meant for moderate rates.
+//!
+//! [1]: https://algo2.iti.kit.edu/documents/cacheefficientbloomfilters-jea.pdf
+
+use {
+    crate::{
+        analysis::{blocked_fp_rate, optimal_bit_count, optimal_capacity},
+        hash::ProbeHasher,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Number of hash bits selecting the pattern, i.e. there are `2^t` patterns.
+pub const PATTERN_BITS: u32 = 12;
+
+const PATTERN_COUNT: usize = 1 << PATTERN_BITS;
+
+/// Maximum number of bits set in a pattern.
+const MAX_HASH_COUNT: usize = 16;
+
+/// Bloom filter setting one of precomputed bit patterns in a single word.
+pub struct PatternBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    words: Vec<u64>,
+    patterns: Box<[u64]>,
+    hasher: H,
+    phantom: PhantomData<K>,
+}
+
+impl<K> PatternBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate)
+    }
+
+    /// Creates a new filter with a desired size (in bytes) and false positive
+    /// rate.
+    pub fn with_size(size: usize, fp_rate: f64) -> Self {
+        Self::with_size_and_hasher(size, fp_rate, ProbeHasher::default())
+    }
+
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> PatternBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter with a desired size (in bytes), false positive
+    /// rate, and hasher.
+    ///
+    /// The size is used as is (rounded up to a whole word), and the number of
+    /// bits per pattern is picked to minimize the false positive rate at the
+    /// capacity a classic Bloom filter of that size would have.
+    pub fn with_size_and_hasher(size: usize, fp_rate: f64, hasher: H) -> Self {
+        let bit_count = (size * 8).max(64);
+        let capacity = optimal_capacity(bit_count, fp_rate);
+        Self::with_parameters(bit_count, best_hash_count(bit_count, capacity).0, hasher)
+    }
+
+    /// Creates a new filter with a desired capacity, false positive rate, and
+    /// hasher.
+    ///
+    /// Starting from the size of an optimal classic Bloom filter, the filter
+    /// is grown until the expected false positive rate of single-word blocks
+    /// meets the target.
+    pub fn with_capacity_and_hasher(capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        let mut bit_count = optimal_bit_count(capacity, fp_rate).max(64);
+        loop {
+            let (k, rate) = best_hash_count(bit_count, capacity);
+            if rate <= fp_rate {
+                return Self::with_parameters(bit_count, k, hasher);
+            }
+            bit_count += bit_count / 32;
+        }
+    }
+
+    fn with_parameters(bit_count: usize, k: usize, hasher: H) -> Self {
+        Self {
+            words: vec![0; bit_count.div_ceil(64)],
+            patterns: patterns(k),
+            hasher,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the hasher used to hash keys.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the number of bits set by each key.
+    pub fn hash_count(&self) -> usize {
+        self.patterns[0].count_ones() as usize
+    }
+
+    /// Returns the memory used by the filter's words, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.words.len() * 8
+    }
+
+    /// Returns the word index and the pattern of a given key.
+    fn locate<Q: Hash + ?Sized>(&self, key: &Q) -> (usize, u64) {
+        let hash = self.hasher.hash_iter(key, 1).next().unwrap_or_default();
+        // High bits select the word, low ones the pattern.
+        let word = ((hash as u128 * self.words.len() as u128) >> 64) as usize;
+        (word, self.patterns[hash as usize % PATTERN_COUNT])
+    }
+}
+
+impl<K, H> QueryFilter<K> for PatternBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let (word, pattern) = self.locate(key);
+        self.words[word] & pattern == pattern
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for PatternBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn insert(&mut self, key: K) {
+        let (word, pattern) = self.locate(&key);
+        self.words[word] |= pattern;
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for PatternBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn clear(&mut self) {
+        self.words.fill(0);
+    }
+}
+
+/// Returns the number of bits per pattern minimizing the false positive rate,
+/// along with that rate.
+///
+/// On top of the rate of a blocked filter with single-word blocks, a query
+/// matches whenever some key in its word picked the very same pattern, which
+/// happens with probability of about `load / 2^t`.
+fn best_hash_count(bit_count: usize, capacity: usize) -> (usize, f64) {
+    let load = 64. * capacity as f64 / bit_count as f64;
+    let collisions = load / PATTERN_COUNT as f64;
+    (1..=MAX_HASH_COUNT)
+        .map(|k| (k, blocked_fp_rate(bit_count, capacity, k, 64) + collisions))
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .unwrap_or((1, 1.))
+}
+
+/// Generates the pattern table: `2^t` words with `k` distinct random bits set.
+///
+/// Patterns are derived from a fixed SplitMix64 sequence, so that equally
+/// sized filters share the same table.
+fn patterns(k: usize) -> Box<[u64]> {
+    let mut state = 0u64;
+    let mut next = || {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    };
+    (0..PATTERN_COUNT)
+        .map(|_| {
+            let mut pattern = 0u64;
+            while (pattern.count_ones() as usize) < k {
+                pattern |= 1 << (next() % 64);
+            }
+            pattern
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns_have_k_bits() {
+        for k in 1..=MAX_HASH_COUNT {
+            let patterns = patterns(k);
+            assert_eq!(patterns.len(), PATTERN_COUNT);
+            assert!(patterns.iter().all(|p| p.count_ones() as usize == k));
+        }
+    }
+}
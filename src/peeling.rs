@@ -0,0 +1,138 @@
+//! Hypergraph peeling shared by xor-based static structures.
+//!
+//! Each key is mapped onto three slots (one per segment of the table), and
+//! the table is solved by repeatedly "peeling" slots that are referenced by a
+//! single remaining key, as described in [Xor Filters: Faster and Smaller Than
+//! Bloom and Cuckoo Filters, 2020][1]. Peeling order, reversed, is the order
+//! in which slots can be assigned so that the xor of a key's three slots
+//! equals whatever value that key should map to.
+//!
+//! [1]: https://arxiv.org/abs/1912.08258
+
+/// Maximum number of seeds to try before giving up on construction.
+pub(crate) const MAX_ATTEMPTS: usize = 100;
+
+/// Returns the length of a single segment, for a table holding `n` keys.
+///
+/// The whole table holds three segments, i.e. `~1.23 * n + 32` slots.
+#[cfg(any(feature = "retrieval", feature = "xor"))]
+pub(crate) fn segment_length(n: usize) -> usize {
+    (32 + (n as f64 * 1.23).ceil() as usize) / 3
+}
+
+/// Returns the three slots (one in each segment) of a given key hash.
+#[cfg(any(feature = "retrieval", feature = "xor"))]
+pub(crate) fn slots(hash: u64, segment_length: usize) -> [usize; 3] {
+    let reduce = |hash: u64| ((hash as u32 as u64 * segment_length as u64) >> 32) as usize;
+    [
+        reduce(hash),
+        reduce(hash.rotate_left(21)) + segment_length,
+        reduce(hash.rotate_left(42)) + 2 * segment_length,
+    ]
+}
+
+/// Peels the hypergraph of given (distinct) key hashes.
+///
+/// On success, returns `(key index, slot)` pairs in peeling order: to solve
+/// the table, assign slots in reverse order. Returns `None` if the graph has
+/// a non-empty 2-core, in which case construction must be retried with a
+/// different seed.
+#[cfg(any(feature = "retrieval", feature = "xor"))]
+pub(crate) fn peel(hashes: &[u64], segment_length: usize) -> Option<Vec<(usize, usize)>> {
+    peel_with(hashes, 3 * segment_length, |hash| {
+        slots(hash, segment_length)
+    })
+}
+
+/// Peels the hypergraph of given (distinct) key hashes, over a table of
+/// `len` slots, with a custom mapping of hashes onto `N` distinct slots.
+///
+/// See [`peel`].
+#[cfg(any(feature = "retrieval", feature = "xor", feature = "fuse"))]
+pub(crate) fn peel_with<const N: usize>(
+    hashes: &[u64],
+    len: usize,
+    slots: impl Fn(u64) -> [usize; N],
+) -> Option<Vec<(usize, usize)>> {
+    let mut counts = vec![0u32; len];
+    let mut xors = vec![0usize; len];
+    for (i, &hash) in hashes.iter().enumerate() {
+        for slot in slots(hash) {
+            counts[slot] += 1;
+            xors[slot] ^= i;
+        }
+    }
+
+    let mut queue = (0..counts.len())
+        .filter(|&slot| counts[slot] == 1)
+        .collect::<Vec<_>>();
+    let mut stack = Vec::with_capacity(hashes.len());
+    while let Some(slot) = queue.pop() {
+        if counts[slot] != 1 {
+            continue;
+        }
+        let i = xors[slot];
+        stack.push((i, slot));
+        for other in slots(hashes[i]) {
+            counts[other] -= 1;
+            xors[other] ^= i;
+            if counts[other] == 1 {
+                queue.push(other);
+            }
+        }
+    }
+
+    (stack.len() == hashes.len()).then_some(stack)
+}
+
+/// Derives the seed of the given construction attempt (SplitMix64).
+pub(crate) fn seed(attempt: usize) -> u64 {
+    let mut z = (attempt as u64)
+        .wrapping_add(1)
+        .wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(all(test, any(feature = "retrieval", feature = "xor")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slots_are_in_distinct_segments() {
+        let segment_length = segment_length(1000);
+        for i in 0..1000 {
+            let slots = slots(seed(i), segment_length);
+            for (segment, slot) in slots.into_iter().enumerate() {
+                assert!(slot >= segment * segment_length);
+                assert!(slot < (segment + 1) * segment_length);
+            }
+        }
+    }
+
+    #[test]
+    fn peel_works() {
+        // Peeling fails with a small probability, so retry with shifted hashes
+        // just like construction retries with different seeds.
+        let segment_length = segment_length(10000);
+        let (hashes, order) = (0..MAX_ATTEMPTS)
+            .find_map(|attempt| {
+                let hashes = (0..10000).map(|i| seed(i + attempt)).collect::<Vec<_>>();
+                peel(&hashes, segment_length).map(|order| (hashes, order))
+            })
+            .expect("peeling failed");
+        assert_eq!(order.len(), hashes.len());
+
+        // Every key is peeled exactly once, from one of its own slots.
+        let mut seen = vec![false; hashes.len()];
+        for (i, slot) in order {
+            assert!(!seen[i]);
+            seen[i] = true;
+            assert!(slots(hashes[i], segment_length).contains(&slot));
+        }
+
+        // Duplicate hashes can never be peeled.
+        assert!(peel(&[1, 1], segment_length).is_none());
+    }
+}
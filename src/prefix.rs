@@ -0,0 +1,186 @@
+//! Sorted hash prefix set.
+//!
+//! A [`HashPrefixSet`] keeps the `b`-byte prefixes of key hashes, sorted and
+//! delta encoded, the way Safe Browsing clients keep their blocklists: lists
+//! are shipped as (updates to) sets of hash prefixes, which are trivial to
+//! merge with a sorted set, and awkward to apply to a Bloom filter. With `n`
+//! keys the false positive rate is about `n / 2^(8b)`.
+//!
+//! Prefixes are stored in runs: each run starts with a full prefix, recorded
+//! in a (binary searched) index, and continues with 16-bit deltas, so a dense
+//! set costs little more than two bytes per key.
+
+use {
+    crate::QueryFilter,
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        iter,
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Maximum number of deltas in a single run, bounding the linear scan that
+/// follows the index search.
+const MAX_RUN: usize = 100;
+
+/// Sorted, delta encoded set of hash prefixes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HashPrefixSet<K> {
+    /// First prefix of each run, and the offset of its deltas.
+    index: Vec<(u64, usize)>,
+    deltas: Vec<u16>,
+    prefix_bytes: u32,
+    len: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K> HashPrefixSet<K>
+where
+    K: Eq + Hash,
+{
+    /// Builds the set from keys, keeping `prefix_bytes`-byte prefixes of
+    /// their hashes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix_bytes` is not within `1..=8`.
+    pub fn from_keys(keys: impl IntoIterator<Item = K>, prefix_bytes: u32) -> Self {
+        Self::check_prefix_bytes(prefix_bytes);
+        let prefixes = keys.into_iter().map(|key| prefix(&key, prefix_bytes));
+        Self::from_prefixes(prefixes, prefix_bytes)
+    }
+
+    /// Builds the set from raw prefixes, e.g. shipped by a list provider, or
+    /// computed with a hash other than the one used by [`from_keys`]. Each
+    /// prefix is given as an integer of `prefix_bytes` bytes; higher bits are
+    /// ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `prefix_bytes` is not within `1..=8`.
+    ///
+    /// [`from_keys`]: HashPrefixSet::from_keys
+    pub fn from_prefixes(prefixes: impl IntoIterator<Item = u64>, prefix_bytes: u32) -> Self {
+        Self::check_prefix_bytes(prefix_bytes);
+        let mask = u64::MAX >> (64 - 8 * prefix_bytes);
+        let mut prefixes = prefixes
+            .into_iter()
+            .map(|prefix| prefix & mask)
+            .collect::<Vec<_>>();
+        prefixes.sort_unstable();
+        prefixes.dedup();
+
+        let mut index = Vec::new();
+        let mut deltas = Vec::new();
+        let mut run = 0;
+        let mut previous = None;
+        for &prefix in &prefixes {
+            match previous.map(|previous| prefix - previous) {
+                Some(delta) if delta <= u16::MAX as u64 && run < MAX_RUN => {
+                    deltas.push(delta as u16);
+                    run += 1;
+                }
+                _ => {
+                    index.push((prefix, deltas.len()));
+                    run = 0;
+                }
+            }
+            previous = Some(prefix);
+        }
+
+        Self {
+            index,
+            deltas,
+            prefix_bytes,
+            len: prefixes.len(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the number of prefixes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the set holds no prefixes.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes kept per prefix.
+    pub fn prefix_bytes(&self) -> u32 {
+        self.prefix_bytes
+    }
+
+    /// Returns the memory used by the prefixes, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.index.len() * size_of::<(u64, usize)>() + self.deltas.len() * 2
+    }
+
+    /// Returns `true` if the set holds a given raw prefix.
+    pub fn contains_prefix(&self, prefix: u64) -> bool {
+        // Last run starting at or before the prefix.
+        let run = self.index.partition_point(|&(first, _)| first <= prefix);
+        let Some(&(mut current, start)) = run.checked_sub(1).map(|run| &self.index[run]) else {
+            return false;
+        };
+        let end = self
+            .index
+            .get(run)
+            .map_or(self.deltas.len(), |&(_, end)| end);
+        for &delta in &self.deltas[start..end] {
+            if current >= prefix {
+                break;
+            }
+            current += delta as u64;
+        }
+        current == prefix
+    }
+
+    /// Returns all prefixes, in ascending order.
+    pub fn prefixes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.index
+            .iter()
+            .enumerate()
+            .flat_map(move |(run, &(first, start))| {
+                let end = self
+                    .index
+                    .get(run + 1)
+                    .map_or(self.deltas.len(), |&(_, end)| end);
+                let rest = self.deltas[start..end]
+                    .iter()
+                    .scan(first, |current, &delta| {
+                        *current += delta as u64;
+                        Some(*current)
+                    });
+                iter::once(first).chain(rest)
+            })
+    }
+
+    fn check_prefix_bytes(prefix_bytes: u32) {
+        assert!(
+            (1..=8).contains(&prefix_bytes),
+            "prefix bytes must be in 1..=8"
+        );
+    }
+}
+
+impl<K> QueryFilter<K> for HashPrefixSet<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.contains_prefix(prefix(key, self.prefix_bytes))
+    }
+}
+
+/// Returns the `prefix_bytes`-byte prefix (the highest bits) of a key's hash.
+fn prefix<Q: Hash + ?Sized>(key: &Q, prefix_bytes: u32) -> u64 {
+    Xxh3Builder::new().hash_one(key) >> (64 - 8 * prefix_bytes)
+}
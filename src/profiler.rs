@@ -0,0 +1,182 @@
+//! Empirical false positive profiling.
+//!
+//! Measures the realized false positive rate of an already built filter
+//! against a holdout set of keys that were never inserted, and compares it
+//! with the theoretical prediction (see [`crate::analysis`]). Intended for
+//! tests and operational checks: a hashing regression typically does not
+//! break membership, it just silently makes the filter less accurate.
+
+use {
+    crate::QueryFilter,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Profiles the false positive rate of query filters.
+#[derive(Debug, Clone, Copy)]
+pub struct FpProfiler<K> {
+    expected_fp_rate: f64,
+    confidence: f64,
+    phantom: PhantomData<K>,
+}
+
+impl<K> FpProfiler<K> {
+    /// Creates a new profiler, expecting the given (theoretical) false
+    /// positive rate. Confidence level defaults to 99%.
+    pub fn new(expected_fp_rate: f64) -> Self {
+        Self {
+            expected_fp_rate,
+            confidence: 0.99,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Sets the confidence level of the computed interval, in `(0, 1)`.
+    pub fn with_confidence(self, confidence: f64) -> Self {
+        Self { confidence, ..self }
+    }
+
+    /// Measures the filter.
+    ///
+    /// Every key from `inserted` is expected to be reported as present (any
+    /// miss is a false negative, i.e. a contract violation), and every key
+    /// from `holdout` must be disjoint with the inserted set, so that each
+    /// positive answer for it is a false positive.
+    pub fn measure<'a, F, Q>(
+        &self,
+        filter: &F,
+        inserted: impl IntoIterator<Item = &'a Q>,
+        holdout: impl IntoIterator<Item = &'a Q>,
+    ) -> FpReport
+    where
+        F: QueryFilter<K>,
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized + 'a,
+    {
+        let (mut inserted_count, mut false_negatives) = (0, 0);
+        for key in inserted {
+            inserted_count += 1;
+            if !filter.contains(key) {
+                false_negatives += 1;
+            }
+        }
+
+        let (mut holdout_count, mut false_positives) = (0, 0);
+        for key in holdout {
+            holdout_count += 1;
+            if filter.contains(key) {
+                false_positives += 1;
+            }
+        }
+
+        FpReport {
+            inserted: inserted_count,
+            false_negatives,
+            holdout: holdout_count,
+            false_positives,
+            expected_fp_rate: self.expected_fp_rate,
+            confidence: self.confidence,
+        }
+    }
+}
+
+/// Direction in which the realized false positive rate deviates from the
+/// expected one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Deviation {
+    /// The filter is less accurate than predicted.
+    Higher,
+    /// The filter is more accurate than predicted (usually means that the
+    /// prediction is off, e.g. filter is under-filled).
+    Lower,
+}
+
+/// Results of a single profiling run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FpReport {
+    /// Number of inserted keys queried.
+    pub inserted: usize,
+    /// Number of inserted keys reported as absent.
+    pub false_negatives: usize,
+    /// Number of holdout keys queried.
+    pub holdout: usize,
+    /// Number of holdout keys reported as present.
+    pub false_positives: usize,
+    /// Theoretical false positive rate.
+    pub expected_fp_rate: f64,
+    /// Confidence level of the interval.
+    pub confidence: f64,
+}
+
+impl FpReport {
+    /// Returns the realized false positive rate.
+    pub fn fp_rate(&self) -> f64 {
+        if self.holdout == 0 {
+            return 0.;
+        }
+        self.false_positives as f64 / self.holdout as f64
+    }
+
+    /// Returns the confidence interval for the false positive rate.
+    ///
+    /// Computed as a Wilson score interval, which (unlike the normal
+    /// approximation) behaves well for the tiny proportions typical of
+    /// filters.
+    pub fn confidence_interval(&self) -> (f64, f64) {
+        if self.holdout == 0 {
+            return (0., 1.);
+        }
+        let n = self.holdout as f64;
+        let p = self.fp_rate();
+        let z = z_score(self.confidence);
+        let z2 = z * z;
+
+        let center = (p + z2 / (2. * n)) / (1. + z2 / n);
+        let margin = z / (1. + z2 / n) * (p * (1. - p) / n + z2 / (4. * n * n)).sqrt();
+        ((center - margin).max(0.), (center + margin).min(1.))
+    }
+
+    /// Returns the deviation from the expected rate, if the expected rate
+    /// falls outside of the confidence interval.
+    pub fn deviation(&self) -> Option<Deviation> {
+        let (lower, upper) = self.confidence_interval();
+        if self.expected_fp_rate < lower {
+            Some(Deviation::Higher)
+        } else if self.expected_fp_rate > upper {
+            Some(Deviation::Lower)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the filter violated its contract (false negatives),
+    /// or is statistically less accurate than predicted.
+    pub fn is_regression(&self) -> bool {
+        self.false_negatives > 0 || self.deviation() == Some(Deviation::Higher)
+    }
+}
+
+/// Returns the two-sided z-score for a given confidence level.
+///
+/// Inverts the standard normal CDF using the rational approximation from
+/// Abramowitz and Stegun (26.2.23), absolute error is below `4.5e-4`.
+fn z_score(confidence: f64) -> f64 {
+    let q = ((1. - confidence) / 2.).clamp(f64::MIN_POSITIVE, 0.5);
+    let t = (-2. * q.ln()).sqrt();
+    let (c0, c1, c2) = (2.515517, 0.802853, 0.010328);
+    let (d1, d2, d3) = (1.432788, 0.189269, 0.001308);
+
+    t - (c0 + c1 * t + c2 * t * t) / (1. + d1 * t + d2 * t * t + d3 * t * t * t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn z_score_works() {
+        let test_cases = [(0.90, 1.645), (0.95, 1.960), (0.99, 2.576), (0.999, 3.291)];
+        for (confidence, z) in test_cases {
+            assert!((z_score(confidence) - z).abs() < 1e-3);
+        }
+    }
+}
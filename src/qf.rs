@@ -0,0 +1,933 @@
+//! Quotient filter.
+//!
+//! Following [Don't Thrash: How to Cache Your Hash on Flash, 2012][1]: each
+//! key is reduced to a `q + r`-bit fingerprint, whose top `q` bits (the
+//! quotient) pick a slot of a table of `2^q`, where the remaining `r` bits
+//! (the remainder) are stored. Remainders sharing a quotient are kept
+//! sorted in a contiguous run, shifted right (linear probing style) when
+//! their slot is taken, with three metadata bits per slot recording enough
+//! to tell which quotient each stored remainder belongs to. The false
+//! positive rate is about `load * 2^-r`.
+//!
+//! Since full fingerprints can be recovered from the table, the filter can
+//! be resized (trading a remainder bit for a quotient bit) and merged with
+//! another filter without access to the original keys.
+//!
+//! Growing doubles the table at once, which stalls the insert that triggers
+//! it for as long as it takes to move every stored fingerprint. With
+//! [`GrowthMode::Incremental`], the table is instead split the way linear
+//! hashing splits buckets: the old table is kept next to the new one, and
+//! each insert moves the runs of a few more quotients over, so that no
+//! single insert moves more than a few runs.
+//!
+//! [1]: https://vldb.org/pvldb/vol5/p1627_michaelabender_vldb2012.pdf
+
+use {
+    crate::{
+        analysis::{check_fp_rate, quotient_remainder_bits},
+        storage::PackedArray,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+        RemovableQueryFilter,
+    },
+    std::{
+        borrow::Borrow,
+        collections::VecDeque,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Largest number of remainder bits.
+pub const MAX_REMAINDER_BITS: u32 = 61;
+
+/// Load factor above which inserts grow the table, when possible.
+const MAX_LOAD: f64 = 0.75;
+
+/// Number of quotients of the old table whose runs each insert moves while
+/// growing incrementally: the old table is split after a quarter as many
+/// inserts as it has slots, long before the new one needs to grow in turn.
+const SPLIT_STEP: usize = 4;
+
+/// Slot metadata: the slot is the canonical slot of some stored remainder.
+const OCCUPIED: u64 = 1;
+/// Slot metadata: the slot holds a remainder that is not first in its run.
+const CONTINUATION: u64 = 1 << 1;
+/// Slot metadata: the slot holds a remainder that is not in its canonical
+/// slot.
+const SHIFTED: u64 = 1 << 2;
+/// Number of metadata bits per slot.
+const METADATA_BITS: u32 = 3;
+
+/// How inserts grow the table once it is 75% full.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum GrowthMode {
+    /// Double the table at once, see [`QuotientFilter::grow`].
+    #[default]
+    Doubling,
+    /// Double the table, but move fingerprints over a few runs per insert.
+    Incremental,
+}
+
+/// Run and cluster length statistics of a quotient filter's table, see
+/// [`QuotientFilter::cluster_stats`].
+///
+/// Lookups scan a key's cluster from its start up to its run, so their cost
+/// grows with cluster length, and shifts in particular.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterStats {
+    /// Number of stored remainders.
+    pub entries: usize,
+    /// Number of runs, i.e. of distinct quotients stored.
+    pub runs: usize,
+    /// Length of the longest run.
+    pub max_run_len: usize,
+    /// Number of clusters (maximal ranges of non-empty slots).
+    pub clusters: usize,
+    /// Length of the longest cluster.
+    pub max_cluster_len: usize,
+    /// Largest distance of a remainder from its canonical slot.
+    pub max_shift: usize,
+}
+
+impl ClusterStats {
+    /// Returns the mean run length, or 0 if the table is empty.
+    pub fn mean_run_len(&self) -> f64 {
+        mean(self.entries, self.runs)
+    }
+
+    /// Returns the mean cluster length, or 0 if the table is empty.
+    pub fn mean_cluster_len(&self) -> f64 {
+        mean(self.entries, self.clusters)
+    }
+}
+
+/// Returns `total / count`, or 0 if `count` is zero.
+fn mean(total: usize, count: usize) -> f64 {
+    if count == 0 {
+        return 0.;
+    }
+    total as f64 / count as f64
+}
+
+/// Quotient filter, supporting removal, resizing, and merging.
+///
+/// Keys are hashed with a fixed hash function, so that any two filters with
+/// the same fingerprint size can be merged.
+pub struct QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Each slot packs metadata bits (low) and a remainder (high).
+    slots: PackedArray,
+    quotient_bits: u32,
+    remainder_bits: u32,
+    /// Number of keys in this table: for an old table being split, only the
+    /// ones not yet moved.
+    len: usize,
+    growth_mode: GrowthMode,
+    /// Old table being split into this one, and the next of its quotients
+    /// whose run is to be moved, while growing incrementally.
+    splitting: Option<(Box<Self>, usize)>,
+    phantom: PhantomData<K>,
+}
+
+impl<K> QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the parameters are invalid, see
+    /// [`with_capacity`](QuotientFilter::with_capacity).
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate).expect("parameters are valid")
+    }
+
+    /// Creates a new filter with a desired capacity and false positive rate.
+    ///
+    /// The table has room for `capacity` keys at a load of at most 75%, and
+    /// remainders are the smallest meeting the rate at that load, see
+    /// [`quotient_remainder_bits`].
+    ///
+    /// Fails with [`InvalidFpRate`](QueryFilterError::InvalidFpRate) unless
+    /// the rate is within `0..1` (exclusive) and met by
+    /// [`MAX_REMAINDER_BITS`]-bit remainders, or if the capacity is so large
+    /// that fingerprints would not fit in 64 bits.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> QueryFilterResult<Self> {
+        check_fp_rate(fp_rate)?;
+        let remainder_bits = quotient_remainder_bits(fp_rate, MAX_LOAD);
+        if remainder_bits > MAX_REMAINDER_BITS {
+            return Err(QueryFilterError::InvalidFpRate(fp_rate));
+        }
+        let slots = (capacity as f64 / MAX_LOAD).ceil().max(2.) as u64;
+        let quotient_bits = slots
+            .checked_next_power_of_two()
+            .map_or(u64::BITS, u64::trailing_zeros);
+        if quotient_bits + remainder_bits > u64::BITS {
+            return Err(QueryFilterError::Other(format!(
+                "invalid capacity: {capacity} keys take {quotient_bits}-bit quotients, too many \
+                 for {remainder_bits}-bit remainders"
+            )));
+        }
+        Self::with_bits(quotient_bits, remainder_bits)
+    }
+
+    /// Creates a new filter with a table of `2^quotient_bits` slots, each
+    /// storing a `remainder_bits`-bit remainder.
+    ///
+    /// Fails unless `quotient_bits` is positive, `remainder_bits` is within
+    /// `1..=MAX_REMAINDER_BITS`, and fingerprints (`quotient_bits +
+    /// remainder_bits` bits) fit in 64 bits.
+    pub fn with_bits(quotient_bits: u32, remainder_bits: u32) -> QueryFilterResult<Self> {
+        check_bits(quotient_bits, remainder_bits)?;
+        Ok(Self {
+            slots: PackedArray::new(1 << quotient_bits, remainder_bits + METADATA_BITS),
+            quotient_bits,
+            remainder_bits,
+            len: 0,
+            growth_mode: GrowthMode::default(),
+            splitting: None,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Sets how inserts grow the table.
+    pub fn with_growth_mode(self, growth_mode: GrowthMode) -> Self {
+        Self {
+            growth_mode,
+            ..self
+        }
+    }
+
+    /// Returns how inserts grow the table.
+    pub fn growth_mode(&self) -> GrowthMode {
+        self.growth_mode
+    }
+
+    /// Returns `true` while an old table is being split into this one, see
+    /// [`GrowthMode::Incremental`].
+    pub fn is_splitting(&self) -> bool {
+        self.splitting.is_some()
+    }
+
+    /// Returns the number of keys in the filter.
+    pub fn len(&self) -> usize {
+        self.len + self.splitting.as_ref().map_or(0, |(old, _)| old.len)
+    }
+
+    /// Returns `true` if the filter holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of slots in the table.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Returns the fraction of slots in use.
+    pub fn load_factor(&self) -> f64 {
+        self.len() as f64 / self.slots.len() as f64
+    }
+
+    /// Returns the number of quotient bits, i.e. the base-2 logarithm of
+    /// the number of slots.
+    pub fn quotient_bits(&self) -> u32 {
+        self.quotient_bits
+    }
+
+    /// Returns the number of remainder bits stored per key.
+    pub fn remainder_bits(&self) -> u32 {
+        self.remainder_bits
+    }
+
+    /// Returns the number of fingerprint bits, i.e. quotient and remainder
+    /// bits.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.quotient_bits + self.remainder_bits
+    }
+
+    /// Returns the memory used by the table (and the old one, while
+    /// splitting), in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        let old = self
+            .splitting
+            .as_ref()
+            .map_or(0, |(old, _)| old.size_in_bytes());
+        self.slots.size_in_bytes() + old
+    }
+
+    /// Inserts a key, failing (without inserting it) if the filter is full.
+    ///
+    /// Once above 75% load, the table is grown according to the
+    /// [growth mode](QuotientFilter::with_growth_mode) unless remainders are
+    /// down to a single bit. The filter is full when a single slot is left.
+    pub fn try_insert(&mut self, key: K) -> QueryFilterResult<()> {
+        if (self.len() + 1) as f64 > MAX_LOAD * self.slot_count() as f64 && self.remainder_bits > 1
+        {
+            match self.growth_mode {
+                GrowthMode::Doubling => self.grow()?,
+                GrowthMode::Incremental => self.start_split(),
+            }
+        }
+        if self.len() + 1 >= self.slot_count() {
+            return Err(QueryFilterError::Full);
+        }
+        let (quotient, remainder) = self.split(self.fingerprint(&key));
+        self.insert_entry(quotient, remainder);
+        self.split_step(SPLIT_STEP);
+        Ok(())
+    }
+
+    /// Doubles the number of slots, moving one bit of each fingerprint from
+    /// the remainder to the quotient: the false positive rate doubles for
+    /// the same number of keys.
+    ///
+    /// Fails if remainders are down to a single bit.
+    pub fn grow(&mut self) -> QueryFilterResult<()> {
+        if self.remainder_bits == 1 {
+            return Err(QueryFilterError::Other(
+                "quotient filter cannot grow past 1-bit remainders".to_owned(),
+            ));
+        }
+        let fingerprints = self.sorted_fingerprints();
+        self.rebuild(self.quotient_bits + 1, fingerprints);
+        event!(slots = self.slot_count(), keys = self.len; "grew quotient filter");
+        Ok(())
+    }
+
+    /// Inserts all keys of `other` into this filter, without access to the
+    /// keys themselves (nor rehashing them): both filters' fingerprints are
+    /// read in sorted order, merged, and written to the table in one pass.
+    ///
+    /// The table grows as needed to hold both filters' keys. Fails if the
+    /// filters have different fingerprint sizes (see
+    /// [`fingerprint_bits`](QuotientFilter::fingerprint_bits)), or if the
+    /// merged filter would be full, in which case this filter is left
+    /// unchanged.
+    pub fn merge(&mut self, other: &Self) -> QueryFilterResult<()> {
+        if self.fingerprint_bits() != other.fingerprint_bits() {
+            return Err(QueryFilterError::IncompatibleFilters(
+                "fingerprint sizes differ",
+            ));
+        }
+        let len = self.len() + other.len();
+        let mut quotient_bits = self.quotient_bits.max(other.quotient_bits);
+        while len as f64 > MAX_LOAD * (1u64 << quotient_bits) as f64
+            && quotient_bits + 1 < self.fingerprint_bits()
+        {
+            quotient_bits += 1;
+        }
+        if len >= 1 << quotient_bits {
+            return Err(QueryFilterError::Full);
+        }
+        let fingerprints = merge_sorted(self.sorted_fingerprints(), other.sorted_fingerprints());
+        self.rebuild(quotient_bits, fingerprints);
+        event!(slots = self.slot_count(), keys = self.len; "merged quotient filter");
+        Ok(())
+    }
+
+    /// Returns the stored fingerprints, as `(quotient, remainder)` pairs in
+    /// canonical order: by quotient, then remainder, with a pair for each
+    /// key inserted (repeated ones included).
+    ///
+    /// Fingerprints are the top [`fingerprint_bits`] bits of the XXH3 hash
+    /// (with the default seed) of keys, so that they can be merged with or
+    /// resharded into other filters without the keys themselves.
+    ///
+    /// [`fingerprint_bits`]: QuotientFilter::fingerprint_bits
+    pub fn fingerprints(&self) -> impl ExactSizeIterator<Item = (usize, u64)> + '_ {
+        self.sorted_fingerprints()
+            .into_iter()
+            .map(|fingerprint| self.split(fingerprint))
+    }
+
+    /// Returns run and cluster length statistics of the table, walking it
+    /// once.
+    ///
+    /// While splitting, the old table is left out: it only shrinks until the
+    /// split completes.
+    pub fn cluster_stats(&self) -> ClusterStats {
+        let mut stats = ClusterStats::default();
+        let n = self.slot_count();
+        let Some(empty) = (0..n).find(|&slot| self.is_empty_slot(slot)) else {
+            return stats;
+        };
+        let (mut run_len, mut cluster_len) = (0, 0);
+        let mut quotients = VecDeque::new();
+        let mut quotient = 0;
+        for slot in (1..=n).map(|i| (empty + i) % n) {
+            if self.is_empty_slot(slot) {
+                cluster_len = 0;
+                continue;
+            }
+            let value = self.slots.get(slot);
+            if value & OCCUPIED != 0 {
+                quotients.push_back(slot);
+            }
+            if value & CONTINUATION == 0 {
+                quotient = quotients
+                    .pop_front()
+                    .expect("every run has an occupied slot");
+                stats.runs += 1;
+                run_len = 0;
+            }
+            if cluster_len == 0 {
+                stats.clusters += 1;
+            }
+            stats.entries += 1;
+            run_len += 1;
+            cluster_len += 1;
+            stats.max_run_len = stats.max_run_len.max(run_len);
+            stats.max_cluster_len = stats.max_cluster_len.max(cluster_len);
+            stats.max_shift = stats.max_shift.max(self.offset(quotient, slot));
+        }
+        stats
+    }
+
+    /// Returns the fingerprints of all stored keys (including the ones left
+    /// in the old table while splitting), in sorted order.
+    fn sorted_fingerprints(&self) -> Vec<u64> {
+        let fingerprints = self.table_fingerprints();
+        let Some((old, next)) = &self.splitting else {
+            return fingerprints;
+        };
+        let mut left = old.table_fingerprints();
+        left.retain(|&fingerprint| old.split(fingerprint).0 >= *next);
+        merge_sorted(fingerprints, left)
+    }
+
+    /// Returns the fingerprints stored in this table, in sorted order.
+    fn table_fingerprints(&self) -> Vec<u64> {
+        let n = self.slot_count();
+        let Some(empty) = (0..n).find(|&slot| self.is_empty_slot(slot)) else {
+            return Vec::new();
+        };
+        let mut fingerprints = Vec::with_capacity(self.len);
+        let mut quotients = VecDeque::new();
+        let mut quotient = 0;
+        for slot in (1..=n).map(|i| (empty + i) % n) {
+            let value = self.slots.get(slot);
+            if value & (OCCUPIED | CONTINUATION | SHIFTED) == 0 {
+                continue;
+            }
+            if value & OCCUPIED != 0 {
+                quotients.push_back(slot as u64);
+            }
+            if value & CONTINUATION == 0 {
+                quotient = quotients
+                    .pop_front()
+                    .expect("every run has an occupied slot");
+            }
+            fingerprints.push(quotient << self.remainder_bits | value >> METADATA_BITS);
+        }
+        // Runs are sorted, from the slot after an empty one on: fingerprints
+        // only decrease where quotients wrap around the end of the table.
+        let wrap = fingerprints
+            .windows(2)
+            .position(|pair| pair[1] < pair[0])
+            .map_or(0, |i| i + 1);
+        fingerprints.rotate_left(wrap);
+        fingerprints
+    }
+
+    /// Replaces the table with one of `2^quotient_bits` slots holding the
+    /// given sorted fingerprints, keeping the fingerprint size.
+    ///
+    /// Each fingerprint goes to its quotient's slot, or right after the
+    /// previous one, whichever comes last. Fingerprints pushed past the end
+    /// of the table wrap around to its start, pushing the ones there in turn:
+    /// positions are recomputed from the number of wrapped slots until it
+    /// settles, which it does as there are fewer fingerprints than slots.
+    fn rebuild(&mut self, quotient_bits: u32, fingerprints: Vec<u64>) {
+        let fingerprint_bits = self.fingerprint_bits();
+        *self = Self::with_bits(quotient_bits, fingerprint_bits - quotient_bits)
+            .expect("fingerprint size is unchanged")
+            .with_growth_mode(self.growth_mode);
+        let n = self.slot_count();
+        let mut wrapped = 0;
+        let positions = loop {
+            let mut pos = wrapped;
+            let positions = fingerprints
+                .iter()
+                .map(|&fingerprint| {
+                    pos = pos.max(self.split(fingerprint).0);
+                    pos += 1;
+                    pos - 1
+                })
+                .collect::<Vec<_>>();
+            if pos.saturating_sub(n) == wrapped {
+                break positions;
+            }
+            wrapped = pos.saturating_sub(n);
+        };
+        let mut previous = None;
+        for (&fingerprint, pos) in fingerprints.iter().zip(positions) {
+            let (quotient, remainder) = self.split(fingerprint);
+            let mut value = remainder << METADATA_BITS;
+            if previous == Some(quotient) {
+                value |= CONTINUATION;
+            }
+            if pos != quotient {
+                value |= SHIFTED;
+            }
+            self.slots.set(pos % n, value);
+            previous = Some(quotient);
+        }
+        for &fingerprint in &fingerprints {
+            let quotient = self.split(fingerprint).0;
+            let value = self.slots.get(quotient);
+            self.slots.set(quotient, value | OCCUPIED);
+        }
+        self.len = fingerprints.len();
+    }
+
+    /// Replaces the table with an empty one of twice as many slots, keeping
+    /// the current one to split into it, see [`GrowthMode::Incremental`].
+    fn start_split(&mut self) {
+        self.split_step(usize::MAX);
+        let table = Self::with_bits(self.quotient_bits + 1, self.remainder_bits - 1)
+            .expect("fingerprint size is unchanged")
+            .with_growth_mode(self.growth_mode);
+        let old = std::mem::replace(self, table);
+        self.splitting = Some((Box::new(old), 0));
+        event!(slots = self.slot_count(), keys = self.len(); "splitting quotient filter");
+    }
+
+    /// Moves the runs of up to `quotients` more quotients of the old table
+    /// being split into this one, if any, dropping the old table once done.
+    ///
+    /// Each run of the old table splits into the runs of two consecutive
+    /// quotients of this one, as a remainder bit moves to the quotient.
+    fn split_step(&mut self, quotients: usize) {
+        let Some((mut old, next)) = self.splitting.take() else {
+            return;
+        };
+        let end = next.saturating_add(quotients).min(old.slot_count());
+        for quotient in next..end {
+            for remainder in old.run(quotient) {
+                let fingerprint = (quotient as u64) << old.remainder_bits | remainder;
+                let (quotient, remainder) = self.split(fingerprint);
+                self.insert_entry(quotient, remainder);
+                old.len -= 1;
+            }
+        }
+        if end < old.slot_count() {
+            self.splitting = Some((old, end));
+        } else {
+            event!(slots = self.slot_count(), keys = self.len; "split quotient filter");
+        }
+    }
+
+    /// Returns the remainders stored in the run of `quotient`, if any.
+    fn run(&self, quotient: usize) -> Vec<u64> {
+        if self.slots.get(quotient) & OCCUPIED == 0 {
+            return Vec::new();
+        }
+        let n = self.slot_count();
+        let mut slot = self.run_start(quotient);
+        let mut remainders = vec![self.slots.get(slot) >> METADATA_BITS];
+        loop {
+            slot = (slot + 1) % n;
+            let value = self.slots.get(slot);
+            if value & CONTINUATION == 0 {
+                return remainders;
+            }
+            remainders.push(value >> METADATA_BITS);
+        }
+    }
+
+    /// Returns `true` if this table holds an entry.
+    fn contains_entry(&self, quotient: usize, remainder: u64) -> bool {
+        if self.slots.get(quotient) & OCCUPIED == 0 {
+            return false;
+        }
+        // Remainders are sorted within the run.
+        let n = self.slot_count();
+        let mut slot = self.run_start(quotient);
+        loop {
+            let stored = self.slots.get(slot) >> METADATA_BITS;
+            if stored >= remainder {
+                return stored == remainder;
+            }
+            slot = (slot + 1) % n;
+            if self.slots.get(slot) & CONTINUATION == 0 {
+                return false;
+            }
+        }
+    }
+
+    /// Removes an entry from this table, if found there.
+    fn remove_entry(&mut self, quotient: usize, remainder: u64) {
+        if self.slots.get(quotient) & OCCUPIED == 0 {
+            return;
+        }
+        let (start, mut entries) = self.cluster(quotient);
+        let Some(at) = entries
+            .iter()
+            .position(|&entry| entry == (quotient, remainder))
+        else {
+            return;
+        };
+        entries.remove(at);
+        self.write_cluster(start, entries.len() + 1, &entries);
+        self.len -= 1;
+    }
+
+    /// Returns the old table holding a fingerprint's entry while splitting,
+    /// if its quotient there is yet to be moved, along with that entry.
+    fn unsplit(&self, fingerprint: u64) -> Option<(&Self, (usize, u64))> {
+        let (old, next) = self.splitting.as_ref()?;
+        let entry = old.split(fingerprint);
+        (entry.0 >= *next).then_some((old, entry))
+    }
+
+    fn fingerprint<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        Xxh3Builder::new().hash_one(key) >> (64 - self.fingerprint_bits())
+    }
+
+    fn split(&self, fingerprint: u64) -> (usize, u64) {
+        let remainder = fingerprint & PackedArray::max_value(self.remainder_bits);
+        ((fingerprint >> self.remainder_bits) as usize, remainder)
+    }
+
+    fn is_empty_slot(&self, slot: usize) -> bool {
+        self.slots.get(slot) & (OCCUPIED | CONTINUATION | SHIFTED) == 0
+    }
+
+    fn insert_entry(&mut self, quotient: usize, remainder: u64) {
+        let (start, mut entries) = self.cluster(quotient);
+        let key = (self.offset(start, quotient), remainder);
+        let at = entries.partition_point(|&(other, rem)| (self.offset(start, other), rem) <= key);
+        entries.insert(at, (quotient, remainder));
+        self.write_cluster(start, entries.len() - 1, &entries);
+        self.len += 1;
+    }
+
+    /// Returns the first slot of the cluster (maximal range of non-empty
+    /// slots) holding `quotient`'s run, and the `(quotient, remainder)`
+    /// entries stored there, in order.
+    ///
+    /// If `quotient`'s slot is empty, the cluster is empty and starts there.
+    ///
+    /// Slots are only ever written by
+    /// [`write_cluster`](QuotientFilter::write_cluster), which keeps an
+    /// occupied slot for every run, in order (and are checked to do so when
+    /// deserialized).
+    fn cluster(&self, quotient: usize) -> (usize, Vec<(usize, u64)>) {
+        let n = self.slot_count();
+        let mut start = quotient;
+        while !self.is_empty_slot(start) && !self.is_empty_slot((start + n - 1) % n) {
+            start = (start + n - 1) % n;
+        }
+
+        let mut entries = Vec::new();
+        let mut quotients = VecDeque::new();
+        let mut current = start;
+        let mut slot = start;
+        while !self.is_empty_slot(slot) {
+            let value = self.slots.get(slot);
+            if value & OCCUPIED != 0 {
+                quotients.push_back(slot);
+            }
+            if value & CONTINUATION == 0 {
+                current = quotients
+                    .pop_front()
+                    .expect("every run has an occupied slot");
+            }
+            entries.push((current, value >> METADATA_BITS));
+            slot = (slot + 1) % n;
+        }
+        (start, entries)
+    }
+
+    /// Returns the slot where the run of an occupied `quotient` starts.
+    ///
+    /// Goes back to the first unshifted slot, whose remainder is the first of
+    /// its quotient's run, then forward again, skipping one run for each
+    /// occupied slot passed on the way to `quotient`.
+    fn run_start(&self, quotient: usize) -> usize {
+        let n = self.slot_count();
+        let mut canonical = quotient;
+        while self.slots.get(canonical) & SHIFTED != 0 {
+            canonical = (canonical + n - 1) % n;
+        }
+        let mut slot = canonical;
+        while canonical != quotient {
+            slot = (slot + 1) % n;
+            while self.slots.get(slot) & CONTINUATION != 0 {
+                slot = (slot + 1) % n;
+            }
+            canonical = (canonical + 1) % n;
+            while self.slots.get(canonical) & OCCUPIED == 0 {
+                canonical = (canonical + 1) % n;
+            }
+        }
+        slot
+    }
+
+    /// Returns the distance from `start` to `slot`, going right.
+    fn offset(&self, start: usize, slot: usize) -> usize {
+        (slot + self.slot_count() - start) % self.slot_count()
+    }
+
+    /// Rewrites the cluster starting at `start`, which held `old_len`
+    /// entries, with new entries sorted by quotient (from `start` on).
+    fn write_cluster(&mut self, start: usize, old_len: usize, entries: &[(usize, u64)]) {
+        let n = self.slot_count();
+        for i in 0..old_len {
+            self.slots.set((start + i) % n, 0);
+        }
+        for &(quotient, _) in entries {
+            let value = self.slots.get(quotient);
+            self.slots.set(quotient, value | OCCUPIED);
+        }
+        let mut pos = 0;
+        for (i, &(quotient, remainder)) in entries.iter().enumerate() {
+            let canonical = self.offset(start, quotient);
+            let continuation = i > 0 && entries[i - 1].0 == quotient;
+            pos = pos.max(canonical);
+            let slot = (start + pos) % n;
+            let mut value = self.slots.get(slot) & OCCUPIED | remainder << METADATA_BITS;
+            if continuation {
+                value |= CONTINUATION;
+            }
+            if pos != canonical {
+                value |= SHIFTED;
+            }
+            self.slots.set(slot, value);
+            pos += 1;
+        }
+    }
+}
+
+/// Merges two sorted lists of fingerprints.
+fn merge_sorted(ours: Vec<u64>, theirs: Vec<u64>) -> Vec<u64> {
+    let mut merged = Vec::with_capacity(ours.len() + theirs.len());
+    let (mut i, mut j) = (0, 0);
+    while i < ours.len() && j < theirs.len() {
+        if ours[i] <= theirs[j] {
+            merged.push(ours[i]);
+            i += 1;
+        } else {
+            merged.push(theirs[j]);
+            j += 1;
+        }
+    }
+    merged.extend_from_slice(&ours[i..]);
+    merged.extend_from_slice(&theirs[j..]);
+    merged
+}
+
+/// Checks that quotient and remainder sizes are valid, see
+/// [`QuotientFilter::with_bits`].
+fn check_bits(quotient_bits: u32, remainder_bits: u32) -> QueryFilterResult<()> {
+    if !(1..=MAX_REMAINDER_BITS).contains(&remainder_bits) {
+        return Err(QueryFilterError::Other(format!(
+            "invalid remainder size: {remainder_bits} bits is not within 1..={MAX_REMAINDER_BITS}"
+        )));
+    }
+    if quotient_bits == 0 || quotient_bits + remainder_bits > 64 {
+        return Err(QueryFilterError::Other(format!(
+            "invalid quotient size: {quotient_bits} bits is not within 1..={}",
+            64 - remainder_bits
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that deserialized slots are consistent, walking each cluster once:
+/// there is an empty slot (for lookups to stop at), `len` non-empty ones,
+/// and every run has an occupied slot (and vice versa) within its cluster,
+/// starting at the first unshifted slot.
+#[cfg(feature = "serde")]
+fn check_slots(slots: &PackedArray, len: usize) -> Result<(), &'static str> {
+    let n = slots.len();
+    let is_empty = |slot| slots.get(slot) & (OCCUPIED | CONTINUATION | SHIFTED) == 0;
+    let Some(empty) = (0..n).find(|&slot| is_empty(slot)) else {
+        return Err("no empty slot");
+    };
+    let mut count = 0;
+    // Occupied slots of the current cluster whose run is yet to start.
+    let mut pending = 0usize;
+    for slot in (1..=n).map(|i| (empty + i) % n) {
+        let value = slots.get(slot);
+        if is_empty(slot) {
+            if pending > 0 {
+                return Err("occupied slot without a run");
+            }
+            continue;
+        }
+        if value & SHIFTED == 0 && (value & OCCUPIED == 0 || value & CONTINUATION != 0) {
+            return Err("unshifted remainder outside of its canonical slot");
+        }
+        if is_empty((slot + n - 1) % n) && value & SHIFTED != 0 {
+            return Err("cluster starts with a shifted remainder");
+        }
+        count += 1;
+        if value & OCCUPIED != 0 {
+            pending += 1;
+        }
+        if value & CONTINUATION == 0 {
+            if pending == 0 {
+                return Err("run without an occupied slot");
+            }
+            pending -= 1;
+        }
+    }
+    if count != len {
+        return Err("key count does not match the slots");
+    }
+    Ok(())
+}
+
+impl<K> QueryFilter<K> for QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let fingerprint = self.fingerprint(key);
+        if let Some((old, (quotient, remainder))) = self.unsplit(fingerprint) {
+            if old.contains_entry(quotient, remainder) {
+                return true;
+            }
+        }
+        let (quotient, remainder) = self.split(fingerprint);
+        self.contains_entry(quotient, remainder)
+    }
+}
+
+impl<K> InsertableQueryFilter<K> for QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Inserts a key.
+    ///
+    /// The table grows as needed, so that inserts only fail once remainders
+    /// are down to a single bit: a filter holds at least `2^(r - 1)` times
+    /// the capacity it was created with, for `r`-bit remainders.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the filter is full, see
+    /// [`try_insert`](QuotientFilter::try_insert) for a fallible version.
+    fn insert(&mut self, key: K) {
+        self.try_insert(key).expect("quotient filter is full");
+    }
+}
+
+impl<K> RemovableQueryFilter<K> for QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Removes a key, which must have been inserted: removing a key that was
+    /// not may remove another key sharing its fingerprint.
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let fingerprint = self.fingerprint(key);
+        if let Some((old, entry)) = self.unsplit(fingerprint) {
+            if old.contains_entry(entry.0, entry.1) {
+                let (old, _) = self.splitting.as_mut().expect("splitting");
+                return old.remove_entry(entry.0, entry.1);
+            }
+        }
+        let (quotient, remainder) = self.split(fingerprint);
+        self.remove_entry(quotient, remainder);
+    }
+}
+
+impl<K> ClearableQueryFilter<K> for QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn clear(&mut self) {
+        self.slots = PackedArray::new(self.slot_count(), self.slots.bits());
+        self.len = 0;
+        self.splitting = None;
+    }
+}
+
+/// Serialized form of a quotient filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "QuotientFilter")]
+struct SerdeQuotientFilter<S> {
+    quotient_bits: u32,
+    remainder_bits: u32,
+    len: usize,
+    #[serde(default)]
+    growth_mode: GrowthMode,
+    slots: S,
+}
+
+#[cfg(feature = "serde")]
+impl<K> serde::Serialize for QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Serializes a single table: while splitting, a copy of the filter with
+    /// all keys moved to the new table.
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.splitting.is_some() {
+            let mut split = Self::with_bits(self.quotient_bits, self.remainder_bits)
+                .expect("sizes are valid")
+                .with_growth_mode(self.growth_mode);
+            split.rebuild(self.quotient_bits, self.sorted_fingerprints());
+            return split.serialize(serializer);
+        }
+        SerdeQuotientFilter {
+            quotient_bits: self.quotient_bits,
+            remainder_bits: self.remainder_bits,
+            len: self.len,
+            growth_mode: self.growth_mode,
+            slots: &self.slots,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K> serde::Deserialize<'de> for QuotientFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeQuotientFilter::<PackedArray>::deserialize(deserializer)?;
+        check_bits(filter.quotient_bits, filter.remainder_bits).map_err(D::Error::custom)?;
+        if filter.slots.len() as u128 != 1 << filter.quotient_bits
+            || filter.slots.bits() != filter.remainder_bits + METADATA_BITS
+        {
+            return Err(D::Error::custom(
+                "slots do not match quotient and remainder sizes",
+            ));
+        }
+        check_slots(&filter.slots, filter.len).map_err(D::Error::custom)?;
+        Ok(Self {
+            slots: filter.slots,
+            quotient_bits: filter.quotient_bits,
+            remainder_bits: filter.remainder_bits,
+            len: filter.len,
+            growth_mode: filter.growth_mode,
+            splitting: None,
+            phantom: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,104 @@
+//! Bloom filters read back without their key type.
+//!
+//! The process reading a persisted filter often does not share the type
+//! definitions of the process that wrote it. A [`RawFilter`] decodes either
+//! encoding of a Bloom filter (see [`BloomFilter::to_bytes`] and
+//! [`BloomFilter::encode_into`]) without a key type, and is queried by the
+//! bytes the keys were hashed as, or by any key hashing the same way as the
+//! original ones.
+//!
+//! ```
+//! use mqfilters::{BloomFilter, InsertableQueryFilter, RawFilter};
+//!
+//! let mut filter = BloomFilter::<u64>::new(1000, 0.01);
+//! filter.insert(42);
+//!
+//! let raw = RawFilter::from_bytes(&filter.to_bytes()).unwrap();
+//! assert!(raw.contains_bytes(&42u64.to_ne_bytes()));
+//! assert!(raw.contains_hash(&42u64));
+//! ```
+
+use {
+    crate::{hash::KeyHasher, BloomFilter, QueryFilterError, QueryFilterResult},
+    std::hash::{Hash, Hasher},
+};
+
+/// Bloom filter of keys of an unknown type, decoded from either of its
+/// encodings.
+///
+/// Only filters using the default hasher type, `ProbeHasher`, can be
+/// decoded: streamed encodings (`MQBF`) must use its default seeds and
+/// strategy too, as they do not record them.
+pub struct RawFilter {
+    filter: BloomFilter<Vec<u8>>,
+}
+
+impl RawFilter {
+    /// Decodes a filter encoded by [`BloomFilter::to_bytes`] (magic `MQBB`)
+    /// or [`BloomFilter::encode_into`] (magic `MQBF`), telling them apart
+    /// by their magic bytes.
+    ///
+    /// Fails if the bytes are not a valid filter of either encoding.
+    pub fn from_bytes(bytes: &[u8]) -> QueryFilterResult<Self> {
+        let filter = match bytes.get(..4) {
+            Some(b"MQBB") => BloomFilter::from_bytes(bytes)?,
+            Some(b"MQBF") => BloomFilter::decode_from(bytes)
+                .map_err(|err| QueryFilterError::Other(err.to_string()))?,
+            _ => {
+                return Err(QueryFilterError::Other(
+                    "invalid Bloom filter: bad magic".into(),
+                ))
+            }
+        };
+        Ok(Self { filter })
+    }
+
+    /// Returns `true` if a key is believed to be in the filter, given the
+    /// bytes its [`Hash`] implementation feeds to the hasher, concatenated.
+    ///
+    /// These are e.g. `key.to_ne_bytes()` for integers, the bytes of a
+    /// string followed by `0xff`, or all chunks of a key inserted with
+    /// [`insert_streamed`](BloomFilter::insert_streamed).
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        let mut key = self.filter.hasher().key_hasher();
+        key.update(bytes);
+        self.filter.contains_streamed(&key)
+    }
+
+    /// Returns `true` if a key is believed to be in the filter, hashing it
+    /// through its [`Hash`] implementation.
+    ///
+    /// The key may be of any type hashing the same way as the original key
+    /// type, e.g. `str` for `String` keys.
+    pub fn contains_hash<Q: Hash + ?Sized>(&self, key: &Q) -> bool {
+        let mut hasher = Feed(self.filter.hasher().key_hasher());
+        key.hash(&mut hasher);
+        self.filter.contains_streamed(&hasher.0)
+    }
+
+    /// Returns the number of bits.
+    pub fn bit_count(&self) -> usize {
+        self.filter.bit_count()
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> usize {
+        self.filter.hash_count()
+    }
+}
+
+/// Feeds the bytes a key hashes as to a key hasher.
+///
+/// Base hashes are seeded XXH3 of these bytes, so that this produces the
+/// same probes as hashing the key itself.
+struct Feed(KeyHasher);
+
+impl Hasher for Feed {
+    fn finish(&self) -> u64 {
+        unreachable!("probes are taken from the key hasher")
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+}
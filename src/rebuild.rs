@@ -0,0 +1,192 @@
+//! Rebuilding filters while they keep serving.
+//!
+//! Filters degrade over their lifetime: Bloom filters fill up past their
+//! capacity, and removable filters accumulate errors from deletions. The
+//! fix is a rebuild from the source of truth, which takes a while for large
+//! key sets. A [`RebuildableFilter`] keeps serving queries and updates from
+//! the old filter meanwhile: updates made during the rebuild are logged, and
+//! replayed onto the new filter right before it is swapped in, so none are
+//! lost, whatever the source of truth reflected when it was read.
+//!
+//! The rebuild can run on a thread of its own
+//! ([`rebuild_in_background`](RebuildableFilter::rebuild_in_background)), or
+//! anywhere else (e.g. an async runtime's blocking pool) between
+//! [`begin_rebuild`](RebuildableFilter::begin_rebuild) and
+//! [`complete_rebuild`](RebuildableFilter::complete_rebuild).
+
+use {
+    crate::{
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+        RemovableQueryFilter,
+    },
+    std::{
+        borrow::Borrow,
+        hash::Hash,
+        sync::{Arc, Mutex, RwLock},
+        thread::{self, JoinHandle},
+    },
+};
+
+/// Update logged during a rebuild, to be replayed onto the new filter.
+type Update<F> = Box<dyn FnOnce(&mut F) + Send>;
+
+/// State shared by the handles of a filter.
+struct Shared<F> {
+    filter: RwLock<F>,
+    /// Updates made since the rebuild began, if one is in progress.
+    log: Mutex<Option<Vec<Update<F>>>>,
+}
+
+/// Shared filter that can be rebuilt and swapped while serving.
+///
+/// Cloning creates another handle to the same filter.
+pub struct RebuildableFilter<F> {
+    shared: Arc<Shared<F>>,
+}
+
+impl<F> Clone for RebuildableFilter<F> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<F> RebuildableFilter<F> {
+    /// Wraps a filter.
+    pub fn new(filter: F) -> Self {
+        Self {
+            shared: Arc::new(Shared {
+                filter: RwLock::new(filter),
+                log: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Runs a closure with the current filter, e.g. to inspect its fill.
+    pub fn with_filter<R>(&self, f: impl FnOnce(&F) -> R) -> R {
+        f(&self.shared.filter.read().unwrap())
+    }
+
+    /// Returns `true` if a rebuild is in progress.
+    pub fn is_rebuilding(&self) -> bool {
+        self.shared.log.lock().unwrap().is_some()
+    }
+
+    /// Starts logging updates, to be replayed onto the rebuilt filter.
+    ///
+    /// The new filter must then be built from the source of truth, and
+    /// passed to [`complete_rebuild`](RebuildableFilter::complete_rebuild)
+    /// (or the rebuild given up with
+    /// [`abort_rebuild`](RebuildableFilter::abort_rebuild)).
+    ///
+    /// Fails if a rebuild is already in progress.
+    pub fn begin_rebuild(&self) -> QueryFilterResult<()> {
+        let mut log = self.shared.log.lock().unwrap();
+        if log.is_some() {
+            return Err(QueryFilterError::Other(
+                "rebuild already in progress".to_owned(),
+            ));
+        }
+        *log = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Replays the updates logged since the rebuild began onto a new filter,
+    /// and swaps it in. Returns the old filter.
+    ///
+    /// Updates are blocked while the log is replayed, queries while the
+    /// filters are swapped.
+    ///
+    /// Fails if no rebuild is in progress, leaving the current filter in
+    /// place.
+    pub fn complete_rebuild(&self, mut filter: F) -> QueryFilterResult<F> {
+        // Lock order (filter, then log) matches the one of updates.
+        let mut current = self.shared.filter.write().unwrap();
+        let updates = self
+            .shared
+            .log
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| QueryFilterError::Other("no rebuild in progress".to_owned()))?;
+        for update in updates {
+            update(&mut filter);
+        }
+        Ok(std::mem::replace(&mut *current, filter))
+    }
+
+    /// Gives up the rebuild in progress, if any, dropping the logged
+    /// updates.
+    pub fn abort_rebuild(&self) {
+        self.shared.log.lock().unwrap().take();
+    }
+
+    /// Applies an update to the current filter, logging it if a rebuild is
+    /// in progress.
+    fn update(&self, update: impl Fn(&mut F) + Send + 'static) {
+        let mut filter = self.shared.filter.write().unwrap();
+        update(&mut filter);
+        if let Some(log) = self.shared.log.lock().unwrap().as_mut() {
+            log.push(Box::new(move |filter| update(filter)));
+        }
+    }
+}
+
+impl<F> RebuildableFilter<F>
+where
+    F: Send + Sync + 'static,
+{
+    /// Builds a new filter on a thread of its own, and swaps it in once
+    /// built. The thread returns the old filter, or fails if the rebuild was
+    /// aborted meanwhile.
+    ///
+    /// `build` must read the source of truth after this call, so that
+    /// updates it misses are logged.
+    ///
+    /// Fails if a rebuild is already in progress.
+    pub fn rebuild_in_background(
+        &self,
+        build: impl FnOnce() -> F + Send + 'static,
+    ) -> QueryFilterResult<JoinHandle<QueryFilterResult<F>>> {
+        self.begin_rebuild()?;
+        let this = self.clone();
+        Ok(thread::spawn(move || this.complete_rebuild(build())))
+    }
+}
+
+impl<F> RebuildableFilter<F> {
+    /// Inserts a key.
+    pub fn insert<K>(&self, key: K)
+    where
+        F: InsertableQueryFilter<K>,
+        K: Eq + Hash + Clone + Send + 'static,
+    {
+        self.update(move |filter| filter.insert(key.clone()));
+    }
+
+    /// Removes a key.
+    pub fn remove<K>(&self, key: K)
+    where
+        F: RemovableQueryFilter<K>,
+        K: Eq + Hash + Send + 'static,
+    {
+        self.update(move |filter| filter.remove(&key));
+    }
+}
+
+impl<K, F> QueryFilter<K> for RebuildableFilter<F>
+where
+    F: QueryFilter<K>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shared.filter.read().unwrap().contains(key)
+    }
+}
@@ -0,0 +1,402 @@
+//! RedisBloom interoperability.
+//!
+//! RedisBloom stores a Bloom filter (`BF.RESERVE`, `BF.ADD`) as a chain of
+//! plain Bloom filters, adding a larger link whenever the last one is full.
+//! `BF.SCANDUMP` exports a chain as a header chunk, describing every link,
+//! followed by the raw bits of the links, and `BF.LOADCHUNK` loads such
+//! chunks back. A [`RedisBloomFilter`] wraps a [`BloomFilter`] that is
+//! bit-compatible with a single-link chain, and converts from and to
+//! `BF.SCANDUMP` chunks, so that filters can be built offline and loaded
+//! into Redis, or dumped from Redis and queried locally.
+//!
+//! RedisBloom hashes an item's bytes with MurmurHash64A twice (the second
+//! time seeded with the first hash), and combines both hashes by double
+//! hashing, which is what [`RedisBloomHasher`] does with the bytes fed by
+//! an item's [`Hash`] implementation. Those bytes only match the item as
+//! sent to Redis for keys wrapped in [`RedisKey`], since e.g. strings also
+//! feed a terminator: use `RedisBloomFilter<RedisKey<String>>` or
+//! `RedisBloomFilter<RedisKey<Vec<u8>>>` to interoperate.
+//!
+//! Only filters with 64-bit hashing (the default since RedisBloom 2.0) are
+//! supported. Chains that have scaled past their first link cannot be
+//! represented by a single Bloom filter and fail to load.
+
+use {
+    crate::{
+        analysis::check_fp_rate,
+        storage::BitStorage,
+        BloomFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    hash_iter::HashIterHasher,
+    std::{
+        borrow::Borrow,
+        hash::{Hash, Hasher},
+    },
+};
+
+/// Seed of the first MurmurHash64A pass.
+const SEED: u64 = 0xc6a4a7935bd1e995;
+
+/// Largest data chunk produced by [`RedisBloomFilter::scandump`], in bytes.
+pub const MAX_CHUNK_BYTES: usize = 10 * 1024 * 1024;
+
+/// Chain option: sizes are not rounded up to a power of two.
+const OPT_NOROUND: u32 = 1;
+/// Chain option: capacity is given in bits rather than entries.
+const OPT_ENTS_IS_BITS: u32 = 2;
+/// Chain option: 64-bit hashing.
+const OPT_FORCE64: u32 = 4;
+
+/// Growth factor RedisBloom applies by default when a chain scales.
+const DEFAULT_EXPANSION: u32 = 2;
+
+/// Length of the chain header preceding link descriptions, in bytes.
+const HEADER_LEN: usize = 20;
+/// Length of a single link description, in bytes.
+const LINK_LEN: usize = 53;
+
+/// Probe sequence generator matching RedisBloom's hashing.
+///
+/// Hashes the bytes fed by a key's [`Hash`] implementation, see
+/// [`RedisKey`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RedisBloomHasher;
+
+impl HashIterHasher<u64> for RedisBloomHasher {
+    fn hash_iter<K: Hash + ?Sized>(&self, key: &K, count: usize) -> impl Iterator<Item = u64> {
+        let mut bytes = ByteSink::default();
+        key.hash(&mut bytes);
+        let a = murmur64a(&bytes.0, SEED);
+        let b = murmur64a(&bytes.0, a);
+        (0..count as u64).map(move |i| a.wrapping_add(i.wrapping_mul(b)))
+    }
+}
+
+/// Key hashed as its raw bytes, the way Redis sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RedisKey<T>(pub T);
+
+impl<T> Hash for RedisKey<T>
+where
+    T: AsRef<[u8]>,
+{
+    fn hash<S: Hasher>(&self, state: &mut S) {
+        state.write(self.0.as_ref());
+    }
+}
+
+/// Bloom filter compatible with a (single-link) RedisBloom filter.
+pub struct RedisBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    filter: BloomFilter<K, RedisBloomHasher>,
+    capacity: usize,
+    fp_rate: f64,
+    len: usize,
+}
+
+impl<K> RedisBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate,
+    /// sized the way `BF.RESERVE` (with `NONSCALING` or not) sizes its first
+    /// link.
+    ///
+    /// Fails unless the capacity is positive and the rate is within `0..1`
+    /// (exclusive).
+    pub fn new(capacity: usize, fp_rate: f64) -> QueryFilterResult<Self> {
+        if capacity == 0 {
+            return Err(QueryFilterError::ZeroCapacity);
+        }
+        check_fp_rate(fp_rate)?;
+        let bpe = bits_per_entry(fp_rate);
+        let bit_count = ((capacity as f64 * bpe) as usize)
+            .max(1)
+            .next_multiple_of(64);
+        let hash_count = (std::f64::consts::LN_2 * bpe).ceil() as usize;
+        Ok(Self {
+            filter: BloomFilter::with_bit_count_and_hasher(bit_count, hash_count, RedisBloomHasher),
+            capacity,
+            fp_rate,
+            len: 0,
+        })
+    }
+
+    /// Loads a filter from the `(iterator, data)` pairs returned by
+    /// successive `BF.SCANDUMP` calls, in order.
+    ///
+    /// The final `(0, "")` pair may be included or not. Fails if the chunks
+    /// are malformed or incomplete, if the filter does not use 64-bit
+    /// hashing, or if it has more than one link.
+    pub fn from_scandump<D>(chunks: impl IntoIterator<Item = (i64, D)>) -> QueryFilterResult<Self>
+    where
+        D: AsRef<[u8]>,
+    {
+        let mut chunks = chunks.into_iter();
+        let (iter, header) = chunks.next().ok_or_else(|| invalid("no header chunk"))?;
+        if iter != 1 {
+            return Err(invalid("first chunk is not the header"));
+        }
+        let link = Link::decode(header.as_ref())?;
+
+        let mut bytes = vec![0; link.bytes];
+        let mut loaded = 0;
+        for (iter, data) in chunks {
+            let data = data.as_ref();
+            if iter == 0 && data.is_empty() {
+                break;
+            }
+            // The iterator returned along a chunk is one past its end.
+            let offset = usize::try_from(iter - 1)
+                .ok()
+                .and_then(|end| end.checked_sub(data.len()))
+                .filter(|offset| offset + data.len() <= bytes.len())
+                .ok_or_else(|| invalid("chunk out of bounds"))?;
+            bytes[offset..offset + data.len()].copy_from_slice(data);
+            loaded += data.len();
+        }
+        if loaded != bytes.len() {
+            return Err(invalid("missing data chunks"));
+        }
+
+        let ones = bytes.iter().enumerate().flat_map(|(i, &byte)| {
+            (0..8)
+                .filter(move |bit| byte & (1 << bit) != 0)
+                .map(move |bit| i * 8 + bit)
+        });
+        Ok(Self {
+            filter: BloomFilter::from_set_bits(link.bytes * 8, link.hashes, RedisBloomHasher, ones),
+            capacity: link.entries,
+            fp_rate: link.error,
+            len: link.size,
+        })
+    }
+
+    /// Returns the `(iterator, data)` pairs to pass to successive
+    /// `BF.LOADCHUNK` calls, in order, as `BF.SCANDUMP` would return them
+    /// (without the final `(0, "")` pair).
+    pub fn scandump(&self) -> Vec<(i64, Vec<u8>)> {
+        let bits = self.filter.bits();
+        let mut bytes = (0..bits.word_count())
+            .flat_map(|i| bits.word(i).to_le_bytes())
+            .collect::<Vec<_>>();
+        bytes.truncate(bits.len() / 8);
+        let link = Link {
+            bytes: bytes.len(),
+            size: self.len,
+            error: self.fp_rate,
+            hashes: self.filter.hash_count(),
+            entries: self.capacity,
+        };
+
+        let mut chunks = vec![(1, link.encode())];
+        let mut end = 1;
+        for data in bytes.chunks(MAX_CHUNK_BYTES) {
+            end += data.len() as i64;
+            chunks.push((end, data.to_vec()));
+        }
+        chunks
+    }
+
+    /// Returns the underlying Bloom filter.
+    pub fn filter(&self) -> &BloomFilter<K, RedisBloomHasher> {
+        &self.filter
+    }
+
+    /// Returns the underlying Bloom filter, consuming the wrapper.
+    pub fn into_filter(self) -> BloomFilter<K, RedisBloomHasher> {
+        self.filter
+    }
+
+    /// Returns the capacity the filter was created with.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the false positive rate the filter was created with.
+    pub fn fp_rate(&self) -> f64 {
+        self.fp_rate
+    }
+
+    /// Returns the number of items added, counted the way RedisBloom does:
+    /// items (probably) in the filter already are not counted.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no items were added.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<K> QueryFilter<K> for RedisBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.filter.contains(key)
+    }
+}
+
+impl<K> InsertableQueryFilter<K> for RedisBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Inserts a key.
+    ///
+    /// Unlike RedisBloom, the filter does not scale once at capacity: its
+    /// false positive rate degrades instead.
+    fn insert(&mut self, key: K) {
+        if !self.filter.contains(&key) {
+            self.len += 1;
+        }
+        self.filter.insert(key);
+    }
+}
+
+/// Description of a chain link, as found in a `BF.SCANDUMP` header.
+///
+/// Headers are packed little-endian structs: the chain's item count (`u64`),
+/// link count, options, and growth factor (`u32` each), followed by each
+/// link's byte count, bit count, item count (`u64` each), false positive
+/// rate, bits per entry (`f64` each), hash count (`u32`), capacity (`u64`),
+/// and log2 of the bit count if rounded to a power of two (`u8`, else zero).
+struct Link {
+    bytes: usize,
+    size: usize,
+    error: f64,
+    hashes: usize,
+    entries: usize,
+}
+
+impl Link {
+    /// Encodes a single-link chain header.
+    fn encode(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(HEADER_LEN + LINK_LEN);
+        header.extend_from_slice(&(self.size as u64).to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+        header.extend_from_slice(&(OPT_NOROUND | OPT_FORCE64).to_le_bytes());
+        header.extend_from_slice(&DEFAULT_EXPANSION.to_le_bytes());
+        header.extend_from_slice(&(self.bytes as u64).to_le_bytes());
+        header.extend_from_slice(&(self.bytes as u64 * 8).to_le_bytes());
+        header.extend_from_slice(&(self.size as u64).to_le_bytes());
+        header.extend_from_slice(&self.error.to_le_bytes());
+        header.extend_from_slice(&bits_per_entry(self.error).to_le_bytes());
+        header.extend_from_slice(&(self.hashes as u32).to_le_bytes());
+        header.extend_from_slice(&(self.entries as u64).to_le_bytes());
+        header.push(0);
+        header
+    }
+
+    /// Decodes a chain header, which must describe a single link.
+    fn decode(header: &[u8]) -> QueryFilterResult<Self> {
+        if header.len() < HEADER_LEN {
+            return Err(invalid("truncated header"));
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(header[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+
+        let link_count = u32_at(8);
+        let options = u32_at(12);
+        if link_count != 1 {
+            return Err(QueryFilterError::Other(format!(
+                "unsupported RedisBloom filter: {link_count} links"
+            )));
+        }
+        if options & OPT_FORCE64 == 0 || options & OPT_ENTS_IS_BITS != 0 {
+            return Err(QueryFilterError::Other(format!(
+                "unsupported RedisBloom filter: options {options:#x}"
+            )));
+        }
+        if header.len() != HEADER_LEN + LINK_LEN {
+            return Err(invalid("header length does not match the link count"));
+        }
+
+        let at = HEADER_LEN;
+        let (bytes, bits) = (u64_at(at), u64_at(at + 8));
+        let hashes = u32_at(at + 40);
+        let n2 = header[at + 52];
+        if bytes == 0 || bits != bytes.saturating_mul(8) || (n2 != 0 && bits != 1 << n2.min(63)) {
+            return Err(invalid("inconsistent bit count"));
+        }
+        if hashes == 0 {
+            return Err(invalid("no hash functions"));
+        }
+        let error = f64::from_le_bytes(header[at + 24..at + 32].try_into().unwrap());
+        if !(error > 0. && error < 1.) {
+            return Err(invalid("false positive rate is not within 0..1"));
+        }
+        let to_usize = |value: u64| usize::try_from(value).map_err(|_| invalid("size overflow"));
+        Ok(Self {
+            bytes: to_usize(bytes)?,
+            size: to_usize(u64_at(at + 16))?,
+            error,
+            hashes: hashes as usize,
+            entries: to_usize(u64_at(at + 44))?,
+        })
+    }
+}
+
+fn invalid(reason: &str) -> QueryFilterError {
+    QueryFilterError::Other(format!("invalid RedisBloom dump: {reason}"))
+}
+
+/// Returns the number of bits per entry RedisBloom allots for a false
+/// positive rate.
+fn bits_per_entry(fp_rate: f64) -> f64 {
+    -fp_rate.ln() / (std::f64::consts::LN_2 * std::f64::consts::LN_2)
+}
+
+/// Hasher collecting the bytes it is fed.
+#[derive(Default)]
+struct ByteSink(Vec<u8>);
+
+impl Hasher for ByteSink {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        unreachable!("bytes are hashed with MurmurHash64A")
+    }
+}
+
+/// MurmurHash64A, reading 8-byte blocks as little-endian.
+fn murmur64a(key: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+
+    let mut h = seed ^ (key.len() as u64).wrapping_mul(M);
+    let mut chunks = key.chunks_exact(8);
+    for chunk in &mut chunks {
+        let mut k = u64::from_le_bytes(chunk.try_into().unwrap());
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^ (h >> R)
+}
@@ -0,0 +1,117 @@
+//! Anti-entropy synchronization of replicated Bloom filters.
+//!
+//! Replicas of a filter that receive inserts independently converge by
+//! exchanging only the words that differ. The bit array is split into blocks
+//! of [`BLOCK_WORDS`] words, and each replica summarizes its filter with one
+//! hash per block ([`digest`]). Given a peer's digest, a replica sends back
+//! the words of mismatching blocks ([`diff`]), and the peer merges them in
+//! ([`apply`]). Merging is a bitwise or, so it is idempotent and order
+//! independent: running the exchange in both directions leaves both replicas
+//! with the union of their keys.
+//!
+//! Replicas must share the size, hasher, and number of hash functions.
+
+use {
+    crate::{storage::BitStorage, BloomFilter, QueryFilterError, QueryFilterResult},
+    hash_iter::HashIterHasher,
+    std::hash::Hash,
+    xxhash_rust::xxh3::xxh3_64,
+};
+
+/// Number of 64-bit words covered by a single block hash.
+pub const BLOCK_WORDS: usize = 64;
+
+/// Per-block hashes summarizing a filter's bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Digest {
+    /// Number of words in the filter.
+    pub word_count: usize,
+    /// Hash of each block of words.
+    pub blocks: Vec<u64>,
+}
+
+/// Words to merge into a replica.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    /// Number of words in the filter the patch was computed from.
+    pub word_count: usize,
+    /// Index and value of each word (zero words are omitted).
+    pub words: Vec<(usize, u64)>,
+}
+
+/// Returns the digest of a filter, to be sent to a peer.
+pub fn digest<K, H, S>(filter: &BloomFilter<K, H, S>) -> Digest
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    let word_count = filter.bits().word_count();
+    let blocks = (0..word_count.div_ceil(BLOCK_WORDS))
+        .map(|block| block_hash(filter.bits(), block))
+        .collect();
+    Digest { word_count, blocks }
+}
+
+/// Returns the words of a filter's blocks that differ from a peer's digest.
+///
+/// Fails if the peer's filter has a different size.
+pub fn diff<K, H, S>(filter: &BloomFilter<K, H, S>, peer: &Digest) -> QueryFilterResult<Patch>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    let bits = filter.bits();
+    let word_count = bits.word_count();
+    check_word_count(word_count, peer.word_count)?;
+    let words = peer
+        .blocks
+        .iter()
+        .enumerate()
+        .filter(|&(block, &hash)| block_hash(bits, block) != hash)
+        .flat_map(|(block, _)| block * BLOCK_WORDS..((block + 1) * BLOCK_WORDS).min(word_count))
+        .map(|index| (index, bits.word(index)))
+        .filter(|&(_, word)| word != 0)
+        .collect();
+    Ok(Patch { word_count, words })
+}
+
+/// Merges a patch received from a peer into a filter.
+///
+/// Fails if the peer's filter has a different size.
+pub fn apply<K, H, S>(filter: &mut BloomFilter<K, H, S>, patch: &Patch) -> QueryFilterResult<()>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    let bits = filter.bits_mut();
+    check_word_count(bits.word_count(), patch.word_count)?;
+    for &(index, mut word) in &patch.words {
+        while word != 0 {
+            let bit = index * 64 + word.trailing_zeros() as usize;
+            if bit >= bits.len() {
+                break;
+            }
+            bits.insert(bit);
+            word &= word - 1;
+        }
+    }
+    Ok(())
+}
+
+fn block_hash<S: BitStorage>(bits: &S, block: usize) -> u64 {
+    let end = ((block + 1) * BLOCK_WORDS).min(bits.word_count());
+    let bytes = (block * BLOCK_WORDS..end)
+        .flat_map(|index| bits.word(index).to_le_bytes())
+        .collect::<Vec<_>>();
+    xxh3_64(&bytes)
+}
+
+fn check_word_count(word_count: usize, peer_word_count: usize) -> QueryFilterResult<()> {
+    if word_count != peer_word_count {
+        return Err(QueryFilterError::IncompatibleFilters("sizes differ"));
+    }
+    Ok(())
+}
@@ -0,0 +1,279 @@
+//! Static retrieval (static function) data structures.
+//!
+//! A retrieval structure stores an `r`-bit value for each key of a fixed set
+//! and retrieves it later, without storing the keys themselves: space is
+//! roughly `1.23 * r` bits per key for the xor-based implementation. Querying
+//! a key outside of the set returns an arbitrary value. See
+//! [`StaticRetrieval`].
+//!
+//! A [`BloomierFilter`] additionally stores an `f`-bit fingerprint of each
+//! key next to its value, which tells keys outside of the set apart, but for
+//! a `2^-f` fraction of them.
+
+use {
+    crate::{
+        peeling::{self, MAX_ATTEMPTS},
+        storage::PackedArray,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+        StaticRetrieval,
+    },
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Seed of the hash function fingerprinting keys in a [`BloomierFilter`],
+/// independent of the ones picking slots.
+const FINGERPRINT_SEED: u64 = 0x2545f4914f6cdd1d;
+
+/// Xor-based retrieval structure, mapping keys to `r`-bit values.
+pub struct XorRetrieval<K>
+where
+    K: Eq + Hash,
+{
+    slots: PackedArray,
+    segment_length: usize,
+    seed: u64,
+    phantom: PhantomData<K>,
+}
+
+impl<K> XorRetrieval<K>
+where
+    K: Eq + Hash,
+{
+    /// Builds the structure from key-value pairs, storing `value_bits` bits
+    /// per value.
+    ///
+    /// Fails if some value does not fit into `value_bits` bits, or if the
+    /// same key is given conflicting values (repeated pairs are fine).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value_bits` is not within `1..=64`.
+    pub fn try_from_pairs(
+        pairs: impl IntoIterator<Item = (K, u64)>,
+        value_bits: u32,
+    ) -> QueryFilterResult<Self> {
+        assert!(
+            (1..=64).contains(&value_bits),
+            "value bits must be in 1..=64"
+        );
+        let pairs = pairs.into_iter().collect::<Vec<_>>();
+        if let Some(&(_, value)) = pairs
+            .iter()
+            .find(|(_, value)| *value > PackedArray::max_value(value_bits))
+        {
+            return Err(QueryFilterError::ValueOutOfRange {
+                value,
+                bits: value_bits,
+            });
+        }
+
+        for attempt in 0..MAX_ATTEMPTS {
+            let seed = peeling::seed(attempt);
+            let mut hashed = pairs
+                .iter()
+                .map(|(key, value)| (hash(seed, key), *value))
+                .collect::<Vec<_>>();
+            hashed.sort_unstable();
+            hashed.dedup();
+            if hashed.windows(2).any(|w| w[0].0 == w[1].0) {
+                // Same hash with different values: given a 64-bit hash this is
+                // the same key, rather than an actual collision.
+                return Err(QueryFilterError::DuplicateKey);
+            }
+
+            let hashes = hashed.iter().map(|(hash, _)| *hash).collect::<Vec<_>>();
+            let segment_length = peeling::segment_length(hashes.len());
+            let Some(order) = peeling::peel(&hashes, segment_length) else {
+                continue;
+            };
+
+            let mut slots = PackedArray::new(3 * segment_length, value_bits);
+            for &(i, slot) in order.iter().rev() {
+                let (hash, value) = hashed[i];
+                let value = peeling::slots(hash, segment_length)
+                    .into_iter()
+                    .filter(|&other| other != slot)
+                    .fold(value, |value, other| value ^ slots.get(other));
+                slots.set(slot, value);
+            }
+
+            event!(
+                keys = hashes.len(),
+                value_bits,
+                attempts = attempt + 1;
+                "built xor retrieval"
+            );
+            return Ok(Self {
+                slots,
+                segment_length,
+                seed,
+                phantom: PhantomData,
+            });
+        }
+        Err(QueryFilterError::ConstructionFailed(MAX_ATTEMPTS))
+    }
+
+    /// Returns the number of bits stored per value.
+    pub fn value_bits(&self) -> u32 {
+        self.slots.bits()
+    }
+
+    /// Returns the memory used by the stored values, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.slots.size_in_bytes()
+    }
+}
+
+impl<K> StaticRetrieval<K, u64> for XorRetrieval<K>
+where
+    K: Eq + Hash,
+{
+    fn get<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        peeling::slots(hash(self.seed, key), self.segment_length)
+            .into_iter()
+            .fold(0, |value, slot| value ^ self.slots.get(slot))
+    }
+}
+
+/// Approximate map from a static set of keys to `r`-bit values, telling keys
+/// outside of the set apart with an `f`-bit fingerprint.
+///
+/// Querying a key outside of the set returns `None`, except for a `2^-f`
+/// fraction of such keys, for which an arbitrary value is returned instead.
+/// Space is roughly `1.23 * (r + f)` bits per key.
+///
+/// ```
+/// use mqfilters::{BloomierFilter, StaticRetrieval};
+///
+/// // 4-bit shard IDs, with a 1/256 chance of a bogus shard for unknown keys.
+/// let shards =
+///     BloomierFilter::try_from_pairs((0..1000u64).map(|key| (key, key % 16)), 4, 8).unwrap();
+/// assert_eq!(shards.get(&42), Some(10));
+/// ```
+pub struct BloomierFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Values, with fingerprints in the upper bits.
+    retrieval: XorRetrieval<K>,
+    value_bits: u32,
+}
+
+impl<K> BloomierFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Builds the filter from key-value pairs, storing `value_bits` bits per
+    /// value and `fingerprint_bits` bits per fingerprint.
+    ///
+    /// Fails if some value does not fit into `value_bits` bits, or if the
+    /// same key is given conflicting values (repeated pairs are fine).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value_bits` or `fingerprint_bits` is zero, or if they add
+    /// up to more than 64.
+    pub fn try_from_pairs(
+        pairs: impl IntoIterator<Item = (K, u64)>,
+        value_bits: u32,
+        fingerprint_bits: u32,
+    ) -> QueryFilterResult<Self> {
+        assert!(
+            value_bits > 0 && fingerprint_bits > 0 && value_bits + fingerprint_bits <= 64,
+            "value and fingerprint bits must be positive and add up to at most 64"
+        );
+        let pairs = pairs.into_iter().collect::<Vec<_>>();
+        if let Some(&(_, value)) = pairs
+            .iter()
+            .find(|(_, value)| *value > PackedArray::max_value(value_bits))
+        {
+            return Err(QueryFilterError::ValueOutOfRange {
+                value,
+                bits: value_bits,
+            });
+        }
+        let retrieval = XorRetrieval::try_from_pairs(
+            pairs.into_iter().map(|(key, value)| {
+                let fingerprint = fingerprint(&key, fingerprint_bits);
+                (key, fingerprint << value_bits | value)
+            }),
+            value_bits + fingerprint_bits,
+        )?;
+        Ok(Self {
+            retrieval,
+            value_bits,
+        })
+    }
+
+    /// Returns the number of bits stored per value.
+    pub fn value_bits(&self) -> u32 {
+        self.value_bits
+    }
+
+    /// Returns the number of bits stored per fingerprint.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.retrieval.value_bits() - self.value_bits
+    }
+
+    /// Returns the probability for a key outside of the set to be given a
+    /// value, i.e. `2^-f`.
+    pub fn fp_rate(&self) -> f64 {
+        (-(self.fingerprint_bits() as f64)).exp2()
+    }
+
+    /// Returns the memory used by the stored values and fingerprints, in
+    /// bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.retrieval.size_in_bytes()
+    }
+}
+
+impl<K> StaticRetrieval<K, Option<u64>> for BloomierFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Returns the value stored for the key, or (most likely) `None` if the
+    /// key is not in the set.
+    fn get<Q>(&self, key: &Q) -> Option<u64>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let stored = self.retrieval.get(key);
+        (stored >> self.value_bits == fingerprint(key, self.fingerprint_bits()))
+            .then(|| stored & PackedArray::max_value(self.value_bits))
+    }
+}
+
+impl<K> QueryFilter<K> for BloomierFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.get(key).is_some()
+    }
+}
+
+/// Returns the `bits`-bit fingerprint of a key.
+fn fingerprint<Q: Hash + ?Sized>(key: &Q, bits: u32) -> u64 {
+    hash(FINGERPRINT_SEED, key) & PackedArray::max_value(bits)
+}
+
+fn hash<Q: Hash + ?Sized>(seed: u64, key: &Q) -> u64 {
+    Xxh3Builder::new().with_seed(seed).hash_one(key)
+}
@@ -0,0 +1,589 @@
+//! Static ribbon filter.
+//!
+//! Following [Ribbon filter: practically smaller than Bloom and Xor,
+//! 2021][1]: like an [xor filter](crate::xor), an `r`-bit fingerprint is
+//! stored for each key of a fixed set, as the solution of a linear system
+//! over GF(2). Here each key maps to a random 64-bit coefficient row,
+//! starting at a random slot, so that the system is banded and can be solved
+//! by on-the-fly Gaussian elimination, with less slack: about `1.1 * r` bits
+//! per key (for sets of up to millions of keys) instead of `1.23 * r`.
+//!
+//! That slack (8% of slots for ten thousand keys, 12% for a million) comes on
+//! top of rounding fingerprints up to whole bits, so sizes are not within a
+//! few percent of the information-theoretic minimum of `log2(1 / fp_rate)`
+//! bits per key: with a 64-bit band, construction of the standard variant
+//! fails at a 6% slack for a million keys. The
+//! [homogeneous](RibbonFilter::homogeneous) variant takes a tunable slack
+//! instead and never fails to build, at the cost of a higher false positive
+//! rate at low slack.
+//!
+//! The [bumped](RibbonFilter::bumped) variant, following [Fast Succinct
+//! Retrieval and Approximate Membership using Ribbon, 2022][2] (BuRR), also
+//! takes a tunable slack and never fails to build, without giving up on the
+//! false positive rate: keys are added bucket by bucket (of 64 starting
+//! slots), and the keys of a bucket that does not fit are bumped
+//! to a fallback layer, itself a bumped ribbon filter of the remaining keys.
+//! A bit per bucket records which ones were bumped, and each key is looked
+//! up in the single layer holding it.
+//!
+//! [1]: https://arxiv.org/abs/2103.02515
+//! [2]: https://arxiv.org/abs/2109.01892
+
+use {
+    crate::{
+        peeling::{self, MAX_ATTEMPTS},
+        storage::PackedArray,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Width of coefficient rows, i.e. of the band.
+const WIDTH: usize = 64;
+
+/// Maximum number of layers of a bumped filter, before giving up on
+/// construction.
+const MAX_LAYERS: usize = 64;
+
+/// Ribbon filter over a fixed set of keys.
+pub struct RibbonFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Layers, each holding the keys not bumped from the previous ones. Only
+    /// [bumped](RibbonFilter::bumped) filters have more than one.
+    layers: Vec<Layer>,
+    /// Whether keys' rows are solved to zero rather than to their
+    /// fingerprints, see [`homogeneous`](RibbonFilter::homogeneous).
+    homogeneous: bool,
+    phantom: PhantomData<K>,
+}
+
+impl<K> RibbonFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Builds the filter from a set of keys, with the smallest fingerprint
+    /// size meeting the desired false positive rate. Repeated keys are fine.
+    ///
+    /// Fails unless `fp_rate` is within `2^-32..1`, or if construction does
+    /// not succeed within a bounded number of attempts, which is vanishingly
+    /// unlikely.
+    pub fn from_keys(keys: impl IntoIterator<Item = K>, fp_rate: f64) -> QueryFilterResult<Self> {
+        let fingerprint_bits = (-fp_rate.log2()).ceil();
+        if !(1. ..=32.).contains(&fingerprint_bits) {
+            return Err(QueryFilterError::InvalidFpRate(fp_rate));
+        }
+        let fingerprint_bits = fingerprint_bits as u32;
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let slot_count = (keys.len() as f64 * space_overhead(keys.len())).ceil() as usize + WIDTH;
+        for attempt in 0..MAX_ATTEMPTS {
+            let seed = peeling::seed(attempt);
+            let mut band = Band::new(slot_count);
+            let banded = keys.iter().all(|key| {
+                let row = Row::new(hash(seed, key), slot_count, fingerprint_bits);
+                band.add(row)
+            });
+            if !banded {
+                continue;
+            }
+
+            event!(
+                keys = keys.len(),
+                fingerprint_bits,
+                attempts = attempt + 1;
+                "built ribbon filter"
+            );
+            return Ok(Self {
+                layers: vec![Layer::new(band.solve(fingerprint_bits, |_| 0), seed)],
+                homogeneous: false,
+                phantom: PhantomData,
+            });
+        }
+        Err(QueryFilterError::ConstructionFailed(MAX_ATTEMPTS))
+    }
+
+    /// Builds a homogeneous ribbon filter from a set of keys, with the
+    /// smallest fingerprint size meeting the desired false positive rate, and
+    /// `space_overhead` slots per key (e.g. `1.01` to `1.10`). Repeated keys
+    /// are fine.
+    ///
+    /// Keys' rows are solved to zero rather than to their fingerprints, with
+    /// free slots filled at random: the system is always solvable, so
+    /// construction never fails, however low the overhead. In exchange, keys
+    /// whose rows are combinations of stored ones always pass, which gets
+    /// likelier at lower overheads: with a 64-bit band, the false positive
+    /// rate is close to `2^-r` from an overhead of about `1.05` on, but a few
+    /// times higher at `1.03`, and unusable at `1.01`.
+    ///
+    /// Fails unless `fp_rate` is within `2^-32..1` and `space_overhead` is
+    /// within `1..=2`.
+    pub fn homogeneous(
+        keys: impl IntoIterator<Item = K>,
+        fp_rate: f64,
+        space_overhead: f64,
+    ) -> QueryFilterResult<Self> {
+        let fingerprint_bits = (-fp_rate.log2()).ceil();
+        if !(1. ..=32.).contains(&fingerprint_bits) {
+            return Err(QueryFilterError::InvalidFpRate(fp_rate));
+        }
+        if !(1. ..=2.).contains(&space_overhead) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid space overhead: {space_overhead} is not within 1..=2"
+            )));
+        }
+        let fingerprint_bits = fingerprint_bits as u32;
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let slot_count = (keys.len() as f64 * space_overhead).ceil() as usize + WIDTH;
+        let seed = peeling::seed(0);
+        let mut band = Band::new(slot_count);
+        for key in &keys {
+            let row = Row::new(hash(seed, key), slot_count, fingerprint_bits);
+            band.add(Row {
+                fingerprint: 0,
+                ..row
+            });
+        }
+        event!(
+            keys = keys.len(),
+            fingerprint_bits,
+            space_overhead;
+            "built homogeneous ribbon filter"
+        );
+        Ok(Self {
+            layers: vec![Layer::new(
+                band.solve(fingerprint_bits, |slot| mix(seed ^ slot as u64)),
+                seed,
+            )],
+            homogeneous: true,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Builds a bumped ribbon filter (BuRR) from a set of keys, with the
+    /// smallest fingerprint size meeting the desired false positive rate, and
+    /// `space_overhead` slots per key in each layer (e.g. `1.` to `1.05`).
+    /// Repeated keys are fine.
+    ///
+    /// Keys that do not fit are bumped to further layers rather than failing
+    /// construction, keeping the false positive rate at `2^-r` whatever the
+    /// overhead. Lower overheads bump more keys to more layers, each also
+    /// costing a bit per 64 slots, yet the smallest filters come from
+    /// overheads close to `1`: about 7% smaller than the standard variant for
+    /// a hundred thousand keys.
+    ///
+    /// Fails unless `fp_rate` is within `2^-32..1` and `space_overhead` is
+    /// within `1..=2`, or if keys are left after 64 layers, which
+    /// is vanishingly unlikely.
+    pub fn bumped(
+        keys: impl IntoIterator<Item = K>,
+        fp_rate: f64,
+        space_overhead: f64,
+    ) -> QueryFilterResult<Self> {
+        let fingerprint_bits = (-fp_rate.log2()).ceil();
+        if !(1. ..=32.).contains(&fingerprint_bits) {
+            return Err(QueryFilterError::InvalidFpRate(fp_rate));
+        }
+        if !(1. ..=2.).contains(&space_overhead) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid space overhead: {space_overhead} is not within 1..=2"
+            )));
+        }
+        let fingerprint_bits = fingerprint_bits as u32;
+        let keys = keys.into_iter().collect::<Vec<_>>();
+        let mut layers = Vec::new();
+        let mut remaining = keys.iter().collect::<Vec<_>>();
+        // The last layer bumps no keys, even if it holds none.
+        while layers.is_empty() || !remaining.is_empty() {
+            if layers.len() == MAX_LAYERS {
+                return Err(QueryFilterError::LevelsExhausted(MAX_LAYERS));
+            }
+            let seed = peeling::seed(layers.len());
+            let (layer, bumped) = Layer::build(&remaining, seed, fingerprint_bits, space_overhead);
+            layers.push(layer);
+            remaining = bumped;
+        }
+        event!(
+            keys = keys.len(),
+            fingerprint_bits,
+            space_overhead,
+            layers = layers.len();
+            "built bumped ribbon filter"
+        );
+        Ok(Self {
+            layers,
+            homogeneous: false,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns `true` if this is a homogeneous ribbon filter, see
+    /// [`homogeneous`](RibbonFilter::homogeneous).
+    pub fn is_homogeneous(&self) -> bool {
+        self.homogeneous
+    }
+
+    /// Returns the number of layers, more than one only if keys were bumped,
+    /// see [`bumped`](RibbonFilter::bumped).
+    pub fn layer_count(&self) -> usize {
+        self.layers.len()
+    }
+
+    /// Returns the number of bits per fingerprint.
+    pub fn fingerprint_bits(&self) -> u32 {
+        self.layers[0].solution.bits()
+    }
+
+    /// Returns the memory used by the stored solutions and bumped buckets, in
+    /// bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| layer.solution.size_in_bytes() + layer.bumped.size_in_bytes())
+            .sum()
+    }
+}
+
+impl<K> QueryFilter<K> for RibbonFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for layer in &self.layers {
+            let row = Row::new(
+                hash(layer.seed, key),
+                layer.solution.len(),
+                layer.solution.bits(),
+            );
+            if layer.is_bumped(row.start / WIDTH) {
+                continue;
+            }
+            let mut coefficients = row.coefficients;
+            let mut value = 0;
+            while coefficients != 0 {
+                let offset = coefficients.trailing_zeros() as usize;
+                value ^= layer.solution.get(row.start + offset);
+                coefficients &= coefficients - 1;
+            }
+            return value == if self.homogeneous { 0 } else { row.fingerprint };
+        }
+        unreachable!("the last layer bumps no keys")
+    }
+}
+
+/// Solution for the keys of a single layer.
+struct Layer {
+    solution: PackedArray,
+    seed: u64,
+    /// Whether the keys of each bucket of [`WIDTH`] starting slots were
+    /// bumped to the next layer, as 1-bit values. Empty if none were.
+    bumped: PackedArray,
+}
+
+impl Layer {
+    /// Creates a layer bumping no keys.
+    fn new(solution: PackedArray, seed: u64) -> Self {
+        Self {
+            solution,
+            seed,
+            bumped: PackedArray::new(0, 1),
+        }
+    }
+
+    /// Builds a layer from a set of keys, adding their rows bucket by bucket,
+    /// in order of starting slot. The keys of buckets whose rows contradict
+    /// the ones already added are bumped instead, and returned.
+    fn build<'a, Q: Hash>(
+        keys: &[&'a Q],
+        seed: u64,
+        fingerprint_bits: u32,
+        space_overhead: f64,
+    ) -> (Self, Vec<&'a Q>) {
+        let slot_count = (keys.len() as f64 * space_overhead).ceil() as usize + WIDTH;
+        let mut rows = keys
+            .iter()
+            .map(|&key| (Row::new(hash(seed, key), slot_count, fingerprint_bits), key))
+            .collect::<Vec<_>>();
+        rows.sort_unstable_by_key(|(row, _)| row.start);
+
+        let mut band = Band::new(slot_count);
+        let mut bumped = PackedArray::new(bucket_count(slot_count), 1);
+        let mut bumped_keys = Vec::new();
+        for bucket in rows.chunk_by(|(a, _), (b, _)| a.start / WIDTH == b.start / WIDTH) {
+            let checkpoint = band.checkpoint();
+            if !bucket.iter().all(|&(row, _)| band.add(row)) {
+                band.rollback(checkpoint);
+                bumped.set(bucket[0].0.start / WIDTH, 1);
+                bumped_keys.extend(bucket.iter().map(|&(_, key)| key));
+            }
+        }
+        if bumped_keys.is_empty() {
+            bumped = PackedArray::new(0, 1);
+        }
+        let layer = Self {
+            solution: band.solve(fingerprint_bits, |_| 0),
+            seed,
+            bumped,
+        };
+        (layer, bumped_keys)
+    }
+
+    /// Returns `true` if the keys of a bucket were bumped to the next layer.
+    fn is_bumped(&self, bucket: usize) -> bool {
+        !self.bumped.is_empty() && self.bumped.get(bucket) == 1
+    }
+}
+
+/// Returns the number of buckets of [`WIDTH`] starting slots, for
+/// `slot_count` slots.
+fn bucket_count(slot_count: usize) -> usize {
+    (slot_count - WIDTH) / WIDTH + 1
+}
+
+/// Equation of a single key: the xor of the solution at slots `start + i`,
+/// for each bit `i` set in `coefficients`, equals `fingerprint`.
+#[derive(Debug, Clone, Copy)]
+struct Row {
+    start: usize,
+    coefficients: u64,
+    fingerprint: u64,
+}
+
+impl Row {
+    fn new(hash: u64, slot_count: usize, fingerprint_bits: u32) -> Self {
+        let starts = (slot_count - WIDTH + 1) as u128;
+        let mixed = mix(hash);
+        Self {
+            start: ((hash as u128 * starts) >> 64) as usize,
+            // The first coefficient is always set, so that the row is
+            // pivoted on its starting slot.
+            coefficients: mixed | 1,
+            fingerprint: mix(mixed) & PackedArray::max_value(fingerprint_bits),
+        }
+    }
+}
+
+/// System of banded equations, in echelon form: the row stored at each slot
+/// (if any) has its first coefficient there.
+struct Band {
+    coefficients: Vec<u64>,
+    fingerprints: Vec<u64>,
+    /// Slots rows were stored at, in order, to roll additions back.
+    stored: Vec<usize>,
+}
+
+impl Band {
+    fn new(slot_count: usize) -> Self {
+        Self {
+            coefficients: vec![0; slot_count],
+            fingerprints: vec![0; slot_count],
+            stored: Vec::new(),
+        }
+    }
+
+    /// Returns a checkpoint to [`rollback`](Band::rollback) to.
+    fn checkpoint(&self) -> usize {
+        self.stored.len()
+    }
+
+    /// Removes the rows added since a checkpoint.
+    fn rollback(&mut self, checkpoint: usize) {
+        for slot in self.stored.drain(checkpoint..) {
+            self.coefficients[slot] = 0;
+            self.fingerprints[slot] = 0;
+        }
+    }
+
+    /// Adds a row, eliminating it against stored ones. Returns `false` if
+    /// the row contradicts them.
+    fn add(&mut self, row: Row) -> bool {
+        let Row {
+            mut start,
+            mut coefficients,
+            mut fingerprint,
+        } = row;
+        loop {
+            if self.coefficients[start] == 0 {
+                self.coefficients[start] = coefficients;
+                self.fingerprints[start] = fingerprint;
+                self.stored.push(start);
+                return true;
+            }
+            coefficients ^= self.coefficients[start];
+            fingerprint ^= self.fingerprints[start];
+            if coefficients == 0 {
+                // Redundant (e.g. a repeated key) unless contradictory.
+                return fingerprint == 0;
+            }
+            let shift = coefficients.trailing_zeros();
+            start += shift as usize;
+            coefficients >>= shift;
+        }
+    }
+
+    /// Solves the system by back substitution, setting free slots (the
+    /// ones without a row) to `free(slot)`.
+    fn solve(&self, fingerprint_bits: u32, free: impl Fn(usize) -> u64) -> PackedArray {
+        let mut solution = PackedArray::new(self.coefficients.len(), fingerprint_bits);
+        for start in (0..self.coefficients.len()).rev() {
+            if self.coefficients[start] == 0 {
+                solution.set(
+                    start,
+                    free(start) & PackedArray::max_value(fingerprint_bits),
+                );
+                continue;
+            }
+            let mut coefficients = self.coefficients[start] & !1;
+            let mut value = self.fingerprints[start];
+            while coefficients != 0 {
+                let offset = coefficients.trailing_zeros() as usize;
+                value ^= solution.get(start + offset);
+                coefficients &= coefficients - 1;
+            }
+            solution.set(start, value);
+        }
+        solution
+    }
+}
+
+fn hash<Q: Hash + ?Sized>(seed: u64, key: &Q) -> u64 {
+    Xxh3Builder::new().with_seed(seed).hash_one(key)
+}
+
+/// Returns the number of slots per key, for `n` keys.
+///
+/// The slack needed for construction to succeed with high probability grows
+/// logarithmically with the number of keys: about 8% for ten thousand keys,
+/// 12% for a million.
+fn space_overhead(n: usize) -> f64 {
+    1. + (n.max(10) as f64).log10() / 50.
+}
+
+/// Remixes a hash (SplitMix64 finalizer), to derive further independent
+/// bits from it.
+fn mix(hash: u64) -> u64 {
+    let mut z = hash;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Serialized form of a ribbon filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "RibbonFilter")]
+struct SerdeRibbonFilter<L> {
+    homogeneous: bool,
+    layers: L,
+}
+
+/// Serialized form of a layer of a ribbon filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Layer")]
+struct SerdeLayer<S> {
+    seed: u64,
+    solution: S,
+    bumped: S,
+}
+
+#[cfg(feature = "serde")]
+impl<K> serde::Serialize for RibbonFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeRibbonFilter {
+            homogeneous: self.homogeneous,
+            layers: &self.layers,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K> serde::Deserialize<'de> for RibbonFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeRibbonFilter::<Vec<Layer>>::deserialize(deserializer)?;
+        let (first, last) = match (filter.layers.first(), filter.layers.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Err(D::Error::custom("no layers")),
+        };
+        let fingerprint_bits = first.solution.bits();
+        if filter
+            .layers
+            .iter()
+            .any(|layer| layer.solution.bits() != fingerprint_bits)
+        {
+            return Err(D::Error::custom("layers differ in fingerprint width"));
+        }
+        if !last.bumped.is_empty() {
+            return Err(D::Error::custom("the last layer bumps keys"));
+        }
+        if filter.homogeneous && filter.layers.len() > 1 {
+            return Err(D::Error::custom("homogeneous filter with several layers"));
+        }
+        Ok(Self {
+            layers: filter.layers,
+            homogeneous: filter.homogeneous,
+            phantom: PhantomData,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Layer {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeLayer {
+            seed: self.seed,
+            solution: &self.solution,
+            bumped: &self.bumped,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Layer {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let layer = SerdeLayer::<PackedArray>::deserialize(deserializer)?;
+        if layer.solution.len() < WIDTH {
+            return Err(D::Error::custom(format!(
+                "expected at least {WIDTH} slots, got {}",
+                layer.solution.len()
+            )));
+        }
+        if layer.solution.bits() > 32 {
+            return Err(D::Error::custom("fingerprints wider than 32 bits"));
+        }
+        let buckets = bucket_count(layer.solution.len());
+        if !layer.bumped.is_empty() && (layer.bumped.len(), layer.bumped.bits()) != (buckets, 1) {
+            return Err(D::Error::custom(format!(
+                "expected no bumped buckets or a bit for each of {buckets}"
+            )));
+        }
+        Ok(Self {
+            solution: layer.solution,
+            seed: layer.seed,
+            bumped: layer.bumped,
+        })
+    }
+}
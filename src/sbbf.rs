@@ -0,0 +1,330 @@
+//! Split-block Bloom filter, compatible with Apache Parquet.
+//!
+//! Each key touches a single 256-bit block (eight 32-bit words), setting one
+//! bit in each word, so that a query costs one cache miss. Layout, hashing,
+//! and bit selection follow the [Parquet specification][1], so the bitset of
+//! a filter written by another Parquet implementation (e.g. parquet-rs or
+//! Arrow) can be loaded with [`from_bytes`](BlockedBloomFilter::from_bytes)
+//! and queried, and vice versa.
+//!
+//! Parquet hashes the plain encoding of a value with 64-bit xxHash (seed 0).
+//! Keys inserted through [`InsertableQueryFilter`] are hashed by feeding
+//! their [`Hash`] implementation to that same hash function, which matches
+//! the plain encoding for integers (on little-endian platforms) but not for,
+//! e.g., strings: to interoperate, use
+//! [`insert_bytes`](BlockedBloomFilter::insert_bytes) and
+//! [`contains_bytes`](BlockedBloomFilter::contains_bytes) with plain-encoded
+//! values instead.
+//!
+//! With the `simd` feature, on x86-64 CPUs supporting AVX2, a block is tested
+//! or updated as a whole with a few vector instructions (detected at run
+//! time, falling back to scalar code otherwise).
+//!
+//! [1]: https://github.com/apache/parquet-format/blob/master/BloomFilter.md
+
+use {
+    crate::{
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    xxhash_rust::xxh64::{xxh64, Xxh64Builder},
+};
+
+/// Number of bytes in a single block.
+pub const BLOCK_BYTES: usize = 32;
+
+/// Smallest bitset size, in bytes.
+pub const MIN_BYTES: usize = BLOCK_BYTES;
+
+/// Largest bitset size, in bytes, as mandated by Parquet.
+pub const MAX_BYTES: usize = 128 * 1024 * 1024;
+
+/// Odd constants, one per word of a block, used to pick a bit in each word.
+const SALT: [u32; 8] = [
+    0x47b6137b, 0x44974d91, 0x8824ad5b, 0xa2b7289d, 0x705495c7, 0x2df1424b, 0x9efc4947, 0x5c6bfb31,
+];
+
+type Block = [u32; 8];
+
+/// Bloom filter with all probes of a key in a single 256-bit block.
+pub struct BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    blocks: Vec<Block>,
+    phantom: PhantomData<K>,
+}
+
+impl<K> BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate)
+    }
+
+    /// Creates a new filter with a desired capacity and false positive rate.
+    ///
+    /// Sized like parquet-rs does, i.e. rounded up to a power of two bytes.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        let bits = -8. * capacity as f64 / (1. - fp_rate.powf(1. / 8.)).ln();
+        Self::with_size(bits as usize / 8)
+    }
+
+    /// Creates a new filter of (about) `size` bytes, rounded up to a power of
+    /// two within [`MIN_BYTES`]`..=`[`MAX_BYTES`].
+    pub fn with_size(size: usize) -> Self {
+        let size = size.clamp(MIN_BYTES, MAX_BYTES).next_power_of_two();
+        Self {
+            blocks: vec![Block::default(); size / BLOCK_BYTES],
+            phantom: PhantomData,
+        }
+    }
+
+    /// Loads a filter from its bitset, as stored in a Parquet file (without
+    /// the Thrift header preceding it).
+    ///
+    /// Fails unless the bitset is a positive multiple of [`BLOCK_BYTES`]
+    /// long.
+    pub fn from_bytes(bytes: &[u8]) -> QueryFilterResult<Self> {
+        if bytes.is_empty() || !bytes.len().is_multiple_of(BLOCK_BYTES) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid bitset: {} bytes is not a positive multiple of {BLOCK_BYTES}",
+                bytes.len()
+            )));
+        }
+        let blocks = bytes
+            .chunks_exact(BLOCK_BYTES)
+            .map(|chunk| {
+                let mut block = Block::default();
+                for (word, bytes) in block.iter_mut().zip(chunk.chunks_exact(4)) {
+                    *word = u32::from_le_bytes(bytes.try_into().unwrap());
+                }
+                block
+            })
+            .collect();
+        Ok(Self {
+            blocks,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the filter's bitset, laid out as stored in a Parquet file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.blocks
+            .iter()
+            .flatten()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+
+    /// Returns the number of blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the memory used by the bitset, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.blocks.len() * BLOCK_BYTES
+    }
+
+    /// Inserts a plain-encoded value, as Parquet does.
+    pub fn insert_bytes(&mut self, bytes: &[u8]) {
+        self.insert_hash(xxh64(bytes, 0));
+    }
+
+    /// Checks whether a plain-encoded value may be in the filter.
+    pub fn contains_bytes(&self, bytes: &[u8]) -> bool {
+        self.contains_hash(xxh64(bytes, 0))
+    }
+
+    /// Inserts a value, given its 64-bit hash.
+    pub fn insert_hash(&mut self, hash: u64) {
+        let index = self.block_index(hash);
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if avx2::is_supported() {
+            // SAFETY: AVX2 is supported.
+            unsafe { avx2::insert(&mut self.blocks[index], hash) };
+            return;
+        }
+        let mask = mask(hash);
+        for (word, bit) in self.blocks[index].iter_mut().zip(mask) {
+            *word |= bit;
+        }
+    }
+
+    /// Checks whether a value may be in the filter, given its 64-bit hash.
+    pub fn contains_hash(&self, hash: u64) -> bool {
+        let block = &self.blocks[self.block_index(hash)];
+        #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+        if avx2::is_supported() {
+            // SAFETY: AVX2 is supported.
+            return unsafe { avx2::contains(block, hash) };
+        }
+        block
+            .iter()
+            .zip(mask(hash))
+            .all(|(word, bit)| word & bit != 0)
+    }
+
+    /// Picks a block from the upper half of a hash.
+    fn block_index(&self, hash: u64) -> usize {
+        (((hash >> 32) * self.blocks.len() as u64) >> 32) as usize
+    }
+}
+
+impl<K> QueryFilter<K> for BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.contains_hash(Xxh64Builder::new(0).hash_one(key))
+    }
+}
+
+impl<K> InsertableQueryFilter<K> for BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn insert(&mut self, key: K) {
+        self.insert_hash(Xxh64Builder::new(0).hash_one(&key));
+    }
+}
+
+impl<K> ClearableQueryFilter<K> for BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn clear(&mut self) {
+        self.blocks.fill(Block::default());
+    }
+}
+
+/// Returns the bit to set in each word of a block, from the lower half of a
+/// hash.
+fn mask(hash: u64) -> [u32; 8] {
+    SALT.map(|salt| 1 << ((hash as u32).wrapping_mul(salt) >> 27))
+}
+
+/// Block operations on 256-bit vectors.
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod avx2 {
+    use {
+        super::{Block, SALT},
+        std::{arch::x86_64::*, sync::OnceLock},
+    };
+
+    /// Returns `true` if the CPU supports AVX2, detecting it on first call
+    /// only.
+    pub(super) fn is_supported() -> bool {
+        static SUPPORTED: OnceLock<bool> = OnceLock::new();
+        *SUPPORTED.get_or_init(|| is_x86_feature_detected!("avx2"))
+    }
+
+    /// Returns the bits to set in a block, as [`mask`](super::mask) does.
+    #[target_feature(enable = "avx2")]
+    unsafe fn mask(hash: u64) -> __m256i {
+        let salt = _mm256_loadu_si256(SALT.as_ptr().cast());
+        let products = _mm256_mullo_epi32(_mm256_set1_epi32(hash as i32), salt);
+        _mm256_sllv_epi32(_mm256_set1_epi32(1), _mm256_srli_epi32(products, 27))
+    }
+
+    /// Sets the bits of a hash in a block.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn insert(block: &mut Block, hash: u64) {
+        let words = _mm256_loadu_si256(block.as_ptr().cast());
+        _mm256_storeu_si256(
+            block.as_mut_ptr().cast(),
+            _mm256_or_si256(words, mask(hash)),
+        );
+    }
+
+    /// Checks whether the bits of a hash are all set in a block.
+    ///
+    /// # Safety
+    ///
+    /// The CPU must support AVX2.
+    #[target_feature(enable = "avx2")]
+    pub(super) unsafe fn contains(block: &Block, hash: u64) -> bool {
+        let words = _mm256_loadu_si256(block.as_ptr().cast());
+        // Set iff no bit of the mask is unset in the block.
+        _mm256_testc_si256(words, mask(hash)) != 0
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn matches_scalar() {
+            if !is_supported() {
+                return;
+            }
+            let mut hash = 0x9e3779b97f4a7c15u64;
+            for _ in 0..1000 {
+                hash = hash.wrapping_mul(0xbf58476d1ce4e5b9).rotate_left(17);
+                let mut block = Block::default();
+                // SAFETY: AVX2 is supported, as checked above.
+                unsafe { insert(&mut block, hash) };
+                assert_eq!(block, super::super::mask(hash));
+                // SAFETY: AVX2 is supported, as checked above.
+                assert!(unsafe { contains(&block, hash) });
+                block[hash as usize % 8] = 0;
+                // SAFETY: AVX2 is supported, as checked above.
+                assert!(!unsafe { contains(&block, hash) });
+            }
+        }
+    }
+}
+
+/// Serialized form of a split-block Bloom filter: its bitset, as stored in a
+/// Parquet file.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "BlockedBloomFilter")]
+struct SerdeBlockedBloomFilter {
+    bits: crate::storage::SerdeBits,
+}
+
+#[cfg(feature = "serde")]
+impl<K> serde::Serialize for BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeBlockedBloomFilter {
+            bits: crate::storage::SerdeBits(self.to_bytes()),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K> serde::Deserialize<'de> for BlockedBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeBlockedBloomFilter::deserialize(deserializer)?;
+        Self::from_bytes(&filter.bits.0).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,341 @@
+//! Stable Bloom filter, for deduplicating unbounded streams.
+//!
+//! Following [Approximately Detecting Duplicates for Streaming Data using
+//! Stable Bloom Filters, 2006][1]: slots are small counters. Inserting a key
+//! first decrements `P` counters picked at random, evicting stale
+//! information, then sets the `k` counters the key probes to their maximum.
+//! The fraction of zero counters, hence the false positive rate, converges
+//! to a fixed value (see
+//! [`stable_fp_rate`](StableBloomFilter::stable_fp_rate)) instead of growing
+//! towards 1 as keys keep coming.
+//!
+//! The price is false negatives: a key is forgotten once one of its counters
+//! has been decremented to zero, i.e. (unless other keys set it again) after
+//! `max` decrements, each insert decrementing a given counter with
+//! probability `P / m`. A key thus survives about `max * m / P` subsequent
+//! inserts: recent duplicates are reliably detected, while the chance of
+//! missing one grows with the number of distinct keys seen in between.
+//!
+//! [1]: https://webdocs.cs.ualberta.ca/~drafiei/papers/DupDet06Sigmod.pdf
+
+use {
+    crate::{
+        analysis::check_fp_rate,
+        hash::ProbeHasher,
+        storage::PackedArray,
+        FilterStats,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Default counter width, in bits.
+pub const DEFAULT_COUNTER_BITS: u32 = 3;
+
+/// Default number of hash functions.
+pub const DEFAULT_HASH_COUNT: usize = 3;
+
+/// Largest counter width, in bits.
+pub const MAX_COUNTER_BITS: u32 = 8;
+
+/// Bloom filter of counters evicting stale keys, with a stable false
+/// positive rate.
+pub struct StableBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    counters: PackedArray,
+    /// Number of counters decremented per insert, i.e. `P`.
+    decrements: usize,
+    hasher: H,
+    k: usize,
+    state: u64,
+    phantom: PhantomData<K>,
+}
+
+impl<K> StableBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter of `counter_count` counters, with a desired
+    /// stable false positive rate, [`DEFAULT_COUNTER_BITS`]-bit counters, and
+    /// [`DEFAULT_HASH_COUNT`] hash functions.
+    pub fn new(counter_count: usize, fp_rate: f64) -> Self {
+        Self::with_params(
+            counter_count,
+            fp_rate,
+            DEFAULT_COUNTER_BITS,
+            DEFAULT_HASH_COUNT,
+        )
+        .expect("default parameters are valid")
+    }
+
+    /// Creates a new filter of `counter_count` counters, with a desired
+    /// stable false positive rate, counter width, and number of hash
+    /// functions.
+    ///
+    /// Fails unless the counter width is within `1..=MAX_COUNTER_BITS`, the
+    /// number of hash functions is positive, and the rate is within `0..1`
+    /// (exclusive).
+    pub fn with_params(
+        counter_count: usize,
+        fp_rate: f64,
+        counter_bits: u32,
+        hash_count: usize,
+    ) -> QueryFilterResult<Self> {
+        Self::with_params_and_hasher(
+            counter_count,
+            fp_rate,
+            counter_bits,
+            hash_count,
+            ProbeHasher::default(),
+        )
+    }
+}
+
+impl<K, H> StableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter of `counter_count` counters, with a desired
+    /// stable false positive rate, counter width, number of hash functions,
+    /// and hasher.
+    ///
+    /// See [`with_params`](StableBloomFilter::with_params).
+    pub fn with_params_and_hasher(
+        counter_count: usize,
+        fp_rate: f64,
+        counter_bits: u32,
+        hash_count: usize,
+        hasher: H,
+    ) -> QueryFilterResult<Self> {
+        if !(1..=MAX_COUNTER_BITS).contains(&counter_bits) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid counter size: {counter_bits} bits is not within 1..={MAX_COUNTER_BITS}"
+            )));
+        }
+        if hash_count == 0 {
+            return Err(QueryFilterError::Other(
+                "invalid hash count: must be positive".to_owned(),
+            ));
+        }
+        check_fp_rate(fp_rate)?;
+        let counter_count = counter_count.max(hash_count + 1);
+        let decrements = decrement_count(counter_count, fp_rate, counter_bits, hash_count);
+        Ok(Self {
+            counters: PackedArray::new(counter_count, counter_bits),
+            decrements,
+            hasher,
+            k: hash_count,
+            state: 0,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the number of counters.
+    pub fn counter_count(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the number of counters decremented per insert.
+    pub fn decrement_count(&self) -> usize {
+        self.decrements
+    }
+
+    /// Returns the memory used by the filter's counters, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.counters.size_in_bytes()
+    }
+
+    /// Returns the false positive rate the filter converges to, as keys keep
+    /// being inserted.
+    pub fn stable_fp_rate(&self) -> f64 {
+        let (m, k, p) = (
+            self.counters.len() as f64,
+            self.k as f64,
+            self.decrements as f64,
+        );
+        let max = PackedArray::max_value(self.counters.bits()) as f64;
+        let zeros = (1. / (1. + 1. / (p * (1. / k - 1. / m)))).powf(max);
+        (1. - zeros).powf(k)
+    }
+
+    /// Returns the fraction of non-zero counters.
+    pub fn fill_ratio(&self) -> f64 {
+        let nonzero = (0..self.counters.len())
+            .filter(|&index| self.counters.get(index) > 0)
+            .count();
+        nonzero as f64 / self.counters.len() as f64
+    }
+
+    /// Returns the estimated current false positive rate, given the current
+    /// fraction of non-zero counters.
+    pub fn approx_fp_rate(&self) -> f64 {
+        self.fill_ratio().powi(self.k as i32)
+    }
+
+    /// Returns the next value of the SplitMix64 generator picking counters to
+    /// decrement.
+    fn next_random(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+}
+
+impl<K, H> QueryFilter<K> for StableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let len = self.counters.len() as u64;
+        self.hasher
+            .hash_iter(key, self.k)
+            .all(|hash| self.counters.get((hash % len) as usize) > 0)
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for StableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Inserts a key, first decrementing [`decrement_count`] counters.
+    ///
+    /// As in the paper, decremented counters are consecutive (from a random
+    /// start), which is as good as independent picks and much cheaper.
+    ///
+    /// [`decrement_count`]: StableBloomFilter::decrement_count
+    fn insert(&mut self, key: K) {
+        let len = self.counters.len();
+        let start = (self.next_random() % len as u64) as usize;
+        for index in (start..start + self.decrements).map(|index| index % len) {
+            let count = self.counters.get(index);
+            if count > 0 {
+                self.counters.set(index, count - 1);
+            }
+        }
+        let max = PackedArray::max_value(self.counters.bits());
+        for hash in self.hasher.hash_iter(&key, self.k) {
+            self.counters.set((hash % len as u64) as usize, max);
+        }
+    }
+}
+
+impl<K, H> FilterStats for StableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn slot_count(&self) -> usize {
+        self.counter_count()
+    }
+
+    fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    fn fill_ratio(&self) -> f64 {
+        self.fill_ratio()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.size_in_bytes()
+    }
+}
+
+/// Returns the number of counters to decrement per insert, for the filter
+/// to converge to a given false positive rate.
+fn decrement_count(counter_count: usize, fp_rate: f64, counter_bits: u32, k: usize) -> usize {
+    let (m, k) = (counter_count as f64, k as f64);
+    let max = PackedArray::max_value(counter_bits) as f64;
+    let zeros = 1. - fp_rate.powf(1. / k);
+    let p = 1. / ((zeros.powf(-1. / max) - 1.) * (1. / k - 1. / m));
+    (p.round() as usize).clamp(1, counter_count)
+}
+
+/// Serialized form of a stable Bloom filter.
+///
+/// The state of the generator picking counters to decrement is not kept: a
+/// deserialized filter decrements different counters than the original
+/// would have, which makes no difference to its guarantees.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "StableBloomFilter")]
+struct SerdeStableBloomFilter<H, S> {
+    hash_count: usize,
+    decrement_count: usize,
+    hasher: H,
+    counters: S,
+}
+
+#[cfg(feature = "serde")]
+impl<K, H> serde::Serialize for StableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeStableBloomFilter {
+            hash_count: self.k,
+            decrement_count: self.decrements,
+            hasher: &self.hasher,
+            counters: &self.counters,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, H> serde::Deserialize<'de> for StableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeStableBloomFilter::<H, PackedArray>::deserialize(deserializer)?;
+        if filter.counters.bits() > MAX_COUNTER_BITS {
+            return Err(D::Error::custom("counters wider than the maximum size"));
+        }
+        if filter.hash_count == 0 || filter.counters.len() <= filter.hash_count {
+            return Err(D::Error::custom("too few counters or hash functions"));
+        }
+        if !(1..=filter.counters.len()).contains(&filter.decrement_count) {
+            return Err(D::Error::custom(
+                "decrement count is not within the counter count",
+            ));
+        }
+        Ok(Self {
+            counters: filter.counters,
+            decrements: filter.decrement_count,
+            hasher: filter.hasher,
+            k: filter.hash_count,
+            state: 0,
+            phantom: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,347 @@
+//! Scalable Bloom filter.
+//!
+//! Following [Scalable Bloom Filters, 2007][1]: a plain Bloom filter's false
+//! positive rate degrades once more keys than its capacity are inserted. A
+//! [`ScalableBloomFilter`] instead chains Bloom filters (slices): once the
+//! last slice is at capacity, a new one is added, `growth_factor` times
+//! larger and with a false positive rate `tightening_ratio` times lower.
+//! With an initial slice at `fp_rate * (1 - tightening_ratio)`, the rates of
+//! all slices sum up to at most `fp_rate`, which thus bounds the overall
+//! rate, however many keys are inserted.
+//!
+//! [1]: https://gsd.di.uminho.pt/members/cbm/ps/dbloom.pdf
+
+use {
+    crate::{
+        hash::ProbeHasher,
+        BloomFilter,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash},
+};
+
+/// Default ratio between the capacities of consecutive slices.
+pub const DEFAULT_GROWTH_FACTOR: usize = 2;
+
+/// Default ratio between the false positive rates of consecutive slices.
+pub const DEFAULT_TIGHTENING_RATIO: f64 = 0.9;
+
+/// Bloom filter growing by adding slices, keeping a bounded false positive
+/// rate.
+pub struct ScalableBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    slices: Vec<BloomFilter<K, H>>,
+    initial_capacity: usize,
+    fp_rate: f64,
+    growth_factor: usize,
+    tightening_ratio: f64,
+    /// Number of keys in the last slice.
+    last_len: usize,
+    len: usize,
+    hasher: H,
+}
+
+impl<K> ScalableBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired initial capacity and (overall)
+    /// false positive rate, with the default growth factor and tightening
+    /// ratio.
+    pub fn new(initial_capacity: usize, fp_rate: f64) -> Self {
+        Self::with_params(
+            initial_capacity,
+            fp_rate,
+            DEFAULT_GROWTH_FACTOR,
+            DEFAULT_TIGHTENING_RATIO,
+        )
+        .expect("default parameters are valid")
+    }
+
+    /// Creates a new filter with a desired initial capacity, (overall) false
+    /// positive rate, growth factor, and tightening ratio.
+    ///
+    /// Fails unless the growth factor is positive and the tightening ratio is
+    /// within `0..1` (exclusive). Lower ratios take more memory per key, but
+    /// let the filter grow further before later slices' rates get tiny.
+    pub fn with_params(
+        initial_capacity: usize,
+        fp_rate: f64,
+        growth_factor: usize,
+        tightening_ratio: f64,
+    ) -> QueryFilterResult<Self> {
+        Self::with_params_and_hasher(
+            initial_capacity,
+            fp_rate,
+            growth_factor,
+            tightening_ratio,
+            ProbeHasher::default(),
+        )
+    }
+}
+
+impl<K, H> ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Creates a new filter with a desired initial capacity, (overall) false
+    /// positive rate, growth factor, tightening ratio, and hasher.
+    ///
+    /// See [`with_params`](ScalableBloomFilter::with_params).
+    pub fn with_params_and_hasher(
+        initial_capacity: usize,
+        fp_rate: f64,
+        growth_factor: usize,
+        tightening_ratio: f64,
+        hasher: H,
+    ) -> QueryFilterResult<Self> {
+        if growth_factor == 0 {
+            return Err(QueryFilterError::Other(
+                "invalid growth factor: must be positive".to_owned(),
+            ));
+        }
+        if !(tightening_ratio > 0. && tightening_ratio < 1.) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid tightening ratio: {tightening_ratio} is not within 0..1"
+            )));
+        }
+        let mut filter = Self {
+            slices: Vec::new(),
+            initial_capacity: initial_capacity.max(1),
+            fp_rate,
+            growth_factor,
+            tightening_ratio,
+            last_len: 0,
+            len: 0,
+            hasher,
+        };
+        filter.add_slice();
+        Ok(filter)
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the number of keys inserted, not counting keys that were
+    /// (probably) in the filter already.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if no keys were inserted.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of slices.
+    pub fn slice_count(&self) -> usize {
+        self.slices.len()
+    }
+
+    /// Returns the slices, from the oldest (smallest) one.
+    pub fn slices(&self) -> &[BloomFilter<K, H>] {
+        &self.slices
+    }
+
+    /// Returns the memory used by all slices' bits, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.slices
+            .iter()
+            .map(|slice| slice.bit_count().div_ceil(8))
+            .sum()
+    }
+
+    /// Returns the estimated (overall) false positive rate, given the
+    /// current fraction of set bits of each slice.
+    pub fn approx_fp_rate(&self) -> f64 {
+        1. - self
+            .slices
+            .iter()
+            .map(|slice| 1. - slice.approx_fp_rate())
+            .product::<f64>()
+    }
+
+    fn add_slice(&mut self) {
+        let (capacity, fp_rate) = self.slice_params(self.slices.len());
+        self.slices.push(BloomFilter::with_capacity_and_hasher(
+            capacity,
+            fp_rate,
+            self.hasher.clone(),
+        ));
+        self.last_len = 0;
+        event!(
+            slice_count = self.slices.len(),
+            capacity,
+            fp_rate;
+            "added scalable bloom filter slice"
+        );
+    }
+}
+
+impl<K, H> ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+{
+    /// Returns the capacity and false positive rate of the `i`-th slice.
+    fn slice_params(&self, i: usize) -> (usize, f64) {
+        let capacity = self
+            .initial_capacity
+            .saturating_mul(self.growth_factor.saturating_pow(i as u32));
+        let fp_rate =
+            self.fp_rate * (1. - self.tightening_ratio) * self.tightening_ratio.powi(i as i32);
+        (capacity, fp_rate)
+    }
+}
+
+impl<K, H> QueryFilter<K> for ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        // Larger slices hold most keys, so check them first.
+        self.slices.iter().rev().any(|slice| slice.contains(key))
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Inserts a key into the last slice, adding a new slice first if the
+    /// last one is at capacity.
+    ///
+    /// Keys (probably) in the filter already are skipped, so that repeated
+    /// inserts do not fill up slices.
+    fn insert(&mut self, key: K) {
+        if self.contains(&key) {
+            return;
+        }
+        if self.last_len >= self.slice_params(self.slices.len() - 1).0 {
+            self.add_slice();
+        }
+        self.slices
+            .last_mut()
+            .expect("there is always a slice")
+            .insert(key);
+        self.last_len += 1;
+        self.len += 1;
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + Clone,
+{
+    /// Removes all keys, going back to a single (initial) slice.
+    fn clear(&mut self) {
+        self.slices.clear();
+        self.len = 0;
+        self.add_slice();
+    }
+}
+
+/// Serialized form of a scalable Bloom filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "ScalableBloomFilter")]
+struct SerdeScalableBloomFilter<H, V> {
+    initial_capacity: usize,
+    fp_rate: f64,
+    growth_factor: usize,
+    tightening_ratio: f64,
+    len: usize,
+    last_len: usize,
+    hasher: H,
+    slices: V,
+}
+
+#[cfg(feature = "serde")]
+impl<K, H> serde::Serialize for ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeScalableBloomFilter {
+            initial_capacity: self.initial_capacity,
+            fp_rate: self.fp_rate,
+            growth_factor: self.growth_factor,
+            tightening_ratio: self.tightening_ratio,
+            len: self.len,
+            last_len: self.last_len,
+            hasher: &self.hasher,
+            slices: &self.slices,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, H> serde::Deserialize<'de> for ScalableBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use {
+            crate::analysis::{optimal_bit_count, optimal_hash_count},
+            serde::de::Error,
+        };
+
+        let filter =
+            SerdeScalableBloomFilter::<H, Vec<BloomFilter<K, H>>>::deserialize(deserializer)?;
+        if filter.slices.is_empty() || filter.initial_capacity == 0 {
+            return Err(D::Error::custom("no slices"));
+        }
+        if filter.growth_factor == 0
+            || !(filter.tightening_ratio > 0. && filter.tightening_ratio < 1.)
+        {
+            return Err(D::Error::custom(
+                "invalid growth factor or tightening ratio",
+            ));
+        }
+        if filter.last_len > filter.len {
+            return Err(D::Error::custom("more keys in the last slice than overall"));
+        }
+        let filter = Self {
+            slices: filter.slices,
+            initial_capacity: filter.initial_capacity,
+            fp_rate: filter.fp_rate,
+            growth_factor: filter.growth_factor,
+            tightening_ratio: filter.tightening_ratio,
+            last_len: filter.last_len,
+            len: filter.len,
+            hasher: filter.hasher,
+        };
+        // Slices must be sized as they would have been when added.
+        for (i, slice) in filter.slices.iter().enumerate() {
+            let (capacity, fp_rate) = filter.slice_params(i);
+            let bit_count = optimal_bit_count(capacity, fp_rate);
+            if slice.bit_count() != bit_count
+                || slice.hash_count() != optimal_hash_count(capacity, bit_count)
+            {
+                return Err(D::Error::custom(format!(
+                    "slice {i} does not match the growth factor and tightening ratio"
+                )));
+            }
+        }
+        Ok(filter)
+    }
+}
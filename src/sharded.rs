@@ -0,0 +1,294 @@
+//! Filters split into independently locked shards.
+//!
+//! A filter behind a single lock serializes all writers. [`ShardedFilter`]
+//! instead routes each key, by a hash independent of the filters' own, to
+//! one of several inner filters (shards), each behind its own [`RwLock`]:
+//! writers only contend when their keys land in the same shard, and readers
+//! never block each other. Each key is only ever inserted into and looked up
+//! in its own shard, so the false positive rate is the one of the shards.
+//!
+//! Lock poisoning is ignored: a panic while inserting into a filter leaves
+//! it valid (if without that key).
+
+#[cfg(feature = "bf")]
+use {
+    crate::{storage::BitStorage, BloomFilter},
+    hash_iter::HashIterHasher,
+};
+use {
+    crate::{
+        ClearableQueryFilter,
+        ConcurrentInsertableQueryFilter,
+        FilterStats,
+        InsertableQueryFilter,
+        MergeableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+    },
+    std::{
+        borrow::Borrow,
+        hash::{BuildHasher, Hash},
+        sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Seed of the hash function routing keys to shards, independent of the
+/// ones used within filters.
+const SHARD_SEED: u64 = 0x6a09e667f3bcc908;
+
+/// Filter split into shards by key hash, each behind its own lock.
+pub struct ShardedFilter<F> {
+    shards: Box<[RwLock<F>]>,
+}
+
+impl<F> ShardedFilter<F> {
+    /// Creates a new filter over given shards, which should all have the same
+    /// parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no shards.
+    pub fn from_shards(shards: impl IntoIterator<Item = F>) -> Self {
+        let shards = shards.into_iter().map(RwLock::new).collect::<Box<[_]>>();
+        assert!(!shards.is_empty(), "there must be at least one shard");
+        Self { shards }
+    }
+
+    /// Creates a new filter of `shard_count` shards, each created by `shard`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_count` is zero.
+    pub fn from_fn(shard_count: usize, shard: impl FnMut() -> F) -> Self {
+        Self::from_shards(std::iter::repeat_with(shard).take(shard_count))
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Returns the index of the shard a key is routed to.
+    pub fn shard_of<Q: Hash + ?Sized>(&self, key: &Q) -> usize {
+        let hash = Xxh3Builder::new().with_seed(SHARD_SEED).hash_one(key);
+        ((hash as u128 * self.shards.len() as u128) >> 64) as usize
+    }
+
+    /// Inserts an element into its shard, locking only that shard.
+    ///
+    /// Shadows [`InsertableQueryFilter::insert`], which takes the filter
+    /// exclusively and so skips locking.
+    pub fn insert<K>(&self, key: K)
+    where
+        F: InsertableQueryFilter<K>,
+        K: Eq + Hash,
+    {
+        self.write_shard(self.shard_of(&key)).insert(key);
+    }
+
+    /// Locks the shard at `index` for reading.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn read_shard(&self, index: usize) -> RwLockReadGuard<'_, F> {
+        self.shards[index]
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the shard at `index` for writing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of range.
+    pub fn write_shard(&self, index: usize) -> RwLockWriteGuard<'_, F> {
+        self.shards[index]
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the shards, in routing order.
+    pub fn into_shards(self) -> Vec<F> {
+        self.shards
+            .into_vec()
+            .into_iter()
+            .map(|shard| shard.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+
+    /// Fails unless both filters have the same number of shards.
+    fn check_same_shard_count(&self, other: &Self) -> QueryFilterResult<()> {
+        if self.shards.len() != other.shards.len() {
+            return Err(QueryFilterError::IncompatibleFilters("shard counts differ"));
+        }
+        Ok(())
+    }
+
+    /// Returns the sum of a statistic over all shards.
+    fn sum(&self, stat: impl Fn(&F) -> usize) -> usize {
+        (0..self.shards.len())
+            .map(|index| stat(&self.read_shard(index)))
+            .sum()
+    }
+
+    /// Returns the shards, without locking them.
+    fn shards_mut(&mut self) -> impl Iterator<Item = &mut F> {
+        self.shards
+            .iter_mut()
+            .map(|shard| shard.get_mut().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+#[cfg(feature = "bf")]
+impl<K> ShardedFilter<BloomFilter<K>>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter of `shard_count` Bloom filters, together sized
+    /// for a desired capacity and false positive rate.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_count` is zero.
+    pub fn new(shard_count: usize, capacity: usize, fp_rate: f64) -> Self {
+        let capacity = capacity.div_ceil(shard_count.max(1));
+        Self::from_fn(shard_count, || BloomFilter::new(capacity, fp_rate))
+    }
+}
+
+#[cfg(feature = "bf")]
+impl<K, H, S> ShardedFilter<BloomFilter<K, H, S>>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+    S: BitStorage,
+{
+    /// Returns the approximate number of keys inserted into all shards.
+    pub fn approx_current_capacity(&self) -> usize {
+        (0..self.shards.len())
+            .map(|index| self.read_shard(index).approx_current_capacity())
+            .sum()
+    }
+}
+
+impl<K, F> QueryFilter<K> for ShardedFilter<F>
+where
+    F: QueryFilter<K>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.read_shard(self.shard_of(key)).contains(key)
+    }
+}
+
+impl<K, F> ConcurrentInsertableQueryFilter<K> for ShardedFilter<F>
+where
+    F: InsertableQueryFilter<K> + Send + Sync,
+{
+    fn insert(&self, key: K)
+    where
+        K: Eq + Hash,
+    {
+        ShardedFilter::insert(self, key);
+    }
+}
+
+/// Inserts through exclusive access, without locking.
+impl<K, F> InsertableQueryFilter<K> for ShardedFilter<F>
+where
+    F: InsertableQueryFilter<K>,
+{
+    fn insert(&mut self, key: K)
+    where
+        K: Eq + Hash,
+    {
+        let index = self.shard_of(&key);
+        self.shards[index]
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key);
+    }
+}
+
+impl<K, F> ClearableQueryFilter<K> for ShardedFilter<F>
+where
+    F: ClearableQueryFilter<K>,
+{
+    fn clear(&mut self) {
+        for shard in self.shards_mut() {
+            shard.clear();
+        }
+    }
+}
+
+/// Combines filters with the same number of shards, shard by shard.
+///
+/// Should shards differ in shape, the ones before the first mismatch are
+/// left combined.
+impl<K, F> MergeableQueryFilter<K> for ShardedFilter<F>
+where
+    F: MergeableQueryFilter<K>,
+{
+    fn try_union_with(&mut self, other: &Self) -> QueryFilterResult<()> {
+        self.check_same_shard_count(other)?;
+        for (index, shard) in self.shards_mut().enumerate() {
+            shard.try_union_with(&other.read_shard(index))?;
+        }
+        event!(shard_count = self.shards.len(); "merged sharded filter union");
+        Ok(())
+    }
+
+    fn try_intersect_with(&mut self, other: &Self) -> QueryFilterResult<()> {
+        self.check_same_shard_count(other)?;
+        for (index, shard) in self.shards_mut().enumerate() {
+            shard.try_intersect_with(&other.read_shard(index))?;
+        }
+        event!(shard_count = self.shards.len(); "merged sharded filter intersection");
+        Ok(())
+    }
+}
+
+/// Aggregates the statistics of all shards.
+impl<F> FilterStats for ShardedFilter<F>
+where
+    F: FilterStats,
+{
+    fn slot_count(&self) -> usize {
+        self.sum(F::slot_count)
+    }
+
+    /// Returns the number of hash functions of the first shard.
+    fn hash_count(&self) -> usize {
+        self.read_shard(0).hash_count()
+    }
+
+    /// Returns the fraction of occupied slots across all shards.
+    fn fill_ratio(&self) -> f64 {
+        let occupied = (0..self.shards.len())
+            .map(|index| {
+                let shard = self.read_shard(index);
+                shard.fill_ratio() * shard.slot_count() as f64
+            })
+            .sum::<f64>();
+        occupied / self.slot_count() as f64
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.sum(F::size_in_bytes)
+    }
+
+    /// Returns the average of the shards' estimated false positive rates, as
+    /// keys are evenly routed to shards.
+    fn approx_fp_rate(&self) -> f64 {
+        let total = (0..self.shards.len())
+            .map(|index| self.read_shard(index).approx_fp_rate())
+            .sum::<f64>();
+        total / self.shards.len() as f64
+    }
+}
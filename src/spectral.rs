@@ -0,0 +1,327 @@
+//! Spectral Bloom filter, estimating how often keys were inserted.
+//!
+//! Following [Spectral Bloom Filters, 2003][1]: like a counting Bloom filter,
+//! each key increments `k` counters, but counters are wide enough to count
+//! occurrences, not just to support removal. The smallest of a key's counters
+//! (minimum selection) is an estimate of its number of occurrences that is
+//! never too low, and exact unless all of the key's counters were also
+//! incremented by other keys, which happens at the filter's false positive
+//! rate. Membership is then the special case of a non-zero estimate.
+//!
+//! This is the same estimate a count-min sketch gives, from a single array of
+//! counters sized for the desired false positive rate.
+//!
+//! Counters saturate at their maximum value, after which they are left as
+//! they are: estimates are then capped at that value.
+//!
+//! [1]: https://theory.stanford.edu/~matias/papers/sbf-sigmod-03.pdf
+
+use {
+    crate::{
+        analysis::{optimal_bit_count, optimal_hash_count},
+        hash::ProbeHasher,
+        storage::PackedArray,
+        ClearableQueryFilter,
+        FilterStats,
+        InsertableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+        QueryFilterResult,
+        RemovableQueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Default counter width, in bits.
+pub const DEFAULT_COUNTER_BITS: u32 = 16;
+
+/// Largest counter width, in bits.
+pub const MAX_COUNTER_BITS: u32 = 64;
+
+/// Bloom filter of counters, estimating the number of occurrences of keys.
+pub struct SpectralBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    counters: PackedArray,
+    hasher: H,
+    k: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K> SpectralBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity (in distinct keys) and
+    /// false positive rate, with [`DEFAULT_COUNTER_BITS`]-bit counters.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate)
+    }
+
+    /// Creates a new filter with a desired capacity (in distinct keys) and
+    /// false positive rate, with [`DEFAULT_COUNTER_BITS`]-bit counters.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_params(capacity, fp_rate, DEFAULT_COUNTER_BITS)
+            .expect("default parameters are valid")
+    }
+
+    /// Creates a new filter with a desired capacity (in distinct keys), false
+    /// positive rate, and counter width.
+    ///
+    /// Fails unless the counter width is within `1..=MAX_COUNTER_BITS`.
+    pub fn with_params(
+        capacity: usize,
+        fp_rate: f64,
+        counter_bits: u32,
+    ) -> QueryFilterResult<Self> {
+        Self::with_params_and_hasher(capacity, fp_rate, counter_bits, ProbeHasher::default())
+    }
+}
+
+impl<K, H> SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter with a desired capacity (in distinct keys), false
+    /// positive rate, counter width, and hasher.
+    ///
+    /// Sized like a plain Bloom filter, with one counter per bit. See
+    /// [`with_params`](SpectralBloomFilter::with_params).
+    pub fn with_params_and_hasher(
+        capacity: usize,
+        fp_rate: f64,
+        counter_bits: u32,
+        hasher: H,
+    ) -> QueryFilterResult<Self> {
+        if !(1..=MAX_COUNTER_BITS).contains(&counter_bits) {
+            return Err(QueryFilterError::Other(format!(
+                "invalid counter size: {counter_bits} bits is not within 1..={MAX_COUNTER_BITS}"
+            )));
+        }
+        let counter_count = optimal_bit_count(capacity, fp_rate).max(1);
+        let k = optimal_hash_count(capacity, counter_count).max(1);
+        Ok(Self {
+            counters: PackedArray::new(counter_count, counter_bits),
+            hasher,
+            k,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the number of counters.
+    pub fn counter_count(&self) -> usize {
+        self.counters.len()
+    }
+
+    /// Returns the number of hash functions.
+    pub fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    /// Returns the largest count a counter holds, at which it saturates.
+    pub fn max_count(&self) -> u64 {
+        PackedArray::max_value(self.counters.bits())
+    }
+
+    /// Returns the memory used by the filter's counters, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.counters.size_in_bytes()
+    }
+
+    /// Returns the fraction of non-zero counters.
+    pub fn fill_ratio(&self) -> f64 {
+        let nonzero = (0..self.counters.len())
+            .filter(|&index| self.counters.get(index) > 0)
+            .count();
+        nonzero as f64 / self.counters.len() as f64
+    }
+
+    /// Returns the approximate number of distinct keys in the filter, from
+    /// the fraction of non-zero counters.
+    pub fn approx_current_capacity(&self) -> usize {
+        let m = self.counters.len() as f64;
+        (-(m / self.k as f64) * (1. - self.fill_ratio()).ln()).round() as usize
+    }
+
+    /// Returns the number of occurrences inserted (repeated keys included)
+    /// and not removed, i.e. the sum of all counters divided by the number of
+    /// hash functions.
+    ///
+    /// Exact as long as no counter saturated; approximate after that.
+    pub fn approx_total_insertions(&self) -> u64 {
+        let sum = (0..self.counters.len())
+            .map(|index| self.counters.get(index) as u128)
+            .sum::<u128>();
+        (sum / self.k as u128).min(u64::MAX as u128) as u64
+    }
+
+    /// Inserts `count` occurrences of a key at once.
+    pub fn insert_count(&mut self, key: K, count: u64) {
+        let max = self.max_count();
+        for index in self.indices(&key) {
+            let value = self.counters.get(index);
+            self.counters
+                .set(index, value.saturating_add(count).min(max));
+        }
+    }
+
+    /// Returns the estimated number of occurrences of a key, i.e. the
+    /// smallest of its counters.
+    ///
+    /// The estimate is never lower than the actual count (unless removals
+    /// did not match inserts, or counters saturated).
+    pub fn estimate_count<Q>(&self, key: &Q) -> u64
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.indices(key)
+            .into_iter()
+            .map(|index| self.counters.get(index))
+            .min()
+            .unwrap_or(0)
+    }
+
+    /// Returns the indices of the counters probed by a key.
+    fn indices<Q: Hash + ?Sized>(&self, key: &Q) -> Vec<usize> {
+        let len = self.counters.len() as u64;
+        self.hasher
+            .hash_iter(key, self.k)
+            .map(|hash| (hash % len) as usize)
+            .collect()
+    }
+}
+
+impl<K, H> QueryFilter<K> for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.estimate_count(key) > 0
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn insert(&mut self, key: K) {
+        self.insert_count(key, 1);
+    }
+}
+
+impl<K, H> RemovableQueryFilter<K> for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Removes an occurrence of a key, which must have been inserted.
+    /// Saturated counters are left as they are.
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let max = self.max_count();
+        for index in self.indices(key) {
+            let count = self.counters.get(index);
+            if count > 0 && count < max {
+                self.counters.set(index, count - 1);
+            }
+        }
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn clear(&mut self) {
+        self.counters = PackedArray::new(self.counters.len(), self.counters.bits());
+    }
+}
+
+impl<K, H> FilterStats for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn slot_count(&self) -> usize {
+        self.counters.len()
+    }
+
+    fn hash_count(&self) -> usize {
+        self.k
+    }
+
+    fn fill_ratio(&self) -> f64 {
+        self.fill_ratio()
+    }
+
+    fn size_in_bytes(&self) -> usize {
+        self.size_in_bytes()
+    }
+}
+
+/// Serialized form of a spectral Bloom filter.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "SpectralBloomFilter")]
+struct SerdeSpectralBloomFilter<H, S> {
+    hash_count: usize,
+    hasher: H,
+    counters: S,
+}
+
+#[cfg(feature = "serde")]
+impl<K, H> serde::Serialize for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdeSpectralBloomFilter {
+            hash_count: self.k,
+            hasher: &self.hasher,
+            counters: &self.counters,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, H> serde::Deserialize<'de> for SpectralBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64> + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let filter = SerdeSpectralBloomFilter::<H, PackedArray>::deserialize(deserializer)?;
+        if filter.counters.is_empty() || filter.hash_count == 0 {
+            return Err(D::Error::custom("no counters or hash functions"));
+        }
+        Ok(Self {
+            counters: filter.counters,
+            hasher: filter.hasher,
+            k: filter.hash_count,
+            phantom: PhantomData,
+        })
+    }
+}
@@ -0,0 +1,215 @@
+//! Squid cache digest interoperability.
+//!
+//! Squid proxies exchange [cache digests][1]: Bloom filters summarizing the
+//! URLs each cache holds, so that peers can predict hits before forwarding a
+//! request. A [`CacheDigest`] is bit-compatible with Squid's: keys are the MD5
+//! of the request method id followed by the URL (Squid's public store key),
+//! the four 32-bit words of the MD5 (in network order) select four bits, and
+//! the digest is exchanged as a 128-byte header block followed by the bit
+//! mask, see [`to_bytes`](CacheDigest::to_bytes).
+//!
+//! [1]: https://wiki.squid-cache.org/SquidFaq/CacheDigests
+
+use {
+    crate::{QueryFilterError, QueryFilterResult},
+    md5::{Digest, Md5},
+};
+
+/// Size of the header block preceding the bit mask.
+pub const HEADER_SIZE: usize = 128;
+
+/// Digest format version written by [`CacheDigest::to_bytes`].
+pub const VERSION: u16 = 5;
+
+/// Oldest digest format version [`CacheDigest::from_bytes`] can read.
+pub const REQUIRED_VERSION: u16 = 3;
+
+/// Number of bits set per key.
+pub const HASH_COUNT: u8 = 4;
+
+/// Bits per entry Squid uses by default (`digest_bits_per_entry`).
+pub const DEFAULT_BITS_PER_ENTRY: u8 = 5;
+
+/// HTTP request method, as identified in Squid's store keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Method {
+    Get = 1,
+    Post = 2,
+    Put = 3,
+    Head = 4,
+    Connect = 5,
+    Trace = 6,
+    Options = 7,
+    Delete = 8,
+}
+
+/// Returns Squid's public store key of a request: the MD5 of the method id
+/// followed by the URL.
+pub fn store_key(method: Method, url: &str) -> [u8; 16] {
+    Md5::new()
+        .chain_update([method as u8])
+        .chain_update(url)
+        .finalize()
+        .into()
+}
+
+/// Cache digest, bit-compatible with Squid's.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheDigest {
+    mask: Vec<u8>,
+    capacity: u32,
+    count: u32,
+    del_count: u32,
+    bits_per_entry: u8,
+}
+
+impl CacheDigest {
+    /// Creates a new empty digest for `capacity` entries, spending
+    /// `bits_per_entry` bits on each (Squid defaults to
+    /// [`DEFAULT_BITS_PER_ENTRY`]).
+    pub fn new(capacity: u32, bits_per_entry: u8) -> Self {
+        // Same rounding as Squid, which also never creates an empty mask.
+        let mask_size = (capacity as usize * bits_per_entry as usize).div_ceil(8);
+        Self {
+            mask: vec![0; mask_size.max(1)],
+            capacity,
+            count: 0,
+            del_count: 0,
+            bits_per_entry,
+        }
+    }
+
+    /// Returns the number of entries the digest was sized for.
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    /// Returns the number of additions so far.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the number of bits spent on each entry.
+    pub fn bits_per_entry(&self) -> u8 {
+        self.bits_per_entry
+    }
+
+    /// Returns the bit mask.
+    pub fn mask(&self) -> &[u8] {
+        &self.mask
+    }
+
+    /// Adds a request to the digest.
+    pub fn insert(&mut self, method: Method, url: &str) {
+        self.insert_key(&store_key(method, url));
+    }
+
+    /// Returns `true` if the request is believed to be in the digest.
+    pub fn contains(&self, method: Method, url: &str) -> bool {
+        self.contains_key(&store_key(method, url))
+    }
+
+    /// Adds a store key to the digest.
+    pub fn insert_key(&mut self, key: &[u8; 16]) {
+        for bit in self.bits(key) {
+            self.mask[bit / 8] |= 1 << (bit % 8);
+        }
+        self.count = self.count.wrapping_add(1);
+    }
+
+    /// Returns `true` if the store key is believed to be in the digest.
+    pub fn contains_key(&self, key: &[u8; 16]) -> bool {
+        self.bits(key)
+            .into_iter()
+            .all(|bit| self.mask[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Encodes the digest the way Squid serves it: the header block, followed
+    /// by the mask.
+    ///
+    /// The header holds, in network byte order, the current and required
+    /// format versions (`u16` each), capacity, count, deletion count, and mask
+    /// size (`i32` each), bits per entry and hash function count (`u8`
+    /// each), padded with zeros to [`HEADER_SIZE`] bytes.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.mask.len());
+        bytes.extend_from_slice(&VERSION.to_be_bytes());
+        bytes.extend_from_slice(&REQUIRED_VERSION.to_be_bytes());
+        for field in [self.capacity, self.count, self.del_count] {
+            bytes.extend_from_slice(&field.to_be_bytes());
+        }
+        bytes.extend_from_slice(&(self.mask.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&[self.bits_per_entry, HASH_COUNT]);
+        bytes.resize(HEADER_SIZE, 0);
+        bytes.extend_from_slice(&self.mask);
+        bytes
+    }
+
+    /// Decodes a digest encoded by Squid (or [`to_bytes`]).
+    ///
+    /// Fails if the data is truncated, requires a newer format version, or
+    /// uses an unsupported number of hash functions.
+    ///
+    /// [`to_bytes`]: CacheDigest::to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> QueryFilterResult<Self> {
+        let invalid =
+            |reason: &str| QueryFilterError::Other(format!("invalid cache digest: {reason}"));
+        let header = bytes
+            .get(..HEADER_SIZE)
+            .ok_or_else(|| invalid("truncated header"))?;
+        let u16_at = |at: usize| u16::from_be_bytes([header[at], header[at + 1]]);
+        let u32_at = |at: usize| u32::from_be_bytes(header[at..at + 4].try_into().unwrap());
+
+        let required = u16_at(2);
+        if required > VERSION {
+            return Err(invalid(&format!("unsupported version {required}")));
+        }
+        if header[21] != HASH_COUNT {
+            return Err(invalid(&format!("unsupported hash count {}", header[21])));
+        }
+        let mask_size = u32_at(16) as usize;
+        let mask = bytes[HEADER_SIZE..]
+            .get(..mask_size)
+            .filter(|mask| !mask.is_empty())
+            .ok_or_else(|| invalid("truncated mask"))?;
+
+        Ok(Self {
+            mask: mask.to_vec(),
+            capacity: u32_at(4),
+            count: u32_at(8),
+            del_count: u32_at(12),
+            bits_per_entry: header[20],
+        })
+    }
+
+    /// Returns the bits selected by a store key.
+    fn bits(&self, key: &[u8; 16]) -> [usize; HASH_COUNT as usize] {
+        let bit_count = (self.mask.len() * 8) as u32;
+        std::array::from_fn(|i| {
+            let word = u32::from_be_bytes(key[4 * i..4 * i + 4].try_into().unwrap());
+            (word % bit_count) as usize
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn squid_key_derivation() {
+        let key = store_key(Method::Get, "http://example.com/");
+        assert_eq!(key, [
+            0x5c, 0x91, 0x09, 0x03, 0x4c, 0x1d, 0x08, 0x9f, 0xb6, 0xd9, 0x2f, 0x72, 0x58, 0xb2,
+            0xf5, 0xc3
+        ]);
+
+        // Bits are taken from the key's words in network order.
+        let digest = CacheDigest::new(1000, DEFAULT_BITS_PER_ENTRY);
+        assert_eq!(
+            digest.bits(&key),
+            [1553008899, 1276971167, 3067686770, 1488123331].map(|word: u32| word as usize % 5000)
+        );
+    }
+}
@@ -0,0 +1,760 @@
+//! Bit array storage backends.
+//!
+//! Filters built on top of a flat bit array are generic over [`BitStorage`],
+//! so that the very same probing logic can run over different memory
+//! layouts. The default is a contiguous [`FixedBitSet`].
+
+#[cfg(all(feature = "mmap", unix))]
+use std::{
+    fs::File,
+    io,
+    os::fd::AsRawFd,
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use {fixedbitset::FixedBitSet, std::sync::Arc};
+
+/// Bit array used as a filter's backing store.
+pub trait BitStorage {
+    /// Returns the number of bits.
+    fn len(&self) -> usize;
+
+    /// Returns `true` if there are no bits at all.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if the bit at `index` is set.
+    fn contains(&self, index: usize) -> bool;
+
+    /// Sets the bit at `index`.
+    fn insert(&mut self, index: usize);
+
+    /// Unsets all bits.
+    fn clear(&mut self);
+
+    /// Returns the number of set bits.
+    fn count_ones(&self) -> usize;
+
+    /// Hints that the bit at `index` is about to be accessed, so that its
+    /// memory can be fetched ahead of time. Does nothing by default.
+    fn prefetch(&self, index: usize) {
+        let _ = index;
+    }
+
+    /// Returns the number of 64-bit words needed to hold all bits.
+    fn word_count(&self) -> usize {
+        self.len().div_ceil(64)
+    }
+
+    /// Returns the `index`-th 64-bit word, i.e. bits `[64 * index, 64 * index
+    /// + 64)`, with the lowest bit first. Bits past the end are unset.
+    fn word(&self, index: usize) -> u64 {
+        let start = index * 64;
+        let end = (start + 64).min(self.len());
+        (start..end)
+            .filter(|&bit| self.contains(bit))
+            .fold(0, |word, bit| word | 1 << (bit - start))
+    }
+}
+
+impl BitStorage for FixedBitSet {
+    fn len(&self) -> usize {
+        FixedBitSet::len(self)
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        FixedBitSet::contains(self, index)
+    }
+
+    fn insert(&mut self, index: usize) {
+        FixedBitSet::insert(self, index)
+    }
+
+    fn clear(&mut self) {
+        FixedBitSet::clear(self)
+    }
+
+    fn count_ones(&self) -> usize {
+        FixedBitSet::count_ones(self, ..)
+    }
+
+    #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+    fn prefetch(&self, index: usize) {
+        use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+        if let Some(block) = self.as_slice().get(index / usize::BITS as usize) {
+            // SAFETY: prefetching has no effect beyond the cache.
+            unsafe { _mm_prefetch::<_MM_HINT_T0>((block as *const usize).cast()) };
+        }
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        // Blocks are `usize`, so on 32-bit targets a word spans two of them.
+        let blocks = self.as_slice();
+        let per_word = (u64::BITS / usize::BITS) as usize;
+        (0..per_word).fold(0, |word, i| {
+            let block = blocks.get(index * per_word + i).copied().unwrap_or(0) as u64;
+            word | block << (i as u32 * usize::BITS)
+        })
+    }
+}
+
+/// Number of 64-bit words in a single chunk of [`SharedBitSet`] (4 KiB).
+const CHUNK_WORDS: usize = 512;
+
+/// Bit array split into reference counted, copy-on-write chunks.
+///
+/// Cloning is `O(chunks)`, as it only bumps reference counts, and clones
+/// share memory until modified: writing a bit copies just the one chunk it
+/// falls into (and only if that chunk is still shared). This makes it cheap to
+/// take point-in-time snapshots of a filter that keeps being updated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedBitSet {
+    chunks: Vec<Arc<[u64; CHUNK_WORDS]>>,
+    len: usize,
+}
+
+impl SharedBitSet {
+    /// Number of bits in a single chunk.
+    pub const CHUNK_BITS: usize = CHUNK_WORDS * 64;
+
+    /// Creates a new bit array with all `len` bits unset.
+    pub fn with_len(len: usize) -> Self {
+        let zeroed = Arc::new([0; CHUNK_WORDS]);
+        Self {
+            chunks: vec![zeroed; len.div_ceil(Self::CHUNK_BITS)],
+            len,
+        }
+    }
+
+    /// Returns the number of chunks the bits are split into.
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns the number of chunks not shared with any other clone.
+    pub fn exclusive_chunk_count(&self) -> usize {
+        self.chunks
+            .iter()
+            .filter(|chunk| Arc::strong_count(chunk) == 1)
+            .count()
+    }
+
+    fn locate(index: usize) -> (usize, usize, u64) {
+        let word = index / 64;
+        (word / CHUNK_WORDS, word % CHUNK_WORDS, 1 << (index % 64))
+    }
+}
+
+impl BitStorage for SharedBitSet {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        let (chunk, word, mask) = Self::locate(index);
+        self.chunks[chunk][word] & mask != 0
+    }
+
+    fn insert(&mut self, index: usize) {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        let (chunk, word, mask) = Self::locate(index);
+        if self.chunks[chunk][word] & mask == 0 {
+            Arc::make_mut(&mut self.chunks[chunk])[word] |= mask;
+        }
+    }
+
+    fn clear(&mut self) {
+        let zeroed = Arc::new([0; CHUNK_WORDS]);
+        self.chunks.fill(zeroed);
+    }
+
+    fn count_ones(&self) -> usize {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter())
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        self.chunks
+            .get(index / CHUNK_WORDS)
+            .map_or(0, |chunk| chunk[index % CHUNK_WORDS])
+    }
+}
+
+impl From<&FixedBitSet> for SharedBitSet {
+    fn from(bits: &FixedBitSet) -> Self {
+        let mut shared = Self::with_len(bits.len());
+        for index in bits.ones() {
+            shared.insert(index);
+        }
+        shared
+    }
+}
+
+/// Bit array kept as a compressed (roaring) bitmap of set bit indices.
+///
+/// Memory grows with the number of set bits rather than with the length, so
+/// a filter pre-allocated for the worst case, but staying sparse, costs a
+/// fraction of its nominal size. Individual accesses are slower than with a
+/// contiguous bit set, and memory exceeds a contiguous bit set's once more
+/// than a few percent of the bits are set.
+#[cfg(feature = "roaring")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseBitSet {
+    bits: roaring::RoaringTreemap,
+    len: usize,
+}
+
+#[cfg(feature = "roaring")]
+impl SparseBitSet {
+    /// Creates a new bit array with all `len` bits unset.
+    pub fn with_len(len: usize) -> Self {
+        Self {
+            bits: roaring::RoaringTreemap::new(),
+            len,
+        }
+    }
+
+    /// Returns the approximate memory used by the set bits, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.bits.serialized_size()
+    }
+}
+
+#[cfg(feature = "roaring")]
+impl BitStorage for SparseBitSet {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        self.bits.contains(index as u64)
+    }
+
+    fn insert(&mut self, index: usize) {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        self.bits.insert(index as u64);
+    }
+
+    fn clear(&mut self) {
+        self.bits.clear();
+    }
+
+    fn count_ones(&self) -> usize {
+        self.bits.len() as usize
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        let start = index as u64 * 64;
+        let mut iter = self.bits.iter();
+        iter.advance_to(start);
+        iter.take_while(|&bit| bit < start + 64)
+            .fold(0, |word, bit| word | 1 << (bit - start))
+    }
+}
+
+/// Hint about how a memory-mapped bit array is about to be accessed, see
+/// [`MmapBitSet::advise`].
+#[cfg(all(feature = "mmap", unix))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// The bits will be accessed soon: read them ahead (`MADV_WILLNEED`).
+    WillNeed,
+    /// The bits will not be accessed soon: their pages may be dropped from
+    /// memory, to be read back from the file on next access
+    /// (`MADV_DONTNEED`).
+    DontNeed,
+    /// Back the mapping with huge pages where possible, for fewer TLB misses
+    /// (`MADV_HUGEPAGE`, Linux only).
+    HugePage,
+}
+
+/// Bit array in a memory-mapped file, see
+/// [`BloomFilter::create_mmap`](crate::BloomFilter::create_mmap).
+///
+/// The mapping is shared: bits are read from and written to the page cache,
+/// and reach the file without an explicit load or save (see
+/// [`flush`](MmapBitSet::flush) to force them to disk). Bits are set
+/// atomically, so that several processes mapping the same file can insert
+/// concurrently without losing each other's bits.
+///
+/// Truncating the file while it is mapped makes accesses past its new end
+/// fault (`SIGBUS`), as with any memory-mapped file.
+#[cfg(all(feature = "mmap", unix))]
+#[derive(Debug)]
+pub struct MmapBitSet {
+    base: NonNull<libc::c_void>,
+    map_len: usize,
+    offset: usize,
+    len: usize,
+}
+
+// SAFETY: the mapping is only accessed through atomics.
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Send for MmapBitSet {}
+#[cfg(all(feature = "mmap", unix))]
+unsafe impl Sync for MmapBitSet {}
+
+#[cfg(all(feature = "mmap", unix))]
+impl MmapBitSet {
+    /// Maps `len` bits of a file opened for reading and writing, stored as
+    /// little-endian `u64` words from byte `offset`, a multiple of 8.
+    ///
+    /// The file must be large enough to hold all words.
+    pub(crate) fn map(file: &File, offset: usize, len: usize) -> io::Result<Self> {
+        assert_eq!(offset % 8, 0, "words must be aligned");
+        let map_len = offset + len.div_ceil(64) * 8;
+        // SAFETY: a fresh shared mapping, not aliasing any Rust memory.
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len.max(1),
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            base: NonNull::new(base).expect("mmap never maps at null"),
+            map_len: map_len.max(1),
+            offset,
+            len,
+        })
+    }
+
+    /// Writes modified bits back to the file, waiting for the write to
+    /// complete.
+    pub fn flush(&self) -> io::Result<()> {
+        // SAFETY: the range is the mapping itself.
+        if unsafe { libc::msync(self.base.as_ptr(), self.map_len, libc::MS_SYNC) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Advises the kernel of how the bits are about to be accessed.
+    ///
+    /// Fails with [`Unsupported`](io::ErrorKind::Unsupported) for
+    /// [`Advice::HugePage`] on platforms other than Linux.
+    pub fn advise(&self, advice: Advice) -> io::Result<()> {
+        let advice = match advice {
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::DontNeed => libc::MADV_DONTNEED,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Advice::HugePage => libc::MADV_HUGEPAGE,
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Advice::HugePage => return Err(io::ErrorKind::Unsupported.into()),
+        };
+        // SAFETY: the range is the mapping itself, and none of the hints
+        // discards data of a shared file mapping.
+        if unsafe { libc::madvise(self.base.as_ptr(), self.map_len, advice) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Locks the bits in memory (`mlock`), paging them in first, so that
+    /// queries never fault. They stay locked until
+    /// [`unlock`](MmapBitSet::unlock)ed or unmapped.
+    ///
+    /// Fails if the process may not lock that much memory (see
+    /// `RLIMIT_MEMLOCK`).
+    pub fn lock(&self) -> io::Result<()> {
+        // SAFETY: the range is the mapping itself.
+        if unsafe { libc::mlock(self.base.as_ptr(), self.map_len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Unlocks bits locked by [`lock`](MmapBitSet::lock).
+    pub fn unlock(&self) -> io::Result<()> {
+        // SAFETY: the range is the mapping itself.
+        if unsafe { libc::munlock(self.base.as_ptr(), self.map_len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn words(&self) -> &[AtomicU64] {
+        // SAFETY: the words lie within the mapping, page-aligned plus a
+        // multiple of 8, and are only ever accessed atomically.
+        unsafe {
+            std::slice::from_raw_parts(
+                self.base.as_ptr().cast::<u8>().add(self.offset).cast(),
+                self.len.div_ceil(64),
+            )
+        }
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl Drop for MmapBitSet {
+    fn drop(&mut self) {
+        // SAFETY: the mapping is not accessed past this point.
+        unsafe { libc::munmap(self.base.as_ptr(), self.map_len) };
+    }
+}
+
+#[cfg(all(feature = "mmap", unix))]
+impl BitStorage for MmapBitSet {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        self.word(index / 64) & (1 << (index % 64)) != 0
+    }
+
+    fn insert(&mut self, index: usize) {
+        assert!(index < self.len, "bit index {index} out of bounds");
+        // Words are stored little-endian.
+        let (word, mask) = (&self.words()[index / 64], (1u64 << (index % 64)).to_le());
+        // Skip the write (dirtying the page) if the bit is already set.
+        if word.load(Ordering::Relaxed) & mask == 0 {
+            word.fetch_or(mask, Ordering::Relaxed);
+        }
+    }
+
+    fn clear(&mut self) {
+        for word in self.words() {
+            word.store(0, Ordering::Relaxed);
+        }
+    }
+
+    fn count_ones(&self) -> usize {
+        (0..self.word_count())
+            .map(|i| self.word(i).count_ones() as usize)
+            .sum()
+    }
+
+    fn word(&self, index: usize) -> u64 {
+        u64::from_le(self.words()[index].load(Ordering::Relaxed))
+    }
+}
+
+/// Array of fixed-width unsigned integers, densely packed into 64-bit words.
+///
+/// Values are `bits` wide (anywhere within `1..=64`), so an array of `len`
+/// values takes `len * bits` bits, rounded up to a whole word.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedArray {
+    words: Vec<u64>,
+    bits: u32,
+    len: usize,
+}
+
+impl PackedArray {
+    /// Creates a new array of `len` zeroed `bits`-wide values.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not within `1..=64`.
+    pub fn new(len: usize, bits: u32) -> Self {
+        assert!((1..=64).contains(&bits), "value width must be in 1..=64");
+        Self {
+            words: vec![0; (len * bits as usize).div_ceil(64)],
+            bits,
+            len,
+        }
+    }
+
+    /// Returns the largest value that fits into `bits` bits.
+    pub fn max_value(bits: u32) -> u64 {
+        u64::MAX >> (64 - bits)
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the array holds no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the width of a single value.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// Returns the memory used by the values, in bytes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.words.len() * 8
+    }
+
+    /// Returns the value at `index`.
+    pub fn get(&self, index: usize) -> u64 {
+        let start = index * self.bits as usize;
+        let (word, offset) = (start / 64, (start % 64) as u32);
+        let mut value = self.words[word] >> offset;
+        if offset + self.bits > 64 {
+            value |= self.words[word + 1] << (64 - offset);
+        }
+        value & Self::max_value(self.bits)
+    }
+
+    /// Sets the value at `index`, truncating it to the array's width.
+    pub fn set(&mut self, index: usize, value: u64) {
+        let start = index * self.bits as usize;
+        let (word, offset) = (start / 64, (start % 64) as u32);
+        let mask = Self::max_value(self.bits);
+        self.words[word] = self.words[word] & !(mask << offset) | (value & mask) << offset;
+        if offset + self.bits > 64 {
+            let spill = 64 - offset;
+            self.words[word + 1] =
+                self.words[word + 1] & !(mask >> spill) | (value & mask) >> spill;
+        }
+    }
+}
+
+/// Packed bits, as base64 or raw bytes depending on the format.
+#[cfg(feature = "serde")]
+pub(crate) struct SerdeBits(pub(crate) Vec<u8>);
+
+#[cfg(feature = "serde")]
+impl SerdeBits {
+    /// Packs words, as little-endian bytes.
+    pub(crate) fn from_words(words: &[u64]) -> Self {
+        Self(words.iter().flat_map(|word| word.to_le_bytes()).collect())
+    }
+
+    /// Unpacks words, failing unless there are `count` of them.
+    pub(crate) fn into_words<E: serde::de::Error>(self, count: usize) -> Result<Vec<u64>, E> {
+        if self.0.len() != count * 8 {
+            return Err(E::custom(format!(
+                "expected {} bytes of words, got {}",
+                count * 8,
+                self.0.len()
+            )));
+        }
+        Ok(self
+            .0
+            .chunks_exact(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect())
+    }
+}
+
+/// Serialized form of a packed array.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "PackedArray")]
+struct SerdePackedArray {
+    len: usize,
+    bits: u32,
+    words: SerdeBits,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PackedArray {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerdePackedArray {
+            len: self.len,
+            bits: self.bits,
+            words: SerdeBits::from_words(&self.words),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PackedArray {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        let array = SerdePackedArray::deserialize(deserializer)?;
+        if !(1..=64).contains(&array.bits) {
+            return Err(D::Error::custom(format!(
+                "value width {} is not within 1..=64",
+                array.bits
+            )));
+        }
+        let count = array
+            .len
+            .checked_mul(array.bits as usize)
+            .ok_or_else(|| D::Error::custom("array length overflows"))?
+            .div_ceil(64);
+        Ok(Self {
+            words: array.words.into_words(count)?,
+            bits: array.bits,
+            len: array.len,
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for SerdeBits {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use base64::Engine;
+
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(&self.0))
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for SerdeBits {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use {base64::Engine, serde::de::Error};
+
+        struct Visitor;
+
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = SerdeBits;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("base64 string or bytes")
+            }
+
+            fn visit_str<E: Error>(self, value: &str) -> Result<SerdeBits, E> {
+                base64::engine::general_purpose::STANDARD
+                    .decode(value)
+                    .map(SerdeBits)
+                    .map_err(E::custom)
+            }
+
+            fn visit_bytes<E: Error>(self, value: &[u8]) -> Result<SerdeBits, E> {
+                Ok(SerdeBits(value.to_vec()))
+            }
+
+            fn visit_byte_buf<E: Error>(self, value: Vec<u8>) -> Result<SerdeBits, E> {
+                Ok(SerdeBits(value))
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(
+                self,
+                mut seq: A,
+            ) -> Result<SerdeBits, A::Error> {
+                let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+                while let Some(byte) = seq.next_element()? {
+                    bytes.push(byte);
+                }
+                Ok(SerdeBits(bytes))
+            }
+        }
+
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_str(Visitor)
+        } else {
+            deserializer.deserialize_byte_buf(Visitor)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copy_on_write() {
+        let mut bits = SharedBitSet::with_len(SharedBitSet::CHUNK_BITS * 4 + 10);
+        assert_eq!(bits.chunk_count(), 5);
+        bits.insert(0);
+        bits.insert(SharedBitSet::CHUNK_BITS * 4 + 9);
+
+        let snapshot = bits.clone();
+        assert_eq!(bits.exclusive_chunk_count(), 0);
+
+        // Setting an already set bit does not copy anything.
+        bits.insert(0);
+        assert_eq!(bits.exclusive_chunk_count(), 0);
+
+        bits.insert(SharedBitSet::CHUNK_BITS + 1);
+        assert_eq!(bits.exclusive_chunk_count(), 1);
+        assert!(bits.contains(SharedBitSet::CHUNK_BITS + 1));
+        assert!(!snapshot.contains(SharedBitSet::CHUNK_BITS + 1));
+        assert_eq!(bits.count_ones(), 3);
+        assert_eq!(snapshot.count_ones(), 2);
+
+        bits.clear();
+        assert_eq!(bits.count_ones(), 0);
+        assert_eq!(snapshot.count_ones(), 2);
+    }
+
+    #[test]
+    fn from_fixed_bit_set() {
+        let mut bits = FixedBitSet::with_capacity(100000);
+        for index in [0, 1, 64, 4095, 99999] {
+            bits.insert(index);
+        }
+
+        let shared = SharedBitSet::from(&bits);
+        assert_eq!(BitStorage::len(&shared), 100000);
+        assert_eq!(BitStorage::count_ones(&shared), 5);
+        for index in 0..100000 {
+            assert_eq!(shared.contains(index), bits.contains(index));
+        }
+    }
+
+    #[test]
+    fn words() {
+        let mut bits = FixedBitSet::with_capacity(200);
+        for index in [0, 63, 64, 130, 199] {
+            bits.insert(index);
+        }
+        let shared = SharedBitSet::from(&bits);
+
+        let expected = [1 | 1 << 63, 1, 1 << 2, 1 << 7];
+        assert_eq!(BitStorage::word_count(&bits), 4);
+        for (i, word) in expected.into_iter().chain([0]).enumerate() {
+            assert_eq!(BitStorage::word(&bits, i), word);
+            assert_eq!(shared.word(i), word);
+        }
+    }
+
+    #[cfg(feature = "roaring")]
+    #[test]
+    fn sparse_bit_set() {
+        let mut bits = SparseBitSet::with_len(1 << 40);
+        for index in [0, 63, 64, 1 << 35, (1 << 40) - 1] {
+            bits.insert(index);
+        }
+        assert_eq!(bits.count_ones(), 5);
+        assert!(bits.contains(1 << 35));
+        assert!(!bits.contains(1 << 36));
+        assert_eq!(bits.word(0), 1 | 1 << 63);
+        assert_eq!(bits.word(1), 1);
+        assert!(bits.size_in_bytes() < 1024);
+
+        bits.clear();
+        assert_eq!(bits.count_ones(), 0);
+    }
+
+    #[test]
+    fn packed_array_works() {
+        let value = |i: usize| (i as u64).wrapping_mul(0x9e3779b97f4a7c15);
+        for bits in [1, 4, 7, 13, 32, 63, 64] {
+            let max = PackedArray::max_value(bits);
+            let mut array = PackedArray::new(100, bits);
+            assert_eq!(array.len(), 100);
+            for i in 0..100 {
+                array.set(i, value(i));
+            }
+            for i in 0..100 {
+                assert_eq!(array.get(i), value(i) & max);
+            }
+            // Overwriting does not affect neighbours.
+            array.set(50, 0);
+            assert_eq!(array.get(49), value(49) & max);
+            assert_eq!(array.get(50), 0);
+            assert_eq!(array.get(51), value(51) & max);
+        }
+    }
+}
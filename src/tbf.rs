@@ -0,0 +1,270 @@
+//! Two-block Bloom filter.
+//!
+//! A middle ground between the classic Bloom filter (where each of the `k`
+//! probes may land anywhere, i.e. up to `k` cache misses per query) and
+//! single-block filters (one cache miss, but noticeably higher false
+//! positive rate, as blocks fill unevenly). Each key selects two random
+//! cache-line sized blocks and splits its `k` probes between them, so a
+//! query touches at most two cache lines, while the false positive rate stays
+//! very close to the one of a standard Bloom filter.
+
+use {
+    crate::{
+        analysis::{optimal_bit_count, optimal_capacity, optimal_hash_count},
+        hash::{KeyHasher, ProbeHasher},
+        ClearableQueryFilter,
+        FreezableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash, marker::PhantomData},
+};
+
+/// Number of bits in a single block (one 64-byte cache line).
+pub const BLOCK_BITS: usize = 512;
+
+const BLOCK_WORDS: usize = BLOCK_BITS / 64;
+
+#[derive(Clone, Copy, Default)]
+#[repr(align(64))]
+struct Block([u64; BLOCK_WORDS]);
+
+/// Bloom filter with probes split across two cache-line sized blocks.
+pub struct TwoBlockBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    blocks: Vec<Block>,
+    hasher: H,
+    k: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K> TwoBlockBloomFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn new(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity(capacity, fp_rate)
+    }
+
+    /// Creates a new filter with a desired size (in bytes) and false positive
+    /// rate.
+    pub fn with_size(size: usize, fp_rate: f64) -> Self {
+        Self::with_size_and_hasher(size, fp_rate, ProbeHasher::default())
+    }
+
+    /// Creates a new filter with a desired capacity and false positive rate.
+    pub fn with_capacity(capacity: usize, fp_rate: f64) -> Self {
+        Self::with_capacity_and_hasher(capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<K, H> TwoBlockBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Creates a new filter with a desired size (in bytes), false positive
+    /// rate, and hasher.
+    pub fn with_size_and_hasher(size: usize, fp_rate: f64, hasher: H) -> Self {
+        let capacity = optimal_capacity(size * 8, fp_rate);
+        Self::with_capacity_and_hasher(capacity, fp_rate, hasher)
+    }
+
+    /// Creates a new filter with a desired capacity, false positive rate, and
+    /// hasher.
+    ///
+    /// The number of bits is the one of an optimal classic Bloom filter,
+    /// rounded up to a whole number of blocks.
+    pub fn with_capacity_and_hasher(capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        let bit_count = optimal_bit_count(capacity, fp_rate);
+        let k = optimal_hash_count(capacity, bit_count);
+        let block_count = bit_count.div_ceil(BLOCK_BITS).max(1);
+        event!(capacity, fp_rate, block_count, k; "created two-block Bloom filter");
+        Self {
+            blocks: vec![Block::default(); block_count],
+            hasher,
+            k,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns the hasher used to generate probe sequences.
+    pub fn hasher(&self) -> &H {
+        &self.hasher
+    }
+
+    /// Returns the approximate number of elements currently in the filter.
+    pub fn approx_current_capacity(&self) -> usize {
+        let bits_count = (self.blocks.len() * BLOCK_BITS) as f64;
+        let ones_count = self
+            .blocks
+            .iter()
+            .flat_map(|block| block.0.iter())
+            .map(|word| word.count_ones() as f64)
+            .sum::<f64>();
+        let hash_count = self.k as f64;
+        let count = -(bits_count / hash_count) * (1. - (ones_count / bits_count)).ln();
+
+        count.round() as usize
+    }
+}
+
+impl<K> TwoBlockBloomFilter<K, ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    /// Inserts a key fed in chunks, see [`KeyHasher`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key hasher was not created from the filter's hasher.
+    pub fn insert_streamed(&mut self, key: &KeyHasher) {
+        assert_eq!(key.hasher(), &self.hasher, "key hashed with another hasher");
+        for (block, bit) in probes(key.probes(self.k + 2), self.k, self.blocks.len()) {
+            self.blocks[block].0[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Returns `true` if a key fed in chunks is believed to be in the filter,
+    /// see [`KeyHasher`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key hasher was not created from the filter's hasher.
+    pub fn contains_streamed(&self, key: &KeyHasher) -> bool {
+        assert_eq!(key.hasher(), &self.hasher, "key hashed with another hasher");
+        probes(key.probes(self.k + 2), self.k, self.blocks.len())
+            .all(|(block, bit)| self.blocks[block].0[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+}
+
+impl<K, H> QueryFilter<K> for TwoBlockBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for (block, bit) in probes(
+            self.hasher.hash_iter(key, self.k + 2),
+            self.k,
+            self.blocks.len(),
+        ) {
+            if self.blocks[block].0[bit / 64] & (1 << (bit % 64)) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl<K, H> InsertableQueryFilter<K> for TwoBlockBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn insert(&mut self, key: K) {
+        for (block, bit) in probes(
+            self.hasher.hash_iter(&key, self.k + 2),
+            self.k,
+            self.blocks.len(),
+        ) {
+            self.blocks[block].0[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+}
+
+impl<K, H> ClearableQueryFilter<K> for TwoBlockBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn clear(&mut self) {
+        event!(block_count = self.blocks.len(), k = self.k; "cleared two-block Bloom filter");
+        self.blocks.fill(Block::default());
+    }
+}
+
+impl<K, H> FreezableQueryFilter<K> for TwoBlockBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    type Frozen = FrozenTwoBlockBloomFilter<K, H>;
+
+    fn freeze(self) -> Self::Frozen {
+        event!(block_count = self.blocks.len(), k = self.k; "froze two-block Bloom filter");
+        FrozenTwoBlockBloomFilter {
+            blocks: self.blocks.into_boxed_slice(),
+            hasher: self.hasher,
+            k: self.k,
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Read-only two-block Bloom filter.
+///
+/// Produced by [`TwoBlockBloomFilter::freeze`], answers queries identically to
+/// the filter it was frozen from, while holding exactly as much memory as the
+/// blocks need.
+pub struct FrozenTwoBlockBloomFilter<K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    blocks: Box<[Block]>,
+    hasher: H,
+    k: usize,
+    phantom: PhantomData<K>,
+}
+
+impl<K, H> QueryFilter<K> for FrozenTwoBlockBloomFilter<K, H>
+where
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        for (block, bit) in probes(
+            self.hasher.hash_iter(key, self.k + 2),
+            self.k,
+            self.blocks.len(),
+        ) {
+            if self.blocks[block].0[bit / 64] & (1 << (bit % 64)) == 0 {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Returns the positions (block index and bit offset within the block) of all
+/// probes of a key, given its probe sequence of length `k + 2`.
+///
+/// The first two values of the probe sequence select the blocks, the
+/// remaining `k` are bit offsets: the first half goes into the first block,
+/// the rest into the second one.
+fn probes(
+    mut hashes: impl Iterator<Item = u64>,
+    k: usize,
+    block_count: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let first = (hashes.next().unwrap_or_default() % block_count as u64) as usize;
+    let second = (hashes.next().unwrap_or_default() % block_count as u64) as usize;
+    let split = k.div_ceil(2);
+
+    hashes.enumerate().map(move |(i, hash)| {
+        let block = if i < split { first } else { second };
+        (block, (hash % BLOCK_BITS as u64) as usize)
+    })
+}
@@ -0,0 +1,510 @@
+//! Property-testing support.
+//!
+//! Implements [`arbitrary::Arbitrary`] for filter parameters (so fuzzers can
+//! drive filter construction), and provides [`proptest`](mod@proptest)
+//! strategies for generating key sets and operation sequences, so that code
+//! built on top of the filters can be property-tested. [`FlakyFilter`] stands
+//! in for a real filter where tests need false positives on cue, and
+//! [`ShadowFilter`] checks a real filter against the exact set of keys it was
+//! given.
+
+use {
+    crate::{
+        hash::{ProbeHasher, ProbeStrategy},
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        RemovableQueryFilter,
+    },
+    arbitrary::{Arbitrary, Unstructured},
+    proptest::{collection, prelude::*},
+    std::{
+        borrow::Borrow,
+        cell::Cell,
+        collections::HashSet,
+        fmt::Debug,
+        hash::{BuildHasher, Hash},
+    },
+    xxhash_rust::xxh3::Xxh3Builder,
+};
+
+/// Largest capacity generated for [`FilterParams`].
+pub const MAX_CAPACITY: usize = 100_000;
+
+/// Smallest false positive rate generated for [`FilterParams`].
+pub const MIN_FP_RATE: f64 = 1e-6;
+
+/// Largest false positive rate generated for [`FilterParams`].
+pub const MAX_FP_RATE: f64 = 0.5;
+
+/// Sizing parameters of a filter.
+///
+/// Generated values are always valid: capacity is within `1..=MAX_CAPACITY`,
+/// and false positive rate within `MIN_FP_RATE..=MAX_FP_RATE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterParams {
+    /// Expected number of items.
+    pub capacity: usize,
+    /// Desired false positive rate.
+    pub fp_rate: f64,
+}
+
+impl<'a> Arbitrary<'a> for FilterParams {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let capacity = u.int_in_range(1..=MAX_CAPACITY)?;
+        // Spread rates uniformly on a log scale.
+        let exponent = u.int_in_range(0..=u16::MAX)? as f64 / u16::MAX as f64;
+        let fp_rate = MIN_FP_RATE * (MAX_FP_RATE / MIN_FP_RATE).powf(exponent);
+        Ok(Self { capacity, fp_rate })
+    }
+}
+
+impl<'a> Arbitrary<'a> for ProbeStrategy {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(*u.choose(&[
+            ProbeStrategy::Double,
+            ProbeStrategy::EnhancedDouble,
+            ProbeStrategy::Triple,
+        ])?)
+    }
+}
+
+impl<'a> Arbitrary<'a> for ProbeHasher {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(ProbeHasher::new(u.arbitrary()?)
+            .with_seed1(u.arbitrary()?)
+            .with_seed2(u.arbitrary()?)
+            .with_seed3(u.arbitrary()?))
+    }
+}
+
+/// Returns a strategy generating valid [`FilterParams`].
+pub fn filter_params() -> impl Strategy<Value = FilterParams> {
+    (1..=MAX_CAPACITY, MIN_FP_RATE.ln()..=MAX_FP_RATE.ln()).prop_map(|(capacity, ln_rate)| {
+        FilterParams {
+            capacity,
+            fp_rate: ln_rate.exp().clamp(MIN_FP_RATE, MAX_FP_RATE),
+        }
+    })
+}
+
+/// Returns a strategy generating [`ProbeHasher`]s with random seeds and
+/// strategies.
+pub fn probe_hashers() -> impl Strategy<Value = ProbeHasher> {
+    let strategies = prop_oneof![
+        Just(ProbeStrategy::Double),
+        Just(ProbeStrategy::EnhancedDouble),
+        Just(ProbeStrategy::Triple),
+    ];
+    (strategies, any::<[u64; 3]>()).prop_map(|(strategy, [seed1, seed2, seed3])| {
+        ProbeHasher::new(strategy)
+            .with_seed1(seed1)
+            .with_seed2(seed2)
+            .with_seed3(seed3)
+    })
+}
+
+/// Returns a strategy generating two disjoint sets of keys: one to be
+/// inserted, and a holdout one to be queried for false positives.
+pub fn disjoint_key_sets<K>(
+    keys: impl Strategy<Value = K> + Clone,
+    max_len: usize,
+) -> impl Strategy<Value = (Vec<K>, Vec<K>)>
+where
+    K: Eq + Hash + Clone + Debug,
+{
+    (
+        collection::hash_set(keys.clone(), 0..=max_len),
+        collection::hash_set(keys, 0..=max_len),
+    )
+        .prop_map(|(inserted, holdout)| {
+            let holdout = holdout.difference(&inserted).cloned().collect();
+            (inserted.into_iter().collect(), holdout)
+        })
+}
+
+/// Single operation on a filter.
+#[derive(Debug, Clone, PartialEq, Eq, Arbitrary)]
+pub enum Operation<K> {
+    /// Insert a key.
+    Insert(K),
+    /// Query a key.
+    Contains(K),
+    /// Remove all keys.
+    Clear,
+}
+
+/// Returns a strategy generating sequences of operations over given keys.
+///
+/// Clearing is rare (about 1 in 50 operations), so that sequences build up
+/// meaningful state.
+pub fn operations<K>(
+    keys: impl Strategy<Value = K> + Clone,
+    max_len: usize,
+) -> impl Strategy<Value = Vec<Operation<K>>>
+where
+    K: Clone + Debug,
+{
+    let operation = prop_oneof![
+        25 => keys.clone().prop_map(Operation::Insert),
+        24 => keys.prop_map(Operation::Contains),
+        1 => Just(Operation::Clear),
+    ];
+    collection::vec(operation, 0..=max_len)
+}
+
+/// Applies operations to a filter, checking it against an exact model.
+///
+/// Returns the number of false positives observed, and panics on any false
+/// negative (i.e. a `Contains` of a key inserted since the last `Clear`
+/// returning `false`).
+pub fn check_operations<K, F>(filter: &mut F, operations: &[Operation<K>]) -> usize
+where
+    K: Eq + Hash + Clone + Debug,
+    F: InsertableQueryFilter<K> + ClearableQueryFilter<K>,
+{
+    let mut model = HashSet::new();
+    let mut false_positives = 0;
+    for operation in operations {
+        match operation {
+            Operation::Insert(key) => {
+                model.insert(key.clone());
+                filter.insert(key.clone());
+            }
+            Operation::Contains(key) => match (filter.contains(key), model.contains(key)) {
+                (false, true) => panic!("false negative for key {key:?}"),
+                (true, false) => false_positives += 1,
+                _ => {}
+            },
+            Operation::Clear => {
+                model.clear();
+                filter.clear();
+            }
+        }
+    }
+    false_positives
+}
+
+/// Test double answering exactly, except for injected false positives.
+///
+/// Keys are kept in an exact set, so answers are predictable: a key is
+/// reported as present if it was inserted, if it was registered as a false
+/// positive, if it falls within the configured false positive rate (decided
+/// by its hash, so the same key always gets the same answer), or if the
+/// filter is saturated. Meant for testing that code built on top of filters
+/// tolerates false positives, without coaxing a real filter into producing
+/// them on cue.
+#[derive(Debug, Clone)]
+pub struct FlakyFilter<K> {
+    keys: HashSet<K>,
+    false_positives: HashSet<K>,
+    fp_rate: f64,
+    seed: u64,
+    saturation: Option<usize>,
+}
+
+impl<K> FlakyFilter<K>
+where
+    K: Eq + Hash,
+{
+    /// Creates a new filter, with no false positives (yet).
+    pub fn new() -> Self {
+        Self {
+            keys: HashSet::new(),
+            false_positives: HashSet::new(),
+            fp_rate: 0.,
+            seed: 0,
+            saturation: None,
+        }
+    }
+
+    /// Sets the rate of false positives among keys not inserted.
+    ///
+    /// Which keys are false positives is decided by their hash (see
+    /// [`with_seed`]), so answers are deterministic.
+    ///
+    /// [`with_seed`]: FlakyFilter::with_seed
+    pub fn with_fp_rate(self, fp_rate: f64) -> Self {
+        Self { fp_rate, ..self }
+    }
+
+    /// Sets the seed picking which keys are false positives.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Registers keys to be reported as present, without being inserted.
+    pub fn with_false_positives(mut self, keys: impl IntoIterator<Item = K>) -> Self {
+        self.false_positives.extend(keys);
+        self
+    }
+
+    /// Makes the filter saturate once it holds `capacity` keys: from then on,
+    /// every key is reported as present, the way an overfilled Bloom filter
+    /// degrades.
+    pub fn with_saturation_at(self, capacity: usize) -> Self {
+        Self {
+            saturation: Some(capacity),
+            ..self
+        }
+    }
+
+    /// Returns the number of inserted keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if no keys are inserted.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns `true` if every key is reported as present.
+    pub fn is_saturated(&self) -> bool {
+        self.saturation
+            .is_some_and(|capacity| self.keys.len() >= capacity)
+    }
+
+    /// Returns `true` if a key is reported as present without having been
+    /// inserted.
+    pub fn is_false_positive<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        !self.keys.contains(key)
+            && (self.is_saturated()
+                || self.false_positives.contains(key)
+                || (Xxh3Builder::new().with_seed(self.seed).hash_one(key) as f64)
+                    < self.fp_rate * 2f64.powi(64))
+    }
+}
+
+impl<K> Default for FlakyFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> QueryFilter<K> for FlakyFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.keys.contains(key) || self.is_false_positive(key)
+    }
+}
+
+impl<K> InsertableQueryFilter<K> for FlakyFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn insert(&mut self, key: K) {
+        self.keys.insert(key);
+    }
+}
+
+impl<K> RemovableQueryFilter<K> for FlakyFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.keys.remove(key);
+    }
+}
+
+impl<K> ClearableQueryFilter<K> for FlakyFilter<K>
+where
+    K: Eq + Hash,
+{
+    fn clear(&mut self) {
+        self.keys.clear();
+    }
+}
+
+/// Filter wrapper checking answers against an exact key set.
+///
+/// Inserted keys are tracked beside the wrapped filter, and every query is
+/// checked against them: a false negative (which no filter may produce)
+/// panics, and false positives are counted, giving the realized false
+/// positive rate. Meant for integration tests, to catch hashing or
+/// serialization bugs that silently break a filter's contract; tracking
+/// keys defeats the purpose of a filter outside of tests.
+#[derive(Debug)]
+pub struct ShadowFilter<K, F> {
+    filter: F,
+    keys: HashSet<K>,
+    negative_queries: Cell<usize>,
+    false_positives: Cell<usize>,
+}
+
+impl<K, F> ShadowFilter<K, F>
+where
+    K: Eq + Hash,
+    F: QueryFilter<K>,
+{
+    /// Wraps an empty filter.
+    pub fn new(filter: F) -> Self {
+        Self {
+            filter,
+            keys: HashSet::new(),
+            negative_queries: Cell::new(0),
+            false_positives: Cell::new(0),
+        }
+    }
+
+    /// Returns the wrapped filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Returns the wrapped filter, mutably, e.g. to replace it with a
+    /// deserialized copy before [`verify`](ShadowFilter::verify)ing it.
+    pub fn filter_mut(&mut self) -> &mut F {
+        &mut self.filter
+    }
+
+    /// Returns the wrapped filter, dropping the tracked keys.
+    pub fn into_inner(self) -> F {
+        self.filter
+    }
+
+    /// Returns the number of tracked keys.
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Returns `true` if no keys are tracked.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns the number of queries for keys not inserted.
+    pub fn negative_queries(&self) -> usize {
+        self.negative_queries.get()
+    }
+
+    /// Returns the number of queries for keys not inserted, answered
+    /// positively.
+    pub fn false_positives(&self) -> usize {
+        self.false_positives.get()
+    }
+
+    /// Returns the realized false positive rate, or `None` if no keys that
+    /// were not inserted were queried.
+    pub fn realized_fp_rate(&self) -> Option<f64> {
+        let queries = self.negative_queries();
+        (queries > 0).then(|| self.false_positives() as f64 / queries as f64)
+    }
+
+    /// Resets the query counts, keeping the tracked keys.
+    pub fn reset_counts(&self) {
+        self.negative_queries.set(0);
+        self.false_positives.set(0);
+    }
+
+    /// Checks that the filter contains every tracked key.
+    ///
+    /// # Panics
+    ///
+    /// Panics on the first tracked key the filter does not contain.
+    pub fn verify(&self)
+    where
+        K: Debug,
+    {
+        for key in &self.keys {
+            assert!(self.filter.contains(key), "false negative for key {key:?}");
+        }
+    }
+}
+
+impl<K, F> QueryFilter<K> for ShadowFilter<K, F>
+where
+    K: Eq + Hash,
+    F: QueryFilter<K>,
+{
+    /// Queries the wrapped filter, checking the answer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the filter does not contain an inserted key.
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        let found = self.filter.contains(key);
+        if self.keys.contains(key) {
+            assert!(found, "false negative for an inserted key");
+        } else {
+            self.negative_queries.set(self.negative_queries.get() + 1);
+            self.false_positives
+                .set(self.false_positives.get() + found as usize);
+        }
+        found
+    }
+}
+
+impl<K, F> InsertableQueryFilter<K> for ShadowFilter<K, F>
+where
+    K: Eq + Hash + Clone,
+    F: InsertableQueryFilter<K>,
+{
+    fn insert(&mut self, key: K) {
+        self.keys.insert(key.clone());
+        self.filter.insert(key);
+    }
+}
+
+impl<K, F> RemovableQueryFilter<K> for ShadowFilter<K, F>
+where
+    K: Eq + Hash,
+    F: RemovableQueryFilter<K>,
+{
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.keys.remove(key);
+        self.filter.remove(key);
+    }
+}
+
+impl<K, F> ClearableQueryFilter<K> for ShadowFilter<K, F>
+where
+    K: Eq + Hash,
+    F: ClearableQueryFilter<K>,
+{
+    fn clear(&mut self) {
+        self.keys.clear();
+        self.filter.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arbitrary_params_are_valid() {
+        let data = (0..4096).map(|i| (i * 37 % 251) as u8).collect::<Vec<_>>();
+        let mut u = Unstructured::new(&data);
+        while !u.is_empty() {
+            let params = FilterParams::arbitrary(&mut u).unwrap();
+            assert!((1..=MAX_CAPACITY).contains(&params.capacity));
+            assert!((MIN_FP_RATE..=MAX_FP_RATE).contains(&params.fp_rate));
+        }
+    }
+}
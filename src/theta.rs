@@ -0,0 +1,564 @@
+//! Theta sketch for set expressions over distinct counts.
+//!
+//! A theta sketch keeps the hashes of distinct items that fall below a
+//! threshold `theta`, which is lowered as the sketch fills up, so that at
+//! most `k` hashes are kept. Retained hashes are a uniform sample of the
+//! distinct items, at sampling rate `theta`, so the number of distinct items
+//! is estimated as `retained / theta`. Unlike bottom-k sketches, sketches
+//! with different `theta` can be combined: union, intersection and
+//! difference are computed over hashes below the smallest `theta` of the
+//! inputs, and the result is itself a sketch. See [Theta Sketch Framework,
+//! 2016][1].
+//!
+//! Hashing and the compact serialization format follow the [Apache
+//! DataSketches][2] library, so sketches can be exchanged with its Java, C++
+//! and Python implementations, as long as the same seed is used.
+//!
+//! [1]: https://arxiv.org/abs/1611.00426
+//! [2]: https://datasketches.apache.org
+
+use {
+    crate::{QueryFilterError, QueryFilterResult},
+    std::collections::BTreeSet,
+};
+
+/// Seed used by DataSketches, unless configured otherwise.
+pub const DEFAULT_SEED: u64 = 9001;
+
+/// Largest `theta`, as a 63-bit threshold, i.e. sampling rate of one.
+const MAX_THETA: u64 = i64::MAX as u64;
+
+/// Serialization version of compact sketches.
+const SERIAL_VERSION: u8 = 3;
+
+/// DataSketches family id of compact sketches.
+const FAMILY_COMPACT: u8 = 3;
+
+const FLAG_READ_ONLY: u8 = 1 << 1;
+const FLAG_EMPTY: u8 = 1 << 2;
+const FLAG_COMPACT: u8 = 1 << 3;
+const FLAG_ORDERED: u8 = 1 << 4;
+const FLAG_SINGLE_ITEM: u8 = 1 << 5;
+
+/// Value that can be added to a theta sketch.
+///
+/// Values are hashed the way DataSketches hashes them: integers widened to
+/// 64 bits, floating point numbers canonicalized (`-0.0` to `0.0`, and all
+/// NaNs to one), strings by their UTF-8 bytes. Empty strings and byte
+/// slices are ignored.
+pub trait ThetaItem {
+    /// Returns the bytes to hash, or `None` if the value is to be ignored.
+    fn theta_bytes(&self) -> Option<Vec<u8>>;
+}
+
+macro_rules! impl_theta_item_for_int {
+    ($($int:ty),*) => {
+        $(
+            impl ThetaItem for $int {
+                fn theta_bytes(&self) -> Option<Vec<u8>> {
+                    Some((*self as i64).to_le_bytes().to_vec())
+                }
+            }
+        )*
+    };
+}
+
+impl_theta_item_for_int!(i8, i16, i32, i64, u8, u16, u32, u64);
+
+impl ThetaItem for f64 {
+    fn theta_bytes(&self) -> Option<Vec<u8>> {
+        let bits = match *self {
+            value if value.is_nan() => 0x7ff8000000000000,
+            0.0 => 0,
+            value => value.to_bits(),
+        };
+        Some(bits.to_le_bytes().to_vec())
+    }
+}
+
+impl ThetaItem for [u8] {
+    fn theta_bytes(&self) -> Option<Vec<u8>> {
+        (!self.is_empty()).then(|| self.to_vec())
+    }
+}
+
+impl ThetaItem for Vec<u8> {
+    fn theta_bytes(&self) -> Option<Vec<u8>> {
+        self.as_slice().theta_bytes()
+    }
+}
+
+impl ThetaItem for str {
+    fn theta_bytes(&self) -> Option<Vec<u8>> {
+        self.as_bytes().theta_bytes()
+    }
+}
+
+impl ThetaItem for String {
+    fn theta_bytes(&self) -> Option<Vec<u8>> {
+        self.as_bytes().theta_bytes()
+    }
+}
+
+/// Updatable theta sketch, keeping up to `k = 2^lg_k` hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThetaSketch {
+    hashes: BTreeSet<u64>,
+    theta: u64,
+    lg_k: u8,
+    seed: u64,
+    empty: bool,
+}
+
+impl ThetaSketch {
+    /// Creates a new empty sketch, keeping up to `2^lg_k` hashes, using the
+    /// default DataSketches seed.
+    ///
+    /// The relative standard error of distinct count estimates is about
+    /// `1 / sqrt(2^lg_k)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lg_k` is not within `4..=26`.
+    pub fn new(lg_k: u8) -> Self {
+        Self::with_seed(lg_k, DEFAULT_SEED)
+    }
+
+    /// Creates a new empty sketch, keeping up to `2^lg_k` hashes, with a
+    /// given hash seed.
+    ///
+    /// Only sketches built with the same seed can be combined.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lg_k` is not within `4..=26`.
+    pub fn with_seed(lg_k: u8, seed: u64) -> Self {
+        assert!((4..=26).contains(&lg_k), "lg_k must be within 4..=26");
+        Self {
+            hashes: BTreeSet::new(),
+            theta: MAX_THETA,
+            lg_k,
+            seed,
+            empty: true,
+        }
+    }
+
+    /// Returns the base-2 logarithm of the maximum number of hashes kept.
+    pub fn lg_k(&self) -> u8 {
+        self.lg_k
+    }
+
+    /// Adds a value to the sketched stream.
+    pub fn insert<Q: ThetaItem + ?Sized>(&mut self, value: &Q) {
+        let Some(bytes) = value.theta_bytes() else {
+            return;
+        };
+        self.empty = false;
+        let hash = murmur3(&bytes, self.seed)[0] >> 1;
+        if hash == 0 || hash >= self.theta || !self.hashes.insert(hash) {
+            return;
+        }
+        if self.hashes.len() > 1 << self.lg_k {
+            self.theta = self.hashes.pop_last().unwrap();
+        }
+    }
+
+    /// Returns the estimated number of distinct values in the stream.
+    ///
+    /// Exact while fewer than `2^lg_k` distinct values have been seen.
+    pub fn estimate(&self) -> f64 {
+        estimate(self.hashes.len(), self.theta)
+    }
+
+    /// Returns the approximate lower bound of the number of distinct values,
+    /// at a given number of standard deviations.
+    pub fn lower_bound(&self, std_devs: f64) -> f64 {
+        lower_bound(self.hashes.len(), self.theta, std_devs)
+    }
+
+    /// Returns the approximate upper bound of the number of distinct values,
+    /// at a given number of standard deviations.
+    pub fn upper_bound(&self, std_devs: f64) -> f64 {
+        upper_bound(self.hashes.len(), self.theta, std_devs)
+    }
+
+    /// Returns `true` if no value has been inserted.
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    /// Returns `true` if the estimate is no longer exact.
+    pub fn is_estimation_mode(&self) -> bool {
+        self.theta < MAX_THETA
+    }
+
+    /// Returns the current sampling rate, within `(0, 1]`.
+    pub fn theta(&self) -> f64 {
+        self.theta as f64 / MAX_THETA as f64
+    }
+
+    /// Returns the number of hashes currently kept.
+    pub fn retained(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns the compact (read-only) form of the sketch, the input to set
+    /// operations and serialization.
+    pub fn compact(&self) -> CompactThetaSketch {
+        CompactThetaSketch {
+            hashes: self.hashes.iter().copied().collect(),
+            theta: self.theta,
+            seed_hash: seed_hash(self.seed),
+            empty: self.empty,
+        }
+    }
+}
+
+/// Read-only theta sketch: the result of set operations, and the serialized
+/// form of sketches.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompactThetaSketch {
+    /// Retained hashes, in ascending order.
+    hashes: Vec<u64>,
+    theta: u64,
+    seed_hash: u16,
+    empty: bool,
+}
+
+impl CompactThetaSketch {
+    /// Returns the estimated number of distinct values.
+    pub fn estimate(&self) -> f64 {
+        estimate(self.hashes.len(), self.theta)
+    }
+
+    /// Returns the approximate lower bound of the number of distinct values,
+    /// at a given number of standard deviations.
+    pub fn lower_bound(&self, std_devs: f64) -> f64 {
+        lower_bound(self.hashes.len(), self.theta, std_devs)
+    }
+
+    /// Returns the approximate upper bound of the number of distinct values,
+    /// at a given number of standard deviations.
+    pub fn upper_bound(&self, std_devs: f64) -> f64 {
+        upper_bound(self.hashes.len(), self.theta, std_devs)
+    }
+
+    /// Returns `true` if the sketched stream had no values.
+    pub fn is_empty(&self) -> bool {
+        self.empty
+    }
+
+    /// Returns `true` if the estimate is not exact.
+    pub fn is_estimation_mode(&self) -> bool {
+        self.theta < MAX_THETA
+    }
+
+    /// Returns the sampling rate, within `(0, 1]`.
+    pub fn theta(&self) -> f64 {
+        self.theta as f64 / MAX_THETA as f64
+    }
+
+    /// Returns the number of retained hashes.
+    pub fn retained(&self) -> usize {
+        self.hashes.len()
+    }
+
+    /// Returns the sketch of values present in both sketched streams.
+    ///
+    /// Fails if the sketches were built with different seeds.
+    pub fn intersection(&self, other: &Self) -> QueryFilterResult<Self> {
+        self.check_compatible(other)?;
+        let theta = self.theta.min(other.theta);
+        let other_hashes = other.hashes.iter().collect::<BTreeSet<_>>();
+        Ok(Self {
+            hashes: self
+                .hashes
+                .iter()
+                .copied()
+                .filter(|hash| *hash < theta && other_hashes.contains(hash))
+                .collect(),
+            theta,
+            seed_hash: self.seed_hash,
+            empty: self.empty || other.empty,
+        })
+    }
+
+    /// Returns the sketch of values present in this sketched stream, but not
+    /// in the other one.
+    ///
+    /// Fails if the sketches were built with different seeds.
+    pub fn difference(&self, other: &Self) -> QueryFilterResult<Self> {
+        self.check_compatible(other)?;
+        if other.empty {
+            return Ok(self.clone());
+        }
+        let theta = self.theta.min(other.theta);
+        let other_hashes = other.hashes.iter().collect::<BTreeSet<_>>();
+        Ok(Self {
+            hashes: self
+                .hashes
+                .iter()
+                .copied()
+                .filter(|hash| *hash < theta && !other_hashes.contains(hash))
+                .collect(),
+            theta,
+            seed_hash: self.seed_hash,
+            empty: self.empty,
+        })
+    }
+
+    /// Encodes the sketch in the DataSketches compact format (serialization
+    /// version 3, ordered).
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let empty = self.empty || (self.hashes.is_empty() && self.theta == MAX_THETA);
+        let single_item = self.hashes.len() == 1 && self.theta == MAX_THETA;
+        let mut flags = FLAG_READ_ONLY | FLAG_COMPACT | FLAG_ORDERED;
+        let preamble_longs = if empty {
+            flags |= FLAG_EMPTY;
+            1
+        } else if single_item {
+            flags |= FLAG_SINGLE_ITEM;
+            1
+        } else if self.theta == MAX_THETA {
+            2
+        } else {
+            3
+        };
+
+        let mut bytes = Vec::with_capacity(8 * (preamble_longs + self.hashes.len()));
+        bytes.extend_from_slice(&[
+            preamble_longs as u8,
+            SERIAL_VERSION,
+            FAMILY_COMPACT,
+            0,
+            0,
+            flags,
+        ]);
+        bytes.extend_from_slice(&self.seed_hash.to_le_bytes());
+        if empty {
+            return bytes;
+        }
+        if preamble_longs > 1 {
+            bytes.extend_from_slice(&(self.hashes.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&1f32.to_le_bytes());
+        }
+        if preamble_longs > 2 {
+            bytes.extend_from_slice(&self.theta.to_le_bytes());
+        }
+        bytes.extend(self.hashes.iter().flat_map(|hash| hash.to_le_bytes()));
+        bytes
+    }
+
+    /// Decodes a sketch encoded by DataSketches (or [`to_bytes`]), in the
+    /// compact format of serialization version 3.
+    ///
+    /// Fails if the data is truncated, or not a version 3 compact sketch.
+    ///
+    /// [`to_bytes`]: CompactThetaSketch::to_bytes
+    pub fn from_bytes(bytes: &[u8]) -> QueryFilterResult<Self> {
+        let invalid =
+            |reason: &str| QueryFilterError::Other(format!("invalid theta sketch: {reason}"));
+        let u64_at = |at: usize| {
+            bytes
+                .get(at..at + 8)
+                .map(|long| u64::from_le_bytes(long.try_into().unwrap()))
+                .ok_or_else(|| invalid("truncated"))
+        };
+        let preamble = u64_at(0)?.to_le_bytes();
+        if preamble[1] != SERIAL_VERSION {
+            return Err(invalid(&format!(
+                "unsupported serial version {}",
+                preamble[1]
+            )));
+        }
+        if preamble[2] != FAMILY_COMPACT {
+            return Err(invalid(&format!("unsupported family {}", preamble[2])));
+        }
+        let preamble_longs = (preamble[0] & 0x3f) as usize;
+        let flags = preamble[5];
+        let seed_hash = u16::from_le_bytes([preamble[6], preamble[7]]);
+
+        let mut sketch = Self {
+            hashes: Vec::new(),
+            theta: MAX_THETA,
+            seed_hash,
+            empty: flags & FLAG_EMPTY != 0,
+        };
+        if sketch.empty {
+            return Ok(sketch);
+        }
+        let count = match preamble_longs {
+            1 => 1,
+            2 | 3 => u64_at(8)? as u32 as usize,
+            _ => return Err(invalid(&format!("unsupported preamble {preamble_longs}"))),
+        };
+        if preamble_longs == 3 {
+            sketch.theta = u64_at(16)?;
+        }
+        sketch.hashes = (0..count)
+            .map(|i| u64_at(8 * (preamble_longs + i)))
+            .collect::<QueryFilterResult<_>>()?;
+        if flags & FLAG_ORDERED == 0 {
+            sketch.hashes.sort_unstable();
+        }
+        Ok(sketch)
+    }
+
+    fn check_compatible(&self, other: &Self) -> QueryFilterResult<()> {
+        if self.seed_hash != other.seed_hash {
+            return Err(QueryFilterError::IncompatibleFilters("different seeds"));
+        }
+        Ok(())
+    }
+}
+
+/// Union of theta sketches, keeping up to `k = 2^lg_k` hashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThetaUnion {
+    sketch: ThetaSketch,
+}
+
+impl ThetaUnion {
+    /// Creates a new empty union, keeping up to `2^lg_k` hashes, for sketches
+    /// using the default DataSketches seed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lg_k` is not within `4..=26`.
+    pub fn new(lg_k: u8) -> Self {
+        Self::with_seed(lg_k, DEFAULT_SEED)
+    }
+
+    /// Creates a new empty union, keeping up to `2^lg_k` hashes, for sketches
+    /// using a given hash seed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `lg_k` is not within `4..=26`.
+    pub fn with_seed(lg_k: u8, seed: u64) -> Self {
+        Self {
+            sketch: ThetaSketch::with_seed(lg_k, seed),
+        }
+    }
+
+    /// Adds a sketch to the union.
+    ///
+    /// Fails if the sketch was built with a different seed.
+    pub fn update(&mut self, sketch: &CompactThetaSketch) -> QueryFilterResult<()> {
+        if sketch.seed_hash != seed_hash(self.sketch.seed) {
+            return Err(QueryFilterError::IncompatibleFilters("different seeds"));
+        }
+        let union = &mut self.sketch;
+        union.empty &= sketch.empty;
+        union.theta = union.theta.min(sketch.theta);
+        let theta = union.theta;
+        union.hashes.retain(|&hash| hash < theta);
+        for &hash in sketch.hashes.iter().take_while(|&&hash| hash < theta) {
+            union.hashes.insert(hash);
+        }
+        while union.hashes.len() > 1 << union.lg_k {
+            union.theta = union.hashes.pop_last().unwrap();
+        }
+        Ok(())
+    }
+
+    /// Returns the sketch of the union of all added sketches' streams.
+    pub fn result(&self) -> CompactThetaSketch {
+        self.sketch.compact()
+    }
+}
+
+/// Returns the number of distinct values estimated from `count` hashes kept
+/// below `theta`.
+fn estimate(count: usize, theta: u64) -> f64 {
+    count as f64 / (theta as f64 / MAX_THETA as f64)
+}
+
+/// Returns the standard deviation of the estimate, with the number of kept
+/// hashes approximately binomial with rate `theta`.
+fn std_dev(count: usize, theta: u64) -> f64 {
+    let theta = theta as f64 / MAX_THETA as f64;
+    (count as f64 * (1. - theta)).sqrt() / theta
+}
+
+fn lower_bound(count: usize, theta: u64, std_devs: f64) -> f64 {
+    (estimate(count, theta) - std_devs * std_dev(count, theta)).max(count as f64)
+}
+
+fn upper_bound(count: usize, theta: u64, std_devs: f64) -> f64 {
+    // With nothing kept, the estimate (zero) says nothing about the spread,
+    // so assume a single value was kept.
+    let spread = std_dev(count.max(1), theta);
+    estimate(count, theta) + std_devs * spread
+}
+
+/// Returns the 16-bit hash of a seed, stored in serialized sketches to catch
+/// combining sketches built with different seeds.
+fn seed_hash(seed: u64) -> u16 {
+    murmur3(&seed.to_le_bytes(), 0)[0] as u16
+}
+
+/// MurmurHash3 (x64, 128-bit), seeding both halves with the full 64-bit seed,
+/// as DataSketches does.
+fn murmur3(data: &[u8], seed: u64) -> [u64; 2] {
+    const C1: u64 = 0x87c37b91114253d5;
+    const C2: u64 = 0x4cf5ad432745937f;
+    let mix1 = |k: u64| k.wrapping_mul(C1).rotate_left(31).wrapping_mul(C2);
+    let mix2 = |k: u64| k.wrapping_mul(C2).rotate_left(33).wrapping_mul(C1);
+    let fmix = |mut k: u64| {
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xff51afd7ed558ccd);
+        k ^= k >> 33;
+        k = k.wrapping_mul(0xc4ceb9fe1a85ec53);
+        k ^ (k >> 33)
+    };
+
+    let (mut h1, mut h2) = (seed, seed);
+    let mut chunks = data.chunks_exact(16);
+    for chunk in &mut chunks {
+        let k1 = u64::from_le_bytes(chunk[..8].try_into().unwrap());
+        let k2 = u64::from_le_bytes(chunk[8..].try_into().unwrap());
+        h1 ^= mix1(k1);
+        h1 = h1
+            .rotate_left(27)
+            .wrapping_add(h2)
+            .wrapping_mul(5)
+            .wrapping_add(0x52dce729);
+        h2 ^= mix2(k2);
+        h2 = h2
+            .rotate_left(31)
+            .wrapping_add(h1)
+            .wrapping_mul(5)
+            .wrapping_add(0x38495ab5);
+    }
+
+    let tail = chunks.remainder();
+    let le = |bytes: &[u8]| bytes.iter().rev().fold(0, |k, &byte| k << 8 | byte as u64);
+    if tail.len() > 8 {
+        h2 ^= mix2(le(&tail[8..]));
+    }
+    if !tail.is_empty() {
+        h1 ^= mix1(le(&tail[..tail.len().min(8)]));
+    }
+
+    h1 ^= data.len() as u64;
+    h2 ^= data.len() as u64;
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    h1 = fmix(h1);
+    h2 = fmix(h2);
+    h1 = h1.wrapping_add(h2);
+    h2 = h2.wrapping_add(h1);
+    [h1, h2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_seed_hash() {
+        // Seed hash of the default seed, as found in DataSketches' sketches.
+        assert_eq!(seed_hash(DEFAULT_SEED), 0x93cc);
+    }
+}
@@ -0,0 +1,156 @@
+//! Approximate deletion for plain filters, with a tombstone filter.
+//!
+//! Plain Bloom filters cannot forget keys, and counting filters pay for
+//! deletion with several times the memory, all the time. [`WithTombstones`]
+//! pairs a filter with a second Bloom filter of removed keys (tombstones):
+//! removing a key inserts it there, and queries subtract it. Memory for
+//! deletion is only spent on keys actually removed, and is reclaimed by
+//! compacting, i.e. rebuilding the primary filter from the live keys.
+//!
+//! Deletion is approximate: a false positive of the tombstone filter makes a
+//! live key look removed, so unlike the primary filter, the combination may
+//! return false negatives, at the tombstone filter's false positive rate. A
+//! removed key also stays removed when inserted again, until compaction.
+
+use {
+    crate::{
+        hash::ProbeHasher,
+        BloomFilter,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+        RemovableQueryFilter,
+    },
+    hash_iter::HashIterHasher,
+    std::{borrow::Borrow, hash::Hash},
+};
+
+/// Filter combined with a filter of removed keys.
+pub struct WithTombstones<F, K, H = ProbeHasher>
+where
+    K: Eq + Hash,
+{
+    filter: F,
+    tombstones: BloomFilter<K, H>,
+    removed: usize,
+    capacity: usize,
+}
+
+impl<F, K> WithTombstones<F, K>
+where
+    F: QueryFilter<K>,
+    K: Eq + Hash,
+{
+    /// Wraps a filter, with a tombstone filter sized for a desired number of
+    /// removed keys and false positive rate.
+    pub fn new(filter: F, capacity: usize, fp_rate: f64) -> Self {
+        Self::with_hasher(filter, capacity, fp_rate, ProbeHasher::default())
+    }
+}
+
+impl<F, K, H> WithTombstones<F, K, H>
+where
+    F: QueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Wraps a filter, with a tombstone filter sized for a desired number of
+    /// removed keys and false positive rate, and a given hasher.
+    pub fn with_hasher(filter: F, capacity: usize, fp_rate: f64, hasher: H) -> Self {
+        Self {
+            filter,
+            tombstones: BloomFilter::with_capacity_and_hasher(capacity, fp_rate, hasher),
+            removed: 0,
+            capacity,
+        }
+    }
+
+    /// Returns the wrapped filter.
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    /// Returns the number of removals since the last compaction.
+    pub fn tombstone_count(&self) -> usize {
+        self.removed
+    }
+
+    /// Returns `true` once the tombstone filter holds its capacity of keys,
+    /// past which its false positives (i.e. live keys reported as removed)
+    /// grow beyond the configured rate.
+    pub fn needs_compaction(&self) -> bool {
+        self.removed >= self.capacity
+    }
+
+    /// Returns the estimated rate at which live keys are reported as removed.
+    pub fn tombstone_fp_rate(&self) -> f64 {
+        self.tombstones.approx_fp_rate()
+    }
+}
+
+impl<F, K, H> WithTombstones<F, K, H>
+where
+    F: InsertableQueryFilter<K> + ClearableQueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Rebuilds the wrapped filter from the live keys (e.g. read from the
+    /// source of truth), and drops all tombstones.
+    pub fn compact(&mut self, live_keys: impl IntoIterator<Item = K>) {
+        self.filter.clear();
+        for key in live_keys {
+            self.filter.insert(key);
+        }
+        self.tombstones.clear();
+        self.removed = 0;
+    }
+}
+
+impl<F, K, H> QueryFilter<K> for WithTombstones<F, K, H>
+where
+    F: QueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    fn contains<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.filter.contains(key) && !self.tombstones.contains(key)
+    }
+}
+
+impl<F, K, H> InsertableQueryFilter<K> for WithTombstones<F, K, H>
+where
+    F: InsertableQueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Inserts a key into the wrapped filter.
+    ///
+    /// A key removed since the last compaction stays reported as absent.
+    fn insert(&mut self, key: K) {
+        self.filter.insert(key);
+    }
+}
+
+impl<F, K, H> RemovableQueryFilter<K> for WithTombstones<F, K, H>
+where
+    F: QueryFilter<K>,
+    K: Eq + Hash,
+    H: HashIterHasher<u64>,
+{
+    /// Records a tombstone for the key, unless it is already reported as
+    /// absent.
+    fn remove<Q>(&mut self, key: &Q)
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        if self.contains(key) {
+            self.tombstones.insert_borrowed(key);
+            self.removed += 1;
+        }
+    }
+}
@@ -0,0 +1,111 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{AgingBloomFilter, ClearableQueryFilter, InsertableQueryFilter, QueryFilter};
+
+#[test]
+fn keys_expire() {
+    let mut filter = AgingBloomFilter::new(60, 1000, 0.01);
+    assert_eq!(filter.generation_count(), 4);
+    assert_eq!(filter.generation_span(), 20);
+
+    filter.insert("a");
+    filter.advance(30);
+    filter.insert("b");
+    filter.advance(65);
+    // Remembered for at least the TTL.
+    assert!(filter.contains("a"));
+    assert!(filter.contains("b"));
+    filter.advance(80);
+    assert!(!filter.contains("a"));
+    assert!(filter.contains("b"));
+    filter.advance(100);
+    assert!(!filter.contains("b"));
+
+    // Time never moves back.
+    filter.advance(10);
+    assert_eq!(filter.generation_start(), 100);
+
+    // Inserting again renews the TTL.
+    filter.insert("c");
+    filter.tick();
+    filter.insert("c");
+    filter.tick();
+    filter.tick();
+    filter.tick();
+    assert_eq!(filter.generation_start(), 180);
+    assert!(filter.contains("c"));
+    filter.tick();
+    assert!(!filter.contains("c"));
+}
+
+#[test]
+fn long_gaps() {
+    let mut filter = AgingBloomFilter::with_params(10, 1000, 0.01, 2).unwrap();
+    assert_eq!(filter.generation_span(), 10);
+    for key in 0..1000u64 {
+        filter.insert(key);
+    }
+    filter.advance(u64::MAX);
+    assert!((0..1000u64).all(|key| !filter.contains(&key)));
+    assert_eq!(filter.generation_start() % 10, 0);
+
+    filter.insert(1);
+    assert!(filter.contains(&1));
+    filter.clear();
+    assert!(!filter.contains(&1));
+}
+
+#[test]
+fn fp_rate() {
+    let mut filter = AgingBloomFilter::new(100, 10_000, 0.01);
+    for key in 0..10_000u64 {
+        filter.advance(key / 100);
+        filter.insert(key);
+    }
+    let fp_count = (10_000..110_000u64)
+        .filter(|key| filter.contains(key))
+        .count();
+    assert!(fp_count < 1500, "fp_count: {fp_count}");
+}
+
+#[test]
+fn invalid_params() {
+    assert!(AgingBloomFilter::<u64>::with_params(0, 1000, 0.01, 4).is_err());
+    assert!(AgingBloomFilter::<u64>::with_params(60, 1000, 0.01, 1).is_err());
+    assert!(AgingBloomFilter::<u64>::with_params(60, 1000, 1.5, 4).is_err());
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn spills_cold_generations() {
+    let dir = std::env::temp_dir().join(format!("mqfilters-{}-aging", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut filter = AgingBloomFilter::with_params(60, 1000, 0.01, 4).unwrap();
+    filter.insert("a");
+    filter.tick();
+    filter.insert("b");
+    assert!(filter.spill_cold_generations(0, &dir).is_err());
+    filter.spill_cold_generations(1, &dir).unwrap();
+    assert!(filter.spill_cold_generations(1, &dir).is_err());
+    assert_eq!(filter.generation_count(), 4);
+    assert_eq!(filter.cold_generation_count(), 3);
+    assert!(filter.contains("a"));
+    assert!(filter.contains("b"));
+
+    // Generations turn cold as they age, and expire as before.
+    filter.insert("c");
+    filter.tick();
+    assert!(["a", "b", "c"].iter().all(|key| filter.contains(key)));
+    filter.tick();
+    assert!(filter.contains("a"));
+    filter.tick();
+    assert!(!filter.contains("a"));
+    assert!(filter.contains("b"));
+    filter.tick();
+    assert!(!filter.contains("b"));
+    assert_eq!(filter.cold_generation_count(), 3);
+
+    filter.clear();
+    assert!(!filter.contains("c"));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "atomic")]
+
+use {
+    mqfilters::{AtomicBloomFilter, QueryFilter},
+    std::{sync::atomic::AtomicU64, thread},
+};
+
+#[test]
+fn shared_region() {
+    let words = (0..AtomicBloomFilter::<u64>::word_count(40000, 0.01))
+        .map(|_| AtomicU64::new(0))
+        .collect::<Vec<_>>();
+
+    // Concurrent writers over the same region.
+    thread::scope(|scope| {
+        for writer in 0..4u64 {
+            let words = &words;
+            scope.spawn(move || {
+                let filter = AtomicBloomFilter::<u64>::new(words, 40000);
+                for i in 0..10000 {
+                    filter.insert(&(writer * 10000 + i));
+                }
+            });
+        }
+    });
+
+    let reader = AtomicBloomFilter::<u64>::new(&words, 40000);
+    for i in 0..40000 {
+        assert!(reader.contains(&i));
+    }
+    let fp_count = (40000..80000).filter(|i| reader.contains(i)).count();
+    assert!((fp_count as f64) < 40000. * 0.01 * 1.5);
+
+    reader.clear();
+    assert!(!reader.contains(&0));
+}
+
+#[test]
+fn owned_words() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<AtomicBloomFilter<std::rc::Rc<u64>>>();
+
+    let filter = AtomicBloomFilter::<u64>::with_capacity(40000, 0.01);
+    thread::scope(|scope| {
+        for writer in 0..4u64 {
+            let filter = &filter;
+            scope.spawn(move || {
+                for i in 0..10000 {
+                    filter.insert(&(writer * 10000 + i));
+                }
+            });
+        }
+    });
+
+    for i in 0..40000 {
+        assert!(filter.contains(&i));
+    }
+    let fp_count = (40000..80000).filter(|i| filter.contains(i)).count();
+    assert!((fp_count as f64) < 40000. * 0.01 * 1.5);
+}
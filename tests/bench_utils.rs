@@ -0,0 +1,86 @@
+#![cfg(all(feature = "bench_utils", feature = "bf"))]
+
+use {
+    mqfilters::{
+        bench_utils::{Access, KeyGen, Workload, Zipf},
+        profiler::FpProfiler,
+        BloomFilter,
+        InsertableQueryFilter,
+    },
+    std::collections::HashSet,
+};
+
+#[test]
+fn generators_are_reproducible() {
+    assert_eq!(
+        KeyGen::new(7).uniform_u64(100),
+        KeyGen::new(7).uniform_u64(100)
+    );
+    assert_ne!(
+        KeyGen::new(7).uniform_u64(100),
+        KeyGen::new(8).uniform_u64(100)
+    );
+    assert_eq!(
+        KeyGen::new(7).strings(100, 4..=16),
+        KeyGen::new(7).strings(100, 4..=16)
+    );
+}
+
+#[test]
+fn disjoint_sets() {
+    let (inserted, holdout) = KeyGen::new(1).disjoint_u64(10000, 5000);
+    let all = inserted.iter().chain(&holdout).collect::<HashSet<_>>();
+    assert_eq!(all.len(), 15000);
+
+    let mut filter = BloomFilter::new(10000, 0.01);
+    for &key in &inserted {
+        filter.insert(key);
+    }
+    let report = FpProfiler::new(0.01).measure(&filter, &inserted, &holdout);
+    assert_eq!(report.false_negatives, 0);
+    assert!(!report.is_regression());
+}
+
+#[test]
+fn strings() {
+    let strings = KeyGen::new(1).strings(1000, 2..=3);
+    assert_eq!(strings.iter().collect::<HashSet<_>>().len(), 1000);
+    assert!(strings.iter().all(|s| (2..=3).contains(&s.len())));
+}
+
+#[test]
+fn zipfian() {
+    let zipf = Zipf::new(1000, 1.);
+    let mut gen = KeyGen::new(1);
+    let mut counts = [0; 1000];
+    for _ in 0..100_000 {
+        counts[zipf.sample(&mut gen)] += 1;
+    }
+    // Rank 1 is drawn about twice as often as rank 2, and ~13% overall.
+    assert!((12000..14500).contains(&counts[0]), "{}", counts[0]);
+    assert!(counts[0] > counts[1] * 3 / 2);
+
+    let keys = KeyGen::new(1).zipfian_u64(1000, 1.2, 10000);
+    assert!(keys.iter().collect::<HashSet<_>>().len() < 1000);
+}
+
+#[test]
+fn workload() {
+    let workload = Workload {
+        operations: 100_000,
+        read_fraction: 0.8,
+        negative_fraction: 0.5,
+        access: Access::Zipfian(0.99),
+        seed: 42,
+    };
+    let mut filter = BloomFilter::new(30000, 0.01);
+    let report = workload.run(&mut filter);
+    assert_eq!(
+        report.inserts + report.positive_reads + report.negative_reads,
+        100_000
+    );
+    assert!((15000..25000).contains(&report.inserts));
+    let fp_rate = report.fp_rate().unwrap();
+    assert!(fp_rate < 0.02, "fp_rate: {fp_rate}");
+    assert!(report.ops_per_sec() > 0.);
+}
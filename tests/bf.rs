@@ -1,4 +1,20 @@
-use mqfilters::{BloomFilter, ClearableQueryFilter, InsertableQueryFilter, QueryFilter};
+#![cfg(feature = "bf")]
+
+use {
+    hash_iter::DoubleHashHasher,
+    mqfilters::{
+        hash::{ProbeHasher, ProbeStrategy},
+        BloomFilter,
+        ClearableQueryFilter,
+        FilterStats,
+        FreezableQueryFilter,
+        InsertableQueryFilter,
+        MergeableQueryFilter,
+        QueryFilter,
+        QueryFilterError,
+    },
+    std::io,
+};
 
 #[test]
 fn default_filter() {
@@ -37,7 +53,7 @@ fn with_size() {
     }
 
     assert!(items_cnt - filter.approx_current_capacity() < 100);
-    assert!((fp_count as f64) < items_cnt as f64 * fp_rate as f64);
+    assert!((fp_count as f64) < items_cnt as f64 * fp_rate);
 }
 
 #[test]
@@ -56,5 +72,564 @@ fn with_capacity() {
     }
 
     assert!(capacity - filter.approx_current_capacity() < 100);
-    assert!((fp_count as f64) < capacity as f64 * fp_rate as f64);
+    assert!((fp_count as f64) < capacity as f64 * fp_rate);
+}
+
+#[test]
+fn probe_strategies() {
+    let fp_rate = 0.001;
+    let capacity = 50000;
+    for strategy in [
+        ProbeStrategy::Double,
+        ProbeStrategy::EnhancedDouble,
+        ProbeStrategy::Triple,
+    ] {
+        let hasher = ProbeHasher::new(strategy);
+        let mut filter = BloomFilter::with_capacity_and_hasher(capacity, fp_rate, hasher);
+        assert_eq!(filter.hasher().strategy(), strategy);
+
+        let mut fp_count = 0;
+        for i in 0..capacity {
+            if filter.contains(&i) {
+                fp_count += 1;
+            }
+            filter.insert(i);
+            assert!(filter.contains(&i));
+        }
+        assert!((fp_count as f64) < capacity as f64 * fp_rate);
+    }
+}
+
+#[test]
+fn hash_iter_hasher() {
+    // Any `HashIterHasher` can drive the filter.
+    let mut filter = BloomFilter::with_capacity_and_hasher(100, 0.01, DoubleHashHasher::new());
+    filter.insert("hello");
+    assert!(filter.contains(&"hello"));
+    assert!(!filter.contains(&"world"));
+}
+
+#[test]
+fn snapshot() {
+    let mut filter = BloomFilter::new(10000, 0.01).into_shared();
+    for i in 0..100 {
+        filter.insert(i);
+    }
+
+    let snapshot = filter.snapshot();
+    for i in 100..200 {
+        filter.insert(i);
+    }
+
+    for i in 0..200 {
+        assert!(filter.contains(&i));
+    }
+    for i in 0..100 {
+        assert!(snapshot.contains(&i));
+    }
+    assert_eq!(snapshot.approx_current_capacity(), 100);
+    assert_eq!(filter.approx_current_capacity(), 200);
+
+    filter.clear();
+    assert!(!filter.contains(&0));
+    assert!(snapshot.contains(&0));
+}
+
+#[test]
+fn freeze() {
+    let mut filter = BloomFilter::new(10000, 0.01);
+    for i in 0..10000 {
+        filter.insert(i);
+    }
+    let expected = (0..20000).map(|i| filter.contains(&i)).collect::<Vec<_>>();
+    let capacity = filter.approx_current_capacity();
+
+    let frozen = filter.freeze();
+    assert_eq!(frozen.approx_current_capacity(), capacity);
+    for (i, expected) in expected.into_iter().enumerate() {
+        assert_eq!(frozen.contains(&i), expected);
+    }
+
+    // Storage used while building does not matter.
+    let mut filter = BloomFilter::new(10000, 0.01).into_shared();
+    for i in 0..10000 {
+        filter.insert(i);
+    }
+    let frozen = filter.snapshot().freeze();
+    for i in 0..10000 {
+        assert!(frozen.contains(&i));
+    }
+}
+
+#[test]
+fn fold_union_with() {
+    let filter = |bit_count, keys| {
+        let mut filter = BloomFilter::with_bit_count(bit_count, 5);
+        for i in keys {
+            filter.insert(i);
+        }
+        filter
+    };
+    let mut small = filter(1 << 16, 0..5000);
+    let large = filter(1 << 18, 5000..10000);
+    let small_rate = small.approx_fp_rate();
+
+    // Folding the larger filter onto the smaller one, either way round.
+    let mut folded = filter(1 << 18, 5000..10000);
+    let rate = folded.fold_union_with(&small).unwrap();
+    assert!(rate > small_rate);
+    assert_eq!(rate, folded.approx_fp_rate());
+    small.fold_union_with(&large).unwrap();
+    for i in 0..10000 {
+        assert!(small.contains(&i));
+        assert!(folded.contains(&i));
+    }
+
+    let other_k = BloomFilter::with_bit_count(1 << 16, 6);
+    assert!(small.fold_union_with(&other_k).is_err());
+    let other_size = BloomFilter::with_bit_count(3 << 15, 5);
+    assert!(small.fold_union_with(&other_size).is_err());
+}
+
+#[test]
+fn builder_validates() {
+    let mut filter = BloomFilter::<u64>::builder(1000, 0.01).build().unwrap();
+    let expected = BloomFilter::<u64>::with_capacity(1000, 0.01);
+    assert_eq!(filter.bit_count(), expected.bit_count());
+    assert_eq!(filter.hash_count(), expected.hash_count());
+    filter.insert(42);
+    assert!(filter.contains(&42));
+
+    let hasher = ProbeHasher::new(ProbeStrategy::Triple);
+    let filter = BloomFilter::<u64>::builder(1000, 0.01)
+        .hasher(hasher)
+        .build()
+        .unwrap();
+    assert_eq!(filter.hasher(), &hasher);
+
+    let build = |capacity, fp_rate| BloomFilter::<u64>::builder(capacity, fp_rate).build().err();
+    assert_eq!(build(0, 0.01), Some(QueryFilterError::ZeroCapacity));
+    for fp_rate in [0., 1., -0.5, 2.] {
+        assert_eq!(
+            build(1000, fp_rate),
+            Some(QueryFilterError::InvalidFpRate(fp_rate))
+        );
+    }
+    assert!(matches!(
+        build(1000, f64::NAN),
+        Some(QueryFilterError::InvalidFpRate(_))
+    ));
+    assert!(matches!(
+        build(usize::MAX, 1e-9),
+        Some(QueryFilterError::TooLarge { .. })
+    ));
+}
+
+#[test]
+fn mergeable() {
+    let filter = |keys| {
+        let mut filter = BloomFilter::with_bit_count(1 << 16, 5);
+        for i in keys {
+            filter.insert(i);
+        }
+        filter
+    };
+    // Partial filters built by shards, merged into one.
+    let shards = [filter(0..3000), filter(3000..6000), filter(6000..9000)];
+    let mut merged = filter(0..0);
+    for shard in &shards {
+        merged.union_with(shard);
+    }
+    assert!((0..9000).all(|i| merged.contains(&i)));
+
+    let mut both = filter(0..6000);
+    both.try_intersect_with(&filter(3000..9000)).unwrap();
+    assert!((3000..6000).all(|i| both.contains(&i)));
+    let fp_count = (0..3000)
+        .chain(6000..9000)
+        .filter(|i| both.contains(i))
+        .count();
+    assert!(fp_count < 600, "fp_count: {fp_count}");
+
+    // Unlike the inherent union, sizes must match.
+    let other_size = BloomFilter::with_bit_count(1 << 17, 5);
+    assert!(matches!(
+        merged.try_union_with(&other_size),
+        Err(QueryFilterError::IncompatibleFilters(_))
+    ));
+    let other_k = BloomFilter::with_bit_count(1 << 16, 6);
+    assert!(merged.try_intersect_with(&other_k).is_err());
+}
+
+#[test]
+fn estimate_difference() {
+    let filter = |keys| {
+        let mut filter = BloomFilter::with_bit_count(1 << 18, 7);
+        for i in keys {
+            filter.insert(i);
+        }
+        filter
+    };
+    // |A \ B| = 10000, |B \ A| = 5000.
+    let a = filter(0..20000);
+    let b = filter(10000..25000);
+    let (a_only, b_only) = a.estimate_difference(&b).unwrap();
+    assert!(a_only.abs_diff(10000) < 500, "a_only: {a_only}");
+    assert!(b_only.abs_diff(5000) < 500, "b_only: {b_only}");
+    assert_eq!(a.estimate_difference(&a).unwrap(), (0, 0));
+
+    let other = BloomFilter::with_bit_count(1 << 17, 7);
+    assert!(a.estimate_difference(&other).is_err());
+}
+
+#[test]
+fn u64_batches() {
+    let mut batched = BloomFilter::new(100000, 0.01);
+    let mut single = BloomFilter::new(100000, 0.01);
+    let keys = (0..100000u64)
+        .map(|i| i.wrapping_mul(0x9e3779b97f4a7c15))
+        .collect::<Vec<_>>();
+    batched.insert_u64_batch(&keys);
+    for &key in &keys {
+        single.insert(key);
+    }
+
+    // Batches are interchangeable with single keys.
+    assert!(batched
+        .contains_u64_batch(&keys)
+        .into_iter()
+        .all(|found| found));
+    let others = (100000..200000u64).collect::<Vec<_>>();
+    let found = batched.contains_u64_batch(&others);
+    for (key, found) in others.iter().zip(found) {
+        assert_eq!(single.contains(key), found);
+        assert_eq!(batched.contains(key), found);
+    }
+    assert!(batched.contains_u64_batch(&[]).is_empty());
+}
+
+#[test]
+fn batches() {
+    let mut batched = BloomFilter::<String>::new(10000, 0.01);
+    let mut single = BloomFilter::<String>::new(10000, 0.01);
+    batched.insert_many((0..10000).map(|i| format!("key-{i}")));
+    for i in 0..10000 {
+        single.insert(format!("key-{i}"));
+    }
+
+    let keys = (0..20000).map(|i| format!("key-{i}")).collect::<Vec<_>>();
+    let refs = keys.iter().map(String::as_str).collect::<Vec<_>>();
+    let found = batched.contains_many(&refs);
+    assert!(found[..10000].iter().all(|&found| found));
+    for (key, found) in refs.iter().zip(found) {
+        assert_eq!(single.contains(*key), found);
+    }
+    assert!(batched.contains_many::<str>(&[]).is_empty());
+}
+
+#[test]
+fn collect() {
+    let filter = (0..1000u64).collect::<BloomFilter<_>>();
+    assert!((0..1000).all(|key| filter.contains(&key)));
+    assert_eq!(
+        filter.bit_count(),
+        BloomFilter::<u64>::new(1000, 0.01).bit_count()
+    );
+
+    // No upper bound: keys are counted first.
+    let filter = BloomFilter::collect_with_fp_rate((0..1000u64).filter(|key| key % 2 == 0), 0.001);
+    assert!((0..1000).step_by(2).all(|key| filter.contains(&key)));
+    let mut keys = 0..;
+    let filter = BloomFilter::collect_with_fp_rate(
+        std::iter::from_fn(|| keys.next().filter(|&key| key < 500u64)),
+        0.001,
+    );
+    assert_eq!(
+        filter.bit_count(),
+        BloomFilter::<u64>::new(500, 0.001).bit_count()
+    );
+
+    let mut extended = BloomFilter::new(2000, 0.01);
+    extended.extend(0..1000u64);
+    extended.extend([5000, 6000]);
+    assert!((0..1000)
+        .chain([5000, 6000])
+        .all(|key| extended.contains(&key)));
+
+    let empty = std::iter::empty::<u64>().collect::<BloomFilter<_>>();
+    assert!(!empty.contains(&1));
+}
+
+#[test]
+fn stats() {
+    let mut filter = BloomFilter::new(1000, 0.01);
+    assert_eq!(filter.fill_ratio(), 0.);
+    assert_eq!(filter.size_in_bytes(), filter.bit_count().div_ceil(64) * 8);
+    filter.extend(0..1000u64);
+    // At capacity, about half of the bits are set.
+    assert!((filter.fill_ratio() - 0.5).abs() < 0.05);
+    assert!((filter.approx_fp_rate() - 0.01).abs() < 0.005);
+
+    let stats: &dyn FilterStats = &filter;
+    assert_eq!(stats.slot_count(), filter.bit_count());
+    assert_eq!(stats.hash_count(), filter.hash_count());
+    assert_eq!(stats.fill_ratio(), filter.fill_ratio());
+    assert_eq!(stats.size_in_bytes(), filter.size_in_bytes());
+    assert_eq!(stats.approx_fp_rate(), filter.approx_fp_rate());
+}
+
+#[test]
+fn bit_ops() {
+    let filter = |keys| {
+        let mut filter = BloomFilter::with_bit_count(1 << 16, 5);
+        filter.extend(keys);
+        filter
+    };
+    let merged = [filter(0..3000), filter(3000..6000), filter(6000..9000)]
+        .into_iter()
+        .reduce(|a, b| a | b)
+        .unwrap();
+    assert!((0..9000).all(|i| merged.contains(&i)));
+
+    let (a, b) = (filter(0..6000), filter(3000..9000));
+    let union = &a | &b;
+    assert_eq!(
+        union.ones().collect::<Vec<_>>(),
+        merged.ones().collect::<Vec<_>>()
+    );
+    let mut both = &a & &b;
+    assert!((3000..6000).all(|i| both.contains(&i)));
+    both &= &filter(4000..5000);
+    assert!((4000..5000).all(|i| both.contains(&i)));
+    let mut a = a;
+    a |= &b;
+    assert!((0..9000).all(|i| a.contains(&i)));
+}
+
+#[test]
+#[should_panic(expected = "filters differ in shape")]
+fn bit_ops_shape_mismatch() {
+    let _ =
+        BloomFilter::<u64>::with_bit_count(1 << 16, 5) | BloomFilter::with_bit_count(1 << 17, 5);
+}
+
+#[test]
+fn set_bits_round_trip() {
+    let hasher = ProbeHasher::new(ProbeStrategy::Triple).with_seed1(42);
+    let mut filter = BloomFilter::with_capacity_and_hasher(1000, 0.01, hasher);
+    for i in 0..100 {
+        filter.insert(i);
+    }
+    let ones = filter.ones().collect::<Vec<_>>();
+    assert!(ones.windows(2).all(|w| w[0] < w[1]));
+    assert!(ones.len() <= 100 * filter.hash_count());
+
+    let rebuilt = BloomFilter::<u64>::from_set_bits(
+        filter.bit_count(),
+        filter.hash_count(),
+        *filter.hasher(),
+        ones,
+    );
+    for i in 0..10000 {
+        assert_eq!(rebuilt.contains(&i), filter.contains(&i));
+    }
+}
+
+#[test]
+fn contains_with_trace() {
+    let mut filter = BloomFilter::new(1000, 0.01);
+    filter.insert("hello");
+
+    let (found, trace) = filter.contains_with_trace("hello");
+    assert!(found);
+    assert_eq!(trace.len(), filter.hash_count());
+    assert!(trace
+        .iter()
+        .all(|&(index, set)| set && index < filter.bit_count()));
+
+    let (found, trace) = filter.contains_with_trace("world");
+    assert!(!found);
+    assert_eq!(trace.len(), filter.hash_count());
+    assert!(trace.iter().any(|&(_, set)| !set));
+    assert_eq!(found, filter.contains("world"));
+}
+
+#[test]
+fn contains_with_confidence() {
+    let mut filter = BloomFilter::new(1000, 0.01);
+    filter.insert(0);
+    assert!(filter.contains_with_confidence(&0) > 0.999_999);
+    assert_eq!(filter.contains_with_confidence(&1), 0.);
+
+    // Confidence drops as the filter fills up.
+    for i in 1..1000 {
+        filter.insert(i);
+    }
+    let confidence = filter.contains_with_confidence(&0);
+    assert!((0.98..0.995).contains(&confidence), "{confidence}");
+    for i in 1000..5000 {
+        filter.insert(i);
+    }
+    assert!(filter.contains_with_confidence(&0) < 0.8);
+}
+
+#[test]
+fn streamed_round_trip() {
+    // Large enough to span several chunks.
+    let mut filter = BloomFilter::new(100_000, 0.001);
+    for i in 0..100_000u64 {
+        filter.insert(i);
+    }
+    let mut bytes = Vec::new();
+    filter.encode_into(&mut bytes).unwrap();
+    assert_eq!(bytes.len(), 25 + filter.bit_count().div_ceil(64) * 8 + 8);
+
+    let read = BloomFilter::<u64>::decode_from(bytes.as_slice()).unwrap();
+    assert_eq!(read.bit_count(), filter.bit_count());
+    assert_eq!(read.hash_count(), filter.hash_count());
+    assert!(read.ones().eq(filter.ones()));
+
+    let mut corrupted = bytes.clone();
+    corrupted[1000] ^= 1;
+    assert!(BloomFilter::<u64>::decode_from(corrupted.as_slice()).is_err());
+    assert!(BloomFilter::<u64>::decode_from(&bytes[..bytes.len() - 1]).is_err());
+
+    let hasher = ProbeHasher::new(ProbeStrategy::Triple);
+    assert!(BloomFilter::<u64, _>::decode_from_with_hasher(bytes.as_slice(), hasher).is_err());
+}
+
+#[test]
+fn streamed_bad_header() {
+    let mut bytes = Vec::new();
+    BloomFilter::<u64>::new(1000, 0.01)
+        .encode_into(&mut bytes)
+        .unwrap();
+    let with_header = |k: u32, bit_count: u64| {
+        let mut bytes = bytes.clone();
+        bytes[5..9].copy_from_slice(&k.to_le_bytes());
+        bytes[9..17].copy_from_slice(&bit_count.to_le_bytes());
+        BloomFilter::<u64>::decode_from(bytes.as_slice())
+            .err()
+            .unwrap()
+            .kind()
+    };
+    assert_eq!(with_header(7, 0), io::ErrorKind::InvalidData);
+    assert_eq!(with_header(0, 1000), io::ErrorKind::InvalidData);
+    // A huge bit count is not allocated for, but runs out of bits.
+    assert_eq!(with_header(7, u64::MAX >> 8), io::ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn bytes_round_trip() {
+    let hasher = ProbeHasher::new(ProbeStrategy::Triple).with_seed2(7);
+    let mut filter = BloomFilter::with_capacity_and_hasher(1000, 0.01, hasher);
+    for i in 0..1000u64 {
+        filter.insert(i);
+    }
+    let bytes = filter.to_bytes();
+    assert_eq!(bytes.len(), 42 + filter.bit_count().div_ceil(64) * 8 + 8);
+
+    // Seeds and strategy travel with the filter.
+    let read = BloomFilter::<u64>::from_bytes(&bytes).unwrap();
+    assert_eq!(read.hasher(), filter.hasher());
+    assert_eq!(read.hash_count(), filter.hash_count());
+    assert!(read.ones().eq(filter.ones()));
+    assert!((0..2000u64).all(|i| read.contains(&i) == filter.contains(&i)));
+
+    let mut corrupted = bytes.clone();
+    corrupted[100] ^= 1;
+    assert!(BloomFilter::<u64>::from_bytes(&corrupted).is_err());
+    assert!(BloomFilter::<u64>::from_bytes(&bytes[..bytes.len() - 8]).is_err());
+    assert!(BloomFilter::<u64>::from_bytes(&[]).is_err());
+}
+
+#[test]
+fn bytes_format_is_stable() {
+    let filter = BloomFilter::<u64>::with_bit_count(12, 3);
+    let bytes = filter.to_bytes();
+    let mut expected = b"MQBB\x01\x01".to_vec();
+    expected.extend_from_slice(&3u32.to_le_bytes());
+    expected.extend_from_slice(&12u64.to_le_bytes());
+    for seed in [12345u64, 67890, 24680] {
+        expected.extend_from_slice(&seed.to_le_bytes());
+    }
+    expected.extend_from_slice(&[0; 8]);
+    assert_eq!(bytes[..bytes.len() - 8], expected);
+
+    // A different version is rejected, even with a valid checksum.
+    let mut body = expected.clone();
+    body[4] = 2;
+    let checksum = xxhash_rust::xxh3::xxh3_64(&body);
+    body.extend_from_slice(&checksum.to_le_bytes());
+    assert!(BloomFilter::<u64>::from_bytes(&body).is_err());
+}
+
+#[test]
+fn subset_and_containment() {
+    let mut small = BloomFilter::with_bit_count(100_000, 5);
+    let mut large = BloomFilter::with_bit_count(100_000, 5);
+    for i in 0..1000u64 {
+        small.insert(i);
+    }
+    for i in 0..3000u64 {
+        large.insert(i);
+    }
+    assert!(small.is_probable_subset(&large).unwrap());
+    assert!(!large.is_probable_subset(&small).unwrap());
+    let containment = small.containment_in(&large).unwrap();
+    assert!(containment > 0.95, "containment: {containment}");
+    let containment = large.containment_in(&small).unwrap();
+    assert!(
+        (0.28..0.38).contains(&containment),
+        "containment: {containment}"
+    );
+
+    let empty = BloomFilter::with_bit_count(100_000, 5);
+    assert!(empty.is_probable_subset(&small).unwrap());
+    assert_eq!(empty.containment_in(&small).unwrap(), 1.);
+
+    let other = BloomFilter::with_bit_count(50_000, 5);
+    assert!(small.is_probable_subset(&other).is_err());
+    assert!(small.containment_in(&other).is_err());
+}
+
+#[test]
+fn pointer_impls() {
+    use std::{rc::Rc, sync::Arc};
+
+    fn fill<F: InsertableQueryFilter<u64> + ClearableQueryFilter<u64>>(filter: &mut F) {
+        filter.clear();
+        filter.insert(1);
+    }
+    fn has_one(filter: impl QueryFilter<u64>) -> bool {
+        filter.contains(&1)
+    }
+
+    let mut filter = BloomFilter::new(100, 0.01);
+    fill(&mut &mut filter);
+    assert!(has_one(&filter));
+    assert!(has_one(&mut filter));
+
+    let mut boxed = Box::new(BloomFilter::new(100, 0.01));
+    fill(&mut boxed);
+    assert!(has_one(&boxed));
+    assert!(has_one(Rc::new(filter)));
+    assert!(has_one(Arc::<BloomFilter<u64>>::from(boxed)));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_contains_many() {
+    let mut filter = BloomFilter::new(100_000, 0.01);
+    for i in 0..100_000u64 {
+        filter.insert(i);
+    }
+    let frozen = filter.freeze();
+    let keys = (0..300_000u64).collect::<Vec<_>>();
+    let found = frozen.par_contains_many(&keys);
+    assert_eq!(found.len(), keys.len());
+    for (key, found) in keys.iter().zip(found) {
+        assert_eq!(found, frozen.contains(key));
+    }
+    assert!(frozen.par_contains_many::<u64>(&[]).is_empty());
 }
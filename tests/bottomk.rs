@@ -0,0 +1,55 @@
+#![cfg(feature = "bottomk")]
+
+use mqfilters::BottomK;
+
+fn sketch(keys: impl IntoIterator<Item = u64>) -> BottomK<u64> {
+    let mut sketch = BottomK::new(1024);
+    for key in keys {
+        sketch.insert(key);
+    }
+    sketch
+}
+
+#[test]
+fn distinct_estimate() {
+    // Small streams are counted exactly, duplicates included.
+    let small = sketch((0..500).chain(0..500));
+    assert_eq!(small.len(), 500);
+    assert_eq!(small.distinct_estimate(), 500.);
+
+    let large = sketch((0..100_000).chain(0..100_000));
+    assert_eq!(large.len(), 1024);
+    let estimate = large.distinct_estimate();
+    assert!(
+        (estimate / 100_000. - 1.).abs() < 0.1,
+        "estimate: {estimate}"
+    );
+}
+
+#[test]
+fn samples_are_distinct_keys() {
+    let sketch = sketch((0..10_000).map(|i| i % 5000));
+    let mut samples = sketch.samples().copied().collect::<Vec<_>>();
+    samples.sort_unstable();
+    samples.dedup();
+    assert_eq!(samples.len(), 1024);
+    assert!(samples.iter().all(|&key| key < 5000));
+}
+
+#[test]
+fn union_and_similarity() {
+    // |A ∩ B| = 5000, |A ∪ B| = 15000.
+    let a = sketch(0..10_000);
+    let b = sketch(5000..15_000);
+    let estimate = a.jaccard_estimate(&b);
+    assert!((estimate - 1. / 3.).abs() < 0.05, "estimate: {estimate}");
+    assert_eq!(a.jaccard_estimate(&a), 1.);
+
+    let mut union = a.clone();
+    union.merge(&b);
+    assert_eq!(union, sketch(0..15_000));
+
+    let empty = BottomK::<u64>::new(1024);
+    assert_eq!(empty.jaccard_estimate(&empty), 1.);
+    assert_eq!(empty.jaccard_estimate(&a), 0.);
+}
@@ -0,0 +1,59 @@
+use {
+    mqfilters::budget::{MemoryBudget, OverBudget},
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+#[test]
+fn enforces_limit() {
+    let budget = MemoryBudget::new(1000);
+    budget.report("users", 600).unwrap();
+    budget.report("sessions", 300).unwrap();
+    assert!(budget.report("orders", 200).is_err());
+    assert_eq!(budget.usage("orders"), None);
+    assert_eq!(budget.total(), 900);
+
+    // Shrinking is always accepted, and frees room for others.
+    budget.report("users", 400).unwrap();
+    budget.report("orders", 200).unwrap();
+    assert_eq!(budget.release("sessions"), Some(300));
+    assert_eq!(budget.usages(), vec![
+        ("orders".to_owned(), 200),
+        ("users".to_owned(), 400)
+    ]);
+
+    budget.set_limit(100);
+    budget.report("users", 300).unwrap();
+    assert!(budget.report("users", 500).is_err());
+    assert_eq!(budget.usage("users"), Some(300));
+}
+
+#[test]
+fn policy_hook() {
+    let overages = Arc::new(AtomicUsize::new(0));
+    let seen = overages.clone();
+    let budget = MemoryBudget::new(1000).with_policy(move |overage| {
+        assert_eq!(overage.limit, 1000);
+        seen.fetch_add(1, Ordering::Relaxed);
+        if overage.name == "critical" {
+            OverBudget::Allow
+        } else {
+            OverBudget::Deny
+        }
+    });
+    budget.report("cache", 800).unwrap();
+    assert!(budget.report("other", 800).is_err());
+    budget.report("critical", 800).unwrap();
+    assert_eq!(budget.total(), 1600);
+    assert_eq!(overages.load(Ordering::Relaxed), 2);
+}
+
+#[test]
+fn global_budget() {
+    let budget = MemoryBudget::global();
+    budget.report("global_budget_test", 1 << 40).unwrap();
+    assert_eq!(budget.usage("global_budget_test"), Some(1 << 40));
+    budget.release("global_budget_test");
+}
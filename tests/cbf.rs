@@ -0,0 +1,119 @@
+#![cfg(feature = "cbf")]
+
+use mqfilters::{
+    cbf::MAX_COUNT,
+    hash::ProbeHasher,
+    ClearableQueryFilter,
+    CountingBloomFilter,
+    FilterStats,
+    InsertableQueryFilter,
+    QueryFilter,
+    QueryFilterError,
+    RemovableQueryFilter,
+};
+
+#[test]
+fn insert_and_remove() {
+    let mut filter = CountingBloomFilter::new(10_000, 0.01);
+    assert_eq!(
+        filter.size_in_bytes(),
+        filter.counter_count().div_ceil(16) * 8
+    );
+    for i in 0..10_000u64 {
+        filter.insert(i);
+    }
+    assert!((0..10_000u64).all(|i| filter.contains(&i)));
+    let fp_rate = filter.approx_fp_rate();
+    assert!(fp_rate < 0.015, "fp_rate: {fp_rate}");
+    assert_eq!(fp_rate, FilterStats::approx_fp_rate(&filter));
+
+    for i in 0..5000u64 {
+        filter.remove(&i);
+    }
+    assert!((5000..10_000u64).all(|i| filter.contains(&i)));
+    let removed = (0..5000u64).filter(|i| filter.contains(i)).count();
+    assert!(removed < 50, "removed: {removed}");
+
+    filter.clear();
+    assert!(!filter.contains(&7000));
+}
+
+#[test]
+fn counters_saturate() {
+    let mut filter =
+        CountingBloomFilter::with_counter_count_and_hasher(64, 1, ProbeHasher::default());
+    for _ in 0..20 {
+        filter.insert("hot");
+    }
+    assert_eq!(filter.saturated_count(), 1);
+    let index = (0..64).find(|&i| filter.counter(i) > 0).unwrap();
+    assert_eq!(filter.counter(index), MAX_COUNT);
+
+    // Saturated counters are never decremented, so the key stays.
+    for _ in 0..20 {
+        filter.remove("hot");
+    }
+    assert!(filter.contains("hot"));
+    assert_eq!(filter.counter(index), MAX_COUNT);
+}
+
+#[test]
+fn counts_insertions() {
+    let mut filter = CountingBloomFilter::new(10_000, 0.01);
+    for i in 0..5000u64 {
+        filter.insert(i);
+    }
+    for i in 0..1000u64 {
+        filter.insert(i);
+        filter.insert(i);
+    }
+    assert_eq!(filter.approx_total_insertions(), 7000);
+    let distinct = filter.approx_current_capacity();
+    assert!(distinct.abs_diff(5000) < 100, "distinct: {distinct}");
+
+    for i in 0..1000u64 {
+        filter.remove(&i);
+    }
+    assert_eq!(filter.approx_total_insertions(), 6000);
+}
+
+#[test]
+fn insert_batch_atomic() {
+    let mut filter =
+        CountingBloomFilter::with_counter_count_and_hasher(64, 2, ProbeHasher::default());
+    for _ in 0..13 {
+        filter.try_insert("hot").unwrap();
+    }
+    let counters =
+        |filter: &CountingBloomFilter<&str>| (0..64).map(|i| filter.counter(i)).collect::<Vec<_>>();
+    let before = counters(&filter);
+
+    // The second "hot" would saturate its counters: the batch is undone.
+    assert_eq!(
+        filter.insert_batch_atomic(["a", "b", "hot", "hot", "c"]),
+        Err(QueryFilterError::Full)
+    );
+    assert_eq!(counters(&filter), before);
+    assert_eq!(filter.saturated_count(), 0);
+
+    filter.insert_batch_atomic(["a", "b", "hot"]).unwrap();
+    assert!(["a", "b", "hot"].iter().all(|key| filter.contains(key)));
+    assert_eq!(filter.try_insert("hot"), Err(QueryFilterError::Full));
+}
+
+#[test]
+#[cfg(feature = "bf")]
+fn to_bloom() {
+    let mut filter = CountingBloomFilter::new(10_000, 0.01);
+    for i in 0..10_000u64 {
+        filter.insert(i);
+    }
+    for i in 0..5000u64 {
+        filter.remove(&i);
+    }
+    let bloom = filter.to_bloom();
+    assert_eq!(bloom.bit_count(), filter.counter_count());
+    assert_eq!(bloom.hash_count(), filter.hash_count());
+    assert!(bloom.size_in_bytes() * 4 <= filter.size_in_bytes() + 8);
+    assert!((0..20_000u64).all(|i| bloom.contains(&i) == filter.contains(&i)));
+}
@@ -0,0 +1,74 @@
+#![cfg(feature = "cidr")]
+
+use {
+    mqfilters::CidrFilter,
+    std::net::{IpAddr, Ipv4Addr},
+};
+
+fn ip(addr: &str) -> IpAddr {
+    addr.parse().unwrap()
+}
+
+#[test]
+fn covers_addresses() {
+    let mut filter = CidrFilter::new(100, 0.001);
+    filter.insert(ip("10.0.0.0"), 8);
+    filter.insert(ip("192.168.1.0"), 24);
+    filter.insert(ip("203.0.113.7"), 32);
+    // Host bits are ignored.
+    filter.insert(ip("2001:db8::1"), 32);
+    assert_eq!(filter.length_count(), (3, 1));
+
+    assert!(filter.covers(ip("10.1.2.3")));
+    assert!(filter.covers(ip("192.168.1.255")));
+    assert!(filter.covers(ip("203.0.113.7")));
+    assert!(filter.covers(ip("2001:db8:ffff::")));
+    assert!(!filter.covers(ip("11.0.0.1")));
+    assert!(!filter.covers(ip("192.168.2.1")));
+    assert!(!filter.covers(ip("203.0.113.8")));
+    assert!(!filter.covers(ip("2001:db9::")));
+    // Families are kept apart.
+    assert!(!filter.covers(ip("::a00:1")));
+
+    assert_eq!(filter.matching_len(ip("10.1.2.3")), Some(8));
+    assert!(filter.contains_prefix(ip("10.255.0.0"), 8));
+    assert!(!filter.contains_prefix(ip("10.0.0.0"), 16));
+
+    filter.clear();
+    assert!(!filter.covers(ip("10.1.2.3")));
+    assert_eq!(filter.length_count(), (0, 0));
+}
+
+#[test]
+fn longest_match() {
+    let mut filter = CidrFilter::new(10, 0.001);
+    filter.insert(ip("0.0.0.0"), 0);
+    filter.insert(ip("172.16.0.0"), 12);
+    assert_eq!(filter.matching_len(ip("172.16.5.5")), Some(12));
+    assert_eq!(filter.matching_len(ip("8.8.8.8")), Some(0));
+    assert_eq!(filter.matching_len(ip("::1")), None);
+}
+
+#[test]
+fn blocklist() {
+    // 10K random-ish /24 networks.
+    let mut filter = CidrFilter::new(10000, 0.001);
+    let network = |i: u32| Ipv4Addr::from(i.wrapping_mul(0x9e3779b9) & !0xff);
+    for i in 0..10000 {
+        filter.insert(network(i).into(), 24);
+    }
+    for i in 0..10000 {
+        let host = u32::from(network(i)) | (i & 0xff);
+        assert!(filter.covers(Ipv4Addr::from(host).into()));
+    }
+    let fp_count = (10000..110000)
+        .filter(|&i| filter.covers(network(i).into()))
+        .count();
+    assert!(fp_count < 200, "fp_count: {fp_count}");
+}
+
+#[test]
+#[should_panic(expected = "exceeds 32 bits")]
+fn prefix_too_long() {
+    CidrFilter::new(10, 0.01).insert(ip("10.0.0.0"), 33);
+}
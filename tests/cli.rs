@@ -0,0 +1,112 @@
+#![cfg(feature = "cli")]
+
+use {
+    mqfilters::{BloomFilter, CuckooFilter, QueryFilter},
+    std::{
+        fs,
+        io::Write,
+        path::PathBuf,
+        process::{Command, Output, Stdio},
+    },
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mqfilters-{}-cli-{name}", std::process::id()))
+}
+
+fn run(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mqfilters"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn keys(range: std::ops::Range<u32>) -> String {
+    range.map(|i| format!("key-{i}\n")).collect()
+}
+
+#[test]
+fn build_and_query() {
+    let keys_path = temp_path("keys");
+    fs::write(&keys_path, keys(0..1000)).unwrap();
+    for kind in ["bloom", "cuckoo"] {
+        let filter_path = temp_path(kind);
+        let (keys, filter) = (keys_path.to_str().unwrap(), filter_path.to_str().unwrap());
+        let output = run(
+            &["build", "--type", kind, "--fp-rate", "0.001", keys, filter],
+            "",
+        );
+        assert!(output.status.success(), "{output:?}");
+
+        // Filters are readable by the library, with string keys.
+        let bytes = fs::read(&filter_path).unwrap();
+        let contains: Box<dyn Fn(&str) -> bool> = match kind {
+            "bloom" => {
+                let filter = BloomFilter::<String>::from_bytes(&bytes).unwrap();
+                Box::new(move |key| filter.contains(key))
+            }
+            _ => {
+                let filter = CuckooFilter::<String>::from_bytes(&bytes).unwrap();
+                Box::new(move |key| filter.contains(key))
+            }
+        };
+        assert!((0..1000).all(|i| contains(&format!("key-{i}"))));
+
+        let output = run(&["query", filter], "key-1\nkey-999\nabsent\n");
+        assert!(output.status.success(), "{output:?}");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(stdout, "key-1\ttrue\nkey-999\ttrue\nabsent\tfalse\n");
+
+        let output = run(&["stats", filter], "");
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.starts_with(&format!("type\t{kind}\n")), "{stdout}");
+        fs::remove_file(&filter_path).unwrap();
+    }
+    fs::remove_file(&keys_path).unwrap();
+}
+
+#[test]
+fn merge() {
+    let paths = ["merge-a", "merge-b", "merge-out"].map(temp_path);
+    let [a, b, merged] = paths.each_ref().map(|path| path.to_str().unwrap());
+    for (path, range) in [(a, 0..500), (b, 500..1000)] {
+        let output = run(&["build", "--capacity", "1000", "-", path], &keys(range));
+        assert!(output.status.success(), "{output:?}");
+    }
+
+    let output = run(&["merge", merged, a, b], "");
+    assert!(output.status.success(), "{output:?}");
+    let filter = BloomFilter::<String>::from_bytes(&fs::read(merged).unwrap()).unwrap();
+    assert!((0..1000).all(|i| filter.contains(&format!("key-{i}"))));
+    paths.iter().for_each(|path| fs::remove_file(path).unwrap());
+}
+
+#[test]
+fn failures() {
+    let path = temp_path("not-a-filter");
+    fs::write(&path, "key\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    for args in [
+        &["query", path][..],
+        &["stats", "/nonexistent/filter"],
+        &["build", "--type", "xor", "-", "/dev/null"],
+        &["build", "--fp-rate", "2", "-", "/dev/null"],
+        &["frobnicate"],
+    ] {
+        let output = run(args, "");
+        assert!(!output.status.success(), "{args:?}");
+        assert!(!output.stderr.is_empty(), "{args:?}");
+    }
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,168 @@
+#![cfg(feature = "cuckoo")]
+
+use mqfilters::{
+    analysis::cuckoo_max_load_factor,
+    hash::ProbeHasher,
+    ClearableQueryFilter,
+    CuckooFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+    QueryFilterError,
+    RemovableQueryFilter,
+};
+
+#[test]
+fn insert_and_remove() {
+    let mut filter = CuckooFilter::new(10_000, 0.001);
+    assert_eq!(filter.bucket_size(), 4);
+    assert_eq!(filter.fingerprint_bits(), 13);
+    for i in 0..10_000u64 {
+        filter.insert(i);
+    }
+    assert_eq!(filter.len(), 10_000);
+    assert!((0..10_000u64).all(|i| filter.contains(&i)));
+    let fp_count = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 200, "fp_count: {fp_count}");
+
+    for i in 0..5000u64 {
+        filter.remove(&i);
+    }
+    assert_eq!(filter.len(), 5000);
+    assert!((5000..10_000u64).all(|i| filter.contains(&i)));
+    let removed = (0..5000u64).filter(|i| filter.contains(i)).count();
+    assert!(removed < 20, "removed: {removed}");
+
+    filter.clear();
+    assert!(filter.is_empty());
+    assert!(!filter.contains(&7000));
+}
+
+#[test]
+fn fills_up() {
+    let mut filter = CuckooFilter::with_params(64, 8, 2).unwrap();
+    let mut inserted = Vec::new();
+    let mut key = 0u64;
+    while filter.try_insert(key).is_ok() {
+        inserted.push(key);
+        key += 1;
+    }
+    assert!(filter.load_factor() > 0.5, "{}", filter.load_factor());
+    assert!(inserted.iter().all(|key| filter.contains(key)));
+    assert_eq!(filter.try_insert(key), Err(QueryFilterError::Full));
+
+    // Removing a key makes room again.
+    filter.remove(&inserted[0]);
+    assert!(inserted[1..].iter().all(|key| filter.contains(key)));
+    assert!(filter.try_insert(key).is_ok());
+}
+
+#[test]
+fn max_load() {
+    for bucket_size in [1, 2, 4, 8] {
+        let mut filter = CuckooFilter::with_params(10_000, 12, bucket_size).unwrap();
+        assert_eq!(
+            filter.max_load_factor(),
+            cuckoo_max_load_factor(bucket_size)
+        );
+        assert!(filter.capacity() >= 10_000);
+        let mut key = 0u64;
+        while filter.try_insert(key).is_ok() {
+            key += 1;
+        }
+        // Fails at the guaranteed bound, not earlier.
+        assert_eq!(
+            filter.len(),
+            filter.capacity(),
+            "bucket size: {bucket_size}"
+        );
+        assert_eq!(filter.try_insert(key), Err(QueryFilterError::Full));
+        filter.remove(&0);
+        assert!(filter.try_insert(key).is_ok());
+    }
+}
+
+#[test]
+fn insert_batch_atomic() {
+    let mut filter = CuckooFilter::with_params(64, 8, 2).unwrap();
+    filter.insert_batch_atomic(0..32u64).unwrap();
+    assert_eq!(filter.len(), 32);
+
+    // Does not fit: the keys inserted before the failure are removed again.
+    assert_eq!(
+        filter.insert_batch_atomic(32..1000u64),
+        Err(QueryFilterError::Full)
+    );
+    assert_eq!(filter.len(), 32);
+    assert!((0..32u64).all(|key| filter.contains(&key)));
+    let fp_count = (32..1000u64).filter(|key| filter.contains(key)).count();
+    assert!(fp_count < 100, "fp_count: {fp_count}");
+    filter.insert_batch_atomic(32..40u64).unwrap();
+    assert!((0..40u64).all(|key| filter.contains(&key)));
+}
+
+#[test]
+fn bytes_round_trip() {
+    let mut filter = CuckooFilter::with_params(64, 8, 2).unwrap();
+    let mut key = 0u64;
+    while filter.try_insert(key).is_ok() {
+        key += 1;
+    }
+    let bytes = filter.to_bytes();
+    let decoded = CuckooFilter::<u64>::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded.len(), filter.len());
+    assert!((0..key).all(|key| decoded.contains(&key)));
+    assert_eq!(decoded.to_bytes(), bytes);
+
+    // Truncated, corrupted, or of another version or hasher.
+    assert!(CuckooFilter::<u64>::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    assert!(CuckooFilter::<u64>::from_bytes(&bytes[..20]).is_err());
+    for at in [0, 4, 40] {
+        let mut corrupted = bytes.clone();
+        corrupted[at] ^= 1;
+        assert!(CuckooFilter::<u64>::from_bytes(&corrupted).is_err());
+    }
+    let hasher = ProbeHasher::default().with_seed1(1);
+    assert!(CuckooFilter::<u64, _>::from_bytes_with_hasher(&bytes, hasher).is_err());
+}
+
+#[test]
+fn invalid_params() {
+    assert_eq!(
+        CuckooFilter::<u64>::with_params(100, 0, 4).err(),
+        Some(QueryFilterError::InvalidFingerprintBits { bits: 0, max: 16 })
+    );
+    assert!(CuckooFilter::<u64>::with_params(100, 17, 4).is_err());
+    assert!(CuckooFilter::<u64>::with_params(100, 8, 0).is_err());
+    assert!(CuckooFilter::<u64>::with_params(100, 8, 9).is_err());
+
+    // Rates needing more than 16-bit fingerprints are rejected, not capped.
+    for fp_rate in [0., 1., f64::NAN, 1e-6] {
+        assert_eq!(
+            CuckooFilter::<u64, _>::with_capacity_and_hasher(100, fp_rate, ProbeHasher::default())
+                .err()
+                .map(|err| matches!(err, QueryFilterError::InvalidFpRate(_))),
+            Some(true)
+        );
+    }
+    let filter =
+        CuckooFilter::<u64, _>::with_capacity_and_hasher(100, 2e-4, ProbeHasher::default())
+            .unwrap();
+    assert_eq!(filter.fingerprint_bits(), 16);
+}
+
+#[test]
+fn holds_its_capacity() {
+    // Trait inserts within the capacity a filter was created with never
+    // panic, whatever the hasher.
+    for seed in 0..50 {
+        for capacity in [1, 100, 1000, 1024, 4096] {
+            let hasher = ProbeHasher::default().with_seed1(seed);
+            let mut filter =
+                CuckooFilter::with_capacity_and_hasher(capacity, 0.01, hasher).unwrap();
+            assert!(filter.capacity() >= capacity);
+            for key in 0..capacity as u64 {
+                filter.insert(key);
+            }
+        }
+    }
+}
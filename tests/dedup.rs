@@ -0,0 +1,41 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{dedup::Observation, WindowedDedup};
+
+#[test]
+fn drops_redeliveries_within_window() {
+    let mut dedup = WindowedDedup::new(100, 1000, 0.001);
+    assert_eq!(dedup.observe("a", 10), Observation::New);
+    assert_eq!(dedup.observe("a", 20), Observation::Duplicate);
+    assert_eq!(dedup.observe("b", 20), Observation::New);
+    // Windows are deduplicated independently.
+    assert_eq!(dedup.observe("a", 150), Observation::New);
+
+    let open = dedup.open_windows().collect::<Vec<_>>();
+    assert_eq!(open.len(), 2);
+    assert_eq!((open[0].start, open[0].end), (0, 100));
+    assert_eq!((open[0].events, open[0].duplicates), (3, 1));
+    assert!(open[0].fp_rate < 0.001);
+}
+
+#[test]
+fn watermark_finalizes_windows() {
+    let mut dedup = WindowedDedup::new(100, 1000, 0.001);
+    for i in 0..300u64 {
+        dedup.observe(i % 50, i);
+    }
+    assert!(dedup.advance_watermark(99).is_empty());
+    let finalized = dedup.advance_watermark(200);
+    assert_eq!(finalized.len(), 2);
+    assert_eq!(finalized[0].start, 0);
+    assert_eq!((finalized[1].events, finalized[1].duplicates), (100, 50));
+    assert_eq!(dedup.open_windows().count(), 1);
+
+    assert_eq!(dedup.observe(1, 150), Observation::Late);
+    assert_eq!(dedup.late_events(), 1);
+    assert_eq!(dedup.observe(1, 250), Observation::Duplicate);
+
+    // The watermark never moves back.
+    assert!(dedup.advance_watermark(50).is_empty());
+    assert_eq!(dedup.watermark(), 200);
+}
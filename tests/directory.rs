@@ -0,0 +1,92 @@
+#![cfg(feature = "bf")]
+
+use {
+    mqfilters::{BloomFilter, FilterDirectory, InsertableQueryFilter},
+    std::{
+        fs,
+        io,
+        path::{Path, PathBuf},
+    },
+};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mqfilters-{}-{name}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Writes a segment filter holding `keys`, as `<id>.mqbb`.
+fn write_segment(dir: &Path, id: &str, keys: std::ops::Range<u64>) {
+    let mut filter = BloomFilter::<u64>::with_capacity(1000, 0.001);
+    keys.for_each(|key| filter.insert(key));
+    fs::write(dir.join(format!("{id}.mqbb")), filter.to_bytes()).unwrap();
+}
+
+#[test]
+fn lazy_loading() {
+    let dir = temp_dir("directory");
+    for day in 0..5u64 {
+        write_segment(&dir, &format!("day-{day}"), day * 1000..(day + 1) * 1000);
+    }
+    fs::write(dir.join("README"), "not a filter").unwrap();
+
+    let directory = FilterDirectory::<u64>::open(&dir, 2).unwrap();
+    assert_eq!(directory.len(), 5);
+    assert_eq!(directory.ids().next(), Some("day-0"));
+    assert_eq!(directory.open_count(), 0);
+
+    assert!(directory.contains("day-3", &3500).unwrap());
+    assert!(!directory.contains("day-3", &500).unwrap());
+    assert_eq!(directory.open_count(), 1);
+    assert!((0..5000).all(|key| directory.contains_any_segment(&key).unwrap()));
+    assert!(!directory.contains_any_segment(&1_000_000).unwrap());
+    assert_eq!(directory.open_count(), 2);
+
+    let err = directory.contains("day-9", &0).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    // Only the two most recently used filters are open, so others are read
+    // again once queried.
+    fs::write(dir.join("day-1.mqbb"), "not a filter").unwrap();
+    let err = directory.contains("day-1", &1000).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refresh() {
+    let dir = temp_dir("directory-refresh");
+    write_segment(&dir, "a", 0..1000);
+    let mut directory = FilterDirectory::<u64>::open(&dir, 4).unwrap();
+    assert!(!directory.contains_any_segment(&1500).unwrap());
+
+    write_segment(&dir, "b", 1000..2000);
+    fs::remove_file(dir.join("a.mqbb")).unwrap();
+    directory.refresh().unwrap();
+    assert_eq!(directory.ids().collect::<Vec<_>>(), ["b"]);
+    assert!(directory.contains_any_segment(&1500).unwrap());
+    assert!(directory.contains("a", &500).is_err());
+
+    assert!(FilterDirectory::<u64>::open(&dir, 0).is_err());
+    assert!(FilterDirectory::<u64>::open(dir.join("missing"), 1).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(all(feature = "mmap", unix))]
+#[test]
+fn mapped_segments() {
+    let dir = temp_dir("directory-mmap");
+    write_segment(&dir, "a", 0..1000);
+    let mut filter =
+        BloomFilter::<u64, _, _>::create_mmap(dir.join("b.mqbm"), 1000, 0.001).unwrap();
+    (1000..2000).for_each(|key| filter.insert(key));
+    filter.flush().unwrap();
+    drop(filter);
+
+    let directory = FilterDirectory::<u64>::open(&dir, 1).unwrap();
+    assert_eq!(directory.ids().collect::<Vec<_>>(), ["a", "b"]);
+    assert!((0..2000).all(|key| directory.contains_any_segment(&key).unwrap()));
+    assert!(directory.contains("b", &1500).unwrap());
+    assert!(!directory.contains("b", &500).unwrap());
+    assert_eq!(directory.open_count(), 1);
+    fs::remove_dir_all(&dir).unwrap();
+}
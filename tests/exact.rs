@@ -0,0 +1,40 @@
+#![cfg(feature = "roaring")]
+
+use mqfilters::{
+    ClearableQueryFilter,
+    ExactU32Filter,
+    InsertableQueryFilter,
+    QueryFilter,
+    RemovableQueryFilter,
+};
+
+#[test]
+fn exact_answers() {
+    let mut filter = ExactU32Filter::new();
+    for i in (0..1_000_000).step_by(3) {
+        filter.insert(i);
+    }
+    assert_eq!(filter.len(), 333_334);
+    for i in 0..1_000_000 {
+        assert_eq!(filter.contains(&i), i % 3 == 0);
+    }
+
+    filter.remove(&3);
+    assert!(!filter.contains(&3));
+    assert!(filter.contains(&6));
+    assert_eq!(filter.keys().take(3).collect::<Vec<_>>(), [0, 6, 9]);
+
+    filter.clear();
+    assert!(filter.is_empty());
+    assert!(!filter.contains(&0));
+}
+
+#[test]
+fn dense_ids_are_compact() {
+    // A contiguous id range takes a few bits per key, at most.
+    let filter = (0..1_000_000).collect::<ExactU32Filter>();
+    assert_eq!(filter.len(), 1_000_000);
+    assert!(filter.size_in_bytes() < 1_000_000 / 4);
+    assert!(filter.contains(&999_999));
+    assert!(!filter.contains(&1_000_000));
+}
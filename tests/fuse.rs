@@ -0,0 +1,67 @@
+#![cfg(feature = "fuse")]
+
+use mqfilters::{fuse::Arity, BinaryFuseFilter, QueryFilter, QueryFilterError};
+
+#[test]
+fn fingerprint_sizes() {
+    let keys = (0..100_000u64).collect::<Vec<_>>();
+    let filter = BinaryFuseFilter::<u64>::from_keys(keys.iter().copied());
+    assert!(keys.iter().all(|key| filter.contains(key)));
+    let fp_count = (100_000..1_100_000u64)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((3000..4800).contains(&fp_count), "fp_count: {fp_count}");
+    // Under 1.2 bytes per key (vs. 1.23 for an xor filter).
+    assert!(
+        filter.size_in_bytes() < 120_000,
+        "{}",
+        filter.size_in_bytes()
+    );
+
+    let filter = BinaryFuseFilter::<u64, u16>::from_keys(keys.iter().copied());
+    assert!(keys.iter().all(|key| filter.contains(key)));
+    let fp_count = (100_000..1_100_000u64)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!(fp_count < 50, "fp_count: {fp_count}");
+
+    let filter = BinaryFuseFilter::<u64, u32>::from_keys(keys.iter().copied());
+    assert!(keys.iter().all(|key| filter.contains(key)));
+}
+
+#[test]
+fn four_wise() {
+    let keys = (0..100_000u64).collect::<Vec<_>>();
+    let three = BinaryFuseFilter::<u64>::from_keys(keys.iter().copied());
+    let four = BinaryFuseFilter::<u64>::with_arity(keys.iter().copied(), Arity::Four).unwrap();
+    assert_eq!(three.arity(), Arity::Three);
+    assert_eq!(four.arity(), Arity::Four);
+    assert!(keys.iter().all(|key| four.contains(key)));
+    let fp_count = (100_000..1_100_000u64).filter(|i| four.contains(i)).count();
+    assert!((3000..4800).contains(&fp_count), "fp_count: {fp_count}");
+    // About 5% smaller at this size.
+    assert!(
+        (four.size_in_bytes() as f64) < 0.97 * three.size_in_bytes() as f64,
+        "{} vs {}",
+        four.size_in_bytes(),
+        three.size_in_bytes()
+    );
+}
+
+#[test]
+fn small_sets() {
+    for n in 0..50u64 {
+        for arity in [Arity::Three, Arity::Four] {
+            let filter = BinaryFuseFilter::<u64, u16>::with_arity(0..n, arity).unwrap();
+            assert!((0..n).all(|key| filter.contains(&key)), "n: {n}");
+        }
+    }
+}
+
+#[test]
+fn duplicate_keys() {
+    assert!(matches!(
+        BinaryFuseFilter::<&str>::try_from_keys(["a", "b", "a"]),
+        Err(QueryFilterError::DuplicateKey)
+    ));
+}
@@ -0,0 +1,79 @@
+#![cfg(feature = "bf")]
+
+use {
+    mqfilters::{BloomFilter, ClearableQueryFilter, FilterGuard, QueryFilter},
+    std::{cell::Cell, io, rc::Rc, thread, time::Duration},
+};
+
+/// Returns a target counting saves, and the count.
+fn counting_target() -> (
+    impl FnMut(&BloomFilter<u64>) -> io::Result<()>,
+    Rc<Cell<usize>>,
+) {
+    let saves = Rc::new(Cell::new(0));
+    let counter = saves.clone();
+    let target = move |_: &BloomFilter<u64>| {
+        counter.set(counter.get() + 1);
+        Ok(())
+    };
+    (target, saves)
+}
+
+#[test]
+fn persists_on_drop() {
+    let (target, saves) = counting_target();
+    let mut guard = FilterGuard::new(BloomFilter::new(100, 0.01), target);
+    guard.insert(1).unwrap();
+    assert!(guard.contains(&1));
+    assert!(guard.is_dirty());
+    assert_eq!(saves.get(), 0);
+    drop(guard);
+    assert_eq!(saves.get(), 1);
+
+    // Clean guards are not saved again.
+    let (target, saves) = counting_target();
+    let mut guard = FilterGuard::new(BloomFilter::new(100, 0.01), target);
+    guard.insert(1).unwrap();
+    guard.persist().unwrap();
+    assert!(!guard.is_dirty());
+    drop(guard);
+    assert_eq!(saves.get(), 1);
+
+    // Mutable access through `DerefMut` counts as a change.
+    let (target, saves) = counting_target();
+    let mut guard = FilterGuard::new(BloomFilter::new(100, 0.01), target);
+    assert!(guard.bit_count() > 0);
+    assert!(!guard.is_dirty());
+    guard.clear();
+    drop(guard);
+    assert_eq!(saves.get(), 1);
+}
+
+#[test]
+fn persists_at_interval() {
+    let (target, saves) = counting_target();
+    let mut guard = FilterGuard::new(BloomFilter::new(100, 0.01), target)
+        .with_interval(Duration::from_millis(20));
+    guard.insert(1).unwrap();
+    guard.tick().unwrap();
+    assert_eq!(saves.get(), 0);
+
+    thread::sleep(Duration::from_millis(30));
+    guard.insert(2).unwrap();
+    assert_eq!(saves.get(), 1);
+    assert!(!guard.is_dirty());
+
+    let (filter, _) = guard.into_inner().unwrap();
+    assert!(filter.contains(&1) && filter.contains(&2));
+    assert_eq!(saves.get(), 1);
+}
+
+#[test]
+fn reports_save_errors() {
+    let target = |_: &BloomFilter<u64>| Err(io::Error::other("disk full"));
+    let mut guard = FilterGuard::new(BloomFilter::new(100, 0.01), target);
+    guard.insert(1).unwrap();
+    assert!(guard.persist().is_err());
+    assert!(guard.is_dirty());
+    assert!(guard.into_inner().is_err());
+}
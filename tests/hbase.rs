@@ -0,0 +1,38 @@
+#![cfg(feature = "hbase")]
+
+use mqfilters::hbase::{BloomChunk, BloomType};
+
+#[test]
+fn chunk_works() {
+    let mut chunk = BloomChunk::new(1000, 0.01);
+    for i in 0..1000 {
+        chunk.insert(&BloomType::Row.key(format!("row-{i}").as_bytes(), b""));
+    }
+    for i in 0..1000 {
+        assert!(chunk.contains(&BloomType::Row.key(format!("row-{i}").as_bytes(), b"")));
+    }
+    let fp_count = (1000..11000)
+        .filter(|i| chunk.contains(&BloomType::Row.key(format!("row-{i}").as_bytes(), b"")))
+        .count();
+    assert!(fp_count < 150, "fp_count: {fp_count}");
+
+    // Chunks read back from their payload answer identically.
+    let read = BloomChunk::from_bytes(chunk.as_bytes().to_vec(), chunk.hash_count());
+    assert_eq!(read, chunk);
+}
+
+#[test]
+fn row_col_keys() {
+    let key = BloomType::RowCol.key(b"row", b"qual");
+    assert_eq!(&key[..6], &[0, 3, b'r', b'o', b'w', 0]);
+    assert_eq!(&key[6..10], b"qual");
+    assert_eq!(&key[10..], &[
+        0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff
+    ]);
+    assert_eq!(BloomType::Row.key(b"row", b"qual"), b"row");
+
+    let mut chunk = BloomChunk::new(10, 0.01);
+    chunk.insert(&key);
+    assert!(chunk.contains(&BloomType::RowCol.key(b"row", b"qual")));
+    assert!(!chunk.contains(&BloomType::Row.key(b"row", b"qual")));
+}
@@ -0,0 +1,37 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::BloomJoin;
+
+struct Order {
+    customer: String,
+    amount: u64,
+}
+
+#[test]
+fn semi_join_reduction() {
+    // 1K customers of interest, 100K orders from 50K customers.
+    let customers = (0..1000).map(|i| format!("customer-{}", i * 50));
+    let mut join = BloomJoin::build(customers, 1000, 0.01);
+    assert_eq!(join.selectivity(), None);
+
+    let orders = (0..100_000u64).map(|i| Order {
+        customer: format!("customer-{}", i % 50_000),
+        amount: i,
+    });
+    let passed = join
+        .filter_rows(orders, |order: &Order| order.customer.as_str())
+        .collect::<Vec<_>>();
+
+    // Every matching order passes, along with a few false positives.
+    let matching = passed.iter().filter(|order| order.amount % 50 == 0).count();
+    assert_eq!(matching, 2000);
+    assert!(passed.len() < 2000 + 2000, "passed: {}", passed.len());
+    assert_eq!(join.passed(), passed.len());
+    assert_eq!(join.passed() + join.suppressed(), 100_000);
+    let selectivity = join.selectivity().unwrap();
+    assert!((0.02..0.04).contains(&selectivity), "{selectivity}");
+
+    join.reset_counts();
+    assert!(join.probe("customer-0"));
+    assert_eq!((join.passed(), join.suppressed()), (1, 0));
+}
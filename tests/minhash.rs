@@ -0,0 +1,57 @@
+#![cfg(feature = "minhash")]
+
+use mqfilters::MinHash;
+
+fn sketch(keys: impl IntoIterator<Item = u64>) -> MinHash<u64> {
+    let mut sketch = MinHash::new(1024);
+    for key in keys {
+        sketch.insert(&key);
+    }
+    sketch
+}
+
+#[test]
+fn jaccard_estimate() {
+    // |A ∩ B| = 5000, |A ∪ B| = 15000.
+    let a = sketch(0..10000);
+    let b = sketch(5000..15000);
+    let estimate = a.jaccard_estimate(&b);
+    assert!((estimate - 1. / 3.).abs() < 0.05, "estimate: {estimate}");
+
+    assert_eq!(a.jaccard_estimate(&a), 1.);
+    assert!(a.jaccard_estimate(&sketch(20000..30000)) < 0.01);
+}
+
+#[test]
+fn sparse_sets_are_densified() {
+    // Far fewer keys than bins, so most bins are empty.
+    let a = sketch(0..40);
+    let b = sketch(20..60);
+    let estimate = a.jaccard_estimate(&b);
+    assert!((estimate - 1. / 3.).abs() < 0.15, "estimate: {estimate}");
+    assert_eq!(a.signature().len(), a.bins());
+}
+
+#[test]
+fn empty_sets() {
+    let empty = MinHash::<u64>::new(64);
+    assert!(empty.is_empty());
+    assert!(empty.signature().is_empty());
+    assert_eq!(empty.jaccard_estimate(&empty), 1.);
+    let mut other = MinHash::new(64);
+    other.insert(&1);
+    assert_eq!(empty.jaccard_estimate(&other), 0.);
+}
+
+#[test]
+fn merge_sketches_union() {
+    let mut a = sketch(0..10000);
+    a.merge(&sketch(10000..20000));
+    assert_eq!(a, sketch(0..20000));
+}
+
+#[test]
+#[should_panic(expected = "sketches differ")]
+fn incompatible_sketches() {
+    MinHash::<u64>::new(64).merge(&MinHash::with_seed(64, 1));
+}
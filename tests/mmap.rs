@@ -0,0 +1,105 @@
+#![cfg(all(feature = "mmap", unix))]
+
+use {
+    mqfilters::{
+        storage::Advice,
+        BloomFilter,
+        ClearableQueryFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+    },
+    std::{fs, io, path::PathBuf},
+};
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mqfilters-{}-{name}", std::process::id()))
+}
+
+#[test]
+fn survives_reopening() {
+    let path = temp_path("reopen");
+    let mut filter = BloomFilter::<u64, _, _>::create_mmap(&path, 10000, 0.01).unwrap();
+    for i in 0..10000 {
+        filter.insert(i);
+    }
+    filter.flush().unwrap();
+    let expected = BloomFilter::<u64>::with_capacity(10000, 0.01);
+    assert_eq!(filter.bit_count(), expected.bit_count());
+    assert_eq!(filter.hash_count(), expected.hash_count());
+    drop(filter);
+
+    let filter = BloomFilter::<u64, _, _>::open_mmap(&path).unwrap();
+    assert!((0..10000).all(|i| filter.contains(&i)));
+    let fp_count = (10000..20000).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 150, "fp_count: {fp_count}");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mappings_share_bits() {
+    let path = temp_path("shared");
+    let mut writer = BloomFilter::<u64, _, _>::create_mmap(&path, 1000, 0.01).unwrap();
+    let mut reader = BloomFilter::<u64, _, _>::open_mmap(&path).unwrap();
+    writer.insert(42);
+    assert!(reader.contains(&42));
+    reader.clear();
+    assert!(!writer.contains(&42));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn invalid_files() {
+    let path = temp_path("invalid");
+    fs::write(&path, [0; 100]).unwrap();
+    let err = BloomFilter::<u64, _, _>::open_mmap(&path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    drop(BloomFilter::<u64, _, _>::create_mmap(&path, 1000, 0.01).unwrap());
+    let len = fs::metadata(&path).unwrap().len();
+    fs::OpenOptions::new()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_len(len - 8)
+        .unwrap();
+    let err = BloomFilter::<u64, _, _>::open_mmap(&path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn to_mmap() {
+    let path = temp_path("copy");
+    let mut filter = BloomFilter::<u64>::with_capacity(1000, 0.01);
+    filter.insert_many(0..1000);
+    let mapped = filter.to_mmap(&path).unwrap();
+    assert_eq!(mapped.bit_count(), filter.bit_count());
+    assert!((0..2000).all(|i| mapped.contains(&i) == filter.contains(&i)));
+    drop(mapped);
+
+    let reopened = BloomFilter::<u64, _, _>::open_mmap(&path).unwrap();
+    assert!((0..1000).all(|i| reopened.contains(&i)));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn advice_and_locking() {
+    let path = temp_path("advice");
+    let mut filter = BloomFilter::<u64>::builder(1000, 0.01)
+        .advise(Advice::WillNeed)
+        .lock()
+        .build_mmap(&path)
+        .unwrap();
+    filter.insert(42);
+    filter.unlock().unwrap();
+    // Dropping pages of a shared mapping keeps the bits in the file.
+    filter.advise(Advice::DontNeed).unwrap();
+    assert!(filter.contains(&42));
+
+    let err = BloomFilter::<u64>::builder(0, 0.01)
+        .build_mmap(&path)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    fs::remove_file(&path).unwrap();
+}
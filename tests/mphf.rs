@@ -0,0 +1,48 @@
+#![cfg(feature = "mphf")]
+
+use mqfilters::{MphfFilter, QueryFilter};
+
+#[test]
+fn from_keys() {
+    let capacity = 100000;
+    let filter = MphfFilter::from_keys(0..capacity, 8).unwrap();
+    assert_eq!(filter.len(), capacity);
+    assert_eq!(filter.fingerprint_bits(), 8);
+
+    // No false negatives.
+    for i in 0..capacity {
+        assert!(filter.contains(&i));
+    }
+
+    // False positive rate is bounded by 2^-r.
+    let fp_count = (capacity..capacity * 2)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((fp_count as f64) < capacity as f64 / 256. * 1.2);
+
+    // Roughly r + 3.7 bits per key.
+    assert!(filter.size_in_bytes() * 8 < capacity * 12);
+}
+
+#[test]
+fn fingerprint_widths() {
+    for bits in [1, 4, 12, 16, 32, 64] {
+        let filter = MphfFilter::from_keys((0..1000).map(|i| format!("key-{i}")), bits).unwrap();
+        for i in 0..1000 {
+            assert!(filter.contains(format!("key-{i}").as_str()));
+        }
+    }
+}
+
+#[test]
+fn empty_and_repeated() {
+    let filter = MphfFilter::<u64>::from_keys([], 8).unwrap();
+    assert!(filter.is_empty());
+    assert!(!filter.contains(&1));
+
+    let filter = MphfFilter::from_keys([1, 2, 2, 3, 1], 8).unwrap();
+    assert_eq!(filter.len(), 3);
+    for i in 1..=3 {
+        assert!(filter.contains(&i));
+    }
+}
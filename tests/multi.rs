@@ -0,0 +1,33 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{multi::MultiFilter, BloomFilter, InsertableQueryFilter, QueryFilter};
+
+#[test]
+fn union_of_filters() {
+    let days = (0..3)
+        .map(|day| {
+            let mut filter = BloomFilter::new(1000, 0.001);
+            for i in 0..1000 {
+                filter.insert(format!("{day}-{i}"));
+            }
+            filter
+        })
+        .collect::<Vec<_>>();
+
+    let union = MultiFilter::new(&days);
+    assert_eq!(union.len(), 3);
+    for day in 0..3 {
+        for i in 0..1000 {
+            let key = format!("{day}-{i}");
+            assert!(union.contains(key.as_str()));
+            // Given the low false positive rate, matches come from the
+            // filter the key was inserted into.
+            assert!(union.position(key.as_str()).unwrap() <= day);
+        }
+    }
+    assert_eq!(union.position("3-0"), None);
+
+    let empty = MultiFilter::<String, BloomFilter<String>>::new([]);
+    assert!(empty.is_empty());
+    assert!(!empty.contains("0-0"));
+}
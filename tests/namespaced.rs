@@ -0,0 +1,39 @@
+#![cfg(feature = "namespaced")]
+
+use mqfilters::NamespacedFilter;
+
+#[test]
+fn namespaces_are_isolated() {
+    let mut filter = NamespacedFilter::new(100, 1000, 0.01);
+    assert_eq!(filter.namespace_count(), 100);
+    assert_eq!(filter.region_bits() % 64, 0);
+
+    for namespace in 0..100 {
+        for i in 0..1000 {
+            filter.insert(namespace, namespace * 1000 + i);
+        }
+    }
+    for namespace in 0..100 {
+        assert!(filter.approx_namespace_capacity(namespace).abs_diff(1000) < 50);
+        for i in 0..1000 {
+            assert!(filter.contains(namespace, &(namespace * 1000 + i)));
+        }
+    }
+
+    // Keys of other namespaces are false positives at the usual rate only.
+    let fp_count = (0..100_000).filter(|key| filter.contains(0, key)).count() - 1000;
+    assert!(fp_count < 99_000 / 100 * 3 / 2, "fp_count: {fp_count}");
+
+    filter.clear_namespace(1);
+    assert_eq!(filter.approx_namespace_capacity(1), 0);
+    assert!(!filter.contains(1, &1000));
+    assert!(filter.contains(0, &0));
+    assert!(filter.contains(2, &2000));
+}
+
+#[test]
+#[should_panic(expected = "namespace 10 out of range")]
+fn namespace_out_of_range() {
+    let filter = NamespacedFilter::<u64>::new(10, 100, 0.01);
+    filter.contains(10, &0);
+}
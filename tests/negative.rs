@@ -0,0 +1,61 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{negative::Lookup, BloomFilter, InsertableQueryFilter, WithNegativeCache};
+
+#[test]
+fn short_circuits_known_misses() {
+    // A tiny, overfilled main filter, so most absent keys get through.
+    let mut filter = BloomFilter::with_bit_count(64, 2);
+    for i in 0..100 {
+        filter.insert(i);
+    }
+    let mut cache = WithNegativeCache::new(filter, 1000, 0.001);
+    assert_eq!(cache.lookup(&5), Lookup::MaybePresent);
+
+    let misses = (1000..1100).filter(|i| cache.lookup(i) == Lookup::MaybePresent);
+    let misses = misses.collect::<Vec<_>>();
+    assert!(misses.len() > 50);
+    for &key in &misses {
+        cache.record_missing(key);
+    }
+    for key in &misses {
+        assert_eq!(cache.lookup(key), Lookup::KnownMissing);
+    }
+    // Present keys still need the backend.
+    let present = (0..100)
+        .filter(|i| cache.lookup(i) == Lookup::MaybePresent)
+        .count();
+    assert!(present >= 99, "present: {present}");
+
+    cache.clear_negative_cache();
+    assert_eq!(cache.lookup(&misses[0]), Lookup::MaybePresent);
+}
+
+#[test]
+fn definite_absence() {
+    let mut cache = WithNegativeCache::new(BloomFilter::new(100, 0.001), 100, 0.01);
+    cache.insert("present");
+    assert_eq!(cache.lookup("present"), Lookup::MaybePresent);
+    assert_eq!(cache.lookup("absent"), Lookup::Absent);
+}
+
+#[test]
+fn generations_expire() {
+    let mut filter = BloomFilter::new(100, 0.01);
+    for i in 0..10000 {
+        filter.insert(i);
+    }
+    let mut cache = WithNegativeCache::new(filter, 10, 0.001);
+    cache.record_missing(0);
+    for i in 1..=10 {
+        cache.record_missing(i);
+    }
+    // Key 0 survived one rotation...
+    assert_eq!(cache.lookup(&0), Lookup::KnownMissing);
+    for i in 11..=20 {
+        cache.record_missing(i);
+    }
+    // ...but not two.
+    assert_eq!(cache.lookup(&0), Lookup::MaybePresent);
+    assert_eq!(cache.lookup(&20), Lookup::KnownMissing);
+}
@@ -0,0 +1,53 @@
+#![cfg(feature = "orc")]
+
+use mqfilters::orc::{decode_index, encode_index, OrcBloomFilter};
+
+#[test]
+fn filter_works() {
+    let mut filter = OrcBloomFilter::new(10000, 0.01);
+    for i in 0..5000 {
+        filter.insert_long(i);
+        filter.insert_str(&format!("value-{i}"));
+    }
+    filter.insert_double(f64::NAN);
+    filter.insert_null();
+    for i in 0..5000 {
+        assert!(filter.contains_long(i));
+        assert!(filter.contains_str(&format!("value-{i}")));
+    }
+    // All NaNs hash alike, like in Java.
+    assert!(filter.contains_double(-f64::NAN));
+    assert!(filter.contains_null());
+
+    let fp_count = (5000..15000).filter(|&i| filter.contains_long(i)).count();
+    assert!(fp_count < 150, "fp_count: {fp_count}");
+}
+
+#[test]
+fn index_round_trip() {
+    let filters = (0..3)
+        .map(|group| {
+            let mut filter = OrcBloomFilter::new(100, 0.05);
+            for i in 0..100 {
+                filter.insert_long(group * 100 + i);
+            }
+            filter
+        })
+        .collect::<Vec<_>>();
+    let bytes = encode_index(&filters);
+    assert_eq!(decode_index(&bytes).unwrap(), filters);
+    assert!(decode_index(&bytes[..bytes.len() - 1]).is_err());
+    assert_eq!(decode_index(&[]).unwrap(), []);
+}
+
+#[test]
+fn legacy_bitset() {
+    // A `BLOOM_FILTER` stream: hash count, then unpacked `fixed64` words.
+    let mut bytes = vec![0x0a, 20, 0x08, 3];
+    for word in [1u64, u64::MAX] {
+        bytes.push(0x11);
+        bytes.extend_from_slice(&word.to_le_bytes());
+    }
+    let filters = decode_index(&bytes).unwrap();
+    assert_eq!(filters, [OrcBloomFilter::from_words(vec![1, u64::MAX], 3)]);
+}
@@ -0,0 +1,72 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{InsertableQueryFilter, PartitionedFilter, QueryFilter};
+
+#[test]
+fn routes_keys() {
+    let mut filter = PartitionedFilter::new(0..4, 10000, 0.01);
+    assert_eq!(filter.partition_count(), 4);
+    for i in 0..20000 {
+        filter.insert(i);
+    }
+    for i in 0..20000 {
+        assert!(filter.contains(&i));
+    }
+
+    // Keys spread evenly across partitions.
+    let mut counts = [0; 4];
+    for i in 0..20000u64 {
+        counts[filter.partition_of(&i).unwrap() as usize] += 1;
+    }
+    assert!(
+        counts.iter().all(|&count| (4000..6000).contains(&count)),
+        "{counts:?}"
+    );
+
+    let fp_count = (20000..120000).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 1500, "fp_count: {fp_count}");
+}
+
+#[test]
+fn bounded_remapping() {
+    let mut filter = PartitionedFilter::<u64>::new([10, 20, 30], 1000, 0.01);
+    let before = (0..10000)
+        .map(|i| filter.partition_of(&i))
+        .collect::<Vec<_>>();
+
+    // Adding a partition only moves keys onto it.
+    filter.add_partition(40);
+    let mut moved = 0;
+    for (i, owner) in before.iter().enumerate() {
+        let now = filter.partition_of(&(i as u64));
+        if now != *owner {
+            assert_eq!(now, Some(40));
+            moved += 1;
+        }
+    }
+    assert!((2000..3000).contains(&moved), "moved: {moved}");
+
+    // Removing it moves them back.
+    filter.remove_partition(40);
+    for (i, owner) in before.iter().enumerate() {
+        assert_eq!(filter.partition_of(&(i as u64)), *owner);
+    }
+}
+
+#[test]
+fn import_export() {
+    let mut source = PartitionedFilter::new([1, 2], 1000, 0.01);
+    for i in 0..1000 {
+        source.insert(i);
+    }
+
+    // Move partition 2 over to a node that only had partition 1.
+    let mut target = PartitionedFilter::new([1], 1000, 0.01);
+    let partition = source.remove_partition(2).unwrap();
+    assert!(target.import_partition(2, partition).is_none());
+    for i in (0..1000).filter(|i| target.partition_of(i) == Some(2)) {
+        assert!(target.contains(&i));
+        assert!(target.partition(2).unwrap().contains(&i));
+    }
+    assert_eq!(target.partition_ids().collect::<Vec<_>>(), [1, 2]);
+}
@@ -0,0 +1,50 @@
+#![cfg(feature = "pbf")]
+
+use mqfilters::{
+    analysis::optimal_bit_count,
+    ClearableQueryFilter,
+    InsertableQueryFilter,
+    PatternBloomFilter,
+    QueryFilter,
+};
+
+#[test]
+fn default_filter() {
+    let mut filter = PatternBloomFilter::new(100, 0.01);
+    assert!(!filter.contains(&"hello"));
+
+    filter.insert("hello");
+    assert!(filter.contains(&"hello"));
+
+    filter.clear();
+    assert!(!filter.contains(&"hello"));
+}
+
+#[test]
+fn with_capacity() {
+    let fp_rate = 0.01;
+    let capacity = 100000;
+    let mut filter = PatternBloomFilter::with_capacity(capacity, fp_rate);
+    // Uneven word load is paid for with space.
+    assert!(filter.size_in_bytes() * 8 > optimal_bit_count(capacity, fp_rate));
+
+    for i in 0..capacity {
+        filter.insert(i);
+        // Ensure that no false negatives are present.
+        assert!(filter.contains(&i));
+    }
+
+    // Fully loaded filter stays close to the target rate for unseen keys.
+    let fp_count = (capacity..capacity * 2)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((fp_count as f64) < capacity as f64 * fp_rate * 1.5);
+}
+
+#[test]
+fn with_size() {
+    let size = 1 << 16;
+    let filter = PatternBloomFilter::<u64>::with_size(size, 0.01);
+    assert_eq!(filter.size_in_bytes(), size);
+    assert!((1..=16).contains(&filter.hash_count()));
+}
@@ -0,0 +1,55 @@
+#![cfg(feature = "prefix")]
+
+use mqfilters::{HashPrefixSet, QueryFilter};
+
+#[test]
+fn from_keys() {
+    let set = HashPrefixSet::from_keys(0..100_000u64, 4);
+    assert_eq!(set.prefix_bytes(), 4);
+    // A collision on 32-bit prefixes is possible, but unlikely.
+    assert!(set.len() > 99_990);
+    for i in 0..100_000u64 {
+        assert!(set.contains(&i));
+    }
+
+    // Expected false positive rate is 100000 / 2^32, i.e. ~2.3e-5.
+    let fp_count = (100_000..1_100_000u64).filter(|i| set.contains(i)).count();
+    assert!(fp_count < 100, "fp_count: {fp_count}");
+}
+
+#[test]
+fn dense_sets_are_compact() {
+    // Gaps between 32-bit prefixes of a million keys fit into 16-bit deltas.
+    let set = HashPrefixSet::from_keys(0..1_000_000u64, 4);
+    assert!(set.size_in_bytes() < set.len() * 5 / 2);
+}
+
+#[test]
+fn from_prefixes() {
+    let prefixes = [7, 3, 3, 1 << 20, (1 << 20) + 1, 0xffff_ffff, 0x1_0000_0005];
+    let set = HashPrefixSet::<String>::from_prefixes(prefixes, 4);
+    // Duplicates are dropped, and prefixes truncated to four bytes.
+    assert_eq!(set.prefixes().collect::<Vec<_>>(), [
+        3,
+        5,
+        7,
+        1 << 20,
+        (1 << 20) + 1,
+        0xffff_ffff
+    ]);
+    for prefix in [3, 5, 7, 1 << 20, (1 << 20) + 1, 0xffff_ffff] {
+        assert!(set.contains_prefix(prefix));
+    }
+    for prefix in [0, 4, 8, (1 << 20) - 1, (1 << 20) + 2, 0xffff_fffe] {
+        assert!(!set.contains_prefix(prefix));
+    }
+
+    // Long runs are split, and still enumerated in order.
+    let prefixes = (0..10_000u64).map(|i| i * 3).collect::<Vec<_>>();
+    let set = HashPrefixSet::<String>::from_prefixes(prefixes.iter().copied(), 2);
+    assert!(set.prefixes().eq(prefixes.iter().map(|p| p & 0xffff)));
+
+    let empty = HashPrefixSet::<String>::from_prefixes([], 4);
+    assert!(empty.is_empty());
+    assert!(!empty.contains("key"));
+}
@@ -0,0 +1,63 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{
+    analysis,
+    profiler::{Deviation, FpProfiler},
+    BloomFilter,
+    InsertableQueryFilter,
+};
+
+#[test]
+fn matches_theoretical_rate() {
+    let capacity = 10000;
+    let fp_rate = 0.01;
+    let mut filter = BloomFilter::new(capacity, fp_rate);
+    let inserted = (0..capacity).collect::<Vec<_>>();
+    let holdout = (capacity..capacity * 11).collect::<Vec<_>>();
+    for key in &inserted {
+        filter.insert(*key);
+    }
+
+    let m = analysis::optimal_bit_count(capacity, fp_rate);
+    let k = analysis::optimal_hash_count(capacity, m);
+    let report =
+        FpProfiler::new(analysis::fp_rate(m, capacity, k)).measure(&filter, &inserted, &holdout);
+
+    assert_eq!(report.inserted, capacity);
+    assert_eq!(report.false_negatives, 0);
+    assert_eq!(report.holdout, capacity * 10);
+    let (lower, upper) = report.confidence_interval();
+    assert!(lower <= report.fp_rate() && report.fp_rate() <= upper);
+    assert_eq!(report.deviation(), None);
+    assert!(!report.is_regression());
+}
+
+#[test]
+fn detects_degraded_filter() {
+    // Filter is overfilled 10x, so the realized rate is far above the target.
+    let mut filter = BloomFilter::new(1000, 0.01);
+    let inserted = (0..10000).collect::<Vec<_>>();
+    let holdout = (10000..20000).collect::<Vec<_>>();
+    for key in &inserted {
+        filter.insert(*key);
+    }
+
+    let report = FpProfiler::new(0.01)
+        .with_confidence(0.999)
+        .measure(&filter, &inserted, &holdout);
+    assert_eq!(report.false_negatives, 0);
+    assert_eq!(report.deviation(), Some(Deviation::Higher));
+    assert!(report.is_regression());
+}
+
+#[test]
+fn detects_false_negatives() {
+    let filter = BloomFilter::<u64>::new(1000, 0.01);
+    let inserted = [1, 2, 3];
+    let holdout = [4, 5, 6];
+
+    let report = FpProfiler::new(0.01).measure(&filter, &inserted, &holdout);
+    assert_eq!(report.false_negatives, 3);
+    assert_eq!(report.false_positives, 0);
+    assert!(report.is_regression());
+}
@@ -0,0 +1,239 @@
+#![cfg(feature = "qf")]
+
+use mqfilters::{
+    qf::{ClusterStats, GrowthMode},
+    ClearableQueryFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+    QueryFilterError,
+    QuotientFilter,
+    RemovableQueryFilter,
+};
+
+#[test]
+fn insert_and_remove() {
+    let mut filter = QuotientFilter::new(10_000, 0.001);
+    assert_eq!(filter.quotient_bits(), 14);
+    assert_eq!(filter.remainder_bits(), 10);
+    for i in 0..10_000u64 {
+        filter.insert(i);
+    }
+    assert_eq!(filter.len(), 10_000);
+    assert!((0..10_000u64).all(|i| filter.contains(&i)));
+    let fp_count = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 100, "fp_count: {fp_count}");
+
+    for i in 0..5000u64 {
+        filter.remove(&i);
+    }
+    assert_eq!(filter.len(), 5000);
+    assert!((5000..10_000u64).all(|i| filter.contains(&i)));
+    let removed = (0..5000u64).filter(|i| filter.contains(i)).count();
+    assert!(removed < 10, "removed: {removed}");
+
+    filter.clear();
+    assert!(filter.is_empty());
+    assert!(!filter.contains(&7000));
+}
+
+#[test]
+fn duplicates() {
+    let mut filter = QuotientFilter::new(100, 0.01);
+    filter.insert("a");
+    filter.insert("a");
+    filter.remove("a");
+    assert!(filter.contains("a"));
+    filter.remove("a");
+    assert!(!filter.contains("a"));
+}
+
+#[test]
+fn grows() {
+    let mut filter = QuotientFilter::with_bits(4, 12).unwrap();
+    for i in 0..10_000u64 {
+        filter.insert(i);
+    }
+    assert_eq!(filter.fingerprint_bits(), 16);
+    assert_eq!(filter.quotient_bits(), 14);
+    assert!((0..10_000u64).all(|i| filter.contains(&i)));
+
+    // Remainders cannot shrink below a bit.
+    let mut filter = QuotientFilter::with_bits(3, 1).unwrap();
+    let mut key = 0u64;
+    while filter.try_insert(key).is_ok() {
+        key += 1;
+    }
+    assert_eq!(filter.try_insert(key), Err(QueryFilterError::Full));
+    assert_eq!(filter.quotient_bits(), 3);
+    assert_eq!(filter.len(), 7);
+    assert!((0..key).all(|i| filter.contains(&i)));
+}
+
+#[test]
+fn grows_incrementally() {
+    let mut doubling = QuotientFilter::with_bits(4, 12).unwrap();
+    let mut filter = QuotientFilter::with_bits(4, 12)
+        .unwrap()
+        .with_growth_mode(GrowthMode::Incremental);
+    let mut splits = 0;
+    for i in 0..10_000u64 {
+        doubling.insert(i);
+        let splitting = filter.is_splitting();
+        filter.insert(i);
+        splits += (!splitting && filter.is_splitting()) as usize;
+        assert!(filter.contains(&(i / 2)), "i: {i}");
+    }
+    assert_eq!(splits, 10);
+    assert_eq!(filter.len(), 10_000);
+    assert_eq!(filter.quotient_bits(), doubling.quotient_bits());
+    assert!(filter.fingerprints().eq(doubling.fingerprints()));
+
+    // Keys are found and removed in either table while splitting.
+    let mut filter = QuotientFilter::with_bits(10, 8)
+        .unwrap()
+        .with_growth_mode(GrowthMode::Incremental);
+    let mut key = 0u64;
+    while !filter.is_splitting() {
+        filter.insert(key);
+        key += 1;
+    }
+    assert!((0..key).all(|i| filter.contains(&i)));
+    for i in (0..key).step_by(2) {
+        filter.remove(&i);
+    }
+    assert_eq!(filter.len() as u64, key / 2);
+    assert!((1..key).step_by(2).all(|i| filter.contains(&i)));
+    while filter.is_splitting() {
+        filter.insert(key);
+        key += 1;
+    }
+    assert!((1..key / 2).step_by(2).all(|i| filter.contains(&i)));
+    let removed = (0..key / 2)
+        .step_by(2)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!(removed < 10, "removed: {removed}");
+}
+
+#[test]
+fn merge() {
+    let mut a = QuotientFilter::new(1000, 0.001);
+    let mut b = QuotientFilter::new(5000, 0.001 * 4.);
+    assert_eq!(a.fingerprint_bits(), b.fingerprint_bits());
+    for i in 0..1000u64 {
+        a.insert(i);
+    }
+    for i in 1000..5000u64 {
+        b.insert(i);
+    }
+    a.merge(&b).unwrap();
+    assert_eq!(a.len(), 5000);
+    assert_eq!(a.quotient_bits(), 13);
+    assert!((0..5000u64).all(|i| a.contains(&i)));
+
+    let c = QuotientFilter::<u64>::new(1000, 0.1);
+    assert!(matches!(
+        a.merge(&c),
+        Err(QueryFilterError::IncompatibleFilters(_))
+    ));
+}
+
+#[test]
+fn fingerprints() {
+    let mut filter = QuotientFilter::with_bits(4, 12).unwrap();
+    for i in 0..10u64 {
+        filter.insert(i);
+    }
+    filter.insert(0);
+    let pairs = filter.fingerprints().collect::<Vec<_>>();
+    assert_eq!(pairs.len(), 11);
+    assert!(pairs.windows(2).all(|pair| pair[0] <= pair[1]));
+    assert!(pairs
+        .iter()
+        .all(|&(quotient, remainder)| quotient < 16 && remainder < 1 << 12));
+    assert!(pairs.windows(2).any(|pair| pair[0] == pair[1]));
+
+    // Full fingerprints are kept when growing, if split differently.
+    let full = |filter: &QuotientFilter<u64>| {
+        filter
+            .fingerprints()
+            .map(|(quotient, remainder)| (quotient as u64) << filter.remainder_bits() | remainder)
+            .collect::<Vec<_>>()
+    };
+    let before = full(&filter);
+    filter.grow().unwrap();
+    assert_eq!(full(&filter), before);
+}
+
+#[test]
+fn invalid_params() {
+    assert!(QuotientFilter::<u64>::with_bits(0, 8).is_err());
+    assert!(QuotientFilter::<u64>::with_bits(8, 0).is_err());
+    assert!(QuotientFilter::<u64>::with_bits(8, 62).is_err());
+    assert!(QuotientFilter::<u64>::with_bits(10, 55).is_err());
+
+    for fp_rate in [0., 1., f64::NAN, 1e-30] {
+        assert!(matches!(
+            QuotientFilter::<u64>::with_capacity(100, fp_rate),
+            Err(QueryFilterError::InvalidFpRate(_))
+        ));
+    }
+    // Fingerprints of huge tables leave no room for remainders.
+    assert!(QuotientFilter::<u64>::with_capacity(usize::MAX, 0.01).is_err());
+    assert!(QuotientFilter::<u64>::with_capacity(1 << 60, 0.01).is_err());
+    let filter = QuotientFilter::<u64>::with_capacity(100, 0.01).unwrap();
+    assert_eq!((filter.quotient_bits(), filter.remainder_bits()), (8, 7));
+}
+
+#[test]
+fn merge_small_tables() {
+    // Small tables, whose clusters often wrap around their end.
+    for seed in 0..300u64 {
+        let keys = (0..seed % 12).map(|i| seed * 100 + i).collect::<Vec<_>>();
+        let other_keys = (0..seed % 7)
+            .map(|i| seed * 100 + 50 + i)
+            .collect::<Vec<_>>();
+        let mut a = QuotientFilter::with_bits(4, 8).unwrap();
+        let mut b = QuotientFilter::with_bits(4, 8).unwrap();
+        keys.iter().for_each(|&key| a.insert(key));
+        other_keys.iter().for_each(|&key| b.insert(key));
+        a.merge(&b).unwrap();
+        assert_eq!(a.len(), keys.len() + other_keys.len());
+        assert!(keys.iter().chain(&other_keys).all(|key| a.contains(key)));
+        keys.iter().chain(&other_keys).for_each(|key| a.remove(key));
+        assert!(a.is_empty());
+    }
+}
+
+#[test]
+fn cluster_stats() {
+    let mut filter = QuotientFilter::with_bits(4, 8).unwrap();
+    assert_eq!(filter.cluster_stats(), ClusterStats::default());
+    assert_eq!(filter.cluster_stats().mean_run_len(), 0.);
+
+    filter.insert(1);
+    filter.insert(1);
+    let stats = filter.cluster_stats();
+    assert_eq!((stats.entries, stats.runs, stats.clusters), (2, 1, 1));
+    assert_eq!(
+        (stats.max_run_len, stats.max_cluster_len, stats.max_shift),
+        (2, 2, 1)
+    );
+    assert_eq!(stats.mean_run_len(), 2.);
+
+    let mut filter = QuotientFilter::new(10_000, 0.01);
+    for i in 0..7000u64 {
+        filter.insert(i);
+    }
+    let stats = filter.cluster_stats();
+    let quotients = filter
+        .fingerprints()
+        .map(|(quotient, _)| quotient)
+        .collect::<std::collections::BTreeSet<_>>();
+    assert_eq!(stats.entries, 7000);
+    assert_eq!(stats.runs, quotients.len());
+    assert!(stats.clusters <= stats.runs);
+    assert!(stats.max_run_len <= stats.max_cluster_len);
+    assert!(stats.max_shift < stats.max_cluster_len);
+    assert!(stats.mean_cluster_len() >= stats.mean_run_len());
+}
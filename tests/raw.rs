@@ -0,0 +1,68 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{
+    hash::{ProbeHasher, ProbeStrategy},
+    BloomFilter,
+    InsertableQueryFilter,
+    RawFilter,
+};
+
+#[test]
+fn both_encodings() {
+    let mut filter = BloomFilter::<String>::new(1000, 0.01);
+    (0..1000).for_each(|i| filter.insert(format!("key-{i}")));
+    let mut streamed = Vec::new();
+    filter.encode_into(&mut streamed).unwrap();
+
+    for bytes in [filter.to_bytes(), streamed] {
+        let raw = RawFilter::from_bytes(&bytes).unwrap();
+        assert_eq!(raw.bit_count(), filter.bit_count());
+        assert_eq!(raw.hash_count(), filter.hash_count());
+        assert!((0..1000).all(|i| raw.contains_hash(format!("key-{i}").as_str())));
+        // Strings hash as their bytes, followed by `0xff`.
+        assert!((0..1000).all(|i| {
+            let key = format!("key-{i}");
+            raw.contains_bytes(&[key.as_bytes(), &[0xff]].concat())
+        }));
+        let fp_count = (1000..2000)
+            .filter(|i| raw.contains_hash(&format!("key-{i}")))
+            .count();
+        assert!(fp_count < 20, "fp_count: {fp_count}");
+    }
+}
+
+#[test]
+fn seeded_and_streamed_keys() {
+    let hasher = ProbeHasher::new(ProbeStrategy::Triple).with_seed1(7);
+    let mut filter = BloomFilter::<u64>::builder(1000, 0.01)
+        .hasher(hasher)
+        .build()
+        .unwrap();
+    filter.insert(42);
+    let mut key = filter.hasher().key_hasher();
+    key.update(b"chunk 1, ");
+    key.update(b"chunk 2");
+    filter.insert_streamed(&key);
+
+    // Seeds and strategy are recorded by `to_bytes` only.
+    let raw = RawFilter::from_bytes(&filter.to_bytes()).unwrap();
+    assert!(raw.contains_hash(&42u64));
+    assert!(raw.contains_bytes(&42u64.to_ne_bytes()));
+    assert!(raw.contains_bytes(b"chunk 1, chunk 2"));
+    assert!(!raw.contains_hash(&43u64));
+    let mut streamed = Vec::new();
+    filter.encode_into(&mut streamed).unwrap();
+    assert!(RawFilter::from_bytes(&streamed).is_err());
+}
+
+#[test]
+fn invalid_bytes() {
+    let filter = BloomFilter::<u64>::new(1000, 0.01);
+    let mut bytes = filter.to_bytes();
+    assert!(RawFilter::from_bytes(&bytes[..40]).is_err());
+    assert!(RawFilter::from_bytes(b"MQ").is_err());
+    bytes[50] ^= 1;
+    assert!(RawFilter::from_bytes(&bytes).is_err());
+    bytes[..4].copy_from_slice(b"MQXX");
+    assert!(RawFilter::from_bytes(&bytes).is_err());
+}
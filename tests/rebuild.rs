@@ -0,0 +1,68 @@
+#![cfg(feature = "bf")]
+
+use {
+    mqfilters::{rebuild::RebuildableFilter, BloomFilter, InsertableQueryFilter, QueryFilter},
+    std::sync::mpsc,
+};
+
+#[test]
+fn rebuild_keeps_concurrent_updates() {
+    // An overfilled filter, to be replaced by a properly sized one.
+    let filter = RebuildableFilter::new(BloomFilter::with_bit_count(64, 2));
+    for i in 0..100u64 {
+        filter.insert(i);
+    }
+    assert!(filter.with_filter(|filter| filter.approx_fp_rate()) > 0.5);
+
+    let (start, started) = mpsc::channel();
+    let (resume, resumed) = mpsc::channel::<()>();
+    let rebuild = filter
+        .rebuild_in_background(move || {
+            let mut filter = BloomFilter::new(1000, 0.001);
+            for i in 0..100u64 {
+                filter.insert(i);
+            }
+            start.send(()).unwrap();
+            resumed.recv().unwrap();
+            filter
+        })
+        .unwrap();
+    assert!(filter.rebuild_in_background(|| unreachable!()).is_err());
+
+    // The old filter keeps serving, and updates are carried over.
+    started.recv().unwrap();
+    assert!(filter.is_rebuilding());
+    filter.insert(1000);
+    assert!(filter.contains(&1000));
+    resume.send(()).unwrap();
+
+    let old = rebuild.join().unwrap().unwrap();
+    assert_eq!(old.bit_count(), 64);
+    assert!(!filter.is_rebuilding());
+    assert!((0..100u64).chain([1000]).all(|i| filter.contains(&i)));
+    assert!(filter.with_filter(|filter| filter.approx_fp_rate()) < 0.001);
+}
+
+#[test]
+fn manual_rebuild() {
+    let filter = RebuildableFilter::new(BloomFilter::new(100, 0.01));
+    assert!(filter
+        .complete_rebuild(BloomFilter::new(100, 0.01))
+        .is_err());
+
+    filter.begin_rebuild().unwrap();
+    filter.insert("logged");
+    filter.abort_rebuild();
+    assert!(filter
+        .complete_rebuild(BloomFilter::new(100, 0.01))
+        .is_err());
+    assert!(filter.contains("logged"));
+
+    filter.begin_rebuild().unwrap();
+    filter.insert("replayed");
+    filter
+        .complete_rebuild(BloomFilter::new(100, 0.01))
+        .unwrap();
+    assert!(filter.contains("replayed"));
+    assert!(!filter.contains("logged"));
+}
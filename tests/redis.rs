@@ -0,0 +1,81 @@
+#![cfg(feature = "redis")]
+
+use mqfilters::{
+    redis::{RedisBloomFilter, RedisKey},
+    InsertableQueryFilter,
+    QueryFilter,
+};
+
+fn key(i: usize) -> RedisKey<String> {
+    RedisKey(format!("item-{i}"))
+}
+
+#[test]
+fn filter_works() {
+    let mut filter = RedisBloomFilter::new(1000, 0.01).unwrap();
+    assert_eq!(filter.filter().bit_count() % 64, 0);
+    assert_eq!(filter.filter().hash_count(), 7);
+    for i in 0..1000 {
+        filter.insert(key(i));
+    }
+    filter.insert(key(0));
+    assert!(filter.len() <= 1000 && filter.len() > 990);
+    assert!((0..1000).all(|i| filter.contains(&key(i))));
+    let fp_count = (1000..11000).filter(|&i| filter.contains(&key(i))).count();
+    assert!(fp_count < 150, "fp_count: {fp_count}");
+
+    assert!(RedisBloomFilter::<RedisKey<String>>::new(0, 0.01).is_err());
+    assert!(RedisBloomFilter::<RedisKey<String>>::new(1000, 1.).is_err());
+}
+
+#[test]
+fn scandump_round_trip() {
+    let mut filter = RedisBloomFilter::new(1000, 0.001).unwrap();
+    for i in 0..500 {
+        filter.insert(RedisKey(format!("item-{i}").into_bytes()));
+    }
+    let chunks = filter.scandump();
+    assert_eq!(chunks[0].0, 1);
+    assert_eq!(chunks[0].1.len(), 20 + 53);
+    assert_eq!(
+        chunks.last().unwrap().0 as usize,
+        1 + filter.filter().bit_count() / 8
+    );
+
+    // Including the final empty chunk, as `BF.SCANDUMP` returns it.
+    let loaded: RedisBloomFilter<RedisKey<Vec<u8>>> =
+        RedisBloomFilter::from_scandump(chunks.iter().cloned().chain([(0, Vec::new())])).unwrap();
+    assert_eq!(loaded.len(), filter.len());
+    assert_eq!(loaded.capacity(), 1000);
+    assert_eq!(loaded.fp_rate(), 0.001);
+    assert_eq!(loaded.scandump(), chunks);
+    assert!((0..500).all(|i| loaded.contains(&RedisKey(format!("item-{i}").into_bytes()))));
+}
+
+#[test]
+fn scandump_is_validated() {
+    let mut filter = RedisBloomFilter::new(1000, 0.01).unwrap();
+    filter.insert(key(1));
+    let chunks = filter.scandump();
+    let load =
+        |chunks: Vec<(i64, Vec<u8>)>| RedisBloomFilter::<RedisKey<String>>::from_scandump(chunks);
+
+    assert!(load(Vec::new()).is_err());
+    // Missing or out of bounds data.
+    assert!(load(chunks[..1].to_vec()).is_err());
+    let mut shifted = chunks.clone();
+    shifted[1].0 += 1;
+    assert!(load(shifted).is_err());
+    // Scaled chains are not supported.
+    let mut scaled = chunks.clone();
+    scaled[0].1[8] = 2;
+    assert!(load(scaled).is_err());
+    // Neither is 32-bit hashing.
+    let mut hash32 = chunks.clone();
+    hash32[0].1[12] = 1;
+    assert!(load(hash32).is_err());
+    // Inconsistent bit count.
+    let mut bits = chunks;
+    bits[0].1[28] ^= 1;
+    assert!(load(bits).is_err());
+}
@@ -0,0 +1,41 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{
+    replication::{apply, diff, digest},
+    BloomFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+};
+
+#[test]
+fn replicas_converge() {
+    let mut a = BloomFilter::with_bit_count(1 << 20, 7);
+    let mut b = BloomFilter::with_bit_count(1 << 20, 7);
+    for i in 0..10000 {
+        a.insert(i);
+        b.insert(i);
+    }
+    // A few divergent inserts touch only a few blocks.
+    for i in 10000..10010 {
+        a.insert(i);
+    }
+    for i in 20000..20010 {
+        b.insert(i);
+    }
+
+    let patch = diff(&a, &digest(&b)).unwrap();
+    assert!(patch.words.len() <= 20 * 7 * 64);
+    apply(&mut b, &patch).unwrap();
+    let patch = diff(&b, &digest(&a)).unwrap();
+    apply(&mut a, &patch).unwrap();
+
+    assert_eq!(digest(&a), digest(&b));
+    assert!(diff(&a, &digest(&b)).unwrap().words.is_empty());
+    for i in (0..10010).chain(20000..20010) {
+        assert!(a.contains(&i));
+        assert!(b.contains(&i));
+    }
+
+    let other = BloomFilter::<u64>::with_bit_count(1 << 19, 7);
+    assert!(diff(&a, &digest(&other)).is_err());
+}
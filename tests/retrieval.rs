@@ -0,0 +1,76 @@
+#![cfg(feature = "retrieval")]
+
+use mqfilters::{BloomierFilter, QueryFilter, QueryFilterError, StaticRetrieval, XorRetrieval};
+
+#[test]
+fn retrieves_values() {
+    let pairs = (0..10000u64).map(|i| (i, i % 16)).collect::<Vec<_>>();
+    let retrieval = XorRetrieval::try_from_pairs(pairs.iter().copied(), 4).unwrap();
+    assert_eq!(retrieval.value_bits(), 4);
+    for (key, value) in pairs {
+        assert_eq!(retrieval.get(&key), value);
+    }
+
+    // Roughly 1.23 * r bits per key.
+    assert!(retrieval.size_in_bytes() * 8 < 10000 * 5);
+}
+
+#[test]
+fn wide_values() {
+    let pairs = (0..1000u64).map(|i| (format!("key-{i}"), i.wrapping_mul(0x9e3779b97f4a7c15)));
+    let retrieval = XorRetrieval::try_from_pairs(pairs.clone(), 64).unwrap();
+    for (key, value) in pairs {
+        assert_eq!(retrieval.get(key.as_str()), value);
+    }
+}
+
+#[test]
+fn empty_and_repeated() {
+    let retrieval = XorRetrieval::<u64>::try_from_pairs([], 8).unwrap();
+    assert_eq!(retrieval.get(&1), 0);
+
+    let retrieval = XorRetrieval::try_from_pairs([(1, 7), (2, 3), (1, 7)], 8).unwrap();
+    assert_eq!(retrieval.get(&1), 7);
+    assert_eq!(retrieval.get(&2), 3);
+}
+
+#[test]
+fn invalid_input() {
+    assert_eq!(
+        XorRetrieval::try_from_pairs([(1, 7), (2, 3), (1, 4)], 8).err(),
+        Some(QueryFilterError::DuplicateKey)
+    );
+    assert_eq!(
+        XorRetrieval::try_from_pairs([(1, 16)], 4).err(),
+        Some(QueryFilterError::ValueOutOfRange { value: 16, bits: 4 })
+    );
+}
+
+#[test]
+fn bloomier_filter() {
+    let pairs = (0..10000u64).map(|i| (i, i % 16)).collect::<Vec<_>>();
+    let filter = BloomierFilter::try_from_pairs(pairs.iter().copied(), 4, 8).unwrap();
+    assert_eq!(filter.value_bits(), 4);
+    assert_eq!(filter.fingerprint_bits(), 8);
+    assert_eq!(filter.fp_rate(), 1. / 256.);
+    for (key, value) in pairs {
+        assert_eq!(filter.get(&key), Some(value));
+        assert!(filter.contains(&key));
+    }
+
+    // Roughly 1/256 of other keys are given a value.
+    let fp_count = (10000..110_000u64)
+        .filter(|key| filter.get(key).is_some())
+        .count();
+    assert!((250..550).contains(&fp_count), "fp_count: {fp_count}");
+    assert!(filter.size_in_bytes() * 8 < 10000 * 15);
+
+    assert_eq!(
+        BloomierFilter::try_from_pairs([(1, 7), (1, 4)], 4, 8).err(),
+        Some(QueryFilterError::DuplicateKey)
+    );
+    assert_eq!(
+        BloomierFilter::try_from_pairs([(1, 16)], 4, 8).err(),
+        Some(QueryFilterError::ValueOutOfRange { value: 16, bits: 4 })
+    );
+}
@@ -0,0 +1,86 @@
+#![cfg(feature = "ribbon")]
+
+use mqfilters::{QueryFilter, RibbonFilter};
+
+#[test]
+fn from_keys() {
+    let keys = (0..100_000u64).collect::<Vec<_>>();
+    let filter = RibbonFilter::from_keys(keys.iter().copied(), 0.01).unwrap();
+    assert_eq!(filter.fingerprint_bits(), 7);
+    assert!(keys.iter().all(|key| filter.contains(key)));
+    let fp_count = (100_000..1_100_000u64)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((6500..9000).contains(&fp_count), "fp_count: {fp_count}");
+    // Within 11% of 7 bits per key, i.e. within 17% of the minimum of
+    // log2(100) bits per key.
+    let bits = filter.size_in_bytes() * 8;
+    assert!(bits < 777_000, "{bits}");
+    assert!((bits as f64) < 1.17 * 100_000. * 100f64.log2(), "{bits}");
+}
+
+#[test]
+fn homogeneous() {
+    let keys = (0..100_000u64).collect::<Vec<_>>();
+    let standard = RibbonFilter::from_keys(keys.iter().copied(), 0.01).unwrap();
+    let filter = RibbonFilter::homogeneous(keys.iter().copied(), 0.01, 1.05).unwrap();
+    assert!(filter.is_homogeneous());
+    assert_eq!(filter.fingerprint_bits(), 7);
+    assert!(keys.iter().all(|key| filter.contains(key)));
+    let fp_count = (100_000..1_100_000u64)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((6500..9500).contains(&fp_count), "fp_count: {fp_count}");
+    assert!(filter.size_in_bytes() < standard.size_in_bytes());
+
+    // Always builds, if with more false positives.
+    let tight = RibbonFilter::homogeneous(keys.iter().copied(), 0.01, 1.).unwrap();
+    assert!(keys.iter().all(|key| tight.contains(key)));
+    assert!(RibbonFilter::<u64>::homogeneous(0..10, 0.01, 0.9).is_err());
+    assert!(RibbonFilter::<u64>::homogeneous(0..10, 0.01, f64::NAN).is_err());
+    assert!(RibbonFilter::<u64>::homogeneous(0..10, 0.01, f64::INFINITY).is_err());
+}
+
+#[test]
+fn small_sets() {
+    for n in 0..50u64 {
+        let filter = RibbonFilter::from_keys((0..n).chain(0..n), 0.001).unwrap();
+        assert!((0..n).all(|key| filter.contains(&key)), "n: {n}");
+    }
+}
+
+#[test]
+fn invalid_fp_rate() {
+    assert!(RibbonFilter::<u64>::from_keys(0..10, 1.).is_err());
+    assert!(RibbonFilter::<u64>::from_keys(0..10, 1e-12).is_err());
+}
+
+#[test]
+fn bumped() {
+    let keys = (0..100_000u64).collect::<Vec<_>>();
+    let standard = RibbonFilter::from_keys(keys.iter().copied(), 0.01).unwrap();
+    let filter = RibbonFilter::bumped(keys.iter().copied(), 0.01, 1.).unwrap();
+    assert!(filter.layer_count() > 1);
+    assert_eq!(filter.fingerprint_bits(), 7);
+    assert!(keys.iter().all(|key| filter.contains(key)));
+    // Bumping keeps the false positive rate, unlike lowering the overhead of
+    // the homogeneous variant.
+    let fp_count = (100_000..1_100_000u64)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((6500..9000).contains(&fp_count), "fp_count: {fp_count}");
+    assert!(filter.size_in_bytes() < standard.size_in_bytes());
+
+    assert_eq!(
+        RibbonFilter::bumped(keys.iter().copied(), 0.01, 1.1)
+            .unwrap()
+            .layer_count(),
+        1
+    );
+    assert!(RibbonFilter::<u64>::bumped(0..10, 0.01, 0.9).is_err());
+    assert!(RibbonFilter::<u64>::bumped(0..10, 1., 1.05).is_err());
+    for n in 0..50u64 {
+        let filter = RibbonFilter::bumped((0..n).chain(0..n), 0.001, 1.).unwrap();
+        assert!((0..n).all(|key| filter.contains(&key)), "n: {n}");
+    }
+}
@@ -0,0 +1,51 @@
+#![cfg(feature = "sbbf")]
+
+use mqfilters::{BlockedBloomFilter, ClearableQueryFilter, InsertableQueryFilter, QueryFilter};
+
+#[test]
+fn insert_and_query() {
+    let mut filter = BlockedBloomFilter::new(10_000, 0.01);
+    // Sized like parquet-rs: ~11.6 KB, rounded up to a power of two.
+    assert_eq!(filter.size_in_bytes(), 16 * 1024);
+    assert_eq!(filter.block_count(), 512);
+    for i in 0..10_000u64 {
+        filter.insert(i);
+    }
+    assert!((0..10_000u64).all(|i| filter.contains(&i)));
+    let fp_count = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 500, "fp_count: {fp_count}");
+
+    filter.clear();
+    assert!(!filter.contains(&1));
+}
+
+#[test]
+fn plain_encoding() {
+    let mut filter = BlockedBloomFilter::<i64>::with_size(1024);
+    filter.insert(42);
+    // Integers hash the same as their plain encoding.
+    assert!(filter.contains_bytes(&42i64.to_le_bytes()));
+
+    filter.insert_bytes(b"hello");
+    assert!(filter.contains_bytes(b"hello"));
+    assert!(!filter.contains_bytes(b"world"));
+}
+
+#[test]
+fn bytes_round_trip() {
+    let mut filter = BlockedBloomFilter::<&str>::with_size(100);
+    assert_eq!(filter.size_in_bytes(), 128);
+    filter.insert_bytes(b"parquet");
+    let bytes = filter.to_bytes();
+    assert_eq!(bytes.len(), 128);
+
+    let loaded = BlockedBloomFilter::<&str>::from_bytes(&bytes).unwrap();
+    assert!(loaded.contains_bytes(b"parquet"));
+    assert_eq!(loaded.to_bytes(), bytes);
+
+    // Each value sets exactly one bit per word of its block.
+    assert_eq!(bytes.iter().map(|b| b.count_ones()).sum::<u32>(), 8);
+
+    assert!(BlockedBloomFilter::<&str>::from_bytes(&[]).is_err());
+    assert!(BlockedBloomFilter::<&str>::from_bytes(&[0; 33]).is_err());
+}
@@ -0,0 +1,39 @@
+#![cfg(feature = "sbf")]
+
+use mqfilters::{InsertableQueryFilter, QueryFilter, StableBloomFilter};
+
+#[test]
+fn stable_fp_rate() {
+    let mut filter = StableBloomFilter::new(100_000, 0.01);
+    assert!((filter.stable_fp_rate() - 0.01).abs() < 0.001);
+
+    // Far more keys than counters: the rate stabilizes instead of saturating.
+    for i in 0..1_000_000u64 {
+        filter.insert(i);
+    }
+    let fp_count = (2_000_000..2_100_000u64)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((500..1500).contains(&fp_count), "fp_count: {fp_count}");
+    assert!((filter.approx_fp_rate() - 0.01).abs() < 0.005);
+}
+
+#[test]
+fn detects_recent_duplicates() {
+    let mut filter = StableBloomFilter::new(100_000, 0.01);
+    for i in 0..1_000_000u64 {
+        filter.insert(i);
+    }
+    // Keys survive about `max * m / P` inserts.
+    let survivors = |range: std::ops::Range<u64>| range.filter(|i| filter.contains(i)).count();
+    assert_eq!(survivors(999_000..1_000_000), 1000);
+    assert!(survivors(0..1000) < 50);
+}
+
+#[test]
+fn invalid_params() {
+    assert!(StableBloomFilter::<u64>::with_params(1000, 0.01, 0, 3).is_err());
+    assert!(StableBloomFilter::<u64>::with_params(1000, 0.01, 9, 3).is_err());
+    assert!(StableBloomFilter::<u64>::with_params(1000, 0.01, 3, 0).is_err());
+    assert!(StableBloomFilter::<u64>::with_params(1000, 1., 3, 3).is_err());
+}
@@ -0,0 +1,52 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{ClearableQueryFilter, InsertableQueryFilter, QueryFilter, ScalableBloomFilter};
+
+#[test]
+fn grows_within_fp_bound() {
+    let mut filter = ScalableBloomFilter::new(1000, 0.01);
+    assert_eq!(filter.slice_count(), 1);
+    for i in 0..100_000u64 {
+        filter.insert(i);
+    }
+    // 1000 + 2000 + ... + 32_000 < 100_000 < 1000 + ... + 64_000.
+    assert_eq!(filter.slice_count(), 7);
+    assert!((0..100_000u64).all(|i| filter.contains(&i)));
+    assert!(
+        filter.approx_fp_rate() < 0.01,
+        "{}",
+        filter.approx_fp_rate()
+    );
+    let fp_count = (100_000..200_000u64).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 1000, "fp_count: {fp_count}");
+
+    filter.clear();
+    assert!(filter.is_empty());
+    assert_eq!(filter.slice_count(), 1);
+    assert!(!filter.contains(&1));
+}
+
+#[test]
+fn repeated_keys() {
+    let mut filter = ScalableBloomFilter::new(10, 0.01);
+    for _ in 0..100 {
+        filter.insert("key");
+    }
+    assert_eq!(filter.len(), 1);
+    assert_eq!(filter.slice_count(), 1);
+}
+
+#[test]
+fn params() {
+    let mut filter = ScalableBloomFilter::with_params(100, 0.01, 4, 0.5).unwrap();
+    for i in 0..2100u64 {
+        filter.insert(i);
+    }
+    // 100 + 400 + 1600 >= 2100 (barring false positives).
+    assert_eq!(filter.slice_count(), 3);
+    assert!(filter.slices()[2].bit_count() > 4 * filter.slices()[1].bit_count());
+
+    assert!(ScalableBloomFilter::<u64>::with_params(100, 0.01, 0, 0.5).is_err());
+    assert!(ScalableBloomFilter::<u64>::with_params(100, 0.01, 2, 1.).is_err());
+    assert!(ScalableBloomFilter::<u64>::with_params(100, 0.01, 2, 0.).is_err());
+}
@@ -0,0 +1,237 @@
+#![cfg(all(feature = "serde", feature = "bf"))]
+
+use mqfilters::{
+    hash::{ProbeHasher, ProbeStrategy},
+    BloomFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+};
+
+fn filter() -> BloomFilter<u64> {
+    let hasher = ProbeHasher::new(ProbeStrategy::Triple).with_seed1(7);
+    let mut filter = BloomFilter::with_capacity_and_hasher(1000, 0.01, hasher);
+    for i in 0..1000 {
+        filter.insert(i);
+    }
+    filter
+}
+
+#[test]
+fn json_is_readable() {
+    let filter = BloomFilter::<u64>::with_bit_count(12, 3);
+    let json = serde_json::to_value(&filter).unwrap();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "bit_count": 12,
+            "hash_count": 3,
+            "hasher": {
+                "seed1": 12345,
+                "seed2": 67890,
+                "seed3": 24680,
+                "strategy": "enhanced_double",
+            },
+            "bits": "AAA=",
+        })
+    );
+}
+
+#[test]
+fn json_round_trip() {
+    let filter = filter();
+    let json = serde_json::to_string_pretty(&filter).unwrap();
+    let read: BloomFilter<u64> = serde_json::from_str(&json).unwrap();
+    assert!(read.ones().eq(filter.ones()));
+    assert_eq!(read.hasher(), filter.hasher());
+    assert_eq!(read.hash_count(), filter.hash_count());
+    for i in 0..2000 {
+        assert_eq!(read.contains(&i), filter.contains(&i));
+    }
+}
+
+#[test]
+fn binary_and_json_agree() {
+    let filter = filter();
+    let bytes = postcard::to_allocvec(&filter).unwrap();
+    let from_binary: BloomFilter<u64> = postcard::from_bytes(&bytes).unwrap();
+    let json = serde_json::to_string(&from_binary).unwrap();
+    let from_json: BloomFilter<u64> = serde_json::from_str(&json).unwrap();
+    assert_eq!(postcard::to_allocvec(&from_json).unwrap(), bytes);
+    assert!(from_json.ones().eq(filter.ones()));
+}
+
+#[test]
+fn invalid_bits() {
+    let json = |bits: &str| {
+        format!(
+            r#"{{"bit_count":12,"hash_count":3,"hasher":{{"seed1":1,"seed2":2,"seed3":3,"strategy":"double"}},"bits":"{bits}"}}"#
+        )
+    };
+    assert!(serde_json::from_str::<BloomFilter<u64>>(&json("AAA=")).is_ok());
+    // Too few bytes, bits past the bit count, and not base64 at all.
+    assert!(serde_json::from_str::<BloomFilter<u64>>(&json("AA==")).is_err());
+    assert!(serde_json::from_str::<BloomFilter<u64>>(&json("APA=")).is_err());
+    assert!(serde_json::from_str::<BloomFilter<u64>>(&json("!!!")).is_err());
+}
+
+#[test]
+#[cfg(all(
+    feature = "cbf",
+    feature = "cuckoo",
+    feature = "fuse",
+    feature = "qf",
+    feature = "ribbon",
+    feature = "sbbf",
+    feature = "sbf",
+    feature = "spectral",
+    feature = "xor"
+))]
+fn other_filters_round_trip() {
+    use mqfilters::{
+        BinaryFuseFilter,
+        BlockedBloomFilter,
+        CountingBloomFilter,
+        CuckooFilter,
+        QuotientFilter,
+        RibbonFilter,
+        ScalableBloomFilter,
+        SpectralBloomFilter,
+        StableBloomFilter,
+        XorFilter,
+    };
+
+    /// Round-trips a value through JSON and a binary format.
+    fn round_trip<T>(value: &T) -> [T; 2]
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        let json = serde_json::to_string(value).unwrap();
+        let bytes = postcard::to_allocvec(value).unwrap();
+        [
+            serde_json::from_str(&json).unwrap(),
+            postcard::from_bytes(&bytes).unwrap(),
+        ]
+    }
+
+    fn check<F: QueryFilter<u64>>(original: &F, read: &[F; 2]) {
+        for i in 0..2000 {
+            assert!(read
+                .iter()
+                .all(|read| read.contains(&i) == original.contains(&i)));
+        }
+    }
+
+    let mut cbf = CountingBloomFilter::new(1000, 0.01);
+    let mut cuckoo = CuckooFilter::new(1000, 0.01);
+    let mut qf = QuotientFilter::new(1000, 0.01);
+    let mut sbbf = BlockedBloomFilter::new(1000, 0.01);
+    let mut sbf = StableBloomFilter::new(1000, 0.01);
+    let mut scalable = ScalableBloomFilter::new(100, 0.01);
+    let mut spectral = SpectralBloomFilter::new(1000, 0.01);
+    for i in 0..1000u64 {
+        cbf.insert(i);
+        cuckoo.insert(i);
+        qf.insert(i);
+        sbbf.insert(i);
+        sbf.insert(i);
+        scalable.insert(i);
+        spectral.insert(i);
+    }
+    check(&cbf, &round_trip(&cbf));
+    check(&cuckoo, &round_trip(&cuckoo));
+    check(&qf, &round_trip(&qf));
+    check(&sbbf, &round_trip(&sbbf));
+    check(&sbf, &round_trip(&sbf));
+    check(&scalable, &round_trip(&scalable));
+    check(&spectral, &round_trip(&spectral));
+
+    let xor = XorFilter::from_keys(0..1000u64).unwrap();
+    check(&xor, &round_trip(&xor));
+    let fuse = BinaryFuseFilter::<u64, u16>::from_keys(0..1000u64);
+    check(&fuse, &round_trip(&fuse));
+    let ribbon = RibbonFilter::from_keys(0..1000u64, 0.01).unwrap();
+    check(&ribbon, &round_trip(&ribbon));
+    let homogeneous = RibbonFilter::homogeneous(0..1000u64, 0.01, 1.05).unwrap();
+    check(&homogeneous, &round_trip(&homogeneous));
+    let bumped = RibbonFilter::bumped(0..1000u64, 0.01, 1.).unwrap();
+    check(&bumped, &round_trip(&bumped));
+
+    // Serialized with all keys moved to the new table.
+    let mut splitting = QuotientFilter::with_bits(10, 8)
+        .unwrap()
+        .with_growth_mode(mqfilters::qf::GrowthMode::Incremental);
+    let mut key = 0u64;
+    while !splitting.is_splitting() {
+        splitting.insert(key);
+        key += 1;
+    }
+    let read = round_trip(&splitting);
+    check(&splitting, &read);
+    assert!(read.iter().all(|read| !read.is_splitting()
+        && read.len() == splitting.len()
+        && read.growth_mode() == splitting.growth_mode()));
+}
+
+#[test]
+#[cfg(all(feature = "qf", feature = "xor"))]
+fn other_filters_are_validated() {
+    use mqfilters::{storage::PackedArray, QuotientFilter, XorFilter};
+
+    let xor = XorFilter::from_keys(0..100u64).unwrap();
+    let mut json = serde_json::to_value(&xor).unwrap();
+    json["segment_length"] = serde_json::json!(1);
+    assert!(serde_json::from_value::<XorFilter<u64>>(json).is_err());
+
+    let qf = QuotientFilter::<u64>::new(100, 0.01);
+    let mut json = serde_json::to_value(&qf).unwrap();
+    json["quotient_bits"] = serde_json::json!(50);
+    assert!(serde_json::from_value::<QuotientFilter<u64>>(json).is_err());
+    let mut json = serde_json::to_value(&qf).unwrap();
+    json["len"] = serde_json::json!(1);
+    assert!(serde_json::from_value::<QuotientFilter<u64>>(json).is_err());
+
+    // Slot metadata that would have lookups loop forever, or find a run
+    // without a quotient.
+    let read = |metadata: &[u64]| {
+        let mut slots = PackedArray::new(16, 7);
+        for (slot, &value) in metadata.iter().enumerate() {
+            slots.set(slot, value);
+        }
+        let json = serde_json::json!({
+            "quotient_bits": 4,
+            "remainder_bits": 4,
+            "len": metadata.iter().filter(|&&value| value != 0).count(),
+            "slots": slots,
+        });
+        serde_json::from_value::<QuotientFilter<u64>>(json)
+    };
+    assert!(read(&[1, 0b110]).is_ok());
+    assert!(read(&[0b110; 16]).is_err());
+    assert!(read(&[0, 0b110]).is_err());
+    assert!(read(&[1, 0b111]).is_err());
+}
+
+#[test]
+fn scalable_slices_are_validated() {
+    use mqfilters::ScalableBloomFilter;
+
+    let mut filter = ScalableBloomFilter::new(100, 0.01);
+    for i in 0..1000u64 {
+        filter.insert(i);
+    }
+    assert!(filter.slice_count() > 1);
+    let json = serde_json::to_value(&filter).unwrap();
+    let read = serde_json::from_value::<ScalableBloomFilter<u64>>(json.clone()).unwrap();
+    assert!((0..1000u64).all(|i| read.contains(&i)));
+
+    for (field, value) in [
+        ("growth_factor", serde_json::json!(3)),
+        ("tightening_ratio", serde_json::json!(0.5)),
+        ("initial_capacity", serde_json::json!(200)),
+        ("last_len", serde_json::json!(2000)),
+    ] {
+        let mut json = json.clone();
+        json[field] = value;
+        assert!(serde_json::from_value::<ScalableBloomFilter<u64>>(json).is_err());
+    }
+}
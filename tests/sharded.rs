@@ -0,0 +1,88 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{
+    BloomFilter,
+    ClearableQueryFilter,
+    FilterStats,
+    InsertableQueryFilter,
+    MergeableQueryFilter,
+    QueryFilter,
+    QueryFilterError,
+    ShardedFilter,
+};
+
+#[test]
+fn concurrent_inserts() {
+    let filter = ShardedFilter::<BloomFilter<u64>>::new(8, 100_000, 0.01);
+    assert_eq!(filter.shard_count(), 8);
+    std::thread::scope(|scope| {
+        for thread in 0..4u64 {
+            let filter = &filter;
+            scope.spawn(move || {
+                for key in thread * 25_000..(thread + 1) * 25_000 {
+                    filter.insert(key);
+                }
+            });
+        }
+    });
+    assert!((0..100_000u64).all(|key| filter.contains(&key)));
+    let fp_count = (100_000..200_000u64)
+        .filter(|key| filter.contains(key))
+        .count();
+    assert!(fp_count < 1500, "fp_count: {fp_count}");
+
+    // Keys are spread evenly across shards.
+    let estimate = filter.approx_current_capacity();
+    assert!(estimate.abs_diff(100_000) < 2_000, "estimate: {estimate}");
+    let per_shard = (0..8).map(|index| filter.read_shard(index).approx_current_capacity());
+    assert!(per_shard
+        .into_iter()
+        .all(|count| count.abs_diff(12_500) < 1_000));
+}
+
+#[test]
+fn stats_and_merge() {
+    let shards = |keys: std::ops::Range<u64>| {
+        let filter = ShardedFilter::from_fn(4, || BloomFilter::with_bit_count(1 << 14, 5));
+        keys.for_each(|key| filter.insert(key));
+        filter
+    };
+    let mut filter = shards(0..1000);
+    assert_eq!(filter.slot_count(), 4 << 14);
+    assert_eq!(filter.hash_count(), 5);
+    assert_eq!(filter.size_in_bytes(), 4 << 11);
+    let ones = (0..4)
+        .map(|index| filter.read_shard(index).ones().count())
+        .sum::<usize>();
+    assert_eq!(filter.fill_ratio(), ones as f64 / (4 << 14) as f64);
+
+    filter.union_with(&shards(1000..2000));
+    assert!((0..2000u64).all(|key| filter.contains(&key)));
+    filter.intersect_with(&shards(500..1500));
+    assert!((500..1500u64).all(|key| filter.contains(&key)));
+
+    let other = ShardedFilter::<BloomFilter<u64>>::new(2, 1000, 0.01);
+    assert_eq!(
+        filter.try_union_with(&other),
+        Err(QueryFilterError::IncompatibleFilters("shard counts differ"))
+    );
+
+    filter.clear();
+    assert_eq!(filter.fill_ratio(), 0.);
+}
+
+#[test]
+fn exclusive_inserts() {
+    let mut filter = ShardedFilter::<BloomFilter<u64>>::new(4, 1000, 0.01);
+    for key in 0..1000u64 {
+        filter.insert(key);
+    }
+    assert!((0..1000u64).all(|key| filter.contains(&key)));
+
+    // Generic code over exclusively owned filters takes sharded ones too.
+    fn fill<F: InsertableQueryFilter<u64>>(filter: &mut F, keys: std::ops::Range<u64>) {
+        keys.for_each(|key| filter.insert(key));
+    }
+    fill(&mut filter, 1000..2000);
+    assert!((0..2000u64).all(|key| filter.contains(&key)));
+}
@@ -0,0 +1,49 @@
+#![cfg(all(feature = "roaring", feature = "bf"))]
+
+use mqfilters::{
+    analysis::{optimal_bit_count, optimal_hash_count},
+    hash::ProbeHasher,
+    storage::SparseBitSet,
+    BloomFilter,
+    FreezableQueryFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+};
+
+#[test]
+fn sparse_storage() {
+    // Sized for 10M keys (~11 MiB contiguous), holding just 10K.
+    let bit_count = optimal_bit_count(10_000_000, 0.01);
+    let hash_count = optimal_hash_count(10_000_000, bit_count);
+    let mut sparse = BloomFilter::with_storage(
+        SparseBitSet::with_len(bit_count),
+        hash_count,
+        ProbeHasher::default(),
+    );
+    let mut dense = BloomFilter::with_bit_count(bit_count, hash_count);
+    for i in 0..10000 {
+        sparse.insert(i);
+        dense.insert(i);
+    }
+    assert!(sparse.ones().eq(dense.ones()));
+    for i in 0..20000 {
+        assert_eq!(sparse.contains(&i), dense.contains(&i));
+    }
+    assert_eq!(
+        sparse.approx_current_capacity(),
+        dense.approx_current_capacity()
+    );
+}
+
+#[test]
+fn sparse_freeze() {
+    let bits = SparseBitSet::with_len(100_000);
+    let mut filter = BloomFilter::with_storage(bits, 7, ProbeHasher::default());
+    for i in 0..1000 {
+        filter.insert(i);
+    }
+    let frozen = filter.freeze();
+    for i in 0..1000 {
+        assert!(frozen.contains(&i));
+    }
+}
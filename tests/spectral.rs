@@ -0,0 +1,60 @@
+#![cfg(feature = "spectral")]
+
+use mqfilters::{
+    ClearableQueryFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+    RemovableQueryFilter,
+    SpectralBloomFilter,
+};
+
+#[test]
+fn estimates_counts() {
+    let mut filter = SpectralBloomFilter::new(10_000, 0.01);
+    // Key `i` occurs `i % 10 + 1` times.
+    for i in 0..10_000u64 {
+        filter.insert_count(i, i % 10);
+        filter.insert(i);
+    }
+    assert!((0..10_000u64).all(|i| filter.estimate_count(&i) > i % 10));
+    let exact = (0..10_000u64)
+        .filter(|i| filter.estimate_count(i) == i % 10 + 1)
+        .count();
+    assert!(exact > 9_800, "exact: {exact}");
+
+    let fp_count = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+    assert!(fp_count < 1500, "fp_count: {fp_count}");
+
+    for i in 0..5_000u64 {
+        filter.remove(&i);
+    }
+    assert!((0..5_000u64).all(|i| filter.estimate_count(&i) >= i % 10));
+
+    filter.clear();
+    assert_eq!(filter.estimate_count(&1), 0);
+}
+
+#[test]
+fn counts_insertions() {
+    let mut filter = SpectralBloomFilter::new(10_000, 0.01);
+    for i in 0..5000u64 {
+        filter.insert_count(i, i % 4 + 1);
+    }
+    assert_eq!(filter.approx_total_insertions(), 12_500);
+    let distinct = filter.approx_current_capacity();
+    assert!(distinct.abs_diff(5000) < 100, "distinct: {distinct}");
+}
+
+#[test]
+fn saturates() {
+    let mut filter = SpectralBloomFilter::with_params(100, 0.01, 4).unwrap();
+    assert_eq!(filter.max_count(), 15);
+    filter.insert_count("hot", 10);
+    filter.insert_count("hot", 10);
+    assert_eq!(filter.estimate_count("hot"), 15);
+    filter.remove("hot");
+    assert_eq!(filter.estimate_count("hot"), 15);
+
+    assert!(SpectralBloomFilter::<u64>::with_params(100, 0.01, 0).is_err());
+    assert!(SpectralBloomFilter::<u64>::with_params(100, 0.01, 65).is_err());
+}
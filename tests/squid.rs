@@ -0,0 +1,41 @@
+#![cfg(feature = "squid")]
+
+use mqfilters::squid::{CacheDigest, Method, DEFAULT_BITS_PER_ENTRY, HEADER_SIZE};
+
+#[test]
+fn digest_works() {
+    let mut digest = CacheDigest::new(1000, DEFAULT_BITS_PER_ENTRY);
+    assert_eq!(digest.mask().len(), 625);
+    for i in 0..1000 {
+        digest.insert(Method::Get, &format!("http://example.com/{i}"));
+    }
+    assert_eq!(digest.count(), 1000);
+    for i in 0..1000 {
+        assert!(digest.contains(Method::Get, &format!("http://example.com/{i}")));
+    }
+    // Five bits per entry and four hash functions make for a ~9% rate.
+    let fp_count = (1000..11000)
+        .filter(|i| digest.contains(Method::Get, &format!("http://example.com/{i}")))
+        .count();
+    assert!(fp_count < 1200, "fp_count: {fp_count}");
+}
+
+#[test]
+fn round_trip() {
+    let mut digest = CacheDigest::new(100, 8);
+    digest.insert(Method::Get, "http://example.com/");
+    let bytes = digest.to_bytes();
+    assert_eq!(bytes.len(), HEADER_SIZE + 100);
+    assert_eq!(&bytes[..4], &[0, 5, 0, 3]);
+
+    let decoded = CacheDigest::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, digest);
+    assert!(decoded.contains(Method::Get, "http://example.com/"));
+    assert!(!decoded.contains(Method::Head, "http://example.com/"));
+
+    assert!(CacheDigest::from_bytes(&bytes[..HEADER_SIZE - 1]).is_err());
+    assert!(CacheDigest::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    let mut newer = bytes.clone();
+    newer[3] = 6;
+    assert!(CacheDigest::from_bytes(&newer).is_err());
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "tbf")]
+
+use mqfilters::{
+    ClearableQueryFilter,
+    FreezableQueryFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+    TwoBlockBloomFilter,
+};
+
+#[test]
+fn default_filter() {
+    let mut filter = TwoBlockBloomFilter::new(100, 0.01);
+    assert_eq!(filter.approx_current_capacity(), 0);
+    assert!(!filter.contains(&"hello"));
+
+    filter.insert("hello");
+    assert!(filter.contains(&"hello"));
+    assert_eq!(filter.approx_current_capacity(), 1);
+
+    filter.insert("hello");
+    filter.insert("hello");
+    assert_eq!(filter.approx_current_capacity(), 1);
+
+    filter.clear();
+    assert!(!filter.contains(&"hello"));
+    assert_eq!(filter.approx_current_capacity(), 0);
+}
+
+#[test]
+fn with_size() {
+    let fp_rate = 0.01;
+    let size = 100 << 13;
+    let mut filter = TwoBlockBloomFilter::with_size(size, fp_rate);
+
+    let items_cnt: usize = 500000;
+    let mut fp_count = 0;
+    for i in 0..items_cnt {
+        if filter.contains(&i) {
+            fp_count += 1;
+        }
+        filter.insert(i);
+        // Ensure that no false negatives are present.
+        assert!(filter.contains(&i));
+    }
+
+    assert!(items_cnt.abs_diff(filter.approx_current_capacity()) < items_cnt / 100);
+    assert!((fp_count as f64) < items_cnt as f64 * fp_rate);
+}
+
+#[test]
+fn with_capacity() {
+    let fp_rate = 0.01;
+    let capacity: usize = 100000;
+    let mut filter = TwoBlockBloomFilter::with_capacity(capacity, fp_rate);
+    let mut fp_count = 0;
+    for i in 0..capacity {
+        if filter.contains(&i) {
+            fp_count += 1;
+        }
+        filter.insert(i);
+        // Ensure that no false negatives are present.
+        assert!(filter.contains(&i));
+    }
+
+    assert!(capacity.abs_diff(filter.approx_current_capacity()) < capacity / 100);
+    assert!((fp_count as f64) < capacity as f64 * fp_rate);
+
+    // Fully loaded filter stays close to the target rate for unseen keys.
+    let fp_count = (capacity..capacity * 2)
+        .filter(|i| filter.contains(i))
+        .count();
+    assert!((fp_count as f64) < capacity as f64 * fp_rate * 1.5);
+}
+
+#[test]
+fn freeze() {
+    let mut filter = TwoBlockBloomFilter::new(10000, 0.01);
+    for i in 0..10000 {
+        filter.insert(i);
+    }
+    let expected = (0..20000).map(|i| filter.contains(&i)).collect::<Vec<_>>();
+
+    let frozen = filter.freeze();
+    for (i, expected) in expected.into_iter().enumerate() {
+        assert_eq!(frozen.contains(&i), expected);
+    }
+}
+
+#[test]
+fn streamed_keys() {
+    let blob = |i: u32| (0..100_000u32).map(move |j| (i ^ j) as u8);
+    let mut filter = TwoBlockBloomFilter::<Vec<u8>>::new(100, 0.001);
+    for i in 0..100 {
+        let mut key = filter.hasher().key_hasher();
+        key.update(&blob(i).collect::<Vec<_>>());
+        filter.insert_streamed(&key);
+    }
+    for i in 0..100 {
+        let mut key = filter.hasher().key_hasher();
+        for chunk in blob(i).collect::<Vec<_>>().chunks(4096) {
+            key.update(chunk);
+        }
+        assert!(filter.contains_streamed(&key));
+    }
+}
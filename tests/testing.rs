@@ -0,0 +1,102 @@
+#![cfg(all(feature = "testing", feature = "bf"))]
+
+use {
+    mqfilters::{
+        testing::{check_operations, disjoint_key_sets, filter_params, operations, probe_hashers},
+        BloomFilter,
+        InsertableQueryFilter,
+        QueryFilter,
+    },
+    proptest::prelude::*,
+};
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn no_false_negatives(
+        params in filter_params(),
+        hasher in probe_hashers(),
+        (inserted, holdout) in disjoint_key_sets(any::<u64>(), 1000),
+    ) {
+        let mut filter =
+            BloomFilter::with_capacity_and_hasher(params.capacity, params.fp_rate, hasher);
+        for key in &inserted {
+            filter.insert(*key);
+        }
+        for key in &inserted {
+            prop_assert!(filter.contains(key));
+        }
+        for key in &holdout {
+            prop_assert!(!inserted.contains(key));
+        }
+    }
+
+    #[test]
+    fn operation_sequences(ops in operations(0..500u32, 2000)) {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        check_operations(&mut filter, &ops);
+    }
+}
+
+#[test]
+fn flaky_filter() {
+    use mqfilters::{testing::FlakyFilter, ClearableQueryFilter, RemovableQueryFilter};
+
+    let mut filter = FlakyFilter::new().with_false_positives(["ghost"]);
+    filter.insert("key");
+    assert!(filter.contains("key"));
+    assert!(filter.contains("ghost"));
+    assert!(filter.is_false_positive("ghost"));
+    assert!(!filter.contains("other"));
+    filter.remove("key");
+    assert!(!filter.contains("key"));
+
+    // Hash-picked false positives are deterministic, and near the rate.
+    let filter = FlakyFilter::<u64>::new().with_fp_rate(0.1).with_seed(7);
+    let fp_count = (0..10000u64).filter(|i| filter.contains(i)).count();
+    assert!((800..1200).contains(&fp_count), "fp_count: {fp_count}");
+    let again = FlakyFilter::<u64>::new().with_fp_rate(0.1).with_seed(7);
+    assert!((0..10000u64).all(|i| filter.contains(&i) == again.contains(&i)));
+
+    let mut filter = FlakyFilter::new().with_saturation_at(2);
+    filter.insert(1);
+    assert!(!filter.contains(&3));
+    filter.insert(2);
+    assert!(filter.is_saturated());
+    assert!(filter.contains(&3));
+    filter.clear();
+    assert!(!filter.contains(&3));
+}
+
+#[test]
+fn shadow_filter() {
+    use {
+        mqfilters::{
+            testing::{FlakyFilter, ShadowFilter},
+            ClearableQueryFilter,
+        },
+        std::panic::{catch_unwind, AssertUnwindSafe},
+    };
+
+    let mut filter = ShadowFilter::new(BloomFilter::new(1000, 0.01));
+    for i in 0..1000u64 {
+        filter.insert(i);
+    }
+    assert!((0..1000u64).all(|i| filter.contains(&i)));
+    assert_eq!(filter.negative_queries(), 0);
+    let _ = (1000..11000u64).filter(|i| filter.contains(i)).count();
+    assert_eq!(filter.negative_queries(), 10000);
+    let fp_rate = filter.realized_fp_rate().unwrap();
+    assert!(fp_rate < 0.02, "fp_rate: {fp_rate}");
+    filter.verify();
+
+    // A filter that loses keys is caught.
+    let mut filter = ShadowFilter::new(FlakyFilter::new());
+    filter.insert("key");
+    filter.filter_mut().clear();
+    let lost = catch_unwind(AssertUnwindSafe(|| filter.contains("key")));
+    assert!(lost.is_err());
+    let lost = catch_unwind(AssertUnwindSafe(|| filter.verify()));
+    assert!(lost.is_err());
+}
@@ -0,0 +1,108 @@
+#![cfg(feature = "theta")]
+
+use mqfilters::{
+    theta::{CompactThetaSketch, ThetaUnion},
+    QueryFilterError,
+    ThetaSketch,
+};
+
+fn sketch(values: impl IntoIterator<Item = u64>) -> ThetaSketch {
+    let mut sketch = ThetaSketch::new(12);
+    for value in values {
+        sketch.insert(&value);
+    }
+    sketch
+}
+
+#[test]
+fn exact_mode() {
+    let mut sketch = ThetaSketch::new(12);
+    assert!(sketch.is_empty());
+    for i in 0..1000 {
+        sketch.insert(&format!("item-{i}"));
+        sketch.insert(&format!("item-{i}"));
+    }
+    // Empty values are ignored.
+    sketch.insert("");
+    assert!(!sketch.is_estimation_mode());
+    assert_eq!(sketch.estimate(), 1000.);
+    assert_eq!(sketch.lower_bound(2.), 1000.);
+    assert_eq!(sketch.upper_bound(2.), 1000.);
+}
+
+#[test]
+fn estimation_mode() {
+    let sketch = sketch(0..1_000_000);
+    assert!(sketch.is_estimation_mode());
+    assert_eq!(sketch.retained(), 4096);
+
+    // Relative standard error is about 1/64.
+    let estimate = sketch.estimate();
+    assert!(
+        (estimate - 1e6).abs() < 1e6 * 3. / 64.,
+        "estimate: {estimate}"
+    );
+    assert!(sketch.lower_bound(3.) < 1e6 && 1e6 < sketch.upper_bound(3.));
+    assert!(sketch.lower_bound(1.) < estimate && estimate < sketch.upper_bound(1.));
+}
+
+#[test]
+fn set_operations() {
+    let a = sketch(0..600_000).compact();
+    let b = sketch(400_000..1_000_000).compact();
+
+    let mut union = ThetaUnion::new(12);
+    union.update(&a).unwrap();
+    union.update(&b).unwrap();
+    let union = union.result();
+    assert!(union.retained() <= 4096);
+    let within = |estimate: f64, expected: f64| (estimate - expected).abs() < expected * 0.1;
+    assert!(within(union.estimate(), 1e6), "{}", union.estimate());
+
+    let both = a.intersection(&b).unwrap();
+    assert!(within(both.estimate(), 2e5), "{}", both.estimate());
+    assert!(both.lower_bound(3.) < 2e5 && 2e5 < both.upper_bound(3.));
+
+    let only_a = a.difference(&b).unwrap();
+    assert!(within(only_a.estimate(), 4e5), "{}", only_a.estimate());
+
+    // Exact sketches yield exact results.
+    let a = sketch(0..100).compact();
+    let b = sketch(50..150).compact();
+    assert_eq!(a.intersection(&b).unwrap().estimate(), 50.);
+    assert_eq!(a.difference(&b).unwrap().estimate(), 50.);
+    assert_eq!(b.difference(&sketch([]).compact()).unwrap(), b);
+}
+
+#[test]
+fn seeds_must_match() {
+    let a = sketch(0..100).compact();
+    let mut other = ThetaSketch::with_seed(12, 42);
+    other.insert(&1u64);
+    let b = other.compact();
+    assert_eq!(
+        a.intersection(&b),
+        Err(QueryFilterError::IncompatibleFilters("different seeds"))
+    );
+    assert!(ThetaUnion::new(12).update(&b).is_err());
+}
+
+#[test]
+fn serialization() {
+    for sketch in [sketch([]), sketch([7]), sketch(0..100), sketch(0..100_000)] {
+        let compact = sketch.compact();
+        let bytes = compact.to_bytes();
+        let expected_len = match sketch.retained() {
+            0 => 8,
+            1 => 16,
+            n if sketch.is_estimation_mode() => 24 + 8 * n,
+            n => 16 + 8 * n,
+        };
+        assert_eq!(bytes.len(), expected_len);
+        // Compact sketch of the default seed.
+        assert_eq!(bytes[1..3], [3, 3]);
+        assert_eq!(bytes[6..8], [0xcc, 0x93]);
+        assert_eq!(CompactThetaSketch::from_bytes(&bytes).unwrap(), compact);
+        assert!(CompactThetaSketch::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,38 @@
+#![cfg(feature = "bf")]
+
+use mqfilters::{
+    BloomFilter,
+    InsertableQueryFilter,
+    QueryFilter,
+    RemovableQueryFilter,
+    WithTombstones,
+};
+
+#[test]
+fn removes_keys() {
+    let mut filter = WithTombstones::new(BloomFilter::new(1000, 0.001), 100, 0.001);
+    for i in 0..1000u64 {
+        filter.insert(i);
+    }
+    for i in 0..100u64 {
+        filter.remove(&i);
+    }
+    assert_eq!(filter.tombstone_count(), 100);
+    assert!(filter.needs_compaction());
+    assert!((0..100u64).all(|i| !filter.contains(&i)));
+    let live = (100..1000u64).filter(|i| filter.contains(i)).count();
+    assert!(live >= 895, "live: {live}");
+
+    // Removing absent keys records nothing.
+    filter.remove(&5000);
+    assert_eq!(filter.tombstone_count(), 100);
+
+    // Removed keys stay removed until compaction.
+    filter.insert(1);
+    assert!(!filter.contains(&1));
+    filter.compact((1..1000u64).filter(|&i| i == 1 || i >= 100));
+    assert_eq!(filter.tombstone_count(), 0);
+    assert!(filter.contains(&1));
+    assert!((100..1000u64).all(|i| filter.contains(&i)));
+    assert_eq!(filter.tombstone_fp_rate(), 0.);
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "xor")]
+
+use mqfilters::{analysis, QueryFilter, QueryFilterError, XorFilter};
+
+#[test]
+fn xor8() {
+    let keys = (0..10_000u64).collect::<Vec<_>>();
+    let filter = XorFilter::from_keys(keys.iter().copied().chain([1, 2, 3])).unwrap();
+    assert_eq!(filter.fingerprint_bits(), 8);
+    assert!(keys.iter().all(|key| filter.contains(key)));
+    let fp_count = (10_000..110_000u64).filter(|i| filter.contains(i)).count();
+    assert!((250..550).contains(&fp_count), "fp_count: {fp_count}");
+    // About 1.23 bytes per key.
+    assert!(
+        filter.size_in_bytes() < 12_400,
+        "{}",
+        filter.size_in_bytes()
+    );
+}
+
+#[test]
+fn xor16() {
+    let keys = (0..10_000).map(|i| format!("key-{i}")).collect::<Vec<_>>();
+    let filter = XorFilter::with_fingerprint_bits(keys.clone(), 16).unwrap();
+    assert!(keys.iter().all(|key| filter.contains(key.as_str())));
+    let fp_count = (0..100_000)
+        .filter(|i| filter.contains(format!("other-{i}").as_str()))
+        .count();
+    assert!(fp_count < 10, "fp_count: {fp_count}");
+
+    assert_eq!(
+        XorFilter::with_fingerprint_bits(keys, 33).err(),
+        Some(QueryFilterError::InvalidFingerprintBits { bits: 33, max: 32 })
+    );
+    assert!(XorFilter::<u64>::from_keys([]).is_ok());
+}
+
+#[test]
+fn fingerprint_widths() {
+    for (fp_rate, fingerprint_bits) in [(1. / 256., 8), (1e-4, 14), (1. / 65536., 16), (1e-9, 30)] {
+        let filter = XorFilter::with_fp_rate(0..10_000u64, fp_rate).unwrap();
+        assert_eq!(filter.fingerprint_bits(), fingerprint_bits);
+        assert_eq!(analysis::xor_fingerprint_bits(fp_rate), fingerprint_bits);
+    }
+
+    // Sizes follow the sizing math, up to rounding to whole words.
+    for fingerprint_bits in [8, 16, 32] {
+        let filter = XorFilter::with_fingerprint_bits(0..10_000u64, fingerprint_bits).unwrap();
+        assert!((0..10_000u64).all(|key| filter.contains(&key)));
+        let expected = analysis::xor_size_in_bytes(10_000, fingerprint_bits);
+        assert!(
+            filter.size_in_bytes().abs_diff(expected) <= 8,
+            "{fingerprint_bits}: {} vs {expected}",
+            filter.size_in_bytes()
+        );
+    }
+    let xor32 = XorFilter::with_fingerprint_bits(0..10_000u64, 32).unwrap();
+    assert!(!(10_000..1_000_000u64).any(|key| xor32.contains(&key)));
+
+    for fp_rate in [1., 0., 1e-10, f64::NAN] {
+        assert!(matches!(
+            XorFilter::with_fp_rate(0..10u64, fp_rate),
+            Err(QueryFilterError::InvalidFpRate(_))
+        ));
+    }
+}